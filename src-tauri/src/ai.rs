@@ -1,6 +1,7 @@
 use std::io::Read;
 use std::path::PathBuf;
-use std::process::Command;
+use std::process::{Command, Output};
+use crate::error::TopographError;
 
 /// Locate the Python binary inside the ml/venv.
 /// Falls back to system `python3` if venv doesn't exist.
@@ -23,77 +24,109 @@ pub fn project_root(_app_handle: &tauri::AppHandle) -> PathBuf {
     manifest_dir.parent().unwrap_or(&manifest_dir).to_path_buf()
 }
 
+/// Save a helper's stdout/stderr to `<tmp>/topograph/<task>.log` so the error
+/// dialog can point at it. Returns None if the log couldn't be written.
+fn write_helper_log(task: &str, output: &Output) -> Option<PathBuf> {
+    let path = std::env::temp_dir().join("topograph").join(format!("{task}.log"));
+    let log = format!(
+        "exit code: {:?}\n\n--- stdout ---\n{}\n--- stderr ---\n{}",
+        output.status.code(),
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    std::fs::write(&path, log).ok()?;
+    Some(path)
+}
+
+fn helper_error(task: &str, message: String, output: &Output) -> TopographError {
+    let err = TopographError::ml(message);
+    match write_helper_log(task, output) {
+        Some(path) => err.with_log(path),
+        None => err,
+    }
+}
+
+/// Run an ML helper script and parse the JSON status it prints on stdout.
+/// `label` names the task in error messages.
+fn run_helper(task: &str, label: &str, command: &mut Command) -> Result<(), TopographError> {
+    let output = command
+        .output()
+        .map_err(|e| TopographError::ml(format!("Failed to spawn Python: {e}")))?;
+
+    if !output.status.success() {
+        return Err(helper_error(
+            task,
+            format!("{label} failed (exit code {:?})", output.status.code()),
+            &output,
+        ));
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let status: serde_json::Value = serde_json::from_str(stdout.trim()).map_err(|e| {
+        helper_error(task, format!("Failed to parse Python output: {e}"), &output)
+    })?;
+
+    if status["success"] != true {
+        let error = status["error"].as_str().unwrap_or("Unknown error");
+        return Err(helper_error(task, format!("{label} error: {error}"), &output));
+    }
+    Ok(())
+}
+
 /// Run depth estimation: takes a PNG image, returns raw f32 heightmap data.
 pub fn run_depth_estimation(
     app_handle: &tauri::AppHandle,
     image_data: &[u8],
     width: u32,
     height: u32,
-) -> Result<Vec<f32>, String> {
+) -> Result<Vec<f32>, TopographError> {
     let root = project_root(app_handle);
     let python = python_bin(&root);
     let script = root.join("ml/depth_estimate.py");
 
     if !script.exists() {
-        return Err(format!("Depth estimation script not found: {}", script.display()));
+        return Err(TopographError::ml(format!("Depth estimation script not found: {}", script.display())));
     }
 
     // Write input PNG to temp file
     let tmp_dir = std::env::temp_dir().join("topograph");
-    std::fs::create_dir_all(&tmp_dir).map_err(|e| format!("Failed to create temp dir: {e}"))?;
+    std::fs::create_dir_all(&tmp_dir).map_err(|e| TopographError::io(format!("Failed to create temp dir: {e}")))?;
 
     let input_path = tmp_dir.join("depth_input.png");
     let output_path = tmp_dir.join("depth_output.bin");
 
     std::fs::write(&input_path, image_data)
-        .map_err(|e| format!("Failed to write input PNG: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to write input PNG: {e}")))?;
 
     // Spawn Python subprocess
-    let output = Command::new(&python)
-        .arg(&script)
-        .arg("--input")
-        .arg(&input_path)
-        .arg("--output")
-        .arg(&output_path)
-        .arg("--width")
-        .arg(width.to_string())
-        .arg("--height")
-        .arg(height.to_string())
-        .output()
-        .map_err(|e| format!("Failed to spawn Python: {e}"))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "Depth estimation failed (exit code {:?}):\nstdout: {stdout}\nstderr: {stderr}",
-            output.status.code()
-        ));
-    }
-
-    // Parse JSON status from stdout
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let status: serde_json::Value = serde_json::from_str(stdout.trim())
-        .map_err(|e| format!("Failed to parse Python output: {e}\nRaw: {stdout}"))?;
-
-    if status["success"] != true {
-        let error = status["error"].as_str().unwrap_or("Unknown error");
-        return Err(format!("Depth estimation error: {error}"));
-    }
+    run_helper(
+        "depth_estimate",
+        "Depth estimation",
+        Command::new(&python)
+            .arg(&script)
+            .arg("--input")
+            .arg(&input_path)
+            .arg("--output")
+            .arg(&output_path)
+            .arg("--width")
+            .arg(width.to_string())
+            .arg("--height")
+            .arg(height.to_string()),
+    )?;
 
     // Read output binary (f32 array, row-major, little-endian)
     let mut file = std::fs::File::open(&output_path)
-        .map_err(|e| format!("Failed to open depth output: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to open depth output: {e}")))?;
     let mut bytes = Vec::new();
     file.read_to_end(&mut bytes)
-        .map_err(|e| format!("Failed to read depth output: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to read depth output: {e}")))?;
 
     let expected_len = (width * height) as usize * 4;
     if bytes.len() != expected_len {
-        return Err(format!(
+        return Err(TopographError::ml(format!(
             "Depth output size mismatch: got {} bytes, expected {expected_len}",
             bytes.len()
-        ));
+        )));
     }
 
     // Convert bytes to f32 array
@@ -116,61 +149,46 @@ pub fn run_inpainting(
     mask_data: &[u8],
     prompt: &str,
     mode: &str,
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, TopographError> {
     let root = project_root(app_handle);
     let python = python_bin(&root);
     let script = root.join("ml/inpaint.py");
 
     if !script.exists() {
-        return Err(format!("Inpainting script not found: {}", script.display()));
+        return Err(TopographError::ml(format!("Inpainting script not found: {}", script.display())));
     }
 
     let tmp_dir = std::env::temp_dir().join("topograph");
-    std::fs::create_dir_all(&tmp_dir).map_err(|e| format!("Failed to create temp dir: {e}"))?;
+    std::fs::create_dir_all(&tmp_dir).map_err(|e| TopographError::io(format!("Failed to create temp dir: {e}")))?;
 
     let image_path = tmp_dir.join("inpaint_image.png");
     let mask_path = tmp_dir.join("inpaint_mask.png");
     let output_path = tmp_dir.join("inpaint_output.png");
 
     std::fs::write(&image_path, image_data)
-        .map_err(|e| format!("Failed to write image: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to write image: {e}")))?;
     std::fs::write(&mask_path, mask_data)
-        .map_err(|e| format!("Failed to write mask: {e}"))?;
-
-    let output = Command::new(&python)
-        .arg(&script)
-        .arg("--image")
-        .arg(&image_path)
-        .arg("--mask")
-        .arg(&mask_path)
-        .arg("--prompt")
-        .arg(prompt)
-        .arg("--output")
-        .arg(&output_path)
-        .arg("--mode")
-        .arg(mode)
-        .output()
-        .map_err(|e| format!("Failed to spawn Python: {e}"))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "Inpainting failed:\nstdout: {stdout}\nstderr: {stderr}"
-        ));
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let status: serde_json::Value = serde_json::from_str(stdout.trim())
-        .map_err(|e| format!("Failed to parse Python output: {e}\nRaw: {stdout}"))?;
-
-    if status["success"] != true {
-        let error = status["error"].as_str().unwrap_or("Unknown error");
-        return Err(format!("Inpainting error: {error}"));
-    }
+        .map_err(|e| TopographError::io(format!("Failed to write mask: {e}")))?;
+
+    run_helper(
+        "inpaint",
+        "Inpainting",
+        Command::new(&python)
+            .arg(&script)
+            .arg("--image")
+            .arg(&image_path)
+            .arg("--mask")
+            .arg(&mask_path)
+            .arg("--prompt")
+            .arg(prompt)
+            .arg("--output")
+            .arg(&output_path)
+            .arg("--mode")
+            .arg(mode),
+    )?;
 
     let result_bytes = std::fs::read(&output_path)
-        .map_err(|e| format!("Failed to read inpainting output: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to read inpainting output: {e}")))?;
 
     // Cleanup
     let _ = std::fs::remove_file(&image_path);
@@ -228,20 +246,20 @@ pub fn run_controlnet_texture(
     heightmap_data: &[f32],
     hm_width: u32,
     hm_height: u32,
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, TopographError> {
     let root = project_root(app_handle);
     let python = python_bin(&root);
     let script = root.join("ml/controlnet_texture.py");
 
     if !script.exists() {
-        return Err(format!(
+        return Err(TopographError::ml(format!(
             "ControlNet texture script not found: {}",
             script.display()
-        ));
+        )));
     }
 
     let tmp_dir = std::env::temp_dir().join("topograph");
-    std::fs::create_dir_all(&tmp_dir).map_err(|e| format!("Failed to create temp dir: {e}"))?;
+    std::fs::create_dir_all(&tmp_dir).map_err(|e| TopographError::io(format!("Failed to create temp dir: {e}")))?;
 
     let image_path = tmp_dir.join("cn_image.png");
     let depth_path = tmp_dir.join("cn_depth.png");
@@ -250,51 +268,36 @@ pub fn run_controlnet_texture(
 
     // Write captured terrain image
     std::fs::write(&image_path, image_data)
-        .map_err(|e| format!("Failed to write image: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to write image: {e}")))?;
 
     // Convert heightmap to grayscale PNG for ControlNet depth conditioning
     let depth_png = heightmap_to_grayscale_png(heightmap_data, hm_width, hm_height)?;
     std::fs::write(&depth_path, &depth_png)
-        .map_err(|e| format!("Failed to write depth image: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to write depth image: {e}")))?;
 
     // Write mask
     std::fs::write(&mask_path, mask_data)
-        .map_err(|e| format!("Failed to write mask: {e}"))?;
-
-    let output = Command::new(&python)
-        .arg(&script)
-        .arg("--image")
-        .arg(&image_path)
-        .arg("--depth")
-        .arg(&depth_path)
-        .arg("--mask")
-        .arg(&mask_path)
-        .arg("--prompt")
-        .arg(prompt)
-        .arg("--output")
-        .arg(&output_path)
-        .output()
-        .map_err(|e| format!("Failed to spawn Python: {e}"))?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        let stdout = String::from_utf8_lossy(&output.stdout);
-        return Err(format!(
-            "ControlNet texture generation failed:\nstdout: {stdout}\nstderr: {stderr}"
-        ));
-    }
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    let status: serde_json::Value = serde_json::from_str(stdout.trim())
-        .map_err(|e| format!("Failed to parse Python output: {e}\nRaw: {stdout}"))?;
-
-    if status["success"] != true {
-        let error = status["error"].as_str().unwrap_or("Unknown error");
-        return Err(format!("ControlNet texture error: {error}"));
-    }
+        .map_err(|e| TopographError::io(format!("Failed to write mask: {e}")))?;
+
+    run_helper(
+        "controlnet_texture",
+        "ControlNet texture generation",
+        Command::new(&python)
+            .arg(&script)
+            .arg("--image")
+            .arg(&image_path)
+            .arg("--depth")
+            .arg(&depth_path)
+            .arg("--mask")
+            .arg(&mask_path)
+            .arg("--prompt")
+            .arg(prompt)
+            .arg("--output")
+            .arg(&output_path),
+    )?;
 
     let result_bytes = std::fs::read(&output_path)
-        .map_err(|e| format!("Failed to read ControlNet output: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to read ControlNet output: {e}")))?;
 
     // Cleanup
     let _ = std::fs::remove_file(&image_path);
@@ -307,17 +310,17 @@ pub fn run_controlnet_texture(
 
 /// Decode a PNG mask image (grayscale) into per-pixel f32 weights [0.0, 1.0].
 /// White (255) = 1.0, Black (0) = 0.0.
-pub fn decode_mask_png(png_data: &[u8], width: u32, height: u32) -> Result<Vec<f32>, String> {
+pub fn decode_mask_png(png_data: &[u8], width: u32, height: u32) -> Result<Vec<f32>, TopographError> {
     // Minimal PNG decode: write to temp, use Python to convert, or decode manually.
     // Use the simplest approach: save PNG, run a tiny Python script to output raw f32.
     let tmp_dir = std::env::temp_dir().join("topograph");
-    std::fs::create_dir_all(&tmp_dir).map_err(|e| format!("Failed to create temp dir: {e}"))?;
+    std::fs::create_dir_all(&tmp_dir).map_err(|e| TopographError::io(format!("Failed to create temp dir: {e}")))?;
 
     let mask_path = tmp_dir.join("mask_decode.png");
     let output_path = tmp_dir.join("mask_decode.bin");
 
     std::fs::write(&mask_path, png_data)
-        .map_err(|e| format!("Failed to write mask: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to write mask: {e}")))?;
 
     // Find python
     let manifest_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -333,15 +336,15 @@ pub fn decode_mask_png(png_data: &[u8], width: u32, height: u32) -> Result<Vec<f
             mask_path.display(), width, height, output_path.display()
         ))
         .output()
-        .map_err(|e| format!("Failed to decode mask: {e}"))?;
+        .map_err(|e| TopographError::ml(format!("Failed to decode mask: {e}")))?;
 
     if !output.status.success() {
         let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!("Mask decode failed: {stderr}"));
+        return Err(helper_error("mask_decode", format!("Mask decode failed: {stderr}"), &output));
     }
 
     let bytes = std::fs::read(&output_path)
-        .map_err(|e| format!("Failed to read decoded mask: {e}"))?;
+        .map_err(|e| TopographError::io(format!("Failed to read decoded mask: {e}")))?;
 
     let _ = std::fs::remove_file(&mask_path);
     let _ = std::fs::remove_file(&output_path);
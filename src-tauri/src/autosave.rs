@@ -0,0 +1,296 @@
+//! Recovery saves of the document in the app data directory while it's being
+//! changed, so a crash costs at most a minute or so of work. A clean exit
+//! removes the recovery file; one found at startup is set aside under its own
+//! name as a crashed session's until the user restores or discards it.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use serde::Serialize;
+use tauri::{AppHandle, Emitter};
+use crate::boundary::Boundary;
+use crate::derived::DerivedCache;
+use crate::detail::DetailPatch;
+use crate::erosion::hydraulic::ErosionMaps;
+use crate::heightmap::Heightmap;
+use crate::mask::MaskSet;
+use crate::noise_gen::Frame;
+use crate::project::{self, ProjectContents, ProjectMetadata};
+use crate::provenance::OperationLog;
+use crate::state::AppState;
+use crate::texture::Texture;
+use crate::usage::UsageStats;
+use crate::world::WorldScale;
+
+/// Least time between saves.
+const INTERVAL: Duration = Duration::from_secs(60);
+/// How long edits must have paused, so saves don't land mid-stroke.
+const SETTLE: Duration = Duration::from_secs(2);
+const TICK: Duration = Duration::from_secs(1);
+
+const RECOVERY_FILE: &str = "recovery.topo";
+/// Crashed sessions' files are `crashed-<unix seconds>.topo`, so one crash
+/// never overwrites another's.
+const CRASHED_PREFIX: &str = "crashed-";
+
+/// Set by the panic hook. Saving stops then, since the document may be half
+/// way through an operation, and the last recovery file outlives the exit.
+static PANICKED: AtomicBool = AtomicBool::new(false);
+
+/// A crashed session's recovery file.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecoveryInfo {
+    pub path: String,
+    /// Seconds since the Unix epoch.
+    pub saved_at: u64,
+}
+
+/// What the saving thread reads, shared with `AppState`.
+struct Document {
+    heightmap: Arc<Mutex<Heightmap>>,
+    world_scale: Arc<Mutex<WorldScale>>,
+    boundary: Arc<Mutex<Boundary>>,
+    usage: Arc<Mutex<UsageStats>>,
+    masks: Arc<Mutex<MaskSet>>,
+    erosion_maps: Arc<Mutex<Option<ErosionMaps>>>,
+    detail_patches: Arc<Mutex<Vec<DetailPatch>>>,
+    canvas_frame: Arc<Mutex<Option<Frame>>>,
+    texture: Arc<Mutex<Option<Texture>>>,
+    metadata: Arc<Mutex<ProjectMetadata>>,
+    operations: Arc<Mutex<OperationLog>>,
+    settings_json: Arc<Mutex<String>>,
+    derived: Arc<Mutex<DerivedCache>>,
+    revision: Arc<AtomicU64>,
+}
+
+/// Outcome of a save, sent to the frontend as the `autosave` event.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AutosaveStatus {
+    /// Seconds since the Unix epoch.
+    pub at: u64,
+    /// Why the save failed; `None` once one succeeds.
+    pub error: Option<String>,
+}
+
+/// Set aside a recovery file left by a crash, install the panic hook and start
+/// saving into `dir`, reporting each save to `app`.
+pub fn start(dir: PathBuf, state: &AppState, app: AppHandle) {
+    let recovery = dir.join(RECOVERY_FILE);
+    if recovery.exists() {
+        let _ = std::fs::rename(&recovery, crashed_path(&dir));
+    }
+
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        PANICKED.store(true, Ordering::SeqCst);
+        previous(info);
+    }));
+
+    let doc = Document {
+        heightmap: state.heightmap.clone(),
+        world_scale: state.world_scale.clone(),
+        boundary: state.boundary.clone(),
+        usage: state.usage.clone(),
+        masks: state.masks.clone(),
+        erosion_maps: state.erosion_maps.clone(),
+        detail_patches: state.detail_patches.clone(),
+        canvas_frame: state.canvas_frame.clone(),
+        texture: state.texture.clone(),
+        metadata: state.metadata.clone(),
+        operations: state.operations.clone(),
+        settings_json: state.settings_json.clone(),
+        derived: state.derived.clone(),
+        revision: state.revision.clone(),
+    };
+    std::thread::spawn(move || run(&dir, &doc, &app));
+}
+
+fn run(dir: &Path, doc: &Document, app: &AppHandle) {
+    // The derived-data scheduler hashes reported heightmap edits for changes. Its first
+    // pass, shortly after startup, sees the whole map as new.
+    std::thread::sleep(TICK);
+    let mut saved_change = doc.derived.lock().unwrap().last_change();
+    let mut saved_revision = doc.revision.load(Ordering::SeqCst);
+    // When the revision was first seen at its current value
+    let (mut seen_revision, mut revised_at) = (saved_revision, Instant::now());
+    let mut saved_at = Instant::now();
+    loop {
+        std::thread::sleep(TICK);
+        if PANICKED.load(Ordering::SeqCst) {
+            return;
+        }
+        let revision = doc.revision.load(Ordering::SeqCst);
+        if revision != seen_revision {
+            (seen_revision, revised_at) = (revision, Instant::now());
+        }
+        if saved_at.elapsed() < INTERVAL {
+            continue;
+        }
+        let Ok(derived) = doc.derived.try_lock() else {
+            continue;
+        };
+        let last_change = derived.last_change();
+        drop(derived);
+        let changed = last_change != saved_change || revision != saved_revision;
+        if !changed || last_change.elapsed() < SETTLE || revised_at.elapsed() < SETTLE {
+            continue;
+        }
+
+        // Busy with a long operation; try again next tick
+        let Ok(hm) = doc.heightmap.try_lock().map(|hm| hm.clone()) else {
+            continue;
+        };
+        let at = std::time::SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let _ = app.emit("autosave", AutosaveStatus { at, error: write(dir, doc, &hm).err() });
+        saved_at = Instant::now();
+        saved_change = last_change;
+        saved_revision = revision;
+    }
+}
+
+/// Incremental, since most saves follow edits to a small part of the map.
+fn write(dir: &Path, doc: &Document, hm: &Heightmap) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    let world_scale = doc.world_scale.lock().unwrap().clone();
+    let boundary = *doc.boundary.lock().unwrap();
+    let usage = doc.usage.lock().unwrap().clone();
+    let masks = doc.masks.lock().unwrap().clone();
+    let erosion_maps = doc.erosion_maps.lock().unwrap().clone();
+    let detail_patches = doc.detail_patches.lock().unwrap().clone();
+    let texture = doc.texture.lock().unwrap().as_ref().map(|t| t.png.clone());
+    let metadata = doc.metadata.lock().unwrap().clone();
+    let operations = doc.operations.lock().unwrap().clone();
+    let settings_json = doc.settings_json.lock().unwrap().clone();
+    let contents = ProjectContents {
+        heightmap: hm,
+        texture_png: texture.as_deref(),
+        settings_json: &settings_json,
+        world_scale: &world_scale,
+        boundary,
+        usage: Some(&usage),
+        history: Vec::new(),
+        masks: Some(&masks),
+        erosion_maps: erosion_maps.as_ref(),
+        detail_patches: &detail_patches,
+        canvas_frame: *doc.canvas_frame.lock().unwrap(),
+        metadata: &metadata,
+        operations: Some(&operations),
+    };
+    project::save_project(&dir.join(RECOVERY_FILE), &contents, true, &|_| {})
+}
+
+/// Remove the recovery file on a clean exit; after a panic it's kept for the
+/// next start.
+pub fn finish(dir: &Path) {
+    if !PANICKED.load(Ordering::SeqCst) {
+        let _ = std::fs::remove_file(dir.join(RECOVERY_FILE));
+    }
+}
+
+/// A free name for setting aside the recovery file found at startup.
+fn crashed_path(dir: &Path) -> PathBuf {
+    let now = std::time::SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    (0..)
+        .map(|n| match n {
+            0 => dir.join(format!("{CRASHED_PREFIX}{now}.topo")),
+            n => dir.join(format!("{CRASHED_PREFIX}{now}-{n}.topo")),
+        })
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+/// The most recently saved crashed session's recovery file, if there is one.
+/// Older ones are offered once it has been restored or discarded.
+pub fn crashed(dir: &Path) -> Option<RecoveryInfo> {
+    std::fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            if !(name.starts_with(CRASHED_PREFIX) && name.ends_with(".topo")) {
+                return None;
+            }
+            let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+            Some(RecoveryInfo {
+                path: path.display().to_string(),
+                saved_at: modified.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0),
+            })
+        })
+        .max_by(|a, b| (a.saved_at, &a.path).cmp(&(b.saved_at, &b.path)))
+}
+
+/// Remove the recovery file `crashed` offers.
+pub fn discard(dir: &Path) -> Result<(), String> {
+    let Some(info) = crashed(dir) else {
+        return Ok(());
+    };
+    match std::fs::remove_file(&info.path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(format!("Failed to remove the recovery file: {e}")),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::SystemTime;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("topograph-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Create `name` in `dir`, last modified `secs` after the epoch.
+    fn touch(dir: &Path, name: &str, secs: u64) -> PathBuf {
+        let path = dir.join(name);
+        let file = std::fs::File::create(&path).unwrap();
+        file.set_modified(SystemTime::UNIX_EPOCH + Duration::from_secs(secs)).unwrap();
+        path
+    }
+
+    #[test]
+    fn newest_crashed_session_is_offered_first() {
+        let dir = temp_dir("autosave-newest");
+        assert!(crashed(&dir).is_none());
+        touch(&dir, "crashed-100.topo", 100);
+        let newest = touch(&dir, "crashed-300.topo", 300);
+        let older = touch(&dir, "crashed-200.topo", 200);
+        touch(&dir, RECOVERY_FILE, 400);
+        touch(&dir, "notes.topo", 500);
+
+        let info = crashed(&dir).unwrap();
+        assert_eq!((info.path.as_str(), info.saved_at), (newest.to_str().unwrap(), 300));
+        discard(&dir).unwrap();
+        assert!(!newest.exists());
+        assert_eq!(crashed(&dir).unwrap().path, older.display().to_string());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn crashed_files_never_overwrite_each_other() {
+        let dir = temp_dir("autosave-names");
+        let first = crashed_path(&dir);
+        std::fs::write(&first, b"").unwrap();
+        let second = crashed_path(&dir);
+        assert_ne!(first, second);
+        let name = second.file_name().unwrap().to_str().unwrap();
+        assert!(name.starts_with(CRASHED_PREFIX) && name.ends_with(".topo"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clean_exit_removes_the_recovery_file() {
+        let dir = temp_dir("autosave-finish");
+        let recovery = touch(&dir, RECOVERY_FILE, 100);
+        finish(&dir);
+        assert!(!recovery.exists());
+        // Nothing to discard is fine too
+        discard(&dir).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,162 @@
+use std::io::BufWriter;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+
+/// Terrain classes, in palette order. Height bands follow `render::hypsometric_tint`
+/// so the classification matches the shaded previews.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+    Water,
+    Beach,
+    Lowland,
+    Highland,
+    Rock,
+    Snow,
+    Cliff,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 7] = [
+        Biome::Water,
+        Biome::Beach,
+        Biome::Lowland,
+        Biome::Highland,
+        Biome::Rock,
+        Biome::Snow,
+        Biome::Cliff,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Biome::Water => "water",
+            Biome::Beach => "beach",
+            Biome::Lowland => "lowland",
+            Biome::Highland => "highland",
+            Biome::Rock => "rock",
+            Biome::Snow => "snow",
+            Biome::Cliff => "cliff",
+        }
+    }
+
+    pub fn color(self) -> [u8; 3] {
+        match self {
+            Biome::Water => [38, 64, 115],
+            Biome::Beach => [194, 179, 128],
+            Biome::Lowland => [77, 133, 56],
+            Biome::Highland => [115, 92, 51],
+            Biome::Rock => [128, 122, 115],
+            Biome::Snow => [235, 237, 245],
+            Biome::Cliff => [90, 80, 72],
+        }
+    }
+}
+
+/// Thresholds for classification. Heights are normalized.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BiomeRules {
+    pub water_level: f32,
+    pub beach_level: f32,
+    pub highland_level: f32,
+    pub rock_level: f32,
+    pub snow_level: f32,
+    /// Slope in degrees above which any land counts as cliff.
+    pub cliff_slope: f32,
+}
+
+impl Default for BiomeRules {
+    fn default() -> Self {
+        Self {
+            water_level: 0.05,
+            beach_level: 0.1,
+            highland_level: 0.35,
+            rock_level: 0.62,
+            snow_level: 0.88,
+            cliff_slope: 40.0,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LegendEntry {
+    pub index: u8,
+    pub name: &'static str,
+    /// `#rrggbb`
+    pub color: String,
+    /// Share of the map in this class, 0-1.
+    pub coverage: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Legend {
+    pub width: u32,
+    pub height: u32,
+    pub meters_per_pixel: f32,
+    pub classes: Vec<LegendEntry>,
+}
+
+/// Palette index per pixel. `slope` holds degrees, as from `DerivedCache::slope`.
+pub fn classify(hm: &Heightmap, slope: &Heightmap, rules: &BiomeRules) -> Vec<u8> {
+    hm.data
+        .iter()
+        .zip(slope.data.iter())
+        .map(|(&h, &s)| {
+            let biome = if h < rules.water_level {
+                Biome::Water
+            } else if s >= rules.cliff_slope {
+                Biome::Cliff
+            } else if h < rules.beach_level {
+                Biome::Beach
+            } else if h < rules.highland_level {
+                Biome::Lowland
+            } else if h < rules.rock_level {
+                Biome::Highland
+            } else if h < rules.snow_level {
+                Biome::Rock
+            } else {
+                Biome::Snow
+            };
+            biome as u8
+        })
+        .collect()
+}
+
+pub fn legend(indices: &[u8], width: u32, height: u32, meters_per_pixel: f32) -> Legend {
+    let mut counts = [0usize; Biome::ALL.len()];
+    for &i in indices {
+        counts[i as usize] += 1;
+    }
+    let total = indices.len().max(1) as f32;
+    let classes = Biome::ALL
+        .iter()
+        .map(|&biome| {
+            let [r, g, b] = biome.color();
+            LegendEntry {
+                index: biome as u8,
+                name: biome.name(),
+                color: format!("#{r:02x}{g:02x}{b:02x}"),
+                coverage: counts[biome as usize] as f32 / total,
+            }
+        })
+        .collect();
+    Legend { width, height, meters_per_pixel, classes }
+}
+
+/// Write the classification as an 8-bit indexed PNG using the biome palette.
+pub fn write_indexed_png(path: &Path, indices: &[u8], width: u32, height: u32) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| format!("Failed to create file: {e}"))?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    let palette: Vec<u8> = Biome::ALL.iter().flat_map(|b| b.color()).collect();
+    encoder.set_palette(palette);
+    let mut writer = encoder
+        .write_header()
+        .map_err(|e| format!("PNG encode error: {e}"))?;
+    writer
+        .write_image_data(indices)
+        .map_err(|e| format!("PNG encode error: {e}"))
+}
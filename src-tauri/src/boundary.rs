@@ -0,0 +1,127 @@
+//! What lies past the heightmap edge. The mode is a per-document setting, so
+//! erosion, brushes, blurs and gradients all treat the border the same way.
+
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum Boundary {
+    /// Repeat the edge pixel.
+    #[default]
+    Clamp,
+    /// Reflect about the edge pixel, without repeating it.
+    Mirror,
+    /// Continue from the opposite edge, for tiling terrain.
+    Wrap,
+    /// Everything past the edge sits at a constant height, e.g. sea level.
+    Fixed { value: f32 },
+}
+
+impl Boundary {
+    pub fn wraps(self) -> bool {
+        self == Boundary::Wrap
+    }
+
+    /// Pixel to read for coordinate `i` on an axis of length `n`. `None` when the
+    /// read falls on the fixed value instead.
+    pub fn resolve(self, i: i64, n: u32) -> Option<u32> {
+        let n = n as i64;
+        if (0..n).contains(&i) {
+            return Some(i as u32);
+        }
+        match self {
+            Boundary::Clamp => Some(i.clamp(0, n - 1) as u32),
+            Boundary::Mirror => {
+                let period = 2 * (n - 1);
+                if period == 0 {
+                    return Some(0);
+                }
+                let m = i.rem_euclid(period);
+                Some(if m < n { m } else { period - m } as u32)
+            }
+            Boundary::Wrap => Some(i.rem_euclid(n) as u32),
+            Boundary::Fixed { .. } => None,
+        }
+    }
+
+    /// Pixel that a write to coordinate `i` lands on. Only wrapping moves writes to
+    /// the other side; in every other mode writes past the edge are dropped.
+    pub fn resolve_write(self, i: i64, n: u32) -> Option<u32> {
+        match self {
+            Boundary::Wrap => Some(i.rem_euclid(n as i64) as u32),
+            _ if (0..n as i64).contains(&i) => Some(i as u32),
+            _ => None,
+        }
+    }
+
+    /// Read `(x, y)` from a `width` x `height` grid through `get`, following this mode.
+    pub fn read(self, x: i64, y: i64, width: u32, height: u32, get: impl Fn(u32, u32) -> f32) -> f32 {
+        match (self.resolve(x, width), self.resolve(y, height)) {
+            (Some(x), Some(y)) => get(x, y),
+            // Only a fixed boundary leaves a coordinate unresolved
+            _ => match self {
+                Boundary::Fixed { value } => value,
+                _ => unreachable!(),
+            },
+        }
+    }
+}
+
+impl Heightmap {
+    /// Height at a pixel that may lie past the edge.
+    pub fn get_bounded(&self, x: i64, y: i64, boundary: Boundary) -> f32 {
+        boundary.read(x, y, self.width, self.height, |x, y| self.get(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inside_coordinates_resolve_to_themselves() {
+        for boundary in [Boundary::Clamp, Boundary::Mirror, Boundary::Wrap, Boundary::Fixed { value: 0.0 }] {
+            assert_eq!(boundary.resolve(3, 5), Some(3));
+            assert_eq!(boundary.resolve_write(3, 5), Some(3));
+        }
+    }
+
+    #[test]
+    fn reads_past_the_edge_follow_the_mode() {
+        let past = |boundary: Boundary| [-2, -1, 5, 6].map(|i| boundary.resolve(i, 5));
+        assert_eq!(past(Boundary::Clamp), [Some(0), Some(0), Some(4), Some(4)]);
+        assert_eq!(past(Boundary::Mirror), [Some(2), Some(1), Some(3), Some(2)]);
+        assert_eq!(past(Boundary::Wrap), [Some(3), Some(4), Some(0), Some(1)]);
+        assert_eq!(past(Boundary::Fixed { value: 0.5 }), [None; 4]);
+        // A single pixel mirrors onto itself
+        assert_eq!(Boundary::Mirror.resolve(-3, 1), Some(0));
+    }
+
+    #[test]
+    fn only_wrapping_moves_writes() {
+        assert_eq!(Boundary::Wrap.resolve_write(-1, 5), Some(4));
+        assert_eq!(Boundary::Wrap.resolve_write(7, 5), Some(2));
+        for boundary in [Boundary::Clamp, Boundary::Mirror, Boundary::Fixed { value: 0.0 }] {
+            assert_eq!(boundary.resolve_write(-1, 5), None);
+            assert_eq!(boundary.resolve_write(5, 5), None);
+        }
+    }
+
+    #[test]
+    fn bounded_reads() {
+        let hm = Heightmap { data: vec![0.0, 0.1, 0.2, 0.3, 0.4, 0.5], width: 3, height: 2 };
+        assert_eq!(hm.get_bounded(1, 1, Boundary::Wrap), 0.4);
+        assert_eq!(hm.get_bounded(-1, 0, Boundary::Wrap), 0.2);
+        assert_eq!(hm.get_bounded(3, 2, Boundary::Clamp), 0.5);
+        assert_eq!(hm.get_bounded(-1, 0, Boundary::Mirror), 0.1);
+        assert_eq!(hm.get_bounded(0, -1, Boundary::Fixed { value: -0.25 }), -0.25);
+    }
+
+    #[test]
+    fn modes_keep_their_serialized_names() {
+        assert_eq!(serde_json::to_value(Boundary::Wrap).unwrap(), serde_json::json!({ "mode": "wrap" }));
+        let fixed: Boundary = serde_json::from_str(r#"{ "mode": "fixed", "value": 0.2 }"#).unwrap();
+        assert_eq!(fixed, Boundary::Fixed { value: 0.2 });
+    }
+}
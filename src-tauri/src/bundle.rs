@@ -0,0 +1,120 @@
+//! Export of the heightmap and the maps derived from it into one folder in a
+//! single pass, named `<folder>_<map>.<ext>` after the folder.
+
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::boundary::Boundary;
+use crate::derived::DerivedCache;
+use crate::heightmap::Heightmap;
+use crate::hydrology::{self, FlowMethod};
+use crate::project;
+use crate::splatmap::{self, SplatRules};
+use crate::world::WorldScale;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MapKind {
+    /// 16-bit PNG.
+    Height,
+    /// Tangent-space normals, OpenGL convention.
+    Normal,
+    /// Ambient occlusion, see `splatmap::occlusion`.
+    Occlusion,
+    /// 16-bit PNG of degrees over 90.
+    Slope,
+    /// 16-bit PNG of log drainage area over the largest on the map.
+    Flow,
+    /// Default splat rules, see `splatmap::export`.
+    Splat,
+    /// The texture as given.
+    Texture,
+}
+
+impl MapKind {
+    pub const ALL: [MapKind; 7] = [
+        MapKind::Height,
+        MapKind::Normal,
+        MapKind::Occlusion,
+        MapKind::Slope,
+        MapKind::Flow,
+        MapKind::Splat,
+        MapKind::Texture,
+    ];
+
+    /// File name suffix.
+    pub fn id(self) -> &'static str {
+        match self {
+            MapKind::Height => "height",
+            MapKind::Normal => "normal",
+            MapKind::Occlusion => "ao",
+            MapKind::Slope => "slope",
+            MapKind::Flow => "flow",
+            MapKind::Splat => "splat",
+            MapKind::Texture => "texture",
+        }
+    }
+}
+
+/// What the maps are made from. `derived` must be refreshed for `hm`.
+pub struct Sources<'a> {
+    pub hm: &'a Heightmap,
+    pub derived: &'a DerivedCache,
+    pub world: &'a WorldScale,
+    pub boundary: Boundary,
+    /// PNG bytes.
+    pub texture: Option<&'a [u8]>,
+}
+
+/// Write each of `maps` once into `dir`, creating it if needed. Returns the
+/// written paths.
+pub fn export(dir: &Path, maps: &[MapKind], sources: &Sources) -> Result<Vec<PathBuf>, String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create export folder: {e}"))?;
+    let stem = dir.file_name().and_then(|n| n.to_str()).unwrap_or("terrain");
+    let base = dir.join(format!("{stem}.png"));
+    let path = |kind: MapKind| project::sidecar_path(&base, &format!("_{}", kind.id()), "png");
+    let (hm, w, h) = (sources.hm, sources.hm.width, sources.hm.height);
+    let save_err = |e: image::ImageError| format!("Failed to save map: {e}");
+
+    let mut written = Vec::new();
+    for kind in MapKind::ALL.into_iter().filter(|k| maps.contains(k)) {
+        match kind {
+            MapKind::Height => project::export_heightmap_png16(&path(kind), hm)?,
+            MapKind::Normal => sources.derived.normal_map().save(path(kind)).map_err(save_err)?,
+            MapKind::Occlusion => {
+                let ao = splatmap::occlusion(hm, sources.world, sources.boundary);
+                let pixels = ao.iter().map(|&v| (v.clamp(0.0, 1.0) * 255.0).round() as u8).collect();
+                let img = image::GrayImage::from_raw(w, h, pixels).ok_or("Failed to create image buffer")?;
+                img.save(path(kind)).map_err(save_err)?;
+            }
+            MapKind::Slope => {
+                let mut slope = sources.derived.slope().clone();
+                for v in &mut slope.data {
+                    *v /= 90.0;
+                }
+                project::export_heightmap_png16(&path(kind), &slope)?;
+            }
+            MapKind::Flow => {
+                let tree = hydrology::drainage(hm, sources.boundary, &|_| {});
+                let area = hydrology::accumulation(&tree, hm, sources.boundary, FlowMethod::DInfinity);
+                let top = area.iter().copied().fold(1.0f32, f32::max).ln().max(f32::EPSILON);
+                let mut flow = Heightmap::new(w, h);
+                for (v, a) in flow.data.iter_mut().zip(&area) {
+                    *v = a.ln() / top;
+                }
+                project::export_heightmap_png16(&path(kind), &flow)?;
+            }
+            MapKind::Splat => {
+                let rules = SplatRules::default();
+                let weights = splatmap::weights(hm, sources.derived.slope(), sources.world, sources.boundary, &rules);
+                written.extend(splatmap::export(&base, w, h, &rules, &weights)?.written);
+                continue;
+            }
+            MapKind::Texture => {
+                let texture = sources.texture.ok_or("There is no texture to export")?;
+                std::fs::write(path(kind), texture).map_err(|e| format!("Failed to write texture: {e}"))?;
+            }
+        }
+        written.push(path(kind));
+    }
+    Ok(written)
+}
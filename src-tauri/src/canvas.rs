@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::noise_gen::{self, Frame, NoiseParams};
+
+/// Largest canvas edge an expansion may produce.
+pub const MAX_CANVAS_SIZE: u32 = 16384;
+
+/// Pixels to add on each side of the canvas.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Expansion {
+    #[serde(default)]
+    pub left: u32,
+    #[serde(default)]
+    pub top: u32,
+    #[serde(default)]
+    pub right: u32,
+    #[serde(default)]
+    pub bottom: u32,
+}
+
+/// Grow the canvas, filling the new area by continuing the generator in `frame`, so
+/// the same seed and parameters extend the original terrain. Edits near the old
+/// border are carried into the new area over `blend_width` pixels to hide the seam.
+/// Returns the new heightmap and the frame to use for further expansion.
+pub fn expand(
+    hm: &Heightmap,
+    frame: Frame,
+    expansion: &Expansion,
+    params: &NoiseParams,
+    blend_width: u32,
+) -> (Heightmap, Frame) {
+    let (left, top) = (expansion.left, expansion.top);
+    let width = hm.width + left + expansion.right;
+    let height = hm.height + top + expansion.bottom;
+    let frame = Frame {
+        origin_x: frame.origin_x - left as f64,
+        origin_y: frame.origin_y - top as f64,
+        ..frame
+    };
+
+    // Raw continuation over the whole new canvas, old area included, so the
+    // difference between edited and generated terrain is known along the border
+    let mut expanded = Heightmap::new(width, height);
+    noise_gen::generate_in_frame(&mut expanded, params, frame);
+
+    let blend = blend_width.max(1) as f32;
+    for y in 0..height {
+        for x in 0..width {
+            // Nearest pixel of the old map, in old-map coordinates
+            let ox = x.saturating_sub(left).min(hm.width - 1);
+            let oy = y.saturating_sub(top).min(hm.height - 1);
+            let (cx, cy) = (ox + left, oy + top);
+            let old = hm.get(ox, oy);
+
+            if cx == x && cy == y {
+                expanded.set(x, y, old);
+                continue;
+            }
+
+            let dx = x as f32 - cx as f32;
+            let dy = y as f32 - cy as f32;
+            let t = ((dx * dx + dy * dy).sqrt() / blend).min(1.0);
+            let fade = 1.0 - t * t * (3.0 - 2.0 * t);
+            let offset = old - expanded.get(cx, cy);
+            let raw = expanded.get(x, y);
+            expanded.set(x, y, (raw + offset * fade).clamp(0.0, 1.0));
+        }
+    }
+
+    (expanded, frame)
+}
+
+/// Zero-filled copy of `src` placed at (`left`, `top`) in a `width` x `height` grid.
+pub fn pad(src: &Heightmap, left: u32, top: u32, width: u32, height: u32) -> Heightmap {
+    let mut out = Heightmap::new(width, height);
+    for y in 0..src.height {
+        let from = (y * src.width) as usize;
+        let to = ((y + top) * width + left) as usize;
+        out.data[to..to + src.width as usize].copy_from_slice(&src.data[from..from + src.width as usize]);
+    }
+    out
+}
+
+/// Copy of the `w` x `h` rectangle at (`x`, `y`). The rectangle must lie inside `src`.
+pub fn crop(src: &Heightmap, x: u32, y: u32, w: u32, h: u32) -> Heightmap {
+    let mut out = Heightmap::new(w, h);
+    for row in 0..h {
+        let from = ((y + row) * src.width + x) as usize;
+        let to = (row * w) as usize;
+        out.data[to..to + w as usize].copy_from_slice(&src.data[from..from + w as usize]);
+    }
+    out
+}
@@ -0,0 +1,220 @@
+use image::{Rgb, RgbImage};
+use serde::Deserialize;
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+/// Cartographic furniture burned into exported map images.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MapFurniture {
+    #[serde(default)]
+    pub scale_bar: bool,
+    #[serde(default)]
+    pub north_arrow: bool,
+    /// Contour spacing in meters. Labelled contours are drawn when set.
+    #[serde(default)]
+    pub contour_interval: Option<f32>,
+}
+
+const BLACK: Rgb<u8> = Rgb([0, 0, 0]);
+const WHITE: Rgb<u8> = Rgb([255, 255, 255]);
+const MARGIN: i32 = 16;
+const TEXT_SCALE: i32 = 2;
+
+/// Draw the requested furniture on top of a rendered map image.
+/// The image is assumed to cover the whole heightmap.
+pub fn burn_in(img: &mut RgbImage, hm: &Heightmap, world: &WorldScale, furniture: &MapFurniture) {
+    if let Some(interval) = furniture.contour_interval.filter(|i| *i > 0.0) {
+        draw_contours(img, hm, world, interval);
+    }
+    if furniture.scale_bar {
+        draw_scale_bar(img, hm, world);
+    }
+    if furniture.north_arrow {
+        draw_north_arrow(img);
+    }
+}
+
+fn draw_contours(img: &mut RgbImage, hm: &Heightmap, world: &WorldScale, interval: f32) {
+    let (w, h) = (img.width() as usize, img.height() as usize);
+    let sx = hm.width as f32 / w as f32;
+    let sy = hm.height as f32 / h as f32;
+
+    // Contour band index per output pixel
+    let mut bands = vec![0i32; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let hv = hm.sample((x as f32 + 0.5) * sx - 0.5, (y as f32 + 0.5) * sy - 0.5);
+            bands[y * w + x] = (world.elevation(hv) / interval).floor() as i32;
+        }
+    }
+
+    // A pixel lies on a contour when the band changes towards its right or lower neighbor
+    let mut on_line = vec![None; w * h];
+    for y in 0..h {
+        for x in 0..w {
+            let b = bands[y * w + x];
+            let right = if x + 1 < w { bands[y * w + x + 1] } else { b };
+            let down = if y + 1 < h { bands[(y + 1) * w + x] } else { b };
+            if right != b || down != b {
+                on_line[y * w + x] = Some(b.max(right).max(down));
+                let p = img.get_pixel_mut(x as u32, y as u32);
+                p.0 = p.0.map(|c| (c as f32 * 0.45) as u8);
+            }
+        }
+    }
+
+    // One label per grid cell keeps the map readable at any contour density
+    let cell = 128;
+    for cy in (0..h).step_by(cell) {
+        for cx in (0..w).step_by(cell) {
+            let center = (cx + cell / 2, cy + cell / 2);
+            let nearest = (cy..(cy + cell).min(h))
+                .flat_map(|y| (cx..(cx + cell).min(w)).map(move |x| (x, y)))
+                .filter_map(|(x, y)| on_line[y * w + x].map(|b| (x, y, b)))
+                .min_by_key(|&(x, y, _)| x.abs_diff(center.0).pow(2) + y.abs_diff(center.1).pow(2));
+
+            if let Some((x, y, band)) = nearest {
+                let label = format_number(band as f32 * interval);
+                let tw = text_width(&label);
+                let th = 7 * TEXT_SCALE;
+                let tx = x as i32 - tw / 2;
+                let ty = y as i32 - th / 2;
+                fill_rect(img, tx - 2, ty - 2, tw + 4, th + 4, WHITE);
+                draw_text(img, tx, ty, &label, BLACK);
+            }
+        }
+    }
+}
+
+fn draw_scale_bar(img: &mut RgbImage, hm: &Heightmap, world: &WorldScale) {
+    let meters_per_px = world.meters_per_pixel * hm.width as f32 / img.width() as f32;
+    if meters_per_px <= 0.0 {
+        return;
+    }
+    let length_m = nice_length(meters_per_px * img.width() as f32 / 4.0);
+    let bar_px = (length_m / meters_per_px).round() as i32;
+    let segments = 4;
+    let seg_px = bar_px / segments;
+    let bar_h = 6;
+
+    let x0 = MARGIN;
+    let y0 = img.height() as i32 - MARGIN - bar_h;
+    let label = format_distance(length_m);
+    let th = 7 * TEXT_SCALE;
+
+    // Backing panel for legibility on any terrain color
+    fill_rect(img, x0 - 6, y0 - th - 10, bar_px.max(text_width(&label)) + 12, th + bar_h + 16, WHITE);
+    fill_rect(img, x0 - 1, y0 - 1, seg_px * segments + 2, bar_h + 2, BLACK);
+    for i in 0..segments {
+        let color = if i % 2 == 0 { BLACK } else { WHITE };
+        fill_rect(img, x0 + i * seg_px, y0, seg_px, bar_h, color);
+    }
+    draw_text(img, x0, y0 - th - 4, &label, BLACK);
+}
+
+fn draw_north_arrow(img: &mut RgbImage) {
+    let size = 28;
+    let cx = img.width() as i32 - MARGIN - size / 2;
+    let top = MARGIN + 7 * TEXT_SCALE + 6;
+    let bottom = top + size;
+    let half = size / 3;
+
+    // Split arrowhead: filled left half, outlined right half
+    for y in top..=bottom {
+        let t = (y - top) as f32 / size as f32;
+        let reach = (t * half as f32).round() as i32;
+        for x in (cx - reach)..=(cx + reach) {
+            let edge = x == cx - reach || x == cx + reach || y == bottom;
+            let color = if x <= cx || edge { BLACK } else { WHITE };
+            put(img, x, y, color);
+        }
+    }
+    let nw = text_width("N");
+    draw_text(img, cx - nw / 2, MARGIN, "N", BLACK);
+}
+
+/// Round a length down to 1, 2 or 5 times a power of ten.
+fn nice_length(target: f32) -> f32 {
+    let magnitude = 10f32.powf(target.log10().floor());
+    let norm = target / magnitude;
+    let step = if norm >= 5.0 {
+        5.0
+    } else if norm >= 2.0 {
+        2.0
+    } else {
+        1.0
+    };
+    step * magnitude
+}
+
+fn format_distance(meters: f32) -> String {
+    if meters >= 1000.0 {
+        format!("{} km", format_number(meters / 1000.0))
+    } else {
+        format!("{} m", format_number(meters))
+    }
+}
+
+fn format_number(v: f32) -> String {
+    if (v - v.round()).abs() < 1e-3 {
+        format!("{}", v.round() as i64)
+    } else {
+        format!("{v:.1}")
+    }
+}
+
+fn put(img: &mut RgbImage, x: i32, y: i32, color: Rgb<u8>) {
+    if x >= 0 && y >= 0 && (x as u32) < img.width() && (y as u32) < img.height() {
+        img.put_pixel(x as u32, y as u32, color);
+    }
+}
+
+fn fill_rect(img: &mut RgbImage, x: i32, y: i32, w: i32, h: i32, color: Rgb<u8>) {
+    for py in y..y + h {
+        for px in x..x + w {
+            put(img, px, py, color);
+        }
+    }
+}
+
+fn text_width(text: &str) -> i32 {
+    (text.chars().count() as i32 * 6 - 1) * TEXT_SCALE
+}
+
+fn draw_text(img: &mut RgbImage, x: i32, y: i32, text: &str, color: Rgb<u8>) {
+    for (i, ch) in text.chars().enumerate() {
+        let gx = x + i as i32 * 6 * TEXT_SCALE;
+        for (row, bits) in glyph(ch).iter().enumerate() {
+            for col in 0..5 {
+                if bits & (0b10000 >> col) != 0 {
+                    let px = gx + col * TEXT_SCALE;
+                    let py = y + row as i32 * TEXT_SCALE;
+                    fill_rect(img, px, py, TEXT_SCALE, TEXT_SCALE, color);
+                }
+            }
+        }
+    }
+}
+
+/// 5x7 bitmap glyphs for the characters used in map labels.
+fn glyph(ch: char) -> [u8; 7] {
+    match ch {
+        '0' => [0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110],
+        '1' => [0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110],
+        '2' => [0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111],
+        '3' => [0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110],
+        '4' => [0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010],
+        '5' => [0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110],
+        '6' => [0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110],
+        '7' => [0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000],
+        '8' => [0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110],
+        '9' => [0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100],
+        'k' => [0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010],
+        'm' => [0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001],
+        'N' => [0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001, 0b10001],
+        '.' => [0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100],
+        '-' => [0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000],
+        _ => [0; 7],
+    }
+}
@@ -0,0 +1,104 @@
+//! Images of the terrain placed on the OS clipboard for pasting into chats and
+//! documents. The webview can't write images to the clipboard on every
+//! platform, so the PNG goes through the system's own clipboard tool.
+
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use image::RgbImage;
+use serde::Deserialize;
+use crate::derived::DerivedCache;
+use crate::render::{self, RenderStyle, Surface};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ClipboardMap {
+    /// Grayscale heights, 8-bit.
+    Heightmap,
+    Hillshade,
+    /// Tangent-space normals, as `get_normal_map`.
+    Normal,
+}
+
+/// Longest side of a copied image by default; pasted images are for looking
+/// at, and chat apps downscale larger ones anyway.
+pub const DEFAULT_SIZE: u32 = 2048;
+
+/// `map` rendered with its longer side at most `max_size`. `derived` must be
+/// refreshed for the surface's base map, whose normals leave out detail patches.
+pub fn render(surface: &Surface, derived: &DerivedCache, map: ClipboardMap, max_size: u32) -> RgbImage {
+    let hm = surface.base;
+    let scale = (max_size as f32 / hm.width.max(hm.height) as f32).min(1.0);
+    let w = ((hm.width as f32 * scale).round() as u32).max(1);
+    let h = ((hm.height as f32 * scale).round() as u32).max(1);
+    match map {
+        ClipboardMap::Heightmap => render::render_preview(surface, w, h, RenderStyle::Grayscale),
+        ClipboardMap::Hillshade => render::render_preview(surface, w, h, RenderStyle::Hillshade),
+        ClipboardMap::Normal => {
+            let normals = derived.normal_map();
+            if w == hm.width && h == hm.height {
+                normals
+            } else {
+                image::imageops::resize(&normals, w, h, image::imageops::FilterType::Triangle)
+            }
+        }
+    }
+}
+
+/// Put PNG bytes on the clipboard: through AppleScript on macOS, .NET's
+/// clipboard on Windows, and `wl-copy` or `xclip` on Linux, which must be
+/// installed.
+pub fn copy_png(png: &[u8]) -> Result<(), String> {
+    let path = std::env::temp_dir().join("topograph-clipboard.png");
+    std::fs::write(&path, png).map_err(|e| format!("Failed to write clipboard image: {e}"))?;
+    let mut command = clipboard_command(&path);
+    let program = command.get_program().to_string_lossy().into_owned();
+    let status = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()
+        .and_then(|mut child| {
+            // wl-copy reads the image from stdin; the others take the file
+            if let Some(mut stdin) = child.stdin.take() {
+                if program == "wl-copy" {
+                    stdin.write_all(png)?;
+                }
+            }
+            child.wait()
+        })
+        .map_err(|e| format!("Failed to run {program}: {e}"))?;
+    if !status.success() {
+        return Err(format!("{program} could not copy the image to the clipboard"));
+    }
+    Ok(())
+}
+
+fn clipboard_command(path: &Path) -> Command {
+    let path = path.to_string_lossy();
+    if cfg!(target_os = "macos") {
+        let mut command = Command::new("osascript");
+        command.args(["-e", &format!("set the clipboard to (read (POSIX file \"{}\") as «class PNGf»)", path.replace('"', "\\\""))]);
+        command
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("powershell");
+        command.args([
+            "-NoProfile",
+            "-STA",
+            "-Command",
+            &format!(
+                "Add-Type -AssemblyName System.Windows.Forms, System.Drawing; \
+                 [System.Windows.Forms.Clipboard]::SetImage([System.Drawing.Image]::FromFile('{}'))",
+                path.replace('\'', "''")
+            ),
+        ]);
+        command
+    } else if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-copy");
+        command.args(["--type", "image/png"]);
+        command
+    } else {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-t", "image/png", "-i", &path]);
+        command
+    }
+}
@@ -1,16 +1,76 @@
-use std::sync::atomic::Ordering;
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tauri::ipc::Response;
-use tauri::{AppHandle, State};
+use std::time::{Duration, Instant};
+use tauri::ipc::{Channel, InvokeResponseBody, Response};
+use tauri::{AppHandle, Manager, State};
 use crate::ai;
-use crate::erosion::{hydraulic, thermal};
-use crate::erosion::hydraulic::HydraulicParams;
+use crate::autosave::{self, RecoveryInfo};
+use crate::biome::{self, BiomeRules};
+use crate::boundary::Boundary;
+use crate::bundle::{self, MapKind};
+use crate::canvas::{self, Expansion};
+use crate::cartography::{self, MapFurniture};
+use crate::clipboard::{self, ClipboardMap};
+use crate::contours::{self, ContourFormat, ContourParams};
+use crate::craters::{self, CraterFieldParams, CraterParams};
+use crate::derived::{GradientMap, MapUnit};
+use crate::detail::{DetailPatch, DetailPatchInfo};
+use crate::elevation_fetch::{self, FetchParams};
+use crate::erosion::{self, coastal, glacial, hydraulic, pipe, stream_power, thermal, Backend, ErosionRun, Masks, Pause};
+use crate::error::TopographError;
+use crate::export_profile::{ExportProfile, ExportTarget};
+use crate::expr::{self, Expression};
+use crate::float_image::{self, Normalization};
+use crate::geotiff::{self, Dem};
+use crate::godot::{self, GodotImport};
+#[cfg(feature = "golden")]
+use crate::golden::{self, CaseResult};
+use crate::erosion::coastal::CoastalParams;
+use crate::erosion::glacial::GlacialParams;
+use crate::erosion::hydraulic::{DropletTrace, ErosionMapKind, ErosionMaps, HydraulicParams};
+use crate::erosion::pipe::PipeParams;
+use crate::erosion::strata::Strata;
+use crate::erosion::stream_power::StreamPowerParams;
 use crate::erosion::thermal::ThermalParams;
+use crate::heightmap::Heightmap;
+use crate::hooks::{self, ExportHook, ExportHookInfo};
+use crate::hydrology;
+use crate::i18n::{self, Locale, LocaleInfo, Text};
 use crate::ipc;
-use crate::noise_gen::{self, NoiseParams};
-use crate::project;
-use crate::sculpt::{self, BrushStroke};
-use crate::state::AppState;
+use crate::mask::{self, MaskChannel, MaskStroke};
+use crate::mosaic::{self, MosaicParams};
+use crate::mountains::{self, MountainRangeParams};
+use crate::noise_gen::{self, BlendMode, Frame, NoiseParams};
+use crate::presets::{self, Preset, PresetKind, PresetStore};
+use crate::progress::{Progress, ProgressTracker, RunState, Stage};
+use crate::project::{self, ProjectContents, ProjectMetadata, RawOptions};
+use crate::provenance::Operation;
+use crate::recent;
+use crate::render::{self, Camera, RenderStyle, Surface};
+use crate::resample::{self, ExportResolution, ResampleFilter};
+use crate::rivers::{self, RiverParams};
+use crate::safety::{CheckpointInfo, DestructiveOp, Snapshot};
+use crate::sculpt::{self, BrushStroke, PlatformParams, RampParams};
+use crate::smoothing::{self, CurvatureFlowParams};
+use crate::snow::{self, SnowParams};
+use crate::splatmap::{self, SplatExport, SplatRules};
+use crate::srtm;
+use crate::stl::{self, StlParams};
+use crate::stack::{self, NoiseLayer};
+use crate::stamp::{self, StampParams, StampTarget};
+use crate::state::{AppState, FlagSignal};
+use crate::stroke_queue;
+use crate::sync_export::{self, SyncExportReport};
+use crate::templates::{self, TemplateStore};
+use crate::texture::{Texture, TextureInfo};
+use crate::tile_export::{self, TileExportParams};
+use crate::tiles::{self, TileGrid};
+use crate::unity::{self, UnityRawOptions};
+use crate::unreal::{self, UnrealImport, UnrealOptions};
+use crate::usage::UsageStats;
+use crate::volcano::{self, VolcanoParams};
+use crate::world::WorldScale;
 
 #[tauri::command]
 pub fn get_heightmap(state: State<'_, AppState>) -> Response {
@@ -20,304 +80,2760 @@ pub fn get_heightmap(state: State<'_, AppState>) -> Response {
 
 #[tauri::command]
 pub fn apply_brush_stroke(stroke: BrushStroke, state: State<'_, AppState>) -> Response {
+    if stroke.begins {
+        state.usage.lock().unwrap().record_stroke();
+    }
+    let boundary = *state.boundary.lock().unwrap();
     let mut hm = state.heightmap.lock().unwrap();
-    let (rx, ry, rw, rh) = sculpt::apply_brush(&mut hm, &stroke);
+    let (rx, ry, rw, rh) = sculpt::apply_brush(&mut hm, &stroke, boundary);
+    state.edits.region((rx, ry, rw, rh));
     if rw == 0 || rh == 0 {
         return Response::new(ipc::pack_full(&hm));
     }
     Response::new(ipc::pack_region(&hm, rx, ry, rw, rh))
 }
 
+/// Open a stroke stream: strokes sent with `queue_brush_stroke` are applied on a worker
+/// thread and changed regions arrive on `on_update`. Replaces any previous stream.
 #[tauri::command]
-pub fn generate_terrain(params: NoiseParams, state: State<'_, AppState>) -> Response {
-    let mut hm = state.heightmap.lock().unwrap();
-    noise_gen::generate_terrain(&mut hm, &params);
-    Response::new(ipc::pack_full(&hm))
+pub fn start_stroke_stream(on_update: Channel<InvokeResponseBody>, state: State<'_, AppState>) {
+    let sender = stroke_queue::spawn(
+        Arc::clone(&state.heightmap),
+        Arc::clone(&state.boundary),
+        Arc::clone(&state.edits),
+        on_update,
+    );
+    *state.stroke_queue.lock().unwrap() = Some(sender);
+}
+
+/// Runs on the thread pool so a full queue can block the caller without stalling the UI.
+#[tauri::command(async)]
+pub fn queue_brush_stroke(stroke: BrushStroke, state: State<'_, AppState>) -> Result<(), TopographError> {
+    if stroke.begins {
+        state.usage.lock().unwrap().record_stroke();
+    }
+    // Clone the sender so a blocked send doesn't hold the state lock
+    let sender = state
+        .stroke_queue
+        .lock()
+        .unwrap()
+        .clone()
+        .ok_or_else(|| TopographError::not_found("No stroke stream is open"))?;
+    sender.send(stroke).map_err(|_| TopographError::not_found("Stroke stream closed"))
+}
+
+#[tauri::command]
+pub fn end_stroke_stream(state: State<'_, AppState>) {
+    // The worker finishes any queued strokes, then exits
+    state.stroke_queue.lock().unwrap().take();
 }
 
 #[tauri::command]
-pub fn run_thermal_erosion(params: ThermalParams, state: State<'_, AppState>) -> Response {
+pub fn apply_ramp(ramp: RampParams, state: State<'_, AppState>) -> Response {
+    let world_scale = state.world_scale.lock().unwrap().clone();
     let mut hm = state.heightmap.lock().unwrap();
-    thermal::erode(&mut hm, &params);
-    Response::new(ipc::pack_full(&hm))
+    let (rx, ry, rw, rh) = sculpt::apply_ramp(&mut hm, &ramp, &world_scale);
+    state.operations.lock().unwrap().record("apply_ramp", serde_json::json!({ "ramp": ramp }), &hm, false);
+    state.edits.region((rx, ry, rw, rh));
+    if rw == 0 || rh == 0 {
+        return Response::new(ipc::pack_full(&hm));
+    }
+    Response::new(ipc::pack_region(&hm, rx, ry, rw, rh))
 }
 
 #[tauri::command]
-pub fn run_hydraulic_erosion(
-    params: HydraulicParams,
-    state: State<'_, AppState>,
-    channel: tauri::ipc::Channel<f32>,
-) -> Result<(), String> {
-    if state
-        .erosion_running
-        .swap(true, Ordering::SeqCst)
-    {
-        return Err("Erosion already running".to_string());
+pub fn apply_platform(platform: PlatformParams, state: State<'_, AppState>) -> Response {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let mut hm = state.heightmap.lock().unwrap();
+    let (rx, ry, rw, rh) = sculpt::apply_platform(&mut hm, &platform, &world_scale);
+    state.operations.lock().unwrap().record("apply_platform", serde_json::json!({ "platform": platform }), &hm, false);
+    state.edits.region((rx, ry, rw, rh));
+    if rw == 0 || rh == 0 {
+        return Response::new(ipc::pack_full(&hm));
     }
-    state.erosion_abort.store(false, Ordering::SeqCst);
+    Response::new(ipc::pack_region(&hm, rx, ry, rw, rh))
+}
 
-    let hm = Arc::clone(&state.heightmap);
-    let abort = Arc::clone(&state.erosion_abort);
-    let running = Arc::clone(&state.erosion_running);
+#[tauri::command]
+pub fn stamp_crater(crater: CraterParams, state: State<'_, AppState>) -> Response {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let mut hm = state.heightmap.lock().unwrap();
+    let (rx, ry, rw, rh) = craters::stamp(&mut hm, &crater, &world_scale);
+    state.edits.region((rx, ry, rw, rh));
+    state.operations.lock().unwrap().record("stamp_crater", serde_json::json!({ "crater": crater }), &hm, false);
+    if rw == 0 || rh == 0 {
+        return Response::new(ipc::pack_full(&hm));
+    }
+    Response::new(ipc::pack_region(&hm, rx, ry, rw, rh))
+}
 
-    std::thread::spawn(move || {
-        {
-            let mut hm_guard = hm.lock().unwrap();
-            hydraulic::erode(&mut hm_guard, &params, &abort, &|progress| {
-                let _ = channel.send(progress);
-            });
-        }
-        running.store(false, Ordering::SeqCst);
-    });
+#[tauri::command]
+pub fn scatter_craters(field: CraterFieldParams, state: State<'_, AppState>) -> Response {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let mut hm = state.heightmap.lock().unwrap();
+    craters::scatter(&mut hm, &field, &world_scale);
+    state.operations.lock().unwrap().record("scatter_craters", serde_json::json!({ "field": field }), &hm, false);
+    state.edits.whole();
+    Response::new(ipc::pack_full(&hm))
+}
 
-    Ok(())
+#[tauri::command]
+pub fn stamp_volcano(volcano: VolcanoParams, state: State<'_, AppState>) -> Response {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let mut hm = state.heightmap.lock().unwrap();
+    let (rx, ry, rw, rh) = volcano::stamp(&mut hm, &volcano, &world_scale);
+    state.edits.region((rx, ry, rw, rh));
+    state.operations.lock().unwrap().record("stamp_volcano", serde_json::json!({ "volcano": volcano }), &hm, false);
+    if rw == 0 || rh == 0 {
+        return Response::new(ipc::pack_full(&hm));
+    }
+    Response::new(ipc::pack_region(&hm, rx, ry, rw, rh))
 }
 
 #[tauri::command]
-pub fn abort_erosion(state: State<'_, AppState>) {
-    state.erosion_abort.store(true, Ordering::SeqCst);
+pub fn apply_mountain_range(range: MountainRangeParams, state: State<'_, AppState>) -> Response {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let mut hm = state.heightmap.lock().unwrap();
+    let (rx, ry, rw, rh) = mountains::apply(&mut hm, &range, &world_scale);
+    state.edits.region((rx, ry, rw, rh));
+    state.operations.lock().unwrap().record("apply_mountain_range", serde_json::json!({ "range": range }), &hm, false);
+    if rw == 0 || rh == 0 {
+        return Response::new(ipc::pack_full(&hm));
+    }
+    Response::new(ipc::pack_region(&hm, rx, ry, rw, rh))
 }
 
 #[tauri::command]
-pub fn run_depth_estimation(
-    image_data: Vec<u8>,
+pub fn generate_terrain(
+    params: NoiseParams,
     mask_data: Option<Vec<u8>>,
-    app_handle: AppHandle,
+    confirmation: Option<String>,
     state: State<'_, AppState>,
-) -> Result<Response, String> {
-    let hm_lock = state.heightmap.lock().unwrap();
-    let width = hm_lock.width;
-    let height = hm_lock.height;
-    drop(hm_lock);
+) -> Result<Response, TopographError> {
+    if mask_data.is_none() && !params.use_selection {
+        guard_destructive(DestructiveOp::Generate, confirmation.as_deref(), &state)?;
+    }
+    let mut hm = state.heightmap.lock().unwrap();
+    let (width, height) = (hm.width, hm.height);
 
-    let depth_values = ai::run_depth_estimation(&app_handle, &image_data, width, height)?;
+    // An uploaded mask takes precedence over the backend selection
+    let mask = match mask_data {
+        Some(png) => Some(mask::decode_png(&png, width, height).map_err(TopographError::format)?),
+        None if params.use_selection => state
+            .masks
+            .lock()
+            .unwrap()
+            .get(MaskChannel::Selection)
+            .filter(|m| m.width == width && m.height == height)
+            .map(|m| m.data.clone()),
+        None => None,
+    };
 
-    let mut hm = state.heightmap.lock().unwrap();
-    if depth_values.len() != hm.data.len() {
-        return Err(format!(
-            "Depth data length mismatch: {} vs {}",
-            depth_values.len(),
-            hm.data.len()
-        ));
+    match mask {
+        Some(mask) => {
+            let weights = if params.mask_feather > 0 {
+                ai::feather_mask(&mask, width, height, params.mask_feather)
+            } else {
+                mask
+            };
+            noise_gen::generate_terrain_masked(&mut hm, &params, &weights);
+        }
+        None => noise_gen::generate_terrain(&mut hm, &params),
     }
+    state.operations.lock().unwrap().record("generate_terrain", serde_json::json!({ "params": params }), &hm, false);
+    // Freshly generated terrain is its own frame again
+    *state.canvas_frame.lock().unwrap() = None;
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
 
-    match mask_data {
-        Some(mask_png) => {
-            // Decode the mask PNG to get per-pixel weights
-            let mask = ai::decode_mask_png(&mask_png, width, height)?;
-
-            // Find the height range of the original terrain in the masked region
-            // so we can scale the depth values to match
-            let mut masked_min = f32::MAX;
-            let mut masked_max = f32::MIN;
-            for i in 0..hm.data.len() {
-                if mask[i] > 0.1 {
-                    masked_min = masked_min.min(hm.data[i]);
-                    masked_max = masked_max.max(hm.data[i]);
-                }
-            }
-            // Also sample a border ring around the mask to get surrounding height context
-            if masked_min > masked_max {
-                masked_min = 0.0;
-                masked_max = 1.0;
-            }
-            // Add some headroom so AI can create features above/below existing terrain
-            let range = (masked_max - masked_min).max(0.05);
-            let target_min = (masked_min - range * 0.3).max(0.0);
-            let target_max = (masked_max + range * 0.3).min(1.0);
+/// Replace the terrain with a per-pixel formula; see `expr::Expression` for the
+/// syntax. `height()` reads the terrain as it was before this call.
+#[tauri::command(async)]
+pub fn generate_from_expression(
+    expression: String,
+    seed: u32,
+    confirmation: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    let expr = Expression::parse(&expression).map_err(TopographError::invalid)?;
+    guard_destructive(DestructiveOp::Generate, confirmation.as_deref(), &state)?;
+    let mut hm = state.heightmap.lock().unwrap();
+    let terrain = hm.clone();
+    let masks = state.masks.lock().unwrap();
+    let selection = masks
+        .get(MaskChannel::Selection)
+        .filter(|m| m.width == hm.width && m.height == hm.height);
+    let inputs = expr::Inputs::new(seed, &terrain, selection);
+    expr::generate(&mut hm, &expr, &inputs);
+    drop(masks);
+    let args = serde_json::json!({ "expression": expression, "seed": seed });
+    state.operations.lock().unwrap().record("generate_from_expression", args, &hm, false);
 
-            // Find depth range in masked area
-            let mut depth_min = f32::MAX;
-            let mut depth_max = f32::MIN;
-            for i in 0..depth_values.len() {
-                if mask[i] > 0.1 {
-                    depth_min = depth_min.min(depth_values[i]);
-                    depth_max = depth_max.max(depth_values[i]);
-                }
-            }
-            let depth_range = (depth_max - depth_min).max(1e-6);
+    *state.canvas_frame.lock().unwrap() = None;
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
 
-            // Blend: remap depth to target range, mix with original using mask weight
-            // Apply Gaussian feathering at mask edges
-            let feathered_mask = ai::feather_mask(&mask, width, height, 8);
-            for i in 0..hm.data.len() {
-                let w = feathered_mask[i];
-                if w > 0.001 {
-                    // Remap depth to match surrounding terrain height range
-                    let normalized = (depth_values[i] - depth_min) / depth_range;
-                    let remapped = target_min + normalized * (target_max - target_min);
-                    hm.data[i] = hm.data[i] * (1.0 - w) + remapped * w;
-                }
-            }
+/// Generate at low resolution into a scratch buffer for live slider feedback; the
+/// document is left untouched. Non-replace blend modes combine with a downsampled
+/// copy of the current terrain. The selection mask is not applied.
+#[tauri::command]
+pub fn preview_terrain(params: NoiseParams, preview_size: u32, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    if !(16..=512).contains(&preview_size) {
+        return Err(TopographError::invalid("Preview size must be between 16 and 512"));
+    }
+    let mut preview = {
+        let hm = state.heightmap.lock().unwrap();
+        if params.blend != BlendMode::Replace {
+            downsampled(&hm, preview_size)
+        } else {
+            let (pw, ph) = preview_dims(&hm, preview_size);
+            Heightmap::new(pw, ph)
         }
-        None => {
-            // No mask — replace entire heightmap (legacy behavior)
-            hm.data.copy_from_slice(&depth_values);
+    };
+    noise_gen::generate_terrain(&mut preview, &params);
+    Ok(Response::new(ipc::pack_full(&preview)))
+}
+
+/// Preview dimensions with the longest edge at `size`, keeping the document's aspect.
+fn preview_dims(hm: &Heightmap, size: u32) -> (u32, u32) {
+    let scale = size as f32 / hm.width.max(hm.height) as f32;
+    let pw = ((hm.width as f32 * scale).round() as u32).max(2);
+    let ph = ((hm.height as f32 * scale).round() as u32).max(2);
+    (pw, ph)
+}
+
+/// `hm` resampled to `preview_dims(hm, size)`.
+fn downsampled(hm: &Heightmap, size: u32) -> Heightmap {
+    let (pw, ph) = preview_dims(hm, size);
+    let mut preview = Heightmap::new(pw, ph);
+    let sx = (hm.width - 1) as f32 / (pw - 1).max(1) as f32;
+    let sy = (hm.height - 1) as f32 / (ph - 1).max(1) as f32;
+    for y in 0..ph {
+        for x in 0..pw {
+            preview.set(x, y, hm.sample(x as f32 * sx, y as f32 * sy));
         }
     }
+    preview
+}
 
-    Ok(Response::new(ipc::pack_full(&hm)))
+/// Low-res previews of `params` with each of `seeds`, for a seed picker. The longest
+/// edge is `size` pixels and the aspect ratio follows the document. Returns the raw
+/// layers packed with `ipc::pack_full_set`, in seed order.
+#[tauri::command(async)]
+pub fn preview_seeds(
+    params: NoiseParams,
+    seeds: Vec<u32>,
+    size: u32,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    if seeds.is_empty() || seeds.len() > 64 {
+        return Err(TopographError::invalid("Request between 1 and 64 seeds"));
+    }
+    if !(16..=512).contains(&size) {
+        return Err(TopographError::invalid("Preview size must be between 16 and 512"));
+    }
+    let (pw, ph) = {
+        let hm = state.heightmap.lock().unwrap();
+        preview_dims(&hm, size)
+    };
+
+    let previews = noise_gen::seed_previews(&params, &seeds, pw, ph);
+    let refs: Vec<&Heightmap> = previews.iter().collect();
+    Ok(Response::new(ipc::pack_full_set(&refs)))
 }
 
+/// Evaluate a layered generation recipe in one pass.
 #[tauri::command]
-pub fn run_inpainting(
-    image_data: Vec<u8>,
-    mask_data: Vec<u8>,
-    prompt: String,
-    mode: String,
-    app_handle: AppHandle,
-) -> Result<Vec<u8>, String> {
-    ai::run_inpainting(&app_handle, &image_data, &mask_data, &prompt, &mode)
+pub fn generate_terrain_stack(
+    layers: Vec<NoiseLayer>,
+    confirmation: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    if layers.iter().all(|l| !l.enabled) {
+        return Err(TopographError::invalid("Stack has no enabled layers"));
+    }
+    guard_destructive(DestructiveOp::Generate, confirmation.as_deref(), &state)?;
+    let mut hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let selection = state
+        .masks
+        .lock()
+        .unwrap()
+        .get(MaskChannel::Selection)
+        .filter(|m| m.width == hm.width && m.height == hm.height)
+        .map(|m| m.data.clone());
+
+    stack::generate(&mut hm, &layers, &world_scale, boundary, selection.as_deref());
+    state.operations.lock().unwrap().record("generate_terrain_stack", serde_json::json!({ "layers": layers }), &hm, false);
+    *state.canvas_frame.lock().unwrap() = None;
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
 }
 
+/// Grow the canvas by continuing generation with `params` past the current bounds.
 #[tauri::command]
-pub fn generate_controlnet_texture(
-    image_data: Vec<u8>,
-    mask_data: Vec<u8>,
-    prompt: String,
-    app_handle: AppHandle,
+pub fn expand_canvas(
+    expansion: Expansion,
+    params: NoiseParams,
+    blend_width: Option<u32>,
+    confirmation: Option<String>,
     state: State<'_, AppState>,
-) -> Result<Vec<u8>, String> {
-    let hm = state.heightmap.lock().unwrap();
-    let data = hm.data.clone();
-    let width = hm.width;
-    let height = hm.height;
-    drop(hm); // Release lock before spawning subprocess
+) -> Result<Response, TopographError> {
+    guard_destructive(DestructiveOp::Resize, confirmation.as_deref(), &state)?;
+    let mut hm = state.heightmap.lock().unwrap();
+    let width = hm.width + expansion.left + expansion.right;
+    let height = hm.height + expansion.top + expansion.bottom;
+    if width == hm.width && height == hm.height {
+        return Ok(Response::new(ipc::pack_full(&hm)));
+    }
+    if width > canvas::MAX_CANVAS_SIZE || height > canvas::MAX_CANVAS_SIZE {
+        return Err(TopographError::invalid(format!("Canvas cannot exceed {0}x{0}", canvas::MAX_CANVAS_SIZE)));
+    }
 
-    ai::run_controlnet_texture(&app_handle, &image_data, &mask_data, &prompt, &data, width, height)
+    let mut frame = state.canvas_frame.lock().unwrap();
+    let current = frame.unwrap_or_else(|| Frame::of(&hm));
+    let (expanded, next) = canvas::expand(&hm, current, &expansion, &params, blend_width.unwrap_or(32));
+    *hm = expanded;
+    *frame = Some(next);
+    let args = serde_json::json!({ "expansion": expansion, "params": params, "blendWidth": blend_width });
+    state.operations.lock().unwrap().record("expand_canvas", args, &hm, false);
+
+    state
+        .masks
+        .lock()
+        .unwrap()
+        .transform(|m| canvas::pad(m, expansion.left, expansion.top, width, height));
+    for patch in state.detail_patches.lock().unwrap().iter_mut() {
+        patch.x += expansion.left;
+        patch.y += expansion.top;
+    }
+    // Tile layout no longer matches the canvas
+    *state.tile_grid.lock().unwrap() = None;
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
 }
 
+/// Crop the canvas to a rectangle, e.g. before exporting part of an expanded canvas.
 #[tauri::command]
-pub fn apply_heightmap_image(
-    image_data: Vec<u8>,
-    mask_data: Option<Vec<u8>>,
+pub fn crop_canvas(
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    confirmation: Option<String>,
     state: State<'_, AppState>,
-) -> Result<Response, String> {
-    // Decode the grayscale PNG to get pixel values
-    let img = image::load_from_memory(&image_data)
-        .map_err(|e| format!("Failed to decode heightmap image: {e}"))?;
-    let gray = img.to_luma8();
-
+) -> Result<Response, TopographError> {
+    guard_destructive(DestructiveOp::Resize, confirmation.as_deref(), &state)?;
     let mut hm = state.heightmap.lock().unwrap();
-    let width = hm.width;
-    let height = hm.height;
+    if w < 2 || h < 2 || x + w > hm.width || y + h > hm.height {
+        return Err(TopographError::invalid("Crop rectangle must lie inside the canvas"));
+    }
 
-    // Resize if needed
-    let resized = if gray.width() != width || gray.height() != height {
-        image::imageops::resize(&gray, width, height, image::imageops::FilterType::Lanczos3)
-    } else {
-        gray
-    };
+    let mut frame = state.canvas_frame.lock().unwrap();
+    let current = frame.unwrap_or_else(|| Frame::of(&hm));
+    *frame = Some(Frame {
+        origin_x: current.origin_x + x as f64,
+        origin_y: current.origin_y + y as f64,
+        ..current
+    });
+    *hm = canvas::crop(&hm, x, y, w, h);
+    let args = serde_json::json!({ "x": x, "y": y, "w": w, "h": h });
+    state.operations.lock().unwrap().record("crop_canvas", args, &hm, false);
 
-    // Convert pixels to normalized heights [0.0, 1.0]
-    let depth_values: Vec<f32> = resized.pixels().map(|p| p.0[0] as f32 / 255.0).collect();
+    state.masks.lock().unwrap().transform(|m| canvas::crop(m, x, y, w, h));
+    // Keep only patches wholly inside the crop
+    state.detail_patches.lock().unwrap().retain_mut(|patch| {
+        let inside = patch.x >= x && patch.y >= y && patch.x + patch.w <= x + w && patch.y + patch.h <= y + h;
+        if inside {
+            patch.x -= x;
+            patch.y -= y;
+        }
+        inside
+    });
+    *state.tile_grid.lock().unwrap() = None;
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
 
-    match mask_data {
-        Some(mask_png) => {
-            let mask = ai::decode_mask_png(&mask_png, width, height)?;
+/// Token that lets one run of `op` through `guard_destructive`. Call only after the
+/// user has confirmed; it expires after a minute.
+#[tauri::command]
+pub fn confirm_destructive(op: DestructiveOp, state: State<'_, AppState>) -> String {
+    state.confirmations.lock().unwrap().issue(op)
+}
 
-            // Find height range in masked region of existing terrain
-            let mut masked_min = f32::MAX;
-            let mut masked_max = f32::MIN;
-            for i in 0..hm.data.len() {
-                if mask[i] > 0.1 {
-                    masked_min = masked_min.min(hm.data[i]);
-                    masked_max = masked_max.max(hm.data[i]);
-                }
-            }
-            if masked_min > masked_max {
-                masked_min = 0.0;
-                masked_max = 1.0;
-            }
-            let range = (masked_max - masked_min).max(0.05);
-            let target_min = (masked_min - range * 0.3).max(0.0);
-            let target_max = (masked_max + range * 0.3).min(1.0);
+/// Redeem `confirmation` for `op`, then checkpoint the document so the operation
+/// can be undone. Call before taking any other state lock.
+fn guard_destructive(op: DestructiveOp, confirmation: Option<&str>, state: &AppState) -> Result<(), TopographError> {
+    if !state.confirmations.lock().unwrap().redeem(confirmation, op) {
+        return Err(TopographError::confirmation_required(format!("{} needs confirmation", op.describe())));
+    }
+    checkpoint(op, state);
+    Ok(())
+}
 
-            // Find depth range in masked area
-            let mut depth_min = f32::MAX;
-            let mut depth_max = f32::MIN;
-            for i in 0..depth_values.len() {
-                if mask[i] > 0.1 {
-                    depth_min = depth_min.min(depth_values[i]);
-                    depth_max = depth_max.max(depth_values[i]);
-                }
-            }
-            let depth_range = (depth_max - depth_min).max(1e-6);
+/// Note a change to the document outside the heightmap for autosave.
+fn document_changed(state: &AppState) {
+    state.revision.fetch_add(1, Ordering::SeqCst);
+}
 
-            // Blend with feathered mask
-            let feathered_mask = ai::feather_mask(&mask, width, height, 8);
-            for i in 0..hm.data.len() {
-                let w = feathered_mask[i];
-                if w > 0.001 {
-                    let normalized = (depth_values[i] - depth_min) / depth_range;
-                    let remapped = target_min + normalized * (target_max - target_min);
-                    hm.data[i] = hm.data[i] * (1.0 - w) + remapped * w;
-                }
-            }
-        }
-        None => {
-            hm.data.copy_from_slice(&depth_values);
-        }
-    }
+fn checkpoint(op: DestructiveOp, state: &AppState) {
+    let snapshot = Snapshot {
+        heightmap: state.heightmap.lock().unwrap().clone(),
+        masks: state.masks.lock().unwrap().clone(),
+        canvas_frame: *state.canvas_frame.lock().unwrap(),
+        detail_patches: state.detail_patches.lock().unwrap().clone(),
+    };
+    state.checkpoints.lock().unwrap().push(op, snapshot);
+}
 
-    Ok(Response::new(ipc::pack_full(&hm)))
+#[tauri::command]
+pub fn list_checkpoints(state: State<'_, AppState>) -> Vec<CheckpointInfo> {
+    state.checkpoints.lock().unwrap().info()
 }
 
+/// Put the document back as it was at a checkpoint. The current state becomes a
+/// checkpoint in its place, so this can be undone the same way.
 #[tauri::command]
-pub fn set_heightmap(data: Vec<f32>, state: State<'_, AppState>) -> Result<(), String> {
+pub fn restore_checkpoint(id: u64, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let restored = state
+        .checkpoints
+        .lock()
+        .unwrap()
+        .take(id)
+        .ok_or_else(|| TopographError::not_found(format!("No checkpoint {id}")))?;
+    checkpoint(DestructiveOp::Restore, &state);
+
     let mut hm = state.heightmap.lock().unwrap();
-    let expected = (hm.width * hm.height) as usize;
-    if data.len() != expected {
-        return Err(format!("Data length mismatch: {} vs {}", data.len(), expected));
+    let resized = hm.width != restored.heightmap.width || hm.height != restored.heightmap.height;
+    *hm = restored.heightmap;
+    *state.masks.lock().unwrap() = restored.masks;
+    document_changed(&state);
+    *state.canvas_frame.lock().unwrap() = restored.canvas_frame;
+    *state.detail_patches.lock().unwrap() = restored.detail_patches;
+    if resized {
+        *state.tile_grid.lock().unwrap() = None;
     }
-    hm.data.copy_from_slice(&data);
-    Ok(())
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
 }
 
+/// Attach a higher-resolution patch over a base rectangle, optionally seeded with
+/// fine noise detail. Returns the updated patch list.
 #[tauri::command]
-pub fn save_project(
-    path: String,
-    texture_png: Option<Vec<u8>>,
-    settings_json: String,
+pub fn add_detail_patch(
+    x: u32,
+    y: u32,
+    w: u32,
+    h: u32,
+    factor: u32,
+    detail: Option<NoiseParams>,
     state: State<'_, AppState>,
-) -> Result<(), String> {
+) -> Result<Vec<DetailPatchInfo>, TopographError> {
     let hm = state.heightmap.lock().unwrap();
-    project::save_project(
-        std::path::Path::new(&path),
-        &hm,
-        texture_png.as_deref(),
-        &settings_json,
-    )
+    let patch = DetailPatch::new(&hm, (x, y, w, h), factor, detail.as_ref()).map_err(TopographError::invalid)?;
+    let mut patches = state.detail_patches.lock().unwrap();
+    patches.push(patch);
+    let args = serde_json::json!({ "x": x, "y": y, "w": w, "h": h, "factor": factor, "detail": detail });
+    state.operations.lock().unwrap().record("add_detail_patch", args, &hm, false);
+    document_changed(&state);
+    Ok(patches.iter().map(DetailPatch::info).collect())
 }
 
 #[tauri::command]
-pub fn load_project(
-    path: String,
-    state: State<'_, AppState>,
-) -> Result<project::LoadProjectResponse, String> {
-    let (new_hm, texture_png, settings_json) =
-        project::load_project(std::path::Path::new(&path))?;
-
-    let mut hm = state.heightmap.lock().unwrap();
-    *hm = new_hm;
+pub fn list_detail_patches(state: State<'_, AppState>) -> Vec<DetailPatchInfo> {
+    state.detail_patches.lock().unwrap().iter().map(DetailPatch::info).collect()
+}
 
-    Ok(project::LoadProjectResponse {
-        texture_png,
-        settings_json,
-    })
+/// A patch's residual grid, to be added to the upsampled base for display.
+#[tauri::command]
+pub fn get_detail_patch(index: usize, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let patches = state.detail_patches.lock().unwrap();
+    let patch = patches.get(index).ok_or_else(|| TopographError::not_found(format!("No detail patch {index}")))?;
+    Ok(Response::new(ipc::pack_full(&patch.residual)))
 }
 
 #[tauri::command]
-pub fn export_heightmap(
-    path: String,
-    format: String,
-    state: State<'_, AppState>,
-) -> Result<(), String> {
-    let hm = state.heightmap.lock().unwrap();
-    let p = std::path::Path::new(&path);
-    match format.as_str() {
-        "png16" => project::export_heightmap_png16(p, &hm),
-        "raw_f32" => project::export_heightmap_raw(p, &hm),
-        _ => Err(format!("Unknown export format: {format}")),
+pub fn remove_detail_patch(index: usize, state: State<'_, AppState>) -> Result<Vec<DetailPatchInfo>, TopographError> {
+    let mut patches = state.detail_patches.lock().unwrap();
+    if index >= patches.len() {
+        return Err(TopographError::not_found(format!("No detail patch {index}")));
     }
+    patches.remove(index);
+    document_changed(&state);
+    Ok(patches.iter().map(DetailPatch::info).collect())
+}
+
+fn check_strata(strata: Option<&Strata>) -> Result<(), TopographError> {
+    strata.map_or(Ok(()), |s| s.validate().map_err(TopographError::invalid))
+}
+
+/// Reject GPU runs up front when no GPU backend is available.
+fn check_backend(backend: Backend) -> Result<(), TopographError> {
+    if backend == Backend::Gpu && !erosion::gpu_available() {
+        return Err(TopographError::invalid("GPU erosion isn't available")
+            .with_hint(i18n::tr(Text::GpuUnavailableHint)));
+    }
+    Ok(())
+}
+
+/// Whether erosion can run with `backend: "gpu"`, and why the last GPU run fell
+/// back to the CPU if it did.
+#[tauri::command(async)]
+pub fn gpu_erosion_available() -> erosion::GpuStatus {
+    erosion::gpu_status()
+}
+
+/// Progress callback for a run that may use the GPU. If the GPU run fails, the
+/// first report from the CPU run that replaces it is preceded by
+/// `RunState::CpuFallback`.
+fn gpu_progress(tracker: &ProgressTracker) -> impl Fn(f32) + '_ {
+    let fallbacks = erosion::gpu_fallbacks();
+    let announced = Cell::new(false);
+    move |fraction| {
+        if !announced.get() && erosion::gpu_fallbacks() != fallbacks {
+            announced.set(true);
+            tracker.announce(RunState::CpuFallback);
+        }
+        tracker.report(fraction);
+    }
+}
+
+/// Longest edge of the terrain snapshots streamed during hydraulic erosion.
+const SNAPSHOT_SIZE: u32 = 256;
+/// Minimum time between two snapshots.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_millis(250);
+/// Most droplet paths one hydraulic run may trace.
+const MAX_TRACED_DROPLETS: u32 = 10_000;
+
+/// Feathered weights restricting an erosion run. As in `generate_terrain`, an
+/// uploaded mask takes precedence over the selection, and a missing selection
+/// leaves the run unrestricted.
+fn selection_weights(
+    use_selection: bool,
+    feather: u32,
+    mask_data: Option<Vec<u8>>,
+    state: &State<'_, AppState>,
+) -> Result<Option<Vec<f32>>, TopographError> {
+    let (width, height) = {
+        let hm = state.heightmap.lock().unwrap();
+        (hm.width, hm.height)
+    };
+    let mask = match mask_data {
+        Some(png) => Some(mask::decode_png(&png, width, height).map_err(TopographError::format)?),
+        None if use_selection => state
+            .masks
+            .lock()
+            .unwrap()
+            .get(MaskChannel::Selection)
+            .filter(|m| m.width == width && m.height == height)
+            .map(|m| m.data.clone()),
+        None => None,
+    };
+    Ok(mask.map(|mask| {
+        if feather > 0 {
+            ai::feather_mask(&mask, width, height, feather)
+        } else {
+            mask
+        }
+    }))
+}
+
+/// Copy of the mask channel an erosion run reads its hardness from, so the run
+/// doesn't hold the mask lock.
+fn hardness_weights(
+    channel: Option<MaskChannel>,
+    state: &State<'_, AppState>,
+) -> Result<Option<Vec<f32>>, TopographError> {
+    let Some(channel) = channel else {
+        return Ok(None);
+    };
+    let (width, height) = {
+        let hm = state.heightmap.lock().unwrap();
+        (hm.width, hm.height)
+    };
+    let masks = state.masks.lock().unwrap();
+    masks
+        .get(channel)
+        .filter(|m| m.width == width && m.height == height)
+        .map(|m| Some(m.data.clone()))
+        .ok_or_else(|| TopographError::not_found(format!("No {channel:?} mask to use as hardness")))
+}
+
+/// Clears `erosion_running` when dropped, so an erosion thread that panics
+/// doesn't leave every later run refused as busy.
+struct RunningFlag(Arc<AtomicBool>, Arc<FlagSignal>);
+
+impl Drop for RunningFlag {
+    fn drop(&mut self) {
+        self.1.set(&self.0, false);
+    }
+}
+
+/// Claim `erosion_running` for a new run, or fail if one is in progress.
+fn claim_erosion(state: &AppState) -> Result<RunningFlag, TopographError> {
+    if state.erosion_running.swap(true, Ordering::SeqCst) {
+        return Err(TopographError::busy("Erosion already running"));
+    }
+    state.erosion_abort.store(false, Ordering::SeqCst);
+    Ok(RunningFlag(Arc::clone(&state.erosion_running), Arc::clone(&state.erosion_signal)))
+}
+
+/// Run `erode` on the locked terrain on its own thread, then record it as
+/// `command` with `args` and count its time in the usage statistics. `erode`
+/// gets the document's boundary and the abort flag to poll.
+fn spawn_erosion(
+    state: &AppState,
+    command: &'static str,
+    args: serde_json::Value,
+    erode: impl FnOnce(&mut Heightmap, Boundary, &AtomicBool) + Send + 'static,
+) -> Result<(), TopographError> {
+    let running = claim_erosion(state)?;
+    let hm = Arc::clone(&state.heightmap);
+    let abort = Arc::clone(&state.erosion_abort);
+    let usage = Arc::clone(&state.usage);
+    let operations = Arc::clone(&state.operations);
+    let edits = Arc::clone(&state.edits);
+    let boundary = *state.boundary.lock().unwrap();
+
+    std::thread::spawn(move || {
+        let _running = running;
+        let started = Instant::now();
+        {
+            let mut hm_guard = hm.lock().unwrap();
+            erode(&mut hm_guard, boundary, &abort);
+            edits.whole();
+            operations.lock().unwrap().record(command, args, &hm_guard, abort.load(Ordering::SeqCst));
+        }
+        usage.lock().unwrap().record_erosion(started.elapsed());
+    });
+    Ok(())
+}
+
+#[tauri::command]
+pub fn run_thermal_erosion(
+    params: ThermalParams,
+    hardness: Option<MaskChannel>,
+    mask_data: Option<Vec<u8>>,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<(), TopographError> {
+    check_backend(params.backend)?;
+    check_strata(params.strata.as_ref())?;
+    let args = serde_json::json!({ "params": params, "hardness": hardness });
+    let hardness = hardness_weights(hardness, &state)?;
+    let selection = selection_weights(params.use_selection, params.mask_feather, mask_data, &state)?;
+    spawn_erosion(&state, "run_thermal_erosion", args, move |hm, boundary, abort| {
+        let tracker = ProgressTracker::new(Stage::Thermal, channel);
+        let masks = Masks { hardness: hardness.as_deref(), selection: selection.as_deref() };
+        thermal::erode(hm, &params, masks, boundary, abort, &gpu_progress(&tracker));
+    })
+}
+
+/// Low-resolution snapshots of the terrain arrive on `snapshots` while a CPU run
+/// is in progress, so the frontend can show it evolving. CPU runs can also be
+/// paused with `pause_erosion`, which releases the terrain; `channel` announces
+/// when the run pauses, resumes and finishes.
+#[tauri::command]
+pub fn run_hydraulic_erosion(
+    params: HydraulicParams,
+    hardness: Option<MaskChannel>,
+    mask_data: Option<Vec<u8>>,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+    snapshots: Channel<InvokeResponseBody>,
+) -> Result<(), TopographError> {
+    check_backend(params.backend)?;
+    check_strata(params.strata.as_ref())?;
+    if params.trace_droplets > MAX_TRACED_DROPLETS {
+        return Err(TopographError::invalid(format!("At most {MAX_TRACED_DROPLETS} droplets can be traced")));
+    }
+    let args = serde_json::json!({ "params": params, "hardness": hardness });
+    let hardness = hardness_weights(hardness, &state)?;
+    let selection = selection_weights(params.use_selection, params.mask_feather, mask_data, &state)?;
+    let running = claim_erosion(&state)?;
+
+    let hm = Arc::clone(&state.heightmap);
+    let abort = Arc::clone(&state.erosion_abort);
+    let pause = Arc::clone(&state.erosion_pause);
+    let suspended = Arc::clone(&state.erosion_suspended);
+    let signal = Arc::clone(&state.erosion_signal);
+    let usage = Arc::clone(&state.usage);
+    let operations = Arc::clone(&state.operations);
+    let erosion_maps = Arc::clone(&state.erosion_maps);
+    let droplet_traces = Arc::clone(&state.droplet_traces);
+    let edits = Arc::clone(&state.edits);
+    let revision = Arc::clone(&state.revision);
+    let boundary = *state.boundary.lock().unwrap();
+    pause.store(false, Ordering::SeqCst);
+
+    std::thread::spawn(move || {
+        let started = Instant::now();
+        let paused_for = Cell::new(Duration::ZERO);
+        let tracker = ProgressTracker::new(Stage::Droplets, channel);
+        {
+            // The run works on its own copy so it can hand the terrain back while
+            // suspended; the lock is held whenever it isn't
+            let held = RefCell::new(Some(hm.lock().unwrap()));
+            let mut work = held.borrow().as_deref().unwrap().clone();
+            let masks = Masks { hardness: hardness.as_deref(), selection: selection.as_deref() };
+            let last_snapshot = Cell::new(Instant::now());
+            let send_snapshot = |hm: &Heightmap| {
+                if last_snapshot.get().elapsed() >= SNAPSHOT_INTERVAL {
+                    last_snapshot.set(Instant::now());
+                    let _ = snapshots.send(InvokeResponseBody::Raw(ipc::pack_full(&downsampled(hm, SNAPSHOT_SIZE))));
+                }
+            };
+            let resized = Cell::new(false);
+            let wait = |work: &mut Heightmap| {
+                let suspended_at = Instant::now();
+                {
+                    let mut guard = held.borrow_mut().take().unwrap();
+                    guard.data.copy_from_slice(&work.data);
+                    edits.whole();
+                }
+                signal.set(&suspended, true);
+                tracker.announce(RunState::Paused);
+                signal.wait_until(|| !pause.load(Ordering::SeqCst) || abort.load(Ordering::SeqCst));
+                let guard = hm.lock().unwrap();
+                if (guard.width, guard.height) == (work.width, work.height) {
+                    work.data.copy_from_slice(&guard.data);
+                } else {
+                    // The masks no longer fit; stop and leave the new terrain be
+                    resized.set(true);
+                    abort.store(true, Ordering::SeqCst);
+                }
+                *held.borrow_mut() = Some(guard);
+                signal.set(&suspended, false);
+                tracker.announce(RunState::Resumed);
+                paused_for.set(paused_for.get() + suspended_at.elapsed());
+            };
+            let recording = hydraulic::erode(
+                &mut work,
+                &params,
+                masks,
+                boundary,
+                &abort,
+                &gpu_progress(&tracker),
+                Some(&send_snapshot),
+                Some(&Pause { requested: &pause, wait: &wait }),
+            );
+            operations
+                .lock()
+                .unwrap()
+                .record("run_hydraulic_erosion", args, &work, abort.load(Ordering::SeqCst));
+            if !resized.get() {
+                held.borrow_mut().as_mut().unwrap().data = work.data;
+                edits.whole();
+            }
+            if recording.maps.is_some() {
+                *erosion_maps.lock().unwrap() = recording.maps;
+                revision.fetch_add(1, Ordering::SeqCst);
+            }
+            if params.trace_droplets > 0 {
+                *droplet_traces.lock().unwrap() = recording.traces;
+            }
+        }
+        usage.lock().unwrap().record_erosion(started.elapsed() - paused_for.get());
+        // Free for the next run before saying so
+        drop(running);
+        tracker.announce(RunState::Finished);
+    });
+
+    Ok(())
+}
+
+/// Droplet paths from the last hydraulic run with `trace_droplets`, in pixel
+/// coordinates.
+#[tauri::command]
+pub fn get_droplet_traces(state: State<'_, AppState>) -> Vec<DropletTrace> {
+    state.droplet_traces.lock().unwrap().clone()
+}
+
+/// Shallow-water erosion with virtual pipes between cells. Runs on the CPU only.
+#[tauri::command]
+pub fn run_pipe_erosion(
+    params: PipeParams,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<(), TopographError> {
+    let args = serde_json::json!({ "params": params });
+    spawn_erosion(&state, "run_pipe_erosion", args, move |hm, boundary, abort| {
+        let tracker = ProgressTracker::new(Stage::Water, channel);
+        pipe::erode(hm, &params, boundary, abort, &|fraction| tracker.report(fraction));
+    })
+}
+
+/// Stream-power incision with uplift, for carving drainage networks over long
+/// timescales. Runs on the CPU only.
+#[tauri::command]
+pub fn run_stream_power_erosion(
+    params: StreamPowerParams,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<(), TopographError> {
+    let args = serde_json::json!({ "params": params });
+    spawn_erosion(&state, "run_stream_power_erosion", args, move |hm, boundary, abort| {
+        let tracker = ProgressTracker::new(Stage::Uplift, channel);
+        stream_power::erode(hm, &params, boundary, abort, &|fraction| tracker.report(fraction));
+    })
+}
+
+/// Glaciers grown above a snowline, carving U-shaped valleys and cirques and
+/// leaving moraines where they melt. Runs on the CPU only.
+#[tauri::command]
+pub fn run_glacial_erosion(
+    params: GlacialParams,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<(), TopographError> {
+    let args = serde_json::json!({ "params": params });
+    spawn_erosion(&state, "run_glacial_erosion", args, move |hm, boundary, abort| {
+        let tracker = ProgressTracker::new(Stage::Glaciers, channel);
+        glacial::erode(hm, &params, boundary, abort, &|fraction| tracker.report(fraction));
+    })
+}
+
+/// Waves at a sea level cutting cliffs and platforms into exposed shores and
+/// building beaches in sheltered ones. Runs on the CPU only.
+#[tauri::command]
+pub fn run_coastal_erosion(
+    params: CoastalParams,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<(), TopographError> {
+    if !(0.0..=1.0).contains(&params.directionality) {
+        return Err(TopographError::invalid("Directionality must be between 0 and 1"));
+    }
+    let args = serde_json::json!({ "params": params });
+    spawn_erosion(&state, "run_coastal_erosion", args, move |hm, boundary, abort| {
+        let tracker = ProgressTracker::new(Stage::Waves, channel);
+        coastal::erode(hm, &params, boundary, abort, &|fraction| tracker.report(fraction));
+    })
+}
+
+/// Run `run` on a downsampled copy of the terrain for quick parameter tweaking and
+/// return the result; the document is left untouched. See `ErosionRun::rescale`
+/// for how the parameters follow the resolution. Masks are not applied.
+#[tauri::command(async)]
+pub fn preview_erosion(
+    mut params: ErosionRun,
+    preview_size: u32,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    if !(16..=512).contains(&preview_size) {
+        return Err(TopographError::invalid("Preview size must be between 16 and 512"));
+    }
+    check_backend(params.backend())?;
+    check_strata(params.strata())?;
+    let mut preview = {
+        let hm = state.heightmap.lock().unwrap();
+        let preview = downsampled(&hm, preview_size);
+        params.rescale(preview.width as f32 / hm.width as f32);
+        preview
+    };
+    let boundary = *state.boundary.lock().unwrap();
+    params.apply(&mut preview, Masks::default(), boundary, &AtomicBool::new(false), &|_| {});
+    Ok(Response::new(ipc::pack_full(&preview)))
+}
+
+/// Run two erosion setups on copies of the current terrain in parallel, leaving the
+/// document untouched. Returns [a, b, b - a] packed with `ipc::pack_full_set`.
+#[tauri::command(async)]
+pub fn compare_erosion(
+    a: ErosionRun,
+    b: ErosionRun,
+    hardness: Option<MaskChannel>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    check_backend(a.backend())?;
+    check_backend(b.backend())?;
+    check_strata(a.strata())?;
+    check_strata(b.strata())?;
+    let hardness = hardness_weights(hardness, &state)?;
+    let selection_a = a.selection().map_or(Ok(None), |feather| selection_weights(true, feather, None, &state))?;
+    let selection_b = b.selection().map_or(Ok(None), |feather| selection_weights(true, feather, None, &state))?;
+    let running = claim_erosion(&state)?;
+
+    let mut result_a = state.heightmap.lock().unwrap().clone();
+    let mut result_b = result_a.clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let abort = &*state.erosion_abort;
+    let started = Instant::now();
+    let masks_a = Masks { hardness: hardness.as_deref(), selection: selection_a.as_deref() };
+    let masks_b = Masks { hardness: hardness.as_deref(), selection: selection_b.as_deref() };
+    std::thread::scope(|s| {
+        s.spawn(|| a.apply(&mut result_a, masks_a, boundary, abort, &|_| {}));
+        b.apply(&mut result_b, masks_b, boundary, abort, &|_| {});
+    });
+    state.usage.lock().unwrap().record_erosion(started.elapsed());
+    drop(running);
+
+    if state.erosion_abort.load(Ordering::SeqCst) {
+        return Err(TopographError::aborted("Comparison aborted"));
+    }
+
+    let mut diff = result_b.clone();
+    for (d, &va) in diff.data.iter_mut().zip(result_a.data.iter()) {
+        *d -= va;
+    }
+    Ok(Response::new(ipc::pack_full_set(&[&result_a, &result_b, &diff])))
+}
+
+/// Stage a pass reports progress as.
+fn erosion_stage(run: &ErosionRun) -> Stage {
+    match run {
+        ErosionRun::Thermal(_) => Stage::Thermal,
+        ErosionRun::Hydraulic(_) => Stage::Droplets,
+        ErosionRun::Pipe(_) => Stage::Water,
+        ErosionRun::StreamPower(_) => Stage::Uplift,
+        ErosionRun::Glacial(_) => Stage::Glaciers,
+        ErosionRun::Coastal(_) => Stage::Waves,
+    }
+}
+
+/// Run `stages` on the current terrain one after another as a single job, each
+/// with its own parameters. Progress for every stage arrives on `channel`, tagged
+/// with the stage's position; aborting stops the stage in progress and skips the
+/// rest. Hydraulic stages don't record maps.
+#[tauri::command]
+pub fn run_erosion_pipeline(
+    stages: Vec<ErosionRun>,
+    hardness: Option<MaskChannel>,
+    mask_data: Option<Vec<u8>>,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<(), TopographError> {
+    if stages.is_empty() {
+        return Err(TopographError::invalid("The erosion pipeline has no stages"));
+    }
+    for stage in &stages {
+        check_backend(stage.backend())?;
+        check_strata(stage.strata())?;
+    }
+    let args = serde_json::json!({ "stages": stages, "hardness": hardness });
+    let hardness = hardness_weights(hardness, &state)?;
+    let selections = stages
+        .iter()
+        .map(|stage| {
+            stage
+                .selection()
+                .map_or(Ok(None), |feather| selection_weights(true, feather, mask_data.clone(), &state))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    spawn_erosion(&state, "run_erosion_pipeline", args, move |hm, boundary, abort| {
+        let count = stages.len() as u32;
+        for (index, (stage, selection)) in stages.iter().zip(&selections).enumerate() {
+            if abort.load(Ordering::SeqCst) {
+                break;
+            }
+            let tracker = ProgressTracker::new(erosion_stage(stage), channel.clone()).with_step(index as u32, count);
+            let masks = Masks { hardness: hardness.as_deref(), selection: selection.as_deref() };
+            stage.apply(hm, masks, boundary, abort, &gpu_progress(&tracker));
+        }
+    })
+}
+
+#[tauri::command]
+pub fn abort_erosion(state: State<'_, AppState>) {
+    state.erosion_signal.set(&state.erosion_abort, true);
+}
+
+/// Suspend the running hydraulic erosion at the end of its current round of
+/// droplets, releasing the terrain for editing until `resume_erosion`. Waits for
+/// it to suspend; false when the run ended first, as runs that can't pause do.
+#[tauri::command(async)]
+pub fn pause_erosion(state: State<'_, AppState>) -> bool {
+    let signal = &state.erosion_signal;
+    signal.set(&state.erosion_pause, true);
+    signal.wait_until(|| state.erosion_suspended.load(Ordering::SeqCst) || !state.erosion_running.load(Ordering::SeqCst));
+    if state.erosion_suspended.load(Ordering::SeqCst) {
+        return true;
+    }
+    signal.set(&state.erosion_pause, false);
+    false
+}
+
+/// Continue a run suspended by `pause_erosion` from the terrain as it now is.
+#[tauri::command]
+pub fn resume_erosion(state: State<'_, AppState>) {
+    state.erosion_signal.set(&state.erosion_pause, false);
+}
+
+#[tauri::command]
+pub fn run_depth_estimation(
+    image_data: Vec<u8>,
+    mask_data: Option<Vec<u8>>,
+    confirmation: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    if mask_data.is_none() {
+        guard_destructive(DestructiveOp::ReplaceFromAi, confirmation.as_deref(), &state)?;
+    }
+    let hm_lock = state.heightmap.lock().unwrap();
+    let width = hm_lock.width;
+    let height = hm_lock.height;
+    drop(hm_lock);
+
+    let depth_values = ai::run_depth_estimation(&app_handle, &image_data, width, height)?;
+
+    let mut hm = state.heightmap.lock().unwrap();
+    if depth_values.len() != hm.data.len() {
+        return Err(TopographError::invalid(format!(
+            "Depth data length mismatch: {} vs {}",
+            depth_values.len(),
+            hm.data.len()
+        )));
+    }
+
+    match mask_data {
+        Some(mask_png) => {
+            // Decode the mask PNG to get per-pixel weights
+            let mask = ai::decode_mask_png(&mask_png, width, height)?;
+
+            // Find the height range of the original terrain in the masked region
+            // so we can scale the depth values to match
+            let mut masked_min = f32::MAX;
+            let mut masked_max = f32::MIN;
+            for (&m, &h) in mask.iter().zip(hm.data.iter()) {
+                if m > 0.1 {
+                    masked_min = masked_min.min(h);
+                    masked_max = masked_max.max(h);
+                }
+            }
+            // Also sample a border ring around the mask to get surrounding height context
+            if masked_min > masked_max {
+                masked_min = 0.0;
+                masked_max = 1.0;
+            }
+            // Add some headroom so AI can create features above/below existing terrain
+            let range = (masked_max - masked_min).max(0.05);
+            let target_min = (masked_min - range * 0.3).max(0.0);
+            let target_max = (masked_max + range * 0.3).min(1.0);
+
+            // Find depth range in masked area
+            let mut depth_min = f32::MAX;
+            let mut depth_max = f32::MIN;
+            for i in 0..depth_values.len() {
+                if mask[i] > 0.1 {
+                    depth_min = depth_min.min(depth_values[i]);
+                    depth_max = depth_max.max(depth_values[i]);
+                }
+            }
+            let depth_range = (depth_max - depth_min).max(1e-6);
+
+            // Blend: remap depth to target range, mix with original using mask weight
+            // Apply Gaussian feathering at mask edges
+            let feathered_mask = ai::feather_mask(&mask, width, height, 8);
+            for i in 0..hm.data.len() {
+                let w = feathered_mask[i];
+                if w > 0.001 {
+                    // Remap depth to match surrounding terrain height range
+                    let normalized = (depth_values[i] - depth_min) / depth_range;
+                    let remapped = target_min + normalized * (target_max - target_min);
+                    hm.data[i] = hm.data[i] * (1.0 - w) + remapped * w;
+                }
+            }
+        }
+        None => {
+            // No mask — replace entire heightmap (legacy behavior)
+            hm.data.copy_from_slice(&depth_values);
+        }
+    }
+
+    state.operations.lock().unwrap().record("run_depth_estimation", serde_json::json!({}), &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
+
+/// Use the frontend's capture if one was sent, otherwise raycast a snapshot in the
+/// backend at the mask's resolution so the two stay pixel-aligned.
+fn conditioning_image(
+    image_data: Option<Vec<u8>>,
+    mask_data: &[u8],
+    camera: Option<Camera>,
+    state: &AppState,
+) -> Result<Vec<u8>, TopographError> {
+    if let Some(image_data) = image_data {
+        return Ok(image_data);
+    }
+
+    let mask = image::load_from_memory(mask_data)
+        .map_err(|e| TopographError::format(format!("Failed to decode mask image: {e}")))?;
+    let camera = camera.unwrap_or_default();
+    let hm = state.heightmap.lock().unwrap();
+    let patches = state.detail_patches.lock().unwrap();
+    let img = render::render_perspective(&Surface::new(&hm, &patches), &camera, mask.width(), mask.height());
+    drop(patches);
+    drop(hm);
+
+    Ok(render::encode_png(&img)?)
+}
+
+#[tauri::command]
+pub fn run_inpainting(
+    image_data: Option<Vec<u8>>,
+    mask_data: Vec<u8>,
+    prompt: String,
+    mode: String,
+    camera: Option<Camera>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, TopographError> {
+    let image_data = conditioning_image(image_data, &mask_data, camera, &state)?;
+    ai::run_inpainting(&app_handle, &image_data, &mask_data, &prompt, &mode)
+}
+
+#[tauri::command]
+pub fn generate_controlnet_texture(
+    image_data: Option<Vec<u8>>,
+    mask_data: Vec<u8>,
+    prompt: String,
+    camera: Option<Camera>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<u8>, TopographError> {
+    let image_data = conditioning_image(image_data, &mask_data, camera, &state)?;
+
+    let hm = state.heightmap.lock().unwrap();
+    let data = hm.data.clone();
+    let width = hm.width;
+    let height = hm.height;
+    drop(hm); // Release lock before spawning subprocess
+
+    ai::run_controlnet_texture(&app_handle, &image_data, &mask_data, &prompt, &data, width, height)
+}
+
+#[tauri::command]
+pub fn apply_heightmap_image(
+    image_data: Vec<u8>,
+    mask_data: Option<Vec<u8>>,
+    confirmation: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    if mask_data.is_none() {
+        guard_destructive(DestructiveOp::ReplaceFromAi, confirmation.as_deref(), &state)?;
+    }
+    // Decode at the image's own bit depth to get normalized heights
+    let image = project::decode_heightmap_image(&image_data).map_err(TopographError::format)?;
+
+    let mut hm = state.heightmap.lock().unwrap();
+    let width = hm.width;
+    let height = hm.height;
+    let depth_values = resample::resample(&image, width, height, ResampleFilter::Lanczos).data;
+
+    match mask_data {
+        Some(mask_png) => {
+            let mask = ai::decode_mask_png(&mask_png, width, height)?;
+
+            // Find height range in masked region of existing terrain
+            let mut masked_min = f32::MAX;
+            let mut masked_max = f32::MIN;
+            for (&m, &h) in mask.iter().zip(hm.data.iter()) {
+                if m > 0.1 {
+                    masked_min = masked_min.min(h);
+                    masked_max = masked_max.max(h);
+                }
+            }
+            if masked_min > masked_max {
+                masked_min = 0.0;
+                masked_max = 1.0;
+            }
+            let range = (masked_max - masked_min).max(0.05);
+            let target_min = (masked_min - range * 0.3).max(0.0);
+            let target_max = (masked_max + range * 0.3).min(1.0);
+
+            // Find depth range in masked area
+            let mut depth_min = f32::MAX;
+            let mut depth_max = f32::MIN;
+            for i in 0..depth_values.len() {
+                if mask[i] > 0.1 {
+                    depth_min = depth_min.min(depth_values[i]);
+                    depth_max = depth_max.max(depth_values[i]);
+                }
+            }
+            let depth_range = (depth_max - depth_min).max(1e-6);
+
+            // Blend with feathered mask
+            let feathered_mask = ai::feather_mask(&mask, width, height, 8);
+            for i in 0..hm.data.len() {
+                let w = feathered_mask[i];
+                if w > 0.001 {
+                    let normalized = (depth_values[i] - depth_min) / depth_range;
+                    let remapped = target_min + normalized * (target_max - target_min);
+                    hm.data[i] = hm.data[i] * (1.0 - w) + remapped * w;
+                }
+            }
+        }
+        None => {
+            hm.data.copy_from_slice(&depth_values);
+        }
+    }
+
+    state.operations.lock().unwrap().record("apply_heightmap_image", serde_json::json!({}), &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
+
+/// Replace the heightmap with an image from disk at its own resolution and bit
+/// depth; see `project::decode_heightmap_image`. Masks follow the new size,
+/// while detail patches, recorded erosion maps and the generation frame no
+/// longer match it and are dropped.
+#[tauri::command(async)]
+pub fn import_heightmap(path: String, confirmation: Option<String>, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let bytes = std::fs::read(&path).map_err(|e| TopographError::io(format!("Failed to read {path}: {e}")))?;
+    let image = project::decode_heightmap_image(&bytes).map_err(TopographError::format)?;
+    let args = serde_json::json!({ "path": path });
+    replace_heightmap(image, "import_heightmap", args, confirmation.as_deref(), &state)
+}
+
+/// Replace the heightmap with a 32-bit float TIFF or OpenEXR from disk, its
+/// samples mapped to heights by `normalization`; otherwise as `import_heightmap`.
+#[tauri::command(async)]
+pub fn import_float_heightmap(
+    path: String,
+    normalization: Option<Normalization>,
+    confirmation: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    let normalization = normalization.unwrap_or_default();
+    normalization.validate().map_err(TopographError::invalid)?;
+    let mut image = float_image::read(std::path::Path::new(&path)).map_err(TopographError::format)?;
+    normalization.apply(&mut image.data);
+    let args = serde_json::json!({ "path": path, "normalization": normalization });
+    replace_heightmap(image, "import_float_heightmap", args, confirmation.as_deref(), &state)
+}
+
+/// Replace the heightmap with a headerless raw file laid out per `options`, as
+/// World Machine, Unity and other pipelines write them. Without `width` and
+/// `height` the file is taken to be square. Float samples are mapped to heights
+/// by `normalization`; otherwise as `import_heightmap`.
+#[tauri::command(async)]
+pub fn import_raw(
+    path: String,
+    width: Option<u32>,
+    height: Option<u32>,
+    options: RawOptions,
+    normalization: Option<Normalization>,
+    confirmation: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    let size = match (width, height) {
+        (Some(w), Some(h)) => Some((w, h)),
+        (None, None) => None,
+        _ => return Err(TopographError::invalid("Give both the width and the height, or neither")),
+    };
+    let normalization = normalization.unwrap_or_default();
+    normalization.validate().map_err(TopographError::invalid)?;
+    let bytes = std::fs::read(&path).map_err(|e| TopographError::io(format!("Failed to read {path}: {e}")))?;
+    let mut image = project::decode_heightmap_raw(&bytes, size, &options).map_err(TopographError::format)?;
+    if options.float {
+        normalization.apply(&mut image.data);
+    }
+    let args = serde_json::json!({
+        "path": path,
+        "width": width,
+        "height": height,
+        "options": options,
+        "normalization": normalization,
+    });
+    replace_heightmap(image, "import_raw", args, confirmation.as_deref(), &state)
+}
+
+/// Replace the heightmap with tiles stitched into one, see `mosaic::assemble`.
+/// Float tiles are mapped to heights by `normalization` over the whole mosaic,
+/// so the seams keep matching.
+#[tauri::command(async)]
+pub fn import_mosaic(
+    paths: Vec<String>,
+    params: MosaicParams,
+    normalization: Option<Normalization>,
+    confirmation: Option<String>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    let normalization = normalization.unwrap_or_default();
+    normalization.validate().map_err(TopographError::invalid)?;
+    let args = serde_json::json!({ "paths": paths, "params": params, "normalization": normalization });
+    let paths: Vec<std::path::PathBuf> = paths.into_iter().map(Into::into).collect();
+    let (mut image, float) = mosaic::assemble(&paths, &params).map_err(TopographError::format)?;
+    if float {
+        normalization.apply(&mut image.data);
+    }
+    replace_heightmap(image, "import_mosaic", args, confirmation.as_deref(), &state)
+}
+
+/// Replace the heightmap with the first band of a GeoTIFF DEM, resampled so its
+/// longer side matches the working map's. The world scale takes the DEM's
+/// elevation range, cell size and georeference; no-data cells are filled with
+/// the lowest elevation.
+#[tauri::command(async)]
+pub fn import_geotiff(path: String, confirmation: Option<String>, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let dem = geotiff::read(std::path::Path::new(&path)).map_err(TopographError::format)?;
+    let args = serde_json::json!({ "path": path });
+    import_dem(dem, None, "import_geotiff", args, confirmation.as_deref(), &state)
+}
+
+/// Replace the heightmap with SRTM `.hgt` tiles stitched into one, their voids
+/// filled; otherwise as `import_geotiff`.
+#[tauri::command(async)]
+pub fn import_srtm(paths: Vec<String>, confirmation: Option<String>, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let args = serde_json::json!({ "paths": paths });
+    let paths: Vec<std::path::PathBuf> = paths.into_iter().map(Into::into).collect();
+    let dem = srtm::read(&paths).map_err(TopographError::format)?;
+    import_dem(dem, None, "import_srtm", args, confirmation.as_deref(), &state)
+}
+
+/// Download real-world terrain for a latitude/longitude box and replace the
+/// heightmap with it at `params.resolution`; otherwise as `import_geotiff`.
+#[tauri::command(async)]
+pub fn fetch_terrain(params: FetchParams, confirmation: Option<String>, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    params.validate().map_err(TopographError::invalid)?;
+    let dem = elevation_fetch::fetch(&params).map_err(TopographError::io)?;
+    let args = serde_json::json!({ "params": params });
+    import_dem(dem, Some(params.resolution), "fetch_terrain", args, confirmation.as_deref(), &state)
+}
+
+/// Swap in `dem` with its longer side at `size`, by default the working map's,
+/// and take its world scale.
+fn import_dem(
+    dem: Dem,
+    size: Option<u32>,
+    command: &str,
+    args: serde_json::Value,
+    confirmation: Option<&str>,
+    state: &AppState,
+) -> Result<Response, TopographError> {
+    let size = size.unwrap_or_else(|| {
+        let hm = state.heightmap.lock().unwrap();
+        hm.width.max(hm.height)
+    });
+    let world = state.world_scale.lock().unwrap().clone();
+    let (image, world) = dem.fit(size, &world).map_err(TopographError::format)?;
+    let response = replace_heightmap(image, command, args, confirmation, state)?;
+    *state.world_scale.lock().unwrap() = world;
+    document_changed(state);
+    Ok(response)
+}
+
+/// Swap in an imported heightmap once `DestructiveOp::Import` is confirmed, and
+/// log it as `command` with `args`.
+fn replace_heightmap(
+    image: Heightmap,
+    command: &str,
+    args: serde_json::Value,
+    confirmation: Option<&str>,
+    state: &AppState,
+) -> Result<Response, TopographError> {
+    let (width, height) = (image.width, image.height);
+    if width < 2 || height < 2 || width > canvas::MAX_CANVAS_SIZE || height > canvas::MAX_CANVAS_SIZE {
+        return Err(TopographError::invalid(format!(
+            "Imported heightmaps must be between 2x2 and {0}x{0}",
+            canvas::MAX_CANVAS_SIZE
+        )));
+    }
+    guard_destructive(DestructiveOp::Import, confirmation, state)?;
+
+    let mut hm = state.heightmap.lock().unwrap();
+    if width != hm.width || height != hm.height {
+        state
+            .masks
+            .lock()
+            .unwrap()
+            .transform(|m| resample::resample(m, width, height, ResampleFilter::Bicubic));
+        *state.tile_grid.lock().unwrap() = None;
+    }
+    *hm = image;
+    *state.canvas_frame.lock().unwrap() = None;
+    state.detail_patches.lock().unwrap().clear();
+    *state.erosion_maps.lock().unwrap() = None;
+    state.droplet_traces.lock().unwrap().clear();
+    state.operations.lock().unwrap().record(command, args, &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
+
+/// Blend a heightmap file into a rectangle or the selection, scaled, rotated
+/// and remapped per `params`, leaving the rest of the map as it is.
+#[tauri::command(async)]
+pub fn import_stamp(path: String, params: StampParams, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    params.validate().map_err(TopographError::invalid)?;
+    let image = stamp::load(std::path::Path::new(&path)).map_err(TopographError::format)?;
+    let selection = match params.target {
+        StampTarget::Selection => selection_weights(true, params.feather.round() as u32, None, &state)?,
+        StampTarget::Rect { .. } => None,
+    };
+    let mut hm = state.heightmap.lock().unwrap();
+    let (rx, ry, rw, rh) = stamp::stamp(&mut hm, &image, &params, selection.as_deref()).map_err(TopographError::invalid)?;
+    let args = serde_json::json!({ "path": path, "params": params });
+    state.operations.lock().unwrap().record("import_stamp", args, &hm, false);
+    state.edits.region((rx, ry, rw, rh));
+    if rw == 0 || rh == 0 {
+        return Ok(Response::new(ipc::pack_full(&hm)));
+    }
+    Ok(Response::new(ipc::pack_region(&hm, rx, ry, rw, rh)))
+}
+
+/// Stretch the heights linearly so the lowest point sits at 0 and the highest at 1.
+#[tauri::command]
+pub fn normalize_heightmap(confirmation: Option<String>, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let range = |hm: &Heightmap| hm.data.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &h| (lo.min(h), hi.max(h)));
+    let (min, max) = range(&state.heightmap.lock().unwrap());
+    if max - min <= f32::EPSILON {
+        return Err(TopographError::invalid("A flat map can't be normalized"));
+    }
+    guard_destructive(DestructiveOp::Normalize, confirmation.as_deref(), &state)?;
+    let mut hm = state.heightmap.lock().unwrap();
+    // Measured again, the map may have changed while the checkpoint was taken
+    let (min, max) = range(&hm);
+    let scale = 1.0 / (max - min).max(f32::EPSILON);
+    for h in hm.data.iter_mut() {
+        *h = (*h - min) * scale;
+    }
+    state.operations.lock().unwrap().record("normalize_heightmap", serde_json::json!({}), &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
+
+/// Smooth away stair-step artifacts, e.g. from 8-bit imports or AI output, while
+/// keeping slopes steeper than the feature threshold.
+#[tauri::command(async)]
+pub fn apply_curvature_flow(params: CurvatureFlowParams, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    if !params.feature_threshold.is_finite() || params.feature_threshold <= 0.0 {
+        return Err(TopographError::invalid("Feature threshold must be positive"));
+    }
+    let boundary = *state.boundary.lock().unwrap();
+    let mut hm = state.heightmap.lock().unwrap();
+    smoothing::curvature_flow(&mut hm, &params, boundary);
+    state.operations.lock().unwrap().record("apply_curvature_flow", serde_json::json!({ "params": params }), &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
+
+#[tauri::command]
+pub fn set_heightmap(data: Vec<f32>, confirmation: Option<String>, state: State<'_, AppState>) -> Result<(), TopographError> {
+    let expected = {
+        let hm = state.heightmap.lock().unwrap();
+        (hm.width * hm.height) as usize
+    };
+    if data.len() != expected {
+        return Err(TopographError::invalid(format!("Data length mismatch: {} vs {}", data.len(), expected)));
+    }
+    guard_destructive(DestructiveOp::ReplaceFromAi, confirmation.as_deref(), &state)?;
+    let mut hm = state.heightmap.lock().unwrap();
+    // A resize may have slipped in while we waited for the checkpoint
+    if hm.data.len() != expected {
+        return Err(TopographError::invalid(format!("Data length mismatch: {} vs {}", data.len(), hm.data.len())));
+    }
+    hm.data.copy_from_slice(&data);
+    state.operations.lock().unwrap().record("set_heightmap", serde_json::json!({}), &hm, false);
+    state.edits.whole();
+    Ok(())
+}
+
+/// Save on a background thread, reporting on `progress`. The document is
+/// copied first, so editing can go on while the file is written.
+#[tauri::command(async)]
+pub fn save_project(
+    path: String,
+    settings_json: String,
+    include_history: bool,
+    incremental: bool,
+    progress: Channel<Progress>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), TopographError> {
+    let tracker = ProgressTracker::new(Stage::Saving, progress);
+    with_document(&state, &settings_json, include_history, |contents| {
+        project::save_project(std::path::Path::new(&path), contents, incremental, &|fraction| tracker.report(fraction))
+    })
+    .map_err(TopographError::io)?;
+    remember_project(&app_handle, &path);
+    Ok(())
+}
+
+/// Call `f` with a copy of the open document, taken under its locks and
+/// released before `f` runs.
+fn with_document<T>(
+    state: &AppState,
+    settings_json: &str,
+    include_history: bool,
+    f: impl FnOnce(&ProjectContents) -> T,
+) -> T {
+    let document = {
+        let hm = state.heightmap.lock().unwrap();
+        // Fields in the order the locks are taken elsewhere
+        project::LoadedProject {
+            heightmap: hm.clone(),
+            settings_json: settings_json.to_string(),
+            world_scale: state.world_scale.lock().unwrap().clone(),
+            boundary: *state.boundary.lock().unwrap(),
+            usage: Some(state.usage.lock().unwrap().clone()),
+            masks: state.masks.lock().unwrap().clone(),
+            erosion_maps: state.erosion_maps.lock().unwrap().clone(),
+            texture_png: state.texture.lock().unwrap().as_ref().map(|t| t.png.clone()),
+            history: if include_history { state.checkpoints.lock().unwrap().iter().cloned().collect() } else { Vec::new() },
+            metadata: state.metadata.lock().unwrap().clone(),
+            operations: state.operations.lock().unwrap().clone(),
+            detail_patches: state.detail_patches.lock().unwrap().clone(),
+            canvas_frame: *state.canvas_frame.lock().unwrap(),
+            damage: None,
+        }
+    };
+    f(&document.contents())
+}
+
+/// Note the panels' settings, for autosaves to keep with the document.
+#[tauri::command]
+pub fn set_project_settings(settings_json: String, state: State<'_, AppState>) {
+    *state.settings_json.lock().unwrap() = settings_json;
+}
+
+/// Load on a background thread, reporting on `progress`. The document is only
+/// replaced once the whole file has been read.
+#[tauri::command(async)]
+pub fn load_project(
+    path: String,
+    progress: Channel<Progress>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<project::LoadProjectResponse, TopographError> {
+    let tracker = ProgressTracker::new(Stage::Loading, progress);
+    let response = open_project(&path, &state, &|fraction| tracker.report(fraction))?;
+    remember_project(&app_handle, &path);
+    Ok(response)
+}
+
+/// Replace the document with the project at `path`. A file that can't be
+/// opened is an I/O error; one that opens but doesn't read as a project is a
+/// format error.
+fn open_project(path: &str, state: &AppState, progress: &dyn Fn(f32)) -> Result<project::LoadProjectResponse, TopographError> {
+    std::fs::File::open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => TopographError::not_found(format!("No project at {path}")),
+        _ => TopographError::io(format!("Failed to open {path}: {e}")),
+    })?;
+    let loaded = project::load_project(std::path::Path::new(path), progress).map_err(|e| {
+        TopographError::format(e).with_hint(i18n::tr(Text::ProjectFormatHint))
+    })?;
+    Ok(install_project(loaded, state))
+}
+
+/// Make `loaded` the document, leaving nothing of the previous one behind.
+fn install_project(loaded: project::LoadedProject, state: &AppState) -> project::LoadProjectResponse {
+    let mut hm = state.heightmap.lock().unwrap();
+    *hm = loaded.heightmap;
+    state.edits.whole();
+    *state.world_scale.lock().unwrap() = loaded.world_scale.clone();
+    *state.boundary.lock().unwrap() = loaded.boundary;
+    *state.masks.lock().unwrap() = loaded.masks;
+    *state.canvas_frame.lock().unwrap() = loaded.canvas_frame;
+    *state.detail_patches.lock().unwrap() = loaded.detail_patches;
+    state.checkpoints.lock().unwrap().restore(loaded.history);
+    *state.erosion_maps.lock().unwrap() = loaded.erosion_maps;
+    state.droplet_traces.lock().unwrap().clear();
+    *state.usage.lock().unwrap() = loaded.usage.unwrap_or_default();
+    *state.metadata.lock().unwrap() = loaded.metadata.clone();
+    *state.operations.lock().unwrap() = loaded.operations;
+    *state.settings_json.lock().unwrap() = loaded.settings_json.clone();
+    // A texture that no longer decodes is dropped rather than failing the load
+    *state.texture.lock().unwrap() = loaded.texture_png.clone().and_then(|png| Texture::from_png(png).ok());
+    document_changed(state);
+
+    project::LoadProjectResponse {
+        texture_png: loaded.texture_png,
+        settings_json: loaded.settings_json,
+        world_scale: loaded.world_scale,
+        boundary: loaded.boundary,
+        metadata: loaded.metadata,
+        damage: loaded.damage,
+    }
+}
+
+/// Templates live in the app data directory, shared by every project.
+fn template_store(app_handle: &AppHandle) -> Result<TemplateStore, TopographError> {
+    let dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| TopographError::io(format!("No data directory for templates: {e}")))?;
+    Ok(TemplateStore::new(dir.join("templates")))
+}
+
+#[tauri::command]
+pub fn list_templates(app_handle: AppHandle) -> Result<Vec<String>, TopographError> {
+    template_store(&app_handle)?.list().map_err(TopographError::io)
+}
+
+/// Save the open document as a template, replacing any of the same name; its
+/// checkpoints are left out. Returns the templates.
+#[tauri::command(async)]
+pub fn save_template(
+    name: String,
+    settings_json: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, TopographError> {
+    templates::validate_name(&name).map_err(TopographError::invalid)?;
+    let store = template_store(&app_handle)?;
+    with_document(&state, &settings_json, false, |contents| store.save(&name, contents)).map_err(TopographError::io)?;
+    store.list().map_err(TopographError::io)
+}
+
+#[tauri::command]
+pub fn delete_template(name: String, app_handle: AppHandle) -> Result<Vec<String>, TopographError> {
+    templates::validate_name(&name).map_err(TopographError::invalid)?;
+    let store = template_store(&app_handle)?;
+    if !store.delete(&name).map_err(TopographError::io)? {
+        return Err(TopographError::not_found(format!("No template named {name:?}")));
+    }
+    store.list().map_err(TopographError::io)
+}
+
+/// Replace the document with a new project from the template `name`, resampled
+/// to `width` x `height`.
+#[tauri::command(async)]
+pub fn new_project_from_template(
+    name: String,
+    width: u32,
+    height: u32,
+    confirmation: Option<String>,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<project::LoadProjectResponse, TopographError> {
+    templates::validate_name(&name).map_err(TopographError::invalid)?;
+    let template = template_store(&app_handle)?
+        .load(&name)
+        .map_err(|e| TopographError::format(e).with_hint(i18n::tr(Text::ProjectFormatHint)))?
+        .ok_or_else(|| TopographError::not_found(format!("No template named {name:?}")))?;
+    let project = templates::instantiate(template, width, height).map_err(TopographError::invalid)?;
+    guard_destructive(DestructiveOp::NewProject, confirmation.as_deref(), &state)?;
+    Ok(install_project(project, &state))
+}
+
+#[tauri::command]
+pub fn get_project_metadata(state: State<'_, AppState>) -> ProjectMetadata {
+    state.metadata.lock().unwrap().clone()
+}
+
+/// Replace the open project's metadata; returns it as stored, trimmed and with
+/// repeated tags dropped.
+#[tauri::command]
+pub fn set_project_metadata(metadata: ProjectMetadata, state: State<'_, AppState>) -> ProjectMetadata {
+    let metadata = metadata.normalized();
+    *state.metadata.lock().unwrap() = metadata.clone();
+    document_changed(&state);
+    metadata
+}
+
+/// The generation, erosion and filter operations run on the open project, oldest
+/// first, each with the arguments that replay it.
+#[tauri::command]
+pub fn get_operation_history(state: State<'_, AppState>) -> Vec<Operation> {
+    state.operations.lock().unwrap().operations().to_vec()
+}
+
+/// Recovery saves live in the app data directory.
+fn recovery_dir(app_handle: &AppHandle) -> Result<std::path::PathBuf, TopographError> {
+    app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|e| TopographError::io(format!("No data directory for recovery saves: {e}")))
+}
+
+/// The autosave of a session that didn't exit cleanly, if there is one.
+#[tauri::command]
+pub fn check_recovery(app_handle: AppHandle) -> Result<Option<RecoveryInfo>, TopographError> {
+    Ok(autosave::crashed(&recovery_dir(&app_handle)?))
+}
+
+/// Load the crashed session's autosave as `load_project` would, then remove it;
+/// from here on the current session's autosaves cover the document.
+#[tauri::command(async)]
+pub fn restore_recovery(
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<project::LoadProjectResponse, TopographError> {
+    let dir = recovery_dir(&app_handle)?;
+    let info = autosave::crashed(&dir).ok_or_else(|| TopographError::not_found("There is no autosave to restore"))?;
+    let response = open_project(&info.path, &state, &|_| {})?;
+    autosave::discard(&dir).map_err(TopographError::io)?;
+    Ok(response)
+}
+
+#[tauri::command]
+pub fn discard_recovery(app_handle: AppHandle) -> Result<(), TopographError> {
+    autosave::discard(&recovery_dir(&app_handle)?).map_err(TopographError::io)
+}
+
+/// Make `png` the terrain's texture, e.g. after the viewer composited a
+/// generated patch into it.
+#[tauri::command(async)]
+pub fn set_texture(png: Vec<u8>, state: State<'_, AppState>) -> Result<TextureInfo, TopographError> {
+    let texture = Texture::from_png(png).map_err(TopographError::format)?;
+    let info = texture.info();
+    *state.texture.lock().unwrap() = Some(texture);
+    document_changed(&state);
+    Ok(info)
+}
+
+/// Replace the texture with a PNG from disk. Returns its bytes for the viewer.
+#[tauri::command(async)]
+pub fn import_texture(path: String, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let texture = Texture::read(std::path::Path::new(&path)).map_err(TopographError::format)?;
+    let png = texture.png.clone();
+    *state.texture.lock().unwrap() = Some(texture);
+    document_changed(&state);
+    Ok(Response::new(png))
+}
+
+#[tauri::command]
+pub fn clear_texture(state: State<'_, AppState>) {
+    *state.texture.lock().unwrap() = None;
+    document_changed(&state);
+}
+
+#[tauri::command]
+pub fn get_texture_info(state: State<'_, AppState>) -> Option<TextureInfo> {
+    state.texture.lock().unwrap().as_ref().map(Texture::info)
+}
+
+/// Heightmap export formats with localized descriptions.
+#[tauri::command]
+pub fn list_export_formats() -> Vec<project::ExportFormatInfo> {
+    project::export_formats()
+}
+
+/// Export the heightmap in `format`, resampled to `resolution` if given without
+/// touching the working map. Detail patches are placed in working-map pixels, so
+/// they are only written alongside exports at the working resolution.
+#[tauri::command(async)]
+pub fn export_heightmap(
+    path: String,
+    format: String,
+    resolution: Option<ExportResolution>,
+    state: State<'_, AppState>,
+) -> Result<(), TopographError> {
+    let hm = state.heightmap.lock().unwrap();
+    let p = std::path::Path::new(&path);
+    let Some(extension) = project::tile_extension(&format) else {
+        return Err(TopographError::invalid(format!("Unknown export format: {format}")));
+    };
+    if let Some(resolution) = &resolution {
+        resolution.validate().map_err(TopographError::invalid)?;
+    }
+    let target = resolution
+        .map(|r| (r.dims(&hm), r.filter))
+        .filter(|&((w, h), _)| w != hm.width || h != hm.height);
+    let resampled = target.map(|((w, h), filter)| resample::resample(&hm, w, h, filter));
+    project::export_heightmap_as(p, resampled.as_ref().unwrap_or(&hm), &format).map_err(TopographError::io)?;
+
+    let mut written = vec![p.to_path_buf()];
+
+    // Engines import landscape holes as a separate visibility mask
+    let masks = state.masks.lock().unwrap();
+    if let Some(holes) = masks.get(MaskChannel::Holes) {
+        if holes.data.iter().any(|&v| v >= 0.5) {
+            let holes_path = project::sidecar_path(p, "_holes", "png");
+            let resampled_holes;
+            let holes = match target {
+                Some(((w, h), filter)) => {
+                    resampled_holes = resample::resample(holes, w, h, filter);
+                    &resampled_holes
+                }
+                None => holes,
+            };
+            project::export_hole_mask(&holes_path, holes).map_err(TopographError::io)?;
+            written.push(holes_path);
+        }
+    }
+    drop(masks);
+
+    // Detail patches go alongside as composited higher-resolution tiles, with a
+    // manifest giving their placement on the base map
+    let patches = state.detail_patches.lock().unwrap();
+    let patches: Vec<&DetailPatch> = patches.iter().filter(|patch| target.is_none() && patch.fits(&hm)).collect();
+    if !patches.is_empty() {
+        for (i, patch) in patches.iter().enumerate() {
+            let patch_path = project::sidecar_path(p, &format!("_detail{i}"), extension);
+            let composite = patch.composite(&hm);
+            project::export_heightmap_as(&patch_path, &composite, &format).map_err(TopographError::io)?;
+            written.push(patch_path);
+        }
+        let infos: Vec<DetailPatchInfo> = patches.iter().map(|patch| patch.info()).collect();
+        let manifest_path = project::sidecar_path(p, "_detail", "json");
+        let json = serde_json::to_string_pretty(&infos)
+            .map_err(|e| format!("Failed to serialize detail manifest: {e}"))?;
+        std::fs::write(&manifest_path, json)
+            .map_err(|e| TopographError::io(format!("Failed to write detail manifest: {e}")))?;
+        written.push(manifest_path);
+    }
+    drop(hm);
+    state.usage.lock().unwrap().record_export(&format);
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "heightmap", &format, &written).map_err(TopographError::script)
+}
+
+/// Export a headerless raw heightmap in the given sample layout, see
+/// `project::export_heightmap_raw`. Hooks see the format as e.g. `raw_u16`.
+#[tauri::command]
+pub fn export_heightmap_raw(path: String, options: RawOptions, state: State<'_, AppState>) -> Result<(), TopographError> {
+    options.validate().map_err(TopographError::invalid)?;
+    {
+        let hm = state.heightmap.lock().unwrap();
+        project::export_heightmap_raw(std::path::Path::new(&path), &hm, &options).map_err(TopographError::io)?;
+    }
+    let format = format!("raw_{}{}", if options.float { "f" } else { "u" }, options.bit_depth);
+    state.usage.lock().unwrap().record_export(&format);
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "heightmap", &format, &[path.into()]).map_err(TopographError::script)
+}
+
+/// Export a 16-bit RAW for Unity's terrain importer, see `unity::export`.
+#[tauri::command]
+pub fn export_unity_raw(path: String, options: UnityRawOptions, state: State<'_, AppState>) -> Result<(), TopographError> {
+    let p = std::path::Path::new(&path);
+    let sidecar = {
+        let hm = state.heightmap.lock().unwrap();
+        let world_scale = state.world_scale.lock().unwrap();
+        unity::export(p, &hm, &world_scale, &options).map_err(TopographError::io)?
+    };
+    state.usage.lock().unwrap().record_export("unityRaw");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "unityRaw", "raw16", &[p.to_path_buf(), sidecar]).map_err(TopographError::script)
+}
+
+/// Export a 16-bit PNG sized for an Unreal landscape, see `unreal::export`.
+/// Returns the import settings also written to the sidecar.
+#[tauri::command(async)]
+pub fn export_unreal_landscape(
+    path: String,
+    options: UnrealOptions,
+    state: State<'_, AppState>,
+) -> Result<UnrealImport, TopographError> {
+    options.validate().map_err(TopographError::invalid)?;
+    let p = std::path::Path::new(&path);
+    let import = {
+        let hm = state.heightmap.lock().unwrap();
+        let world_scale = state.world_scale.lock().unwrap();
+        unreal::export(p, &hm, &world_scale, &options).map_err(TopographError::io)?
+    };
+    state.usage.lock().unwrap().record_export("unreal");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "unreal", "png16", &[p.to_path_buf(), import.sidecar.clone()])
+        .map_err(TopographError::script)?;
+    Ok(import)
+}
+
+/// Export an EXR of elevations in meters for Godot terrain plugins, see
+/// `godot::export`. Returns the import settings also written to the sidecar.
+#[tauri::command(async)]
+pub fn export_godot(path: String, state: State<'_, AppState>) -> Result<GodotImport, TopographError> {
+    let p = std::path::Path::new(&path);
+    let import = {
+        let hm = state.heightmap.lock().unwrap();
+        let world_scale = state.world_scale.lock().unwrap();
+        godot::export(p, &hm, &world_scale).map_err(TopographError::io)?
+    };
+    state.usage.lock().unwrap().record_export("godot");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "godot", "exr", &[p.to_path_buf(), import.sidecar.clone()])
+        .map_err(TopographError::script)?;
+    Ok(import)
+}
+
+/// Run the saved export profile `name` through the export command for its
+/// target, so usage and hooks see it as that export. Returns the main files
+/// written: the heightmap, or every tile and the tile manifest.
+#[tauri::command(async)]
+pub fn run_export_profile(
+    name: String,
+    app_handle: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, TopographError> {
+    presets::validate_name(&name).map_err(TopographError::invalid)?;
+    let preset = preset_store(&app_handle)?
+        .load(PresetKind::Export, &name)
+        .map_err(TopographError::format)?
+        .ok_or_else(|| TopographError::not_found(format!("No export profile named {name:?}")))?;
+    let profile: ExportProfile = serde_json::from_value(preset.params).map_err(|e| TopographError::format(e.to_string()))?;
+    std::fs::create_dir_all(&profile.destination)
+        .map_err(|e| TopographError::io(format!("Failed to create {}: {e}", profile.destination.display())))?;
+
+    let path = profile.path().display().to_string();
+    match profile.target {
+        ExportTarget::Heightmap { format, resolution } => export_heightmap(path.clone(), format, resolution, state)?,
+        ExportTarget::Raw { options } => export_heightmap_raw(path.clone(), options, state)?,
+        ExportTarget::Tiles { params } => return export_tiles(path, params, false, state),
+        ExportTarget::Unity { options } => export_unity_raw(path.clone(), options, state)?,
+        ExportTarget::Unreal { options } => drop(export_unreal_landscape(path.clone(), options, state)?),
+        ExportTarget::Godot => drop(export_godot(path.clone(), state)?),
+    }
+    Ok(vec![path])
+}
+
+/// Tiled export that only rewrites tiles changed since the last export to `dir`.
+#[tauri::command]
+pub fn export_heightmap_sync(
+    dir: String,
+    format: String,
+    tile_size: u32,
+    state: State<'_, AppState>,
+) -> Result<SyncExportReport, TopographError> {
+    let hm = state.heightmap.lock().unwrap();
+    let report = sync_export::export(std::path::Path::new(&dir), &hm, &format, tile_size).map_err(TopographError::io)?;
+    drop(hm);
+    state.usage.lock().unwrap().record_export("sync");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "heightmapSync", &format, &report.written).map_err(TopographError::script)?;
+    Ok(report)
+}
+
+/// Slice the heightmap, and the texture with `include_texture`, into a grid of
+/// tiles next to `path`, see `tile_export::export`. Returns the written paths.
+#[tauri::command(async)]
+pub fn export_tiles(
+    path: String,
+    params: TileExportParams,
+    include_texture: bool,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, TopographError> {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let written = {
+        let hm = state.heightmap.lock().unwrap();
+        params.validate(&hm).map_err(TopographError::invalid)?;
+        let texture = state.texture.lock().unwrap();
+        let texture = match (include_texture, texture.as_ref()) {
+            (false, _) => None,
+            (true, Some(texture)) => Some(&texture.image),
+            (true, None) => return Err(TopographError::invalid("There is no texture to export")),
+        };
+        tile_export::export(std::path::Path::new(&path), &hm, texture, &world_scale, &params)
+            .map_err(TopographError::io)?
+    };
+    state.usage.lock().unwrap().record_export("tiles");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "tiles", &params.format, &written).map_err(TopographError::script)?;
+    Ok(written.iter().map(|p| p.display().to_string()).collect())
+}
+
+#[tauri::command]
+pub fn render_preview(
+    width: u32,
+    height: u32,
+    style: RenderStyle,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    render::check_size("Preview", width, height).map_err(TopographError::invalid)?;
+    let hm = state.heightmap.lock().unwrap();
+    let patches = state.detail_patches.lock().unwrap();
+    let img = render::render_preview(&Surface::new(&hm, &patches), width, height, style);
+    drop(patches);
+    drop(hm);
+
+    Ok(Response::new(render::encode_png(&img)?))
+}
+
+#[tauri::command]
+pub fn render_snapshot(
+    camera: Option<Camera>,
+    width: u32,
+    height: u32,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    render::check_size("Snapshot", width, height).map_err(TopographError::invalid)?;
+    let camera = camera.unwrap_or_default();
+    let hm = state.heightmap.lock().unwrap();
+    let patches = state.detail_patches.lock().unwrap();
+    let img = render::render_perspective(&Surface::new(&hm, &patches), &camera, width, height);
+    drop(patches);
+    drop(hm);
+
+    Ok(Response::new(render::encode_png(&img)?))
+}
+
+#[tauri::command]
+pub fn export_map_image(
+    path: String,
+    width: u32,
+    height: u32,
+    style: RenderStyle,
+    furniture: Option<MapFurniture>,
+    state: State<'_, AppState>,
+) -> Result<(), TopographError> {
+    render::check_size("Image", width, height).map_err(TopographError::invalid)?;
+    let hm = state.heightmap.lock().unwrap();
+    let patches = state.detail_patches.lock().unwrap();
+    let mut img = render::render_preview(&Surface::new(&hm, &patches), width, height, style);
+    drop(patches);
+    if let Some(furniture) = furniture {
+        let world_scale = state.world_scale.lock().unwrap();
+        cartography::burn_in(&mut img, &hm, &world_scale, &furniture);
+    }
+    drop(hm);
+
+    img.save(&path).map_err(|e| TopographError::io(format!("Failed to save map image: {e}")))?;
+    state.usage.lock().unwrap().record_export("mapImage");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "mapImage", "png", &[path.into()]).map_err(TopographError::script)
+}
+
+/// Export the terrain classification as an indexed PNG, with a `_legend.json`
+/// sidecar naming each palette index and its coverage.
+#[tauri::command]
+pub fn export_biome_map(path: String, rules: Option<BiomeRules>, state: State<'_, AppState>) -> Result<(), TopographError> {
+    let rules = rules.unwrap_or_default();
+    let hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    derived.refresh(&hm, &world_scale, boundary);
+    let indices = biome::classify(&hm, derived.slope(), &rules);
+    let (width, height) = (hm.width, hm.height);
+    drop(derived);
+    drop(hm);
+
+    let p = std::path::Path::new(&path);
+    biome::write_indexed_png(p, &indices, width, height).map_err(TopographError::io)?;
+    let legend = biome::legend(&indices, width, height, world_scale.meters_per_pixel);
+    let legend_path = project::sidecar_path(p, "_legend", "json");
+    let json = serde_json::to_string_pretty(&legend)
+        .map_err(|e| format!("Failed to serialize legend: {e}"))?;
+    std::fs::write(&legend_path, json).map_err(|e| TopographError::io(format!("Failed to write legend: {e}")))?;
+    state.usage.lock().unwrap().record_export("biomeMap");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "biomeMap", "png", &[p.to_path_buf(), legend_path]).map_err(TopographError::script)
+}
+
+/// Write the chosen maps into the folder `path` in one pass, see `bundle::export`.
+/// The texture map needs a texture. Returns the written paths.
+#[tauri::command(async)]
+pub fn export_bundle(
+    path: String,
+    maps: Vec<MapKind>,
+    state: State<'_, AppState>,
+) -> Result<Vec<String>, TopographError> {
+    if maps.is_empty() {
+        return Err(TopographError::invalid("Choose at least one map to export"));
+    }
+    let hm = state.heightmap.lock().unwrap();
+    let texture = state.texture.lock().unwrap();
+    if maps.contains(&MapKind::Texture) && texture.is_none() {
+        return Err(TopographError::invalid("There is no texture to export"));
+    }
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    derived.refresh(&hm, &world_scale, boundary);
+    let sources = bundle::Sources {
+        hm: &hm,
+        derived: &derived,
+        world: &world_scale,
+        boundary,
+        texture: texture.as_ref().map(|t| t.png.as_slice()),
+    };
+    let written = bundle::export(std::path::Path::new(&path), &maps, &sources).map_err(TopographError::io)?;
+    drop(derived);
+    drop(texture);
+    drop(hm);
+    state.usage.lock().unwrap().record_export("bundle");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "bundle", "png", &written).map_err(TopographError::script)?;
+    Ok(written.iter().map(|p| p.display().to_string()).collect())
+}
+
+/// Trace contours every `interval` meters and write them as SVG or GeoJSON, see
+/// `contours::write`.
+#[tauri::command(async)]
+pub fn export_contours(path: String, params: ContourParams, state: State<'_, AppState>) -> Result<(), TopographError> {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    {
+        let hm = state.heightmap.lock().unwrap();
+        let lines = contours::trace(&hm, &world_scale, &params).map_err(TopographError::invalid)?;
+        contours::write(std::path::Path::new(&path), &hm, &world_scale, &lines, params.format).map_err(TopographError::io)?;
+    }
+    state.usage.lock().unwrap().record_export("contours");
+
+    let format = match params.format {
+        ContourFormat::Svg => "svg",
+        ContourFormat::GeoJson => "geojson",
+    };
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "contours", format, &[path.into()]).map_err(TopographError::script)
+}
+
+/// Export material layer weights as RGBA splatmaps next to `path`, with a
+/// `_splat.json` manifest, see `splatmap::export`.
+#[tauri::command(async)]
+pub fn export_splatmap(path: String, rules: Option<SplatRules>, state: State<'_, AppState>) -> Result<SplatExport, TopographError> {
+    let rules = rules.unwrap_or_default();
+    rules.validate().map_err(TopographError::invalid)?;
+    let hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    derived.refresh(&hm, &world_scale, boundary);
+    let weights = splatmap::weights(&hm, derived.slope(), &world_scale, boundary, &rules);
+    let (width, height) = (hm.width, hm.height);
+    drop(derived);
+    drop(hm);
+
+    let export = splatmap::export(std::path::Path::new(&path), width, height, &rules, &weights).map_err(TopographError::io)?;
+    state.usage.lock().unwrap().record_export("splatmap");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "splatmap", "png", &export.written).map_err(TopographError::script)?;
+    Ok(export)
+}
+
+/// Fill every depression up to its spill point, see `hydrology::priority_flood`,
+/// leaving a surface water can drain off from anywhere. Returns the heightmap,
+/// followed with `lake_depth` by how far each pixel was raised, i.e. the depth of
+/// the lakes the basins would hold, packed with `ipc::pack_full_set`.
+#[tauri::command(async)]
+pub fn fill_sinks(
+    epsilon: f32,
+    lake_depth: bool,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<Response, TopographError> {
+    if !(0.0..=0.001).contains(&epsilon) {
+        return Err(TopographError::invalid("Fill epsilon must be between 0 and 0.001"));
+    }
+    let boundary = *state.boundary.lock().unwrap();
+    let mut hm = state.heightmap.lock().unwrap();
+    let tracker = ProgressTracker::new(Stage::Sinks, channel);
+    let flood = hydrology::priority_flood(&hm, boundary, epsilon, &|fraction| tracker.report(fraction));
+    let mut depth = Heightmap::new(hm.width, hm.height);
+    for ((d, &filled), &original) in depth.data.iter_mut().zip(&flood.filled).zip(&hm.data) {
+        *d = filled - original;
+    }
+    hm.data = flood.filled;
+    let args = serde_json::json!({ "epsilon": epsilon, "lakeDepth": lake_depth });
+    state.operations.lock().unwrap().record("fill_sinks", args, &hm, false);
+    tracker.report(1.0);
+
+    let maps: &[&Heightmap] = if lake_depth { &[&hm, &depth] } else { &[&hm] };
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full_set(maps)))
+}
+
+/// Carve a river network along the current terrain's drainage, see
+/// `rivers::carve`. Returns the carved heightmap followed by the channel map,
+/// packed with `ipc::pack_full_set`.
+#[tauri::command(async)]
+pub fn carve_rivers(
+    params: RiverParams,
+    state: State<'_, AppState>,
+    channel: Channel<Progress>,
+) -> Result<Response, TopographError> {
+    if params.threshold < 1.0 {
+        return Err(TopographError::invalid("River threshold must be at least one pixel"));
+    }
+    if params.width <= 0.0 || params.max_width < params.width {
+        return Err(TopographError::invalid("River width must be positive and at most the maximum width"));
+    }
+    if params.depth < 0.0 {
+        return Err(TopographError::invalid("River depth must not be negative"));
+    }
+    let boundary = *state.boundary.lock().unwrap();
+    let mut hm = state.heightmap.lock().unwrap();
+    let tracker = ProgressTracker::new(Stage::Rivers, channel);
+    let channels = rivers::carve(&mut hm, &params, boundary, &|fraction| tracker.report(fraction));
+    state.operations.lock().unwrap().record("carve_rivers", serde_json::json!({ "params": params }), &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full_set(&[&hm, &channels])))
+}
+
+/// Simulate snow on the current terrain into the snow mask channel and return it.
+/// None settles on the water mask.
+#[tauri::command(async)]
+pub fn run_snow(params: SnowParams, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    if params.snowfall <= 0.0 || params.transition < 0.0 {
+        return Err(TopographError::invalid("Snowfall must be positive and the transition non-negative"));
+    }
+    if !(0.0..90.0).contains(&params.max_slope) {
+        return Err(TopographError::invalid("Maximum snow slope must be between 0 and 90 degrees"));
+    }
+    let hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut masks = state.masks.lock().unwrap();
+    let water = masks
+        .get(MaskChannel::Water)
+        .filter(|m| m.width == hm.width && m.height == hm.height)
+        .map(|m| m.data.as_slice());
+    let depth = snow::simulate(&hm, &params, &world_scale, boundary, water);
+    state.operations.lock().unwrap().record("run_snow", serde_json::json!({ "params": params }), &hm, false);
+
+    let mask = masks.get_or_create(MaskChannel::Snow, hm.width, hm.height);
+    *mask = snow::coverage(&depth, &params);
+    document_changed(&state);
+    Ok(Response::new(ipc::pack_full(mask)))
+}
+
+/// Export the snow cover from `run_snow` as a 16-bit grayscale PNG.
+#[tauri::command]
+pub fn export_snow_map(path: String, state: State<'_, AppState>) -> Result<(), TopographError> {
+    {
+        let hm = state.heightmap.lock().unwrap();
+        let masks = state.masks.lock().unwrap();
+        let snow = masks
+            .get(MaskChannel::Snow)
+            .filter(|m| m.width == hm.width && m.height == hm.height)
+            .ok_or_else(|| TopographError::not_found("No snow cover; run the snow simulation first"))?;
+        project::export_heightmap_png16(std::path::Path::new(&path), snow).map_err(TopographError::io)?;
+    }
+    state.usage.lock().unwrap().record_export("snowMap");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "snowMap", "png", &[path.into()]).map_err(TopographError::script)
+}
+
+/// Export the terrain as a watertight binary STL for 3D printing, see `stl::write`.
+#[tauri::command(async)]
+pub fn export_stl(path: String, params: StlParams, state: State<'_, AppState>) -> Result<(), TopographError> {
+    params.validate().map_err(TopographError::invalid)?;
+    {
+        let hm = state.heightmap.lock().unwrap();
+        stl::write(std::path::Path::new(&path), &hm, &params).map_err(TopographError::io)?;
+    }
+    state.usage.lock().unwrap().record_export("stl");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "stl", "stl", &[path.into()]).map_err(TopographError::script)
+}
+
+/// Per-pixel slope in degrees, in the full-heightmap binary format.
+#[tauri::command]
+pub fn get_slope_map(state: State<'_, AppState>) -> Response {
+    let hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    derived.refresh(&hm, &world_scale, boundary);
+    Response::new(ipc::pack_full(derived.slope()))
+}
+
+/// Recorded maps, as long as they still match the heightmap's size.
+fn current_erosion_maps<'a>(
+    maps: &'a Option<ErosionMaps>,
+    hm: &Heightmap,
+) -> Result<&'a ErosionMaps, TopographError> {
+    maps.as_ref()
+        .filter(|m| m.dimensions() == (hm.width, hm.height))
+        .ok_or_else(|| TopographError::not_found("No erosion maps; run hydraulic erosion with recordMaps first"))
+}
+
+/// One erosion map from the last recording hydraulic run, scaled to [0, 1] as in
+/// the PNG export, in the full-heightmap binary format.
+#[tauri::command]
+pub fn get_erosion_map(kind: ErosionMapKind, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let hm = state.heightmap.lock().unwrap();
+    let maps = state.erosion_maps.lock().unwrap();
+    let maps = current_erosion_maps(&maps, &hm)?;
+    Ok(Response::new(ipc::pack_full(&maps.normalized(kind))))
+}
+
+/// Write every erosion map as a 16-bit PNG next to `path`, e.g. `terrain_flow.png`.
+/// Returns the written paths.
+#[tauri::command]
+pub fn export_erosion_maps(path: String, state: State<'_, AppState>) -> Result<Vec<String>, TopographError> {
+    let p = std::path::Path::new(&path);
+    let mut written = Vec::new();
+    {
+        let hm = state.heightmap.lock().unwrap();
+        let maps = state.erosion_maps.lock().unwrap();
+        let maps = current_erosion_maps(&maps, &hm)?;
+        for kind in ErosionMapKind::ALL {
+            let map_path = project::sidecar_path(p, &format!("_{}", kind.id()), "png");
+            project::export_heightmap_png16(&map_path, &maps.normalized(kind)).map_err(TopographError::io)?;
+            written.push(map_path);
+        }
+    }
+    state.usage.lock().unwrap().record_export("erosionMaps");
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, "erosionMaps", "png", &written).map_err(TopographError::script)?;
+    Ok(written.iter().map(|p| p.display().to_string()).collect())
+}
+
+/// Write the slope or aspect map, for texture masks or GIS. Degrees keep values
+/// beyond [0, 1], so they need a float format.
+#[tauri::command]
+pub fn export_gradient_map(
+    path: String,
+    map: GradientMap,
+    unit: MapUnit,
+    format: String,
+    state: State<'_, AppState>,
+) -> Result<(), TopographError> {
+    if project::tile_extension(&format).is_none() {
+        return Err(TopographError::invalid(format!("Unknown export format: {format}")));
+    }
+    if unit == MapUnit::Degrees && format == "png16" {
+        return Err(TopographError::invalid("Maps in degrees need a float format; use EXR, TIFF or RAW"));
+    }
+    let p = std::path::Path::new(&path);
+    {
+        let hm = state.heightmap.lock().unwrap();
+        let world_scale = state.world_scale.lock().unwrap().clone();
+        let boundary = *state.boundary.lock().unwrap();
+        let mut derived = state.derived.lock().unwrap();
+        derived.refresh(&hm, &world_scale, boundary);
+        let values = derived.gradient_map(map, unit);
+        drop(derived);
+        project::export_heightmap_as(p, &values, &format).map_err(TopographError::io)?;
+    }
+    let kind = match map {
+        GradientMap::Slope => "slopeMap",
+        GradientMap::Aspect => "aspectMap",
+    };
+    state.usage.lock().unwrap().record_export(kind);
+
+    let export_hooks = state.export_hooks.lock().unwrap();
+    hooks::run_all(&export_hooks, kind, &format, &[p.to_path_buf()]).map_err(TopographError::script)?;
+    Ok(())
+}
+
+/// Tangent-space normal map PNG (OpenGL convention: green points north).
+#[tauri::command]
+pub fn get_normal_map(state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    derived.refresh(&hm, &world_scale, boundary);
+    let img = derived.normal_map();
+    drop(derived);
+    drop(hm);
+
+    Ok(Response::new(render::encode_png(&img)?))
+}
+
+/// Copy a PNG of `map` to the OS clipboard, its longer side capped at
+/// `max_size` (`clipboard::DEFAULT_SIZE` if omitted).
+#[tauri::command(async)]
+pub fn copy_map_to_clipboard(
+    map: ClipboardMap,
+    max_size: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<(), TopographError> {
+    let max_size = max_size.unwrap_or(clipboard::DEFAULT_SIZE);
+    if !(16..=canvas::MAX_CANVAS_SIZE).contains(&max_size) {
+        return Err(TopographError::invalid(format!("Image size must be between 16 and {}", canvas::MAX_CANVAS_SIZE)));
+    }
+    let hm = state.heightmap.lock().unwrap();
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    if map == ClipboardMap::Normal {
+        derived.refresh(&hm, &world_scale, boundary);
+    }
+    let patches = state.detail_patches.lock().unwrap();
+    let img = clipboard::render(&Surface::new(&hm, &patches), &derived, map, max_size);
+    drop(patches);
+    drop(derived);
+    drop(hm);
+
+    clipboard::copy_png(&render::encode_png(&img)?).map_err(TopographError::io)
+}
+
+/// Downsampled heightmap; level 1 is half resolution.
+#[tauri::command]
+pub fn get_heightmap_mip(level: usize, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let hm = state.heightmap.lock().unwrap();
+    if level == 0 {
+        return Ok(Response::new(ipc::pack_full(&hm)));
+    }
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let boundary = *state.boundary.lock().unwrap();
+    let mut derived = state.derived.lock().unwrap();
+    derived.refresh(&hm, &world_scale, boundary);
+    let mip = derived.mip(level).ok_or_else(|| TopographError::not_found(format!("No mip level {level}")))?;
+    Ok(Response::new(ipc::pack_full(mip)))
+}
+
+#[tauri::command]
+pub fn get_world_scale(state: State<'_, AppState>) -> WorldScale {
+    state.world_scale.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_world_scale(world_scale: WorldScale, state: State<'_, AppState>) -> Result<(), TopographError> {
+    // Written so NaN fails each check
+    if !(world_scale.meters_per_pixel.is_finite() && world_scale.meters_per_pixel > 0.0) {
+        return Err(TopographError::invalid("Meters per pixel must be positive"));
+    }
+    let span = world_scale.max_elevation - world_scale.min_elevation;
+    if !(world_scale.min_elevation.is_finite() && world_scale.max_elevation.is_finite() && span.is_finite()) {
+        return Err(TopographError::invalid("Elevations must be finite numbers"));
+    }
+    if world_scale.max_elevation <= world_scale.min_elevation {
+        return Err(TopographError::invalid("Max elevation must be above min elevation"));
+    }
+    *state.world_scale.lock().unwrap() = world_scale;
+    document_changed(&state);
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_boundary(state: State<'_, AppState>) -> Boundary {
+    *state.boundary.lock().unwrap()
+}
+
+#[tauri::command]
+pub fn set_boundary(boundary: Boundary, state: State<'_, AppState>) -> Result<(), TopographError> {
+    if let Boundary::Fixed { value } = boundary {
+        if !(0.0..=1.0).contains(&value) {
+            return Err(TopographError::invalid("Fixed boundary height must be between 0 and 1"));
+        }
+    }
+    *state.boundary.lock().unwrap() = boundary;
+    document_changed(&state);
+    Ok(())
+}
+
+/// Elevation in meters at a pixel position, bilinearly interpolated.
+#[tauri::command]
+pub fn sample_height(x: f32, y: f32, state: State<'_, AppState>) -> f32 {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let hm = state.heightmap.lock().unwrap();
+    world_scale.elevation(hm.sample(x, y))
+}
+
+/// Elevations in meters at several pixel positions, in order.
+#[tauri::command]
+pub fn sample_heights(points: Vec<[f32; 2]>, state: State<'_, AppState>) -> Vec<f32> {
+    let world_scale = state.world_scale.lock().unwrap().clone();
+    let hm = state.heightmap.lock().unwrap();
+    points
+        .iter()
+        .map(|p| world_scale.elevation(hm.sample(p[0], p[1])))
+        .collect()
+}
+
+#[tauri::command]
+pub fn get_mask(channel: MaskChannel, state: State<'_, AppState>) -> Response {
+    let (width, height) = {
+        let hm = state.heightmap.lock().unwrap();
+        (hm.width, hm.height)
+    };
+    let mut masks = state.masks.lock().unwrap();
+    let mask = masks.get_or_create(channel, width, height);
+    Response::new(ipc::pack_full(mask))
+}
+
+#[tauri::command]
+pub fn paint_mask(
+    channel: MaskChannel,
+    stroke: MaskStroke,
+    state: State<'_, AppState>,
+) -> Response {
+    let (width, height) = {
+        let hm = state.heightmap.lock().unwrap();
+        (hm.width, hm.height)
+    };
+    let mut masks = state.masks.lock().unwrap();
+    let mask = masks.get_or_create(channel, width, height);
+    let (rx, ry, rw, rh) = mask::paint(mask, &stroke);
+    document_changed(&state);
+    if rw == 0 || rh == 0 {
+        return Response::new(ipc::pack_full(mask));
+    }
+    Response::new(ipc::pack_region(mask, rx, ry, rw, rh))
+}
+
+#[tauri::command]
+pub fn clear_mask(channel: MaskChannel, state: State<'_, AppState>) {
+    state.masks.lock().unwrap().clear(channel);
+    document_changed(&state);
+}
+
+/// Fill a mask channel from a grayscale PNG on disk, resized to the heightmap.
+#[tauri::command]
+pub fn import_mask(channel: MaskChannel, path: String, state: State<'_, AppState>) -> Result<Response, TopographError> {
+    let png = std::fs::read(&path).map_err(|e| TopographError::io(format!("Failed to read {path}: {e}")))?;
+    let (width, height) = {
+        let hm = state.heightmap.lock().unwrap();
+        (hm.width, hm.height)
+    };
+    let weights = mask::decode_png(&png, width, height).map_err(TopographError::format)?;
+    let mut masks = state.masks.lock().unwrap();
+    let mask = masks.get_or_create(channel, width, height);
+    mask.data = weights;
+    document_changed(&state);
+    Ok(Response::new(ipc::pack_full(mask)))
+}
+
+#[tauri::command]
+pub fn configure_tile_grid(
+    tiles_x: u32,
+    tiles_y: u32,
+    base_seed: u32,
+    state: State<'_, AppState>,
+) -> Result<TileGrid, TopographError> {
+    let hm = state.heightmap.lock().unwrap();
+    if tiles_x == 0 || tiles_y == 0 || tiles_x > hm.width || tiles_y > hm.height {
+        return Err(TopographError::invalid(format!("Invalid tile grid {tiles_x}x{tiles_y}")));
+    }
+    drop(hm);
+
+    let grid = TileGrid::new(tiles_x, tiles_y, base_seed);
+    *state.tile_grid.lock().unwrap() = Some(grid.clone());
+    Ok(grid)
+}
+
+#[tauri::command]
+pub fn get_tile_grid(state: State<'_, AppState>) -> Option<TileGrid> {
+    state.tile_grid.lock().unwrap().clone()
+}
+
+#[tauri::command]
+pub fn set_tile_lock(
+    tx: u32,
+    ty: u32,
+    locked: bool,
+    state: State<'_, AppState>,
+) -> Result<(), TopographError> {
+    let mut guard = state.tile_grid.lock().unwrap();
+    let grid = guard.as_mut().ok_or_else(|| TopographError::not_found("No tile grid configured"))?;
+    let idx = grid.index(tx, ty).ok_or_else(|| TopographError::not_found(format!("Tile ({tx}, {ty}) out of range")))?;
+    grid.locked[idx] = locked;
+    Ok(())
+}
+
+/// Regenerate a single tile. Unlocked tiles get `seed` or a fresh random seed;
+/// locked tiles always regenerate with their stored seed.
+#[tauri::command]
+pub fn regenerate_tile(
+    tx: u32,
+    ty: u32,
+    params: NoiseParams,
+    seed: Option<u32>,
+    blend_width: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    let mut hm = state.heightmap.lock().unwrap();
+    let mut guard = state.tile_grid.lock().unwrap();
+    let grid = guard.as_mut().ok_or_else(|| TopographError::not_found("No tile grid configured"))?;
+    let idx = grid.index(tx, ty).ok_or_else(|| TopographError::not_found(format!("Tile ({tx}, {ty}) out of range")))?;
+
+    if grid.locked[idx] {
+        if seed.is_some() {
+            return Err(TopographError::invalid(format!("Tile ({tx}, {ty}) has a locked seed")));
+        }
+    } else {
+        grid.seeds[idx] = seed.unwrap_or_else(rand::random);
+    }
+
+    let (rx, ry, rw, rh) = tiles::regenerate_tile(
+        &mut hm,
+        grid,
+        tx,
+        ty,
+        grid.seeds[idx],
+        &params,
+        blend_width.unwrap_or(16),
+    );
+    // Locked tiles reject a `seed`; unlocked ones replay with the one drawn here
+    let seed = (!grid.locked[idx]).then_some(grid.seeds[idx]);
+    let args = serde_json::json!({ "tx": tx, "ty": ty, "params": params, "seed": seed, "blendWidth": blend_width });
+    state.operations.lock().unwrap().record("regenerate_tile", args, &hm, false);
+    state.edits.region((rx, ry, rw, rh));
+    Ok(Response::new(ipc::pack_region(&hm, rx, ry, rw, rh)))
+}
+
+/// Reseed every unlocked tile and regenerate the whole map from the tile seeds;
+/// locked tiles reproduce their previous content for unchanged params.
+#[tauri::command]
+pub fn regenerate_unlocked_tiles(
+    params: NoiseParams,
+    blend_width: Option<u32>,
+    state: State<'_, AppState>,
+) -> Result<Response, TopographError> {
+    let mut hm = state.heightmap.lock().unwrap();
+    let mut guard = state.tile_grid.lock().unwrap();
+    let grid = guard.as_mut().ok_or_else(|| TopographError::not_found("No tile grid configured"))?;
+
+    for (seed, &locked) in grid.seeds.iter_mut().zip(grid.locked.iter()) {
+        if !locked {
+            *seed = rand::random();
+        }
+    }
+    tiles::generate_tiled(&mut hm, grid, &params, blend_width.unwrap_or(16));
+    let args = serde_json::json!({ "params": params, "blendWidth": blend_width });
+    state.operations.lock().unwrap().record("regenerate_unlocked_tiles", args, &hm, false);
+    state.edits.whole();
+    Ok(Response::new(ipc::pack_full(&hm)))
+}
+
+#[tauri::command]
+pub fn add_export_hook(path: String, state: State<'_, AppState>) -> Result<Vec<ExportHookInfo>, TopographError> {
+    let hook = ExportHook::load(std::path::Path::new(&path)).map_err(TopographError::script)?;
+    let mut export_hooks = state.export_hooks.lock().unwrap();
+    // Re-adding a script reloads it in place
+    match export_hooks.iter_mut().find(|h| h.path == hook.path) {
+        Some(existing) => *existing = hook,
+        None => export_hooks.push(hook),
+    }
+    Ok(export_hooks.iter().map(ExportHook::info).collect())
+}
+
+#[tauri::command]
+pub fn list_export_hooks(state: State<'_, AppState>) -> Vec<ExportHookInfo> {
+    state.export_hooks.lock().unwrap().iter().map(ExportHook::info).collect()
+}
+
+#[tauri::command]
+pub fn remove_export_hook(path: String, state: State<'_, AppState>) -> Vec<ExportHookInfo> {
+    let mut export_hooks = state.export_hooks.lock().unwrap();
+    export_hooks.retain(|h| h.path != std::path::Path::new(&path));
+    export_hooks.iter().map(ExportHook::info).collect()
+}
+
+/// Run the golden-terrain suite; mismatching results are written into `output_dir`.
+#[cfg(feature = "golden")]
+#[tauri::command(async)]
+pub fn run_golden_suite(output_dir: Option<String>) -> Result<Vec<CaseResult>, TopographError> {
+    Ok(golden::check(output_dir.as_deref().map(std::path::Path::new))?)
+}
+
+/// Overwrite the recorded golden fingerprints with the current output.
+#[cfg(feature = "golden")]
+#[tauri::command(async)]
+pub fn record_golden_suite() -> Result<usize, TopographError> {
+    Ok(golden::record()?)
+}
+
+#[tauri::command]
+pub fn get_locale() -> Locale {
+    i18n::locale()
+}
+
+#[tauri::command]
+pub fn list_locales() -> Vec<LocaleInfo> {
+    i18n::available()
+}
+
+/// Switch the backend locale and rebuild the menu in it.
+#[tauri::command]
+pub fn set_locale(locale: Locale, app_handle: AppHandle) -> Result<(), TopographError> {
+    i18n::set_locale(locale);
+    crate::refresh_menu(&app_handle).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// The recent projects list lives in the app config directory.
+fn recent_dir(app_handle: &AppHandle) -> Result<std::path::PathBuf, TopographError> {
+    app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| TopographError::io(format!("No config directory for recent projects: {e}")))
+}
+
+/// Put a project just opened or saved at the top of Open Recent. A list that
+/// can't be updated doesn't fail the save or load.
+fn remember_project(app_handle: &AppHandle, path: &str) {
+    let Ok(dir) = recent_dir(app_handle) else {
+        return;
+    };
+    if recent::add(&dir, path).is_ok() {
+        let _ = crate::refresh_menu(app_handle);
+    }
+}
+
+/// Projects recently opened or saved that still exist, most recent first.
+#[tauri::command]
+pub fn list_recent_projects(app_handle: AppHandle) -> Result<Vec<String>, TopographError> {
+    Ok(recent::list(&recent_dir(&app_handle)?))
+}
+
+#[tauri::command]
+pub fn clear_recent_projects(app_handle: AppHandle) -> Result<(), TopographError> {
+    recent::clear(&recent_dir(&app_handle)?).map_err(TopographError::io)?;
+    crate::refresh_menu(&app_handle).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[tauri::command]
+pub fn get_usage_stats(state: State<'_, AppState>) -> UsageStats {
+    state.usage.lock().unwrap().clone()
+}
+
+/// Turn usage tracking for the open project on or off. Counters are kept either way.
+#[tauri::command]
+pub fn set_usage_tracking(enabled: bool, state: State<'_, AppState>) -> UsageStats {
+    let mut usage = state.usage.lock().unwrap();
+    usage.set_enabled(enabled);
+    usage.clone()
+}
+
+#[tauri::command]
+pub fn reset_usage_stats(state: State<'_, AppState>) -> UsageStats {
+    let mut usage = state.usage.lock().unwrap();
+    usage.reset();
+    usage.clone()
+}
+
+/// Presets live in the app config directory, shared by every project.
+fn preset_store(app_handle: &AppHandle) -> Result<PresetStore, TopographError> {
+    let dir = app_handle
+        .path()
+        .app_config_dir()
+        .map_err(|e| TopographError::io(format!("No config directory for presets: {e}")))?;
+    Ok(PresetStore::new(dir.join("presets")))
+}
+
+#[tauri::command]
+pub fn list_presets(kind: PresetKind, app_handle: AppHandle) -> Result<Vec<String>, TopographError> {
+    preset_store(&app_handle)?.list(kind).map_err(TopographError::io)
+}
+
+/// Save `params` as a preset, replacing any of the same name and kind. The
+/// parameters must be valid for that kind. Returns the presets of that kind.
+#[tauri::command]
+pub fn save_preset(
+    kind: PresetKind,
+    name: String,
+    params: serde_json::Value,
+    app_handle: AppHandle,
+) -> Result<Vec<String>, TopographError> {
+    presets::validate_name(&name).map_err(TopographError::invalid)?;
+    kind.check(&params).map_err(TopographError::invalid)?;
+    let store = preset_store(&app_handle)?;
+    store.save(kind, &name, &params).map_err(TopographError::io)?;
+    store.list(kind).map_err(TopographError::io)
+}
+
+#[tauri::command]
+pub fn delete_preset(kind: PresetKind, name: String, app_handle: AppHandle) -> Result<Vec<String>, TopographError> {
+    presets::validate_name(&name).map_err(TopographError::invalid)?;
+    let store = preset_store(&app_handle)?;
+    if !store.delete(kind, &name).map_err(TopographError::io)? {
+        return Err(TopographError::not_found(format!("No preset named {name:?}")));
+    }
+    store.list(kind).map_err(TopographError::io)
+}
+
+/// A preset's parameters, for the frontend to load into its controls. A preset
+/// edited by hand into something the commands would reject is a format error.
+#[tauri::command]
+pub fn apply_preset(kind: PresetKind, name: String, app_handle: AppHandle) -> Result<Preset, TopographError> {
+    presets::validate_name(&name).map_err(TopographError::invalid)?;
+    preset_store(&app_handle)?
+        .load(kind, &name)
+        .map_err(TopographError::format)?
+        .ok_or_else(|| TopographError::not_found(format!("No preset named {name:?}")))
 }
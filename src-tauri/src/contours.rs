@@ -0,0 +1,232 @@
+//! Contour lines traced with marching squares and written as vector files: SVG
+//! for map-making and laser cutting, GeoJSON for GIS.
+
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::path::Path;
+use rayon::prelude::*;
+use serde::Deserialize;
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub enum ContourFormat {
+    #[default]
+    #[serde(rename = "svg")]
+    Svg,
+    #[serde(rename = "geojson")]
+    GeoJson,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ContourParams {
+    /// Spacing in meters; contours sit at whole multiples of it.
+    pub interval: f32,
+    /// Every this many contours is an index contour, drawn heavier in SVG and
+    /// flagged in GeoJSON; 0 for none.
+    pub index_every: u32,
+    pub format: ContourFormat,
+}
+
+impl Default for ContourParams {
+    fn default() -> Self {
+        Self {
+            interval: 50.0,
+            index_every: 5,
+            format: ContourFormat::Svg,
+        }
+    }
+}
+
+/// Most contour levels one export may trace.
+pub const MAX_LEVELS: usize = 2000;
+
+/// All the lines at one elevation, in pixel coordinates. Closed rings repeat
+/// their first point at the end.
+pub struct Contour {
+    pub elevation: f32,
+    pub index: bool,
+    pub lines: Vec<Vec<[f32; 2]>>,
+}
+
+/// Trace a contour at every multiple of the interval within the map's range.
+pub fn trace(hm: &Heightmap, world: &WorldScale, params: &ContourParams) -> Result<Vec<Contour>, String> {
+    if !(params.interval.is_finite() && params.interval > 0.0) {
+        return Err("Contour interval must be positive".into());
+    }
+    let relief = world.max_elevation - world.min_elevation;
+    if relief <= 0.0 {
+        return Err("The world scale has no relief to contour".into());
+    }
+    let (lo, hi) = hm.data.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let first = (world.elevation(lo) / params.interval).ceil() as i64;
+    let last = (world.elevation(hi) / params.interval).floor() as i64;
+    if last - first + 1 > MAX_LEVELS as i64 {
+        return Err(format!("Interval gives more than {MAX_LEVELS} contours; use a larger one"));
+    }
+
+    Ok((first..=last)
+        .into_par_iter()
+        .map(|k| {
+            let elevation = k as f32 * params.interval;
+            Contour {
+                elevation,
+                index: params.index_every > 0 && k.rem_euclid(params.index_every as i64) == 0,
+                lines: trace_level(hm, (elevation - world.min_elevation) / relief),
+            }
+        })
+        .filter(|c| !c.lines.is_empty())
+        .collect())
+}
+
+/// Polylines where the surface crosses `level`. Crossings are keyed by the grid
+/// edge they lie on, horizontal edges even and vertical odd; each is shared by
+/// the two cells either side, which is how segments are chained together.
+fn trace_level(hm: &Heightmap, level: f32) -> Vec<Vec<[f32; 2]>> {
+    let (w, h) = (hm.width as usize, hm.height as usize);
+    let at = |x: usize, y: usize| hm.data[y * w + x];
+    let horizontal = |x: usize, y: usize| 2 * (y * w + x);
+    let vertical = |x: usize, y: usize| 2 * (y * w + x) + 1;
+    let crossing = |a: f32, b: f32| (level - a) / (b - a);
+
+    let mut points: HashMap<usize, [f32; 2]> = HashMap::new();
+    let mut segments: Vec<[usize; 2]> = Vec::new();
+    for y in 0..h.saturating_sub(1) {
+        for x in 0..w.saturating_sub(1) {
+            let (a, b, c, d) = (at(x, y), at(x + 1, y), at(x + 1, y + 1), at(x, y + 1));
+            let case = [a, b, c, d].iter().fold(0u8, |case, &v| case << 1 | (v >= level) as u8);
+            if case == 0 || case == 15 {
+                continue;
+            }
+            let (top, right, bottom, left) = (horizontal(x, y), vertical(x + 1, y), horizontal(x, y + 1), vertical(x, y));
+            // Saddles follow the cell's mean: inside corners joined across the
+            // center when it is inside too
+            let center_inside = (a + b + c + d) / 4.0 >= level;
+            let pairs: &[[usize; 2]] = match case {
+                1 | 14 => &[[left, bottom]],
+                2 | 13 => &[[bottom, right]],
+                3 | 12 => &[[left, right]],
+                4 | 11 => &[[top, right]],
+                6 | 9 => &[[top, bottom]],
+                7 | 8 => &[[top, left]],
+                5 if center_inside => &[[top, left], [right, bottom]],
+                5 => &[[top, right], [left, bottom]],
+                10 if center_inside => &[[top, right], [left, bottom]],
+                _ => &[[top, left], [right, bottom]],
+            };
+            for &[e0, e1] in pairs {
+                for e in [e0, e1] {
+                    points.entry(e).or_insert_with(|| match e {
+                        e if e == top => [x as f32 + crossing(a, b), y as f32],
+                        e if e == bottom => [x as f32 + crossing(d, c), (y + 1) as f32],
+                        e if e == left => [x as f32, y as f32 + crossing(a, d)],
+                        _ => [(x + 1) as f32, y as f32 + crossing(b, c)],
+                    });
+                }
+                segments.push([e0, e1]);
+            }
+        }
+    }
+
+    let mut by_edge: HashMap<usize, Vec<usize>> = HashMap::new();
+    for (i, s) in segments.iter().enumerate() {
+        for e in s {
+            by_edge.entry(*e).or_default().push(i);
+        }
+    }
+    let mut used = vec![false; segments.len()];
+    let mut lines = Vec::new();
+    // Open lines end on the map border at an edge only one segment touches;
+    // starting there keeps them whole. Whatever remains is closed rings.
+    let mut starts: Vec<(usize, usize)> = by_edge
+        .iter()
+        .filter(|(_, s)| s.len() == 1)
+        .map(|(&e, s)| (s[0], e))
+        .collect();
+    starts.sort_unstable();
+    starts.extend(segments.iter().enumerate().map(|(i, s)| (i, s[0])));
+    for (start, edge) in starts {
+        if used[start] {
+            continue;
+        }
+        let mut line = vec![points[&edge]];
+        let (mut segment, mut edge) = (start, edge);
+        loop {
+            used[segment] = true;
+            let [e0, e1] = segments[segment];
+            edge = if e0 == edge { e1 } else { e0 };
+            line.push(points[&edge]);
+            match by_edge[&edge].iter().find(|&&s| !used[s]) {
+                Some(&next) => segment = next,
+                None => break,
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// Write `contours` in the chosen format.
+pub fn write(path: &Path, hm: &Heightmap, world: &WorldScale, contours: &[Contour], format: ContourFormat) -> Result<(), String> {
+    let text = match format {
+        ContourFormat::Svg => svg(hm, contours),
+        ContourFormat::GeoJson => geojson(hm, world, contours)?,
+    };
+    std::fs::write(path, text).map_err(|e| format!("Failed to write contours: {e}"))
+}
+
+/// One path per elevation in pixel units, so the drawing overlays a heightmap
+/// image of the same size. Samples sit at pixel centers.
+fn svg(hm: &Heightmap, contours: &[Contour]) -> String {
+    let mut out = String::new();
+    let _ = writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+    let _ = writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{0}" height="{1}" viewBox="0 0 {0} {1}">"#,
+        hm.width, hm.height
+    );
+    let _ = writeln!(out, r#"<g fill="none" stroke="black" stroke-linecap="round" stroke-linejoin="round">"#);
+    for contour in contours {
+        let width = if contour.index { 1.0 } else { 0.5 };
+        let _ = write!(out, r#"<path data-elevation="{}" stroke-width="{width}" d=""#, contour.elevation);
+        for line in &contour.lines {
+            for (i, [x, y]) in line.iter().enumerate() {
+                let command = if i == 0 { 'M' } else { 'L' };
+                let _ = write!(out, "{command}{:.2} {:.2}", x + 0.5, y + 0.5);
+            }
+        }
+        let _ = writeln!(out, r#""/>"#);
+    }
+    let _ = writeln!(out, "</g>");
+    let _ = writeln!(out, "</svg>");
+    out
+}
+
+/// A FeatureCollection with one MultiLineString per elevation. Coordinates are
+/// meters east and north of the bottom-left sample.
+fn geojson(hm: &Heightmap, world: &WorldScale, contours: &[Contour]) -> Result<String, String> {
+    let bottom = (hm.height - 1) as f32;
+    let round = |v: f32| (v as f64 * 100.0).round() / 100.0;
+    let features: Vec<serde_json::Value> = contours
+        .iter()
+        .map(|contour| {
+            let lines: Vec<Vec<[f64; 2]>> = contour
+                .lines
+                .iter()
+                .map(|line| {
+                    line.iter()
+                        .map(|&[x, y]| [round(x * world.meters_per_pixel), round((bottom - y) * world.meters_per_pixel)])
+                        .collect()
+                })
+                .collect();
+            serde_json::json!({
+                "type": "Feature",
+                "properties": { "elevation": contour.elevation, "index": contour.index },
+                "geometry": { "type": "MultiLineString", "coordinates": lines },
+            })
+        })
+        .collect();
+    let collection = serde_json::json!({ "type": "FeatureCollection", "features": features });
+    serde_json::to_string(&collection).map_err(|e| format!("Failed to serialize contours: {e}"))
+}
@@ -0,0 +1,128 @@
+use std::path::{Path, PathBuf};
+use crate::project;
+
+const USAGE: &str = "\
+Usage: topograph --convert [--upgrade] [--export png16|raw_f32|exr|tiff32] [--out DIR] <PATH>...
+
+Batch-process .topo projects without opening the editor. PATH may be a project file
+or a folder, in which case every .topo file directly inside it is processed.
+
+  --upgrade       Rewrite projects in the current format version
+  --export FMT    Export each project's heightmap (png16, raw_f32, exr or tiff32)
+  --out DIR       Write results into DIR instead of next to each project";
+
+struct Options {
+    upgrade: bool,
+    export: Option<String>,
+    out_dir: Option<PathBuf>,
+    inputs: Vec<PathBuf>,
+}
+
+/// Entry point for `--convert`; `args` are the arguments after the flag.
+/// Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let options = match parse_args(args) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            return 2;
+        }
+    };
+
+    let projects = match collect_projects(&options.inputs) {
+        Ok(projects) => projects,
+        Err(e) => {
+            eprintln!("{e}");
+            return 1;
+        }
+    };
+    if projects.is_empty() {
+        eprintln!("No .topo files found");
+        return 1;
+    }
+    if let Some(dir) = &options.out_dir {
+        if let Err(e) = std::fs::create_dir_all(dir) {
+            eprintln!("Failed to create {}: {e}", dir.display());
+            return 1;
+        }
+    }
+
+    let mut failures = 0;
+    for path in &projects {
+        match convert_one(path, &options) {
+            Ok(()) => println!("ok      {}", path.display()),
+            Err(e) => {
+                eprintln!("failed  {}: {e}", path.display());
+                failures += 1;
+            }
+        }
+    }
+    println!("{} converted, {failures} failed", projects.len() - failures);
+    if failures > 0 { 1 } else { 0 }
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options { upgrade: false, export: None, out_dir: None, inputs: Vec::new() };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--upgrade" => options.upgrade = true,
+            "--export" => {
+                let format = iter.next().ok_or("--export needs a format")?;
+                if project::tile_extension(format).is_none() {
+                    return Err(format!("Unknown export format: {format}"));
+                }
+                options.export = Some(format.clone());
+            }
+            "--out" => options.out_dir = Some(iter.next().ok_or("--out needs a folder")?.into()),
+            flag if flag.starts_with("--") => return Err(format!("Unknown option: {flag}")),
+            path => options.inputs.push(path.into()),
+        }
+    }
+    if !options.upgrade && options.export.is_none() {
+        return Err("Nothing to do: pass --upgrade and/or --export".to_string());
+    }
+    if options.inputs.is_empty() {
+        return Err("No input paths given".to_string());
+    }
+    Ok(options)
+}
+
+fn collect_projects(inputs: &[PathBuf]) -> Result<Vec<PathBuf>, String> {
+    let mut projects = Vec::new();
+    for input in inputs {
+        if input.is_dir() {
+            let entries = std::fs::read_dir(input)
+                .map_err(|e| format!("Failed to read {}: {e}", input.display()))?;
+            let mut found: Vec<PathBuf> = entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|ext| ext == "topo"))
+                .collect();
+            found.sort();
+            projects.extend(found);
+        } else {
+            projects.push(input.clone());
+        }
+    }
+    Ok(projects)
+}
+
+fn convert_one(path: &Path, options: &Options) -> Result<(), String> {
+    let loaded = project::load_project(path, &|_| {})?;
+    let dir = options
+        .out_dir
+        .as_deref()
+        .or_else(|| path.parent())
+        .unwrap_or(Path::new("."));
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("project");
+
+    if options.upgrade {
+        project::save_project(&dir.join(format!("{stem}.topo")), &loaded.contents(), false, &|_| {})?;
+    }
+
+    if let Some(format) = options.export.as_deref() {
+        let extension = project::tile_extension(format).unwrap_or("r32");
+        project::export_heightmap_as(&dir.join(format!("{stem}.{extension}")), &loaded.heightmap, format)?;
+    }
+    Ok(())
+}
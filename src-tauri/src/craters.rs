@@ -0,0 +1,148 @@
+use noise::{NoiseFn, Perlin};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+/// Ejecta blanket reach in crater radii.
+const EJECTA_REACH: f32 = 3.0;
+
+/// A single impact crater stamped at a point.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CraterParams {
+    pub x: f32,
+    pub y: f32,
+    /// Rim-to-rim diameter in pixels.
+    pub diameter: f32,
+    /// Rim height above the surrounding terrain in meters.
+    pub rim_height: f32,
+    /// Floor depth below the surrounding terrain in meters.
+    pub floor_depth: f32,
+    /// Strength of the radial ejecta streaks, 0 for a clean blanket.
+    #[serde(default)]
+    pub ejecta: f32,
+    #[serde(default)]
+    pub seed: u32,
+}
+
+/// A scattered crater field. Diameters follow a power law, so small craters vastly
+/// outnumber large ones as on real cratered surfaces.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CraterFieldParams {
+    pub count: u32,
+    /// Diameter range in pixels.
+    pub min_diameter: f32,
+    pub max_diameter: f32,
+    /// Power-law exponent of the size distribution; around 2 for old surfaces.
+    pub size_exponent: f32,
+    /// Floor depth as a fraction of diameter (about 0.2 for simple craters).
+    pub depth_ratio: f32,
+    /// Rim height as a fraction of floor depth.
+    pub rim_ratio: f32,
+    #[serde(default)]
+    pub ejecta: f32,
+    pub seed: u32,
+}
+
+/// Stamp one crater on top of the existing terrain.
+/// Returns bounding box of affected region: (x, y, w, h).
+pub fn stamp(hm: &mut Heightmap, crater: &CraterParams, world: &WorldScale) -> (u32, u32, u32, u32) {
+    let radius = crater.diameter * 0.5;
+    if radius < 0.5 {
+        return (0, 0, 0, 0);
+    }
+
+    let range = (world.max_elevation - world.min_elevation).max(1e-6);
+    let rim = crater.rim_height / range;
+    let depth = crater.floor_depth / range;
+    let rays = Perlin::new(crater.seed);
+
+    let reach = radius * EJECTA_REACH;
+    let x0 = (crater.x - reach).floor().max(0.0) as u32;
+    let y0 = (crater.y - reach).floor().max(0.0) as u32;
+    let x1 = ((crater.x + reach).ceil().max(0.0) as u32).min(hm.width - 1);
+    let y1 = ((crater.y + reach).ceil().max(0.0) as u32).min(hm.height - 1);
+    if x0 > x1 || y0 > y1 {
+        return (0, 0, 0, 0);
+    }
+
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            let dx = px as f32 - crater.x;
+            let dy = py as f32 - crater.y;
+            let r = (dx * dx + dy * dy).sqrt() / radius;
+            if r >= EJECTA_REACH {
+                continue;
+            }
+
+            let offset = if r < 1.0 {
+                // Parabolic bowl rising from the floor to the rim crest
+                -depth + (depth + rim) * r * r
+            } else {
+                // Ejecta thins with the cube of distance and fades out at the reach
+                let fade = 1.0 - smoothstep(EJECTA_REACH - 1.0, EJECTA_REACH, r);
+                let mut blanket = rim / (r * r * r) * fade;
+                if crater.ejecta > 0.0 {
+                    let angle = dy.atan2(dx) as f64;
+                    let streak = rays.get([angle.cos() * 4.0, angle.sin() * 4.0 + r as f64 * 0.5]) as f32;
+                    blanket *= (1.0 + crater.ejecta * streak * 2.0).max(0.0);
+                }
+                blanket
+            };
+
+            let current = hm.get(px, py);
+            hm.set(px, py, (current + offset).clamp(0.0, 1.0));
+        }
+    }
+
+    (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+}
+
+/// Scatter a field of craters across the whole map.
+pub fn scatter(hm: &mut Heightmap, field: &CraterFieldParams, world: &WorldScale) {
+    let mut rng = StdRng::seed_from_u64(field.seed as u64);
+    let min_d = field.min_diameter.max(1.0);
+    let max_d = field.max_diameter.max(min_d);
+
+    let mut craters: Vec<CraterParams> = (0..field.count)
+        .map(|_| {
+            let diameter = power_law(rng.gen(), min_d, max_d, field.size_exponent);
+            let depth = diameter * field.depth_ratio * world.meters_per_pixel;
+            CraterParams {
+                x: rng.gen_range(0.0..hm.width as f32),
+                y: rng.gen_range(0.0..hm.height as f32),
+                diameter,
+                rim_height: depth * field.rim_ratio,
+                floor_depth: depth,
+                ejecta: field.ejecta,
+                seed: rng.gen(),
+            }
+        })
+        .collect();
+
+    // Large craters first so smaller, younger impacts overprint them
+    craters.sort_by(|a, b| b.diameter.total_cmp(&a.diameter));
+    for crater in &craters {
+        stamp(hm, crater, world);
+    }
+}
+
+/// Inverse-CDF sample of a truncated power law `p(d) ~ d^-exponent` on `[min, max]`.
+fn power_law(u: f32, min: f32, max: f32, exponent: f32) -> f32 {
+    let k = 1.0 - exponent;
+    if k.abs() < 1e-3 {
+        // exponent == 1 degenerates to log-uniform
+        return min * (max / min).powf(u);
+    }
+    let a = min.powf(k);
+    let b = max.powf(k);
+    (a + u * (b - a)).powf(1.0 / k)
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -0,0 +1,402 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use serde::Deserialize;
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+/// Tile edge in pixels for change tracking and incremental recomputation.
+const TILE_SIZE: u32 = 64;
+/// How often the scheduler looks for changes.
+const TICK: Duration = Duration::from_millis(400);
+/// Quiet period after the last edit before precomputation starts.
+const IDLE_DELAY: Duration = Duration::from_millis(800);
+/// Tiles recomputed per tick, keeping each heightmap lock short.
+const TILES_PER_TICK: usize = 16;
+/// Mips stop once both dimensions reach this size.
+const MIN_MIP_SIZE: u32 = 16;
+/// Edited regions kept apart before they count as one whole-map edit.
+const MAX_PENDING_EDITS: usize = 4096;
+
+/// Aspect of level ground, where there is no downhill direction; the usual GIS
+/// convention.
+pub const FLAT_ASPECT: f32 = -1.0;
+
+/// Map computed from the terrain gradient.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GradientMap {
+    /// Steepness, 0 to 90 degrees.
+    Slope,
+    /// Compass direction the ground faces, i.e. the downhill direction, 0 to 360
+    /// degrees clockwise from north (the top of the map).
+    Aspect,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MapUnit {
+    Degrees,
+    /// Degrees scaled into [0, 1]: slope over 90, aspect over 360. Level ground
+    /// has aspect 0.
+    Normalized,
+}
+
+/// Heightmap changes reported by the edit paths since the cache last looked.
+/// Kept apart from the cache so reporting an edit never waits on a
+/// recomputation.
+pub struct Edits {
+    pending: Mutex<PendingEdits>,
+}
+
+#[derive(Default)]
+struct PendingEdits {
+    /// Edited rectangles, `(x, y, width, height)` in pixels.
+    rects: Vec<(u32, u32, u32, u32)>,
+    /// The whole map was rewritten or replaced, possibly at another size.
+    whole: bool,
+}
+
+impl Edits {
+    /// Pixels inside `rect`, `(x, y, width, height)`, changed.
+    pub fn region(&self, (x, y, width, height): (u32, u32, u32, u32)) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        if pending.rects.len() >= MAX_PENDING_EDITS {
+            pending.whole = true;
+            pending.rects.clear();
+        }
+        if !pending.whole {
+            pending.rects.push((x, y, width, height));
+        }
+    }
+
+    /// The whole map was rewritten or replaced.
+    pub fn whole(&self) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.whole = true;
+        pending.rects.clear();
+    }
+
+    fn is_pending(&self) -> bool {
+        let pending = self.pending.lock().unwrap();
+        pending.whole || !pending.rects.is_empty()
+    }
+
+    fn take(&self) -> PendingEdits {
+        std::mem::take(&mut *self.pending.lock().unwrap())
+    }
+}
+
+/// Data derived from the heightmap that analysis panels and exports need often.
+/// Kept up to date per tile, so an edit only invalidates what it touched.
+pub struct DerivedCache {
+    width: u32,
+    height: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    hashes: Vec<u64>,
+    dirty: Vec<bool>,
+    world: Option<WorldScale>,
+    boundary: Boundary,
+    /// Unit surface normals (+x east, +y south, +z up) in world units.
+    normals: Vec<[f32; 3]>,
+    /// Slope in degrees, stored in a height grid so it packs like one.
+    slope: Heightmap,
+    /// 2x box-filtered levels; `mips[0]` is half resolution.
+    mips: Vec<Heightmap>,
+    mips_stale: bool,
+    last_change: Instant,
+    edits: Arc<Edits>,
+}
+
+impl DerivedCache {
+    pub fn new() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            tiles_x: 0,
+            tiles_y: 0,
+            hashes: Vec::new(),
+            dirty: Vec::new(),
+            world: None,
+            boundary: Boundary::default(),
+            normals: Vec::new(),
+            slope: Heightmap::new(0, 0),
+            mips: Vec::new(),
+            mips_stale: true,
+            last_change: Instant::now(),
+            // Nothing is computed yet
+            edits: Arc::new(Edits { pending: Mutex::new(PendingEdits { rects: Vec::new(), whole: true }) }),
+        }
+    }
+
+    /// Where edit paths report what they changed.
+    pub fn edits(&self) -> Arc<Edits> {
+        Arc::clone(&self.edits)
+    }
+
+    /// Bring everything up to date now. Cheap when the idle scheduler already has.
+    pub fn refresh(&mut self, hm: &Heightmap, world: &WorldScale, boundary: Boundary) {
+        self.apply_edits(hm, world, boundary);
+        self.compute_dirty_tiles(hm, usize::MAX);
+        if self.mips_stale {
+            self.rebuild_mips(hm);
+        }
+    }
+
+    pub fn slope(&self) -> &Heightmap {
+        &self.slope
+    }
+
+    /// Tangent-space normal map (OpenGL convention: green points north).
+    pub fn normal_map(&self) -> image::RgbImage {
+        let encode = |v: f32| ((v * 0.5 + 0.5) * 255.0).round() as u8;
+        let pixels: Vec<u8> = self
+            .normals
+            .iter()
+            .flat_map(|n| [encode(n[0]), encode(-n[1]), encode(n[2])])
+            .collect();
+        image::RgbImage::from_raw(self.width, self.height, pixels).expect("one normal per pixel")
+    }
+
+    /// Aspect in degrees per pixel, from the normals; `FLAT_ASPECT` where the
+    /// ground is level.
+    pub fn aspect(&self) -> Heightmap {
+        let mut aspect = Heightmap::new(self.width, self.height);
+        for (a, &[nx, ny, _]) in aspect.data.iter_mut().zip(&self.normals) {
+            // Normals lean downhill; +x is east and +y south
+            *a = if nx.hypot(ny) < 1e-6 {
+                FLAT_ASPECT
+            } else {
+                nx.atan2(-ny).to_degrees().rem_euclid(360.0)
+            };
+        }
+        aspect
+    }
+
+    /// Slope or aspect in `unit`, shaped like the heightmap.
+    pub fn gradient_map(&self, map: GradientMap, unit: MapUnit) -> Heightmap {
+        let (mut values, full_turn) = match map {
+            GradientMap::Slope => (self.slope.clone(), 90.0),
+            GradientMap::Aspect => (self.aspect(), 360.0),
+        };
+        if unit == MapUnit::Normalized {
+            for v in &mut values.data {
+                *v = (*v / full_turn).max(0.0);
+            }
+        }
+        values
+    }
+
+    /// Level 1 is half resolution; `None` past the smallest level.
+    pub fn mip(&self, level: usize) -> Option<&Heightmap> {
+        level.checked_sub(1).and_then(|i| self.mips.get(i))
+    }
+
+    /// When the heightmap was last seen to change.
+    pub fn last_change(&self) -> Instant {
+        self.last_change
+    }
+
+    /// Whether a scheduler tick has anything to do, checked without the heightmap.
+    fn has_work(&self, world: &WorldScale, boundary: Boundary) -> bool {
+        self.edits.is_pending()
+            || self.world.as_ref() != Some(world)
+            || self.boundary != boundary
+            || self.mips_stale
+            || self.dirty.contains(&true)
+    }
+
+    /// Rehash the tiles under the edits reported since the last call and flag the
+    /// ones whose content changed. Every tile is rehashed only after a whole-map
+    /// edit or a change of size, scale or boundary. Returns whether anything
+    /// changed.
+    fn apply_edits(&mut self, hm: &Heightmap, world: &WorldScale, boundary: Boundary) -> bool {
+        let edits = self.edits.take();
+        let reset = hm.width != self.width
+            || hm.height != self.height
+            || self.world.as_ref() != Some(world)
+            || self.boundary != boundary;
+        if reset {
+            self.resize(hm, world, boundary);
+        }
+
+        let mut changed = false;
+        if reset || edits.whole {
+            changed = self.rehash(hm, (0, 0, self.tiles_x, self.tiles_y));
+        } else {
+            for (x, y, w, h) in edits.rects {
+                let (tx0, ty0) = ((x / TILE_SIZE).min(self.tiles_x), (y / TILE_SIZE).min(self.tiles_y));
+                let tx1 = x.saturating_add(w).div_ceil(TILE_SIZE).min(self.tiles_x);
+                let ty1 = y.saturating_add(h).div_ceil(TILE_SIZE).min(self.tiles_y);
+                changed |= self.rehash(hm, (tx0, ty0, tx1, ty1));
+            }
+        }
+        if changed {
+            self.mips_stale = true;
+            self.last_change = Instant::now();
+        }
+        changed
+    }
+
+    /// Rehash tiles `tx0..tx1` by `ty0..ty1` and flag the ones whose content
+    /// changed. Returns whether any did.
+    fn rehash(&mut self, hm: &Heightmap, (tx0, ty0, tx1, ty1): (u32, u32, u32, u32)) -> bool {
+        let mut changed = false;
+        for ty in ty0..ty1 {
+            for tx in tx0..tx1 {
+                let index = (ty * self.tiles_x + tx) as usize;
+                let hash = hash_tile(hm, tx, ty);
+                if hash != self.hashes[index] {
+                    self.hashes[index] = hash;
+                    // Normals and slope read one pixel past the tile, so neighbors go
+                    // stale too, across the edge when the map wraps
+                    for oy in -1..=1 {
+                        for ox in -1..=1 {
+                            let nx = self.boundary.resolve_write(tx as i64 + ox, self.tiles_x);
+                            let ny = self.boundary.resolve_write(ty as i64 + oy, self.tiles_y);
+                            if let (Some(nx), Some(ny)) = (nx, ny) {
+                                self.dirty[(ny * self.tiles_x + nx) as usize] = true;
+                            }
+                        }
+                    }
+                    changed = true;
+                }
+            }
+        }
+        changed
+    }
+
+    fn resize(&mut self, hm: &Heightmap, world: &WorldScale, boundary: Boundary) {
+        self.width = hm.width;
+        self.height = hm.height;
+        self.tiles_x = hm.width.div_ceil(TILE_SIZE);
+        self.tiles_y = hm.height.div_ceil(TILE_SIZE);
+        let tiles = (self.tiles_x * self.tiles_y) as usize;
+        // A hash that can't match forces every tile through detection
+        self.hashes = vec![u64::MAX; tiles];
+        self.dirty = vec![true; tiles];
+        self.world = Some(world.clone());
+        self.boundary = boundary;
+        self.normals = vec![[0.0, 0.0, 1.0]; hm.data.len()];
+        self.slope = Heightmap::new(hm.width, hm.height);
+        self.mips.clear();
+        self.mips_stale = true;
+    }
+
+    /// Recompute up to `budget` dirty tiles. Returns how many were done.
+    fn compute_dirty_tiles(&mut self, hm: &Heightmap, budget: usize) -> usize {
+        let Some(world) = self.world.clone() else {
+            return 0;
+        };
+        let relief = world.max_elevation - world.min_elevation;
+        let run = 2.0 * world.meters_per_pixel.max(1e-6);
+
+        let mut done = 0;
+        for index in 0..self.dirty.len() {
+            if done >= budget {
+                break;
+            }
+            if !self.dirty[index] {
+                continue;
+            }
+            let tx = index as u32 % self.tiles_x;
+            let ty = index as u32 / self.tiles_x;
+            let (x0, y0) = (tx * TILE_SIZE, ty * TILE_SIZE);
+            let (x1, y1) = ((x0 + TILE_SIZE).min(hm.width), (y0 + TILE_SIZE).min(hm.height));
+
+            let at = |x: i64, y: i64| hm.get_bounded(x, y, self.boundary);
+            for y in y0..y1 {
+                for x in x0..x1 {
+                    let (ix, iy) = (x as i64, y as i64);
+                    let dzdx = (at(ix + 1, iy) - at(ix - 1, iy)) * relief / run;
+                    let dzdy = (at(ix, iy + 1) - at(ix, iy - 1)) * relief / run;
+                    let len = (dzdx * dzdx + dzdy * dzdy + 1.0).sqrt();
+                    self.normals[(y * hm.width + x) as usize] = [-dzdx / len, -dzdy / len, 1.0 / len];
+                    self.slope.set(x, y, (dzdx * dzdx + dzdy * dzdy).sqrt().atan().to_degrees());
+                }
+            }
+            self.dirty[index] = false;
+            done += 1;
+        }
+        done
+    }
+
+    fn rebuild_mips(&mut self, hm: &Heightmap) {
+        self.mips.clear();
+        let mut src = hm;
+        while src.width > MIN_MIP_SIZE || src.height > MIN_MIP_SIZE {
+            let next = downsample(src, self.boundary);
+            self.mips.push(next);
+            src = self.mips.last().unwrap();
+        }
+        self.mips_stale = false;
+    }
+}
+
+/// Start the idle-time scheduler. It never blocks editing: ticks where the heightmap
+/// or cache is busy are skipped, and work only starts once edits have settled.
+/// Ticks with no reported edits and nothing left to compute don't touch the
+/// heightmap at all.
+pub fn spawn_scheduler(
+    heightmap: Arc<Mutex<Heightmap>>,
+    world_scale: Arc<Mutex<WorldScale>>,
+    boundary: Arc<Mutex<Boundary>>,
+    cache: Arc<Mutex<DerivedCache>>,
+) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(TICK);
+
+        let world = world_scale.lock().unwrap().clone();
+        let boundary = *boundary.lock().unwrap();
+        let Ok(mut cache) = cache.try_lock() else {
+            continue;
+        };
+        if !cache.has_work(&world, boundary) {
+            continue;
+        }
+        let Ok(hm) = heightmap.try_lock() else {
+            continue;
+        };
+
+        if cache.apply_edits(&hm, &world, boundary) || cache.last_change.elapsed() < IDLE_DELAY {
+            continue;
+        }
+        if cache.compute_dirty_tiles(&hm, TILES_PER_TICK) == 0 && cache.mips_stale {
+            cache.rebuild_mips(&hm);
+        }
+    });
+}
+
+/// Halve resolution with a 2x2 box filter; odd edges read past the map per `boundary`.
+fn downsample(src: &Heightmap, boundary: Boundary) -> Heightmap {
+    let w = src.width.div_ceil(2).max(1);
+    let h = src.height.div_ceil(2).max(1);
+    let mut dst = Heightmap::new(w, h);
+    for y in 0..h {
+        for x in 0..w {
+            let (sx, sy) = (x as i64 * 2, y as i64 * 2);
+            let at = |x: i64, y: i64| src.get_bounded(x, y, boundary);
+            let sum = at(sx, sy) + at(sx + 1, sy) + at(sx, sy + 1) + at(sx + 1, sy + 1);
+            dst.set(x, y, sum * 0.25);
+        }
+    }
+    dst
+}
+
+/// Cheap multiplicative hash over a tile's sample bits, used only for change detection.
+fn hash_tile(hm: &Heightmap, tx: u32, ty: u32) -> u64 {
+    let (x0, y0) = (tx * TILE_SIZE, ty * TILE_SIZE);
+    let (x1, y1) = ((x0 + TILE_SIZE).min(hm.width), (y0 + TILE_SIZE).min(hm.height));
+    let mut h: u64 = 0x9E37_79B9_7F4A_7C15;
+    for y in y0..y1 {
+        let row = (y * hm.width) as usize;
+        for v in &hm.data[row + x0 as usize..row + x1 as usize] {
+            h = (h ^ v.to_bits() as u64).wrapping_mul(0x0100_0000_01b3).rotate_left(5);
+        }
+    }
+    h
+}
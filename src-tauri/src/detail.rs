@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use crate::canvas;
+use crate::heightmap::Heightmap;
+use crate::noise_gen::{self, Frame, NoiseParams};
+
+/// Largest density multiplier a patch may use.
+pub const MAX_FACTOR: u32 = 8;
+
+/// A higher-resolution sub-grid over part of the base map. It stores only a residual
+/// on top of the bilinearly upsampled base, so edits to the base carry through and
+/// the patch meets the surrounding terrain exactly at its border.
+#[derive(Clone)]
+pub struct DetailPatch {
+    /// Covered base pixels: the patch's corner vertices sit on base pixels
+    /// (x, y) and (x + w - 1, y + h - 1).
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    /// Patch samples per base pixel along each axis.
+    pub factor: u32,
+    /// ((w - 1) * factor + 1) x ((h - 1) * factor + 1) offsets in normalized height.
+    pub residual: Heightmap,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DetailPatchInfo {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+    pub factor: u32,
+}
+
+impl DetailPatch {
+    /// New patch over the base rectangle. With `detail`, its noise is generated at
+    /// patch resolution as a zero-mean residual (heights relative to the params'
+    /// offset), faded out towards the border; otherwise the patch starts flat.
+    pub fn new(
+        base: &Heightmap,
+        (x, y, w, h): (u32, u32, u32, u32),
+        factor: u32,
+        detail: Option<&NoiseParams>,
+    ) -> Result<Self, String> {
+        if !(2..=MAX_FACTOR).contains(&factor) {
+            return Err(format!("Detail factor must be between 2 and {MAX_FACTOR}"));
+        }
+        if w < 2 || h < 2 || x + w > base.width || y + h > base.height {
+            return Err("Detail patch must cover at least 2x2 pixels inside the map".to_string());
+        }
+
+        let (pw, ph) = residual_size(w, h, factor);
+        if pw > canvas::MAX_CANVAS_SIZE || ph > canvas::MAX_CANVAS_SIZE {
+            return Err(format!(
+                "A {w}x{h} patch at {factor}x would be {pw}x{ph}; patches can't exceed {0}x{0}",
+                canvas::MAX_CANVAS_SIZE
+            ));
+        }
+        let mut residual = Heightmap::new(pw, ph);
+        if let Some(params) = detail {
+            // Same noise space as the base map, sampled `factor` times denser
+            let frame = Frame {
+                origin_x: (x * factor) as f64,
+                origin_y: (y * factor) as f64,
+                unit_w: (base.width * factor) as f64,
+                unit_h: (base.height * factor) as f64,
+            };
+            noise_gen::generate_in_frame(&mut residual, params, frame);
+
+            let fade_px = (w.min(h) as f32 / 8.0).max(1.0) * factor as f32;
+            for py in 0..ph {
+                for px in 0..pw {
+                    let edge = px.min(py).min(pw - 1 - px).min(ph - 1 - py) as f32;
+                    let t = (edge / fade_px).min(1.0);
+                    let fade = t * t * (3.0 - 2.0 * t);
+                    let value = residual.get(px, py) - params.offset as f32;
+                    residual.set(px, py, value * fade);
+                }
+            }
+        }
+
+        Ok(Self { x, y, w, h, factor, residual })
+    }
+
+    /// A patch placed per `info` with a residual read back, e.g. from a project.
+    pub fn from_parts(info: DetailPatchInfo, residual: Heightmap) -> Result<Self, String> {
+        let DetailPatchInfo { x, y, w, h, factor } = info;
+        if !(2..=MAX_FACTOR).contains(&factor) || w < 2 || h < 2 {
+            return Err(format!("Invalid detail patch {w}x{h} at {factor}x"));
+        }
+        if (residual.width, residual.height) != residual_size(w, h, factor) {
+            return Err(format!("Detail residual is {}x{}, which doesn't fit a {w}x{h} patch at {factor}x", residual.width, residual.height));
+        }
+        Ok(Self { x, y, w, h, factor, residual })
+    }
+
+    pub fn info(&self) -> DetailPatchInfo {
+        DetailPatchInfo { x: self.x, y: self.y, w: self.w, h: self.h, factor: self.factor }
+    }
+
+    pub fn fits(&self, base: &Heightmap) -> bool {
+        self.x + self.w <= base.width && self.y + self.h <= base.height
+    }
+
+    /// Final height at fractional base pixel coordinates, or `None` outside the
+    /// patch.
+    pub fn sample(&self, base: &Heightmap, x: f32, y: f32) -> Option<f32> {
+        let px = (x - self.x as f32) * self.factor as f32;
+        let py = (y - self.y as f32) * self.factor as f32;
+        let inside = (0.0..=(self.residual.width - 1) as f32).contains(&px)
+            && (0.0..=(self.residual.height - 1) as f32).contains(&py);
+        inside.then(|| (base.sample(x, y) + self.residual.sample(px, py)).clamp(0.0, 1.0))
+    }
+
+    /// Final heights at patch resolution: upsampled base plus residual.
+    pub fn composite(&self, base: &Heightmap) -> Heightmap {
+        let mut out = Heightmap::new(self.residual.width, self.residual.height);
+        let step = 1.0 / self.factor as f32;
+        for py in 0..out.height {
+            for px in 0..out.width {
+                let bx = self.x as f32 + px as f32 * step;
+                let by = self.y as f32 + py as f32 * step;
+                let h = base.sample(bx, by) + self.residual.get(px, py);
+                out.set(px, py, h.clamp(0.0, 1.0));
+            }
+        }
+        out
+    }
+}
+
+/// Residual grid size of a `w` x `h` patch at `factor` samples per base pixel.
+pub fn residual_size(w: u32, h: u32, factor: u32) -> (u32, u32) {
+    let side = |n: u32| n.saturating_sub(1).saturating_mul(factor).saturating_add(1);
+    (side(w), side(h))
+}
+
+/// Height at fractional base pixel coordinates with `patches` composited over
+/// `base`. Where patches overlap, the one added last wins.
+pub fn sample(base: &Heightmap, patches: &[DetailPatch], x: f32, y: f32) -> f32 {
+    patches.iter().rev().find_map(|patch| patch.sample(base, x, y)).unwrap_or_else(|| base.sample(x, y))
+}
+
+/// Spacing of the finest samples at fractional base pixel coordinates, in base
+/// pixels: a patch's sample spacing inside it, 1 elsewhere.
+pub fn spacing(patches: &[DetailPatch], x: f32, y: f32) -> f32 {
+    patches
+        .iter()
+        .rev()
+        .find(|p| x >= p.x as f32 && y >= p.y as f32 && x <= (p.x + p.w - 1) as f32 && y <= (p.y + p.h - 1) as f32)
+        .map_or(1.0, |p| 1.0 / p.factor as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(width: u32, height: u32) -> Heightmap {
+        let data = (0..width * height).map(|i| (i % width) as f32 / width as f32).collect();
+        Heightmap { data, width, height }
+    }
+
+    #[test]
+    fn flat_patch_matches_base() {
+        let base = ramp(16, 16);
+        let patch = DetailPatch::new(&base, (2, 3, 6, 5), 4, None).unwrap();
+        assert_eq!((patch.residual.width, patch.residual.height), (21, 17));
+        for &(x, y) in &[(2.0, 3.0), (4.25, 5.5), (7.0, 7.0)] {
+            assert_eq!(patch.sample(&base, x, y), Some(base.sample(x, y)));
+        }
+        assert_eq!(patch.sample(&base, 1.5, 4.0), None);
+        assert_eq!(patch.sample(&base, 7.5, 4.0), None);
+    }
+
+    #[test]
+    fn residual_adds_inside_the_patch_only() {
+        let base = ramp(16, 16);
+        let mut patch = DetailPatch::new(&base, (2, 2, 4, 4), 2, None).unwrap();
+        // Patch sample (2, 2) sits on base pixel (3, 3)
+        patch.residual.set(2, 2, 0.1);
+        let patches = [patch];
+        assert!((sample(&base, &patches, 3.0, 3.0) - (base.get(3, 3) + 0.1)).abs() < 1e-6);
+        assert_eq!(sample(&base, &patches, 10.0, 10.0), base.get(10, 10));
+        assert_eq!(spacing(&patches, 3.0, 3.0), 0.5);
+        assert_eq!(spacing(&patches, 10.0, 10.0), 1.0);
+    }
+
+    #[test]
+    fn last_patch_wins() {
+        let base = ramp(16, 16);
+        let mut first = DetailPatch::new(&base, (0, 0, 8, 8), 2, None).unwrap();
+        first.residual.data.fill(0.2);
+        let second = DetailPatch::new(&base, (4, 4, 8, 8), 4, None).unwrap();
+        let patches = [first, second];
+        assert_eq!(sample(&base, &patches, 5.0, 5.0), base.get(5, 5));
+        assert_eq!(spacing(&patches, 5.0, 5.0), 0.25);
+        assert!((sample(&base, &patches, 2.0, 2.0) - (base.get(2, 2) + 0.2)).abs() < 1e-6);
+    }
+
+    #[test]
+    fn invalid_patches_are_rejected() {
+        let base = ramp(16, 16);
+        assert!(DetailPatch::new(&base, (0, 0, 4, 4), 1, None).is_err());
+        assert!(DetailPatch::new(&base, (0, 0, 4, 4), MAX_FACTOR + 1, None).is_err());
+        assert!(DetailPatch::new(&base, (0, 0, 1, 4), 2, None).is_err());
+        assert!(DetailPatch::new(&base, (14, 0, 4, 4), 2, None).is_err());
+
+        let info = DetailPatchInfo { x: 0, y: 0, w: 4, h: 4, factor: 2 };
+        assert!(DetailPatch::from_parts(info, Heightmap::new(7, 7)).is_ok());
+        assert!(DetailPatch::from_parts(info, Heightmap::new(8, 7)).is_err());
+        assert!(DetailPatch::from_parts(DetailPatchInfo { factor: 0, ..info }, Heightmap::new(1, 1)).is_err());
+    }
+
+    #[test]
+    fn oversized_patch_is_rejected() {
+        let side = canvas::MAX_CANVAS_SIZE / 4 + 2;
+        let base = Heightmap::new(side, 2);
+        assert!(DetailPatch::new(&base, (0, 0, side, 2), MAX_FACTOR, None).is_err());
+        assert_eq!(residual_size(u32::MAX, 2, MAX_FACTOR), (u32::MAX, MAX_FACTOR + 1));
+    }
+}
@@ -0,0 +1,171 @@
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+
+/// Aeolian bedform patterns, named for the wind regime that builds them.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DunePattern {
+    /// Long sinuous ridges perpendicular to a steady wind.
+    #[default]
+    Transverse,
+    /// Isolated crescents with horns pointing downwind, on sand-starved ground.
+    Barchan,
+    /// Pyramidal dunes with radiating arms from multidirectional winds.
+    Star,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DuneParams {
+    pub pattern: DunePattern,
+    /// Direction the wind blows towards, in degrees clockwise from north.
+    pub wind_direction: f64,
+    /// Crest spacing as a fraction of the map width.
+    pub wavelength: f64,
+    /// Crest sinuosity and breakup, 0 for perfectly regular dunes.
+    pub irregularity: f64,
+}
+
+impl Default for DuneParams {
+    fn default() -> Self {
+        Self {
+            pattern: DunePattern::Transverse,
+            wind_direction: 90.0,
+            wavelength: 0.08,
+            irregularity: 0.5,
+        }
+    }
+}
+
+/// Dune heights in [-1, 1], evaluated per point so it slots into noise generation.
+pub struct DuneField {
+    pattern: DunePattern,
+    /// Unit vector the wind blows along, in map coordinates (+y south).
+    wind: (f64, f64),
+    wavelength: f64,
+    irregularity: f64,
+    warp: Perlin,
+    cells: Perlin,
+}
+
+impl DuneField {
+    pub fn new(params: &DuneParams, seed: u32) -> Self {
+        let angle = params.wind_direction.to_radians();
+        Self {
+            pattern: params.pattern,
+            wind: (angle.sin(), -angle.cos()),
+            wavelength: params.wavelength.max(1e-3),
+            irregularity: params.irregularity.max(0.0),
+            warp: Perlin::new(seed),
+            cells: Perlin::new(seed.wrapping_add(1)),
+        }
+    }
+
+    pub fn sample(&self, x: f64, y: f64) -> f64 {
+        // Coordinates in wavelengths: u runs downwind, v across the wind
+        let u = (x * self.wind.0 + y * self.wind.1) / self.wavelength;
+        let v = (-x * self.wind.1 + y * self.wind.0) / self.wavelength;
+
+        match self.pattern {
+            DunePattern::Transverse => {
+                let bend = self.warp.get([v * 0.25, u * 0.1]) * self.irregularity * 1.5;
+                let h = asymmetric_profile(u + bend);
+                // Slow modulation of crest height along the ridge
+                let strength = 0.75 + 0.25 * self.cells.get([v * 0.3, u * 0.3]);
+                h * strength * 2.0 - 1.0
+            }
+            DunePattern::Barchan => self.barchan(u, v),
+            DunePattern::Star => self.star(u, v),
+        }
+    }
+
+    /// Crescents on a jittered lattice, staggered row to row.
+    fn barchan(&self, u: f64, v: f64) -> f64 {
+        // Barchans are spaced further apart than they are long
+        let spacing = 2.0;
+        let (su, sv) = (u / spacing, v / spacing);
+        let mut h: f64 = 0.0;
+        let row = su.floor();
+        for dr in -1..=1 {
+            let r = row + dr as f64;
+            let stagger = if (r as i64).rem_euclid(2) == 1 { 0.5 } else { 0.0 };
+            let col = (sv - stagger).floor();
+            for dc in -1..=1 {
+                let c = col + dc as f64;
+                let jitter_u = self.cells.get([r * 1.7, c * 1.3]) * self.irregularity * 0.4;
+                let jitter_v = self.cells.get([c * 1.9 + 31.0, r * 1.1]) * self.irregularity * 0.4;
+                let size = 0.7 + 0.3 * self.warp.get([r * 0.7 + 5.0, c * 0.7]);
+                let cu = (u - (r + 0.5 + jitter_u) * spacing) / size;
+                let cv = (v - (c + 0.5 + stagger + jitter_v) * spacing) / size;
+                h = h.max(crescent(cu, cv) * size);
+            }
+        }
+        h * 2.0 - 1.0
+    }
+
+    /// Pyramidal peaks on a jittered lattice, each with 3-5 arms radiating outwards.
+    fn star(&self, u: f64, v: f64) -> f64 {
+        let spacing = 1.5;
+        let (su, sv) = (u / spacing, v / spacing);
+        let mut h: f64 = 0.0;
+        for dr in -1..=1 {
+            for dc in -1..=1 {
+                let r = su.floor() + dr as f64;
+                let c = sv.floor() + dc as f64;
+                let jitter_u = self.cells.get([r * 1.7, c * 1.3]) * (0.2 + self.irregularity * 0.3);
+                let jitter_v = self.cells.get([c * 1.9 + 31.0, r * 1.1]) * (0.2 + self.irregularity * 0.3);
+                let du = u - (r + 0.5 + jitter_u) * spacing;
+                let dv = v - (c + 0.5 + jitter_v) * spacing;
+
+                let arms = 3.0 + ((self.warp.get([r * 0.9 + 11.0, c * 0.9]) + 1.0) * 1.5).floor().min(2.0);
+                let twist = self.warp.get([r * 0.5, c * 0.5 + 17.0]) * std::f64::consts::PI;
+                let dist = (du * du + dv * dv).sqrt();
+                // Arms curve slightly with distance, as they do under shifting winds
+                let theta = dv.atan2(du) + twist + dist * self.irregularity;
+                let arm = (arms * theta * 0.5).cos().abs().powi(6);
+                let reach = spacing * (0.25 + 0.5 * arm);
+                h = h.max((1.0 - dist / reach).max(0.0).powf(1.3));
+            }
+        }
+        h * 2.0 - 1.0
+    }
+}
+
+/// One dune period in [0, 1] along the wind: a gentle stoss slope rising to the crest,
+/// then a short, steep slip face (about a fifth of the wavelength).
+fn asymmetric_profile(u: f64) -> f64 {
+    const CREST: f64 = 0.8;
+    let t = u.rem_euclid(1.0);
+    if t < CREST {
+        let s = t / CREST;
+        s * s * (3.0 - 2.0 * s)
+    } else {
+        let s = (t - CREST) / (1.0 - CREST);
+        1.0 - s.powf(0.6)
+    }
+}
+
+/// A barchan centered at the origin with the wind along +u: a mound whose lee side is
+/// hollowed by a slip face, leaving horns trailing downwind. Returns height in [0, 1].
+fn crescent(u: f64, v: f64) -> f64 {
+    let body = (1.0 - (u * u / 0.25 + v * v / 0.36)).max(0.0);
+    if body <= 0.0 {
+        return horns(u, v);
+    }
+    // Slip-face bowl cut into the downwind half
+    let bowl_u = u - 0.35;
+    let bowl = (1.0 - (bowl_u * bowl_u / 0.12 + v * v / 0.2)).max(0.0);
+    (body.sqrt() - bowl * 1.2).max(0.0).max(horns(u, v))
+}
+
+fn horns(u: f64, v: f64) -> f64 {
+    // Two tapered ridges sweeping downwind from the flanks
+    if !(0.0..=0.9).contains(&u) {
+        return 0.0;
+    }
+    let t = u / 0.9;
+    let offset = 0.55 - 0.1 * t;
+    let width = 0.12 * (1.0 - t) + 0.02;
+    let d = (v.abs() - offset).abs();
+    ((1.0 - d / width).max(0.0) * (1.0 - t) * 0.5).max(0.0)
+}
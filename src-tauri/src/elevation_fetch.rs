@@ -0,0 +1,183 @@
+//! Real-world terrain downloaded for a latitude/longitude box from the AWS
+//! Terrain Tiles (Mapzen terrarium encoding, attribution required when the
+//! result is published).
+
+use std::f64::consts::PI;
+use std::io::Read;
+use std::time::Duration;
+use image::RgbImage;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::canvas;
+use crate::geotiff::Dem;
+use crate::heightmap::Heightmap;
+use crate::world::Georeference;
+
+const TILE_URL: &str = "https://s3.amazonaws.com/elevation-tiles-prod/terrarium";
+const TILE_SIZE: u32 = 256;
+/// Deepest zoom the tiles are published at, about 5 m per pixel at the equator.
+const MAX_ZOOM: u32 = 15;
+/// Downloads allowed for one fetch; larger boxes use a coarser zoom.
+const MAX_TILES: usize = 256;
+/// Tiles downloading at once, on threads of their own rather than the shared
+/// rayon pool the rest of the app computes on.
+const DOWNLOAD_THREADS: usize = 8;
+/// Longest one tile may take, connecting included.
+const TILE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest tile accepted; published tiles are well under 200 KB.
+const MAX_TILE_BYTES: u64 = 4 << 20;
+/// Web Mercator stops short of the poles.
+const MAX_LATITUDE: f64 = 85.0511;
+
+/// Area to fetch, in degrees.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FetchParams {
+    pub west: f64,
+    pub south: f64,
+    pub east: f64,
+    pub north: f64,
+    /// Pixels along the longer side of the result; the other follows the
+    /// box's proportions on the ground.
+    pub resolution: u32,
+}
+
+impl FetchParams {
+    pub fn validate(&self) -> Result<(), String> {
+        let finite = [self.west, self.south, self.east, self.north].iter().all(|v| v.is_finite());
+        if !finite || self.west < -180.0 || self.east > 180.0 || self.west >= self.east {
+            return Err("Longitudes must run west to east within -180 to 180".into());
+        }
+        if self.south < -MAX_LATITUDE || self.north > MAX_LATITUDE || self.south >= self.north {
+            return Err(format!("Latitudes must run south to north within ±{MAX_LATITUDE}"));
+        }
+        if !(2..=canvas::MAX_CANVAS_SIZE).contains(&self.resolution) {
+            return Err(format!("Resolution must be between 2 and {}", canvas::MAX_CANVAS_SIZE));
+        }
+        Ok(())
+    }
+
+    /// Output size keeping the box's proportions on the ground.
+    fn dims(&self) -> (u32, u32) {
+        let latitude = ((self.south + self.north) / 2.0).to_radians();
+        let ground_w = (self.east - self.west) * latitude.cos();
+        let ground_h = self.north - self.south;
+        let short = |ratio: f64| ((self.resolution as f64 * ratio).round() as u32).max(2);
+        if ground_w >= ground_h {
+            (self.resolution, short(ground_h / ground_w))
+        } else {
+            (short(ground_w / ground_h), self.resolution)
+        }
+    }
+}
+
+/// Position in pixels of the whole world's Web Mercator image at `zoom`.
+fn mercator(lon: f64, lat: f64, zoom: u32) -> (f64, f64) {
+    let size = (TILE_SIZE << zoom) as f64;
+    let lat = lat.to_radians();
+    let y = (1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0;
+    ((lon + 180.0) / 360.0 * size, y * size)
+}
+
+/// Tiles covering the box at `zoom`, as inclusive x and y ranges.
+fn tile_range(params: &FetchParams, zoom: u32) -> ((u32, u32), (u32, u32)) {
+    let last = (1u32 << zoom) - 1;
+    let (x0, y0) = mercator(params.west, params.north, zoom);
+    let (x1, y1) = mercator(params.east, params.south, zoom);
+    let tile = |v: f64| ((v / TILE_SIZE as f64).floor().max(0.0) as u32).min(last);
+    ((tile(x0), tile(x1)), (tile(y0), tile(y1)))
+}
+
+/// The shallowest zoom with at least as many pixels across the box as the
+/// output, capped by what's published and by `MAX_TILES`.
+fn pick_zoom(params: &FetchParams, width: u32, height: u32) -> u32 {
+    let enough = |zoom: u32| {
+        let (x0, y0) = mercator(params.west, params.north, zoom);
+        let (x1, y1) = mercator(params.east, params.south, zoom);
+        x1 - x0 >= width as f64 && y1 - y0 >= height as f64
+    };
+    let mut zoom = (0..=MAX_ZOOM).find(|&z| enough(z)).unwrap_or(MAX_ZOOM);
+    while zoom > 0 {
+        let ((x0, x1), (y0, y1)) = tile_range(params, zoom);
+        if ((x1 - x0 + 1) * (y1 - y0 + 1)) as usize <= MAX_TILES {
+            break;
+        }
+        zoom -= 1;
+    }
+    zoom
+}
+
+fn download(agent: &ureq::Agent, zoom: u32, x: u32, y: u32) -> Result<RgbImage, String> {
+    let url = format!("{TILE_URL}/{zoom}/{x}/{y}.png");
+    let response = agent.get(&url).call().map_err(|e| match e {
+        ureq::Error::Status(status, _) => format!("Failed to download {url}: the server answered {status}"),
+        e => format!("Failed to download {url}: {e}"),
+    })?;
+    let mut bytes = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_TILE_BYTES)
+        .read_to_end(&mut bytes)
+        .map_err(|e| format!("Failed to download {url}: {e}"))?;
+    let tile = image::load_from_memory(&bytes).map_err(|e| format!("Failed to decode {url}: {e}"))?;
+    Ok(tile.to_rgb8())
+}
+
+/// Download the tiles over the box, stitch them and sample the mosaic onto a
+/// latitude/longitude grid of `params.resolution` along its longer side, so
+/// the DEM is georeferenced in WGS 84.
+pub fn fetch(params: &FetchParams) -> Result<Dem, String> {
+    params.validate()?;
+    let (width, height) = params.dims();
+    let zoom = pick_zoom(params, width, height);
+    let ((tx0, tx1), (ty0, ty1)) = tile_range(params, zoom);
+    let tiles: Vec<(u32, u32)> = (ty0..=ty1).flat_map(|y| (tx0..=tx1).map(move |x| (x, y))).collect();
+    let agent = ureq::AgentBuilder::new().timeout(TILE_TIMEOUT).build();
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(DOWNLOAD_THREADS)
+        .build()
+        .map_err(|e| format!("Failed to start the download threads: {e}"))?;
+    let images = pool.install(|| {
+        tiles
+            .par_iter()
+            .map(|&(x, y)| download(&agent, zoom, x, y))
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+
+    // Terrarium packs meters plus 32768 into red, green and blue as 256ths
+    let (mw, mh) = ((tx1 - tx0 + 1) * TILE_SIZE, (ty1 - ty0 + 1) * TILE_SIZE);
+    let mut mosaic = Heightmap::new(mw, mh);
+    for (&(x, y), img) in tiles.iter().zip(&images) {
+        let (ox, oy) = ((x - tx0) * TILE_SIZE, (y - ty0) * TILE_SIZE);
+        for (px, py, p) in img.enumerate_pixels().filter(|(px, py, _)| *px < TILE_SIZE && *py < TILE_SIZE) {
+            let meters = p[0] as f32 * 256.0 + p[1] as f32 + p[2] as f32 / 256.0 - 32768.0;
+            mosaic.data[((oy + py) * mw + ox + px) as usize] = meters;
+        }
+    }
+
+    // Corner samples sit on the box's edges; pixel centers are at half pixels
+    let (dx, dy) = ((params.east - params.west) / (width - 1) as f64, (params.north - params.south) / (height - 1) as f64);
+    let mut samples = Heightmap::new(width, height);
+    samples.data.par_chunks_mut(width as usize).enumerate().for_each(|(row, out)| {
+        let lat = params.north - row as f64 * dy;
+        for (col, v) in out.iter_mut().enumerate() {
+            let (x, y) = mercator(params.west + col as f64 * dx, lat, zoom);
+            let x = (x - (tx0 * TILE_SIZE) as f64 - 0.5) as f32;
+            let y = (y - (ty0 * TILE_SIZE) as f64 - 0.5) as f32;
+            *v = mosaic.sample(x, y);
+        }
+    });
+
+    Ok(Dem {
+        samples,
+        nodata: None,
+        georeference: Some(Georeference {
+            epsg: Some(4326),
+            geographic: true,
+            origin: [params.west - dx / 2.0, params.north + dy / 2.0],
+            pixel_size: [dx, dy],
+        }),
+        linear_unit: 1.0,
+        vertical_unit: 1.0,
+    })
+}
@@ -0,0 +1,217 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+
+/// Wave action at a fixed sea level: exposed shores are cut back into cliffs above
+/// a wave-cut platform, and the debris is washed into sheltered shallows as beaches.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoastalParams {
+    pub iterations: u32,
+    /// Normalized height of the water surface.
+    pub sea_level: f32,
+    /// Height cut from a fully exposed shore pixel per iteration.
+    pub wave_energy: f32,
+    /// Compass direction the waves come from, in degrees; 0 is the top of the map,
+    /// 90 the right.
+    #[serde(default)]
+    pub wind_direction: f32,
+    /// 0 lets waves arrive equally from every side, 1 only from `wind_direction`.
+    #[serde(default)]
+    pub directionality: f32,
+    /// Depth below sea level that waves plane the shore down to.
+    #[serde(default = "default_platform_depth")]
+    pub platform_depth: f32,
+    /// Fraction of the suspended sediment a calm pixel settles per iteration.
+    #[serde(default = "default_deposit_rate")]
+    pub deposit_rate: f32,
+    /// Open water, as a fraction of the map width, past which longer fetch
+    /// doesn't build bigger waves.
+    #[serde(default = "default_max_fetch")]
+    pub max_fetch: f32,
+}
+
+fn default_platform_depth() -> f32 {
+    0.002
+}
+
+fn default_deposit_rate() -> f32 {
+    0.2
+}
+
+fn default_max_fetch() -> f32 {
+    0.25
+}
+
+/// Fetch directions, the way each ray looks out to sea.
+const DIRECTIONS: [(i64, i64); 8] = [(0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1)];
+/// Rate suspended sediment spreads between water pixels; the 4-neighbor diffusion
+/// stability limit is 0.25.
+const SPREAD: f32 = 0.2;
+/// Height above sea level that swash can pile a beach to.
+const BEACH_CREST: f32 = 0.001;
+/// Deep water settles sediment at this fraction of the shallow-water rate.
+const DEEP_SETTLING: f32 = 0.1;
+
+/// How exposed each pixel is to waves, in [0, 1]: open water upwind of it in
+/// each direction, weighted towards the wind and capped at `max_fetch`. Each
+/// direction is one sweep that extends the run of water from the upwind
+/// neighbor. Off the map, clamped and mirrored edges continue the edge pixel, a
+/// fixed edge is open sea when it lies below sea level, and wrapped maps sweep
+/// twice so runs can cross the seam.
+fn exposure(hm: &Heightmap, params: &CoastalParams, boundary: Boundary) -> Vec<f32> {
+    let (w, h) = (hm.width as i64, hm.height as i64);
+    let max_fetch = (params.max_fetch * w as f32).max(1.0);
+    let wet = |i: usize| hm.data[i] < params.sea_level;
+    let off_map_wet = |i: usize| match boundary {
+        Boundary::Fixed { value } => value < params.sea_level,
+        _ => wet(i),
+    };
+
+    let mut exposure = vec![0.0f32; hm.data.len()];
+    let mut fetch = vec![0.0f32; hm.data.len()];
+    let mut total_weight = 0.0;
+    for &(dx, dy) in &DIRECTIONS {
+        // Compass bearing of the direction the ray looks towards
+        let bearing = (dx as f32).atan2(-dy as f32);
+        let facing = (bearing - params.wind_direction.to_radians()).cos().max(0.0);
+        let weight = (1.0 - params.directionality) + params.directionality * facing;
+        if weight <= 0.0 {
+            continue;
+        }
+        total_weight += weight;
+        let step = if dx != 0 && dy != 0 { std::f32::consts::SQRT_2 } else { 1.0 };
+
+        // Visit upwind pixels first: against the ray's direction on each axis
+        let xs: Vec<i64> = if dx > 0 { (0..w).rev().collect() } else { (0..w).collect() };
+        let ys: Vec<i64> = if dy > 0 { (0..h).rev().collect() } else { (0..h).collect() };
+        let passes = if boundary.wraps() { 2 } else { 1 };
+        fetch.fill(0.0);
+        for _ in 0..passes {
+            for &y in &ys {
+                for &x in &xs {
+                    let i = (y * w + x) as usize;
+                    let upwind = boundary
+                        .resolve_write(x + dx, w as u32)
+                        .zip(boundary.resolve_write(y + dy, h as u32))
+                        .map(|(ux, uy)| uy as usize * w as usize + ux as usize);
+                    fetch[i] = match upwind {
+                        Some(u) if wet(u) => (fetch[u] + step).min(max_fetch),
+                        Some(_) => 0.0,
+                        None if off_map_wet(i) => max_fetch,
+                        None => 0.0,
+                    };
+                }
+            }
+        }
+        for (e, f) in exposure.iter_mut().zip(&fetch) {
+            *e += weight * f;
+        }
+    }
+
+    if total_weight > 0.0 {
+        let norm = 1.0 / (total_weight * max_fetch);
+        exposure.par_iter_mut().for_each(|e| *e *= norm);
+    }
+    exposure
+}
+
+/// Every iteration measures wave exposure, cuts exposed shore pixels (land next
+/// to water) down towards the platform, spreads the debris through the water and
+/// lets it settle where the waves are weak. Tall shores take longer to cut
+/// through, so they retreat as cliffs while low ones are planed away. Beaches stop
+/// building just above sea level, where waves can take them again. Whatever is
+/// still suspended at the end settles in place.
+pub fn erode(
+    hm: &mut Heightmap,
+    params: &CoastalParams,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) {
+    let w = hm.width as usize;
+    let h = hm.height as usize;
+    let platform = params.sea_level - params.platform_depth;
+    let mut load = vec![0.0f32; w * h];
+    let mut next_load = vec![0.0f32; w * h];
+
+    let neighbors = |x: usize, y: usize| {
+        [(-1i64, 0i64), (1, 0), (0, -1), (0, 1)].into_iter().filter_map(move |(dx, dy)| {
+            let nx = boundary.resolve_write(x as i64 + dx, w as u32)?;
+            let ny = boundary.resolve_write(y as i64 + dy, h as u32)?;
+            Some(ny as usize * w + nx as usize)
+        })
+    };
+
+    for i in 0..params.iterations {
+        if abort.load(Ordering::Relaxed) {
+            break;
+        }
+        progress(i as f32 / params.iterations as f32);
+
+        let exposed = exposure(hm, params, boundary);
+
+        // Cut the shore and hand the debris to the water beside it
+        for y in 0..h {
+            for x in 0..w {
+                let idx = y * w + x;
+                let height = hm.data[idx];
+                if height < params.sea_level || height <= platform {
+                    continue;
+                }
+                let wet = neighbors(x, y).filter(|&n| hm.data[n] < params.sea_level).count();
+                if wet == 0 {
+                    continue;
+                }
+                let cut = (params.wave_energy * exposed[idx]).min(height - platform);
+                hm.data[idx] -= cut;
+                let share = cut / wet as f32;
+                for n in neighbors(x, y).filter(|&n| hm.data[n] < params.sea_level) {
+                    load[n] += share;
+                }
+            }
+        }
+
+        // Spread suspended sediment between water pixels
+        let bed = &hm.data;
+        let load_ref = &load;
+        next_load.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, v) in row.iter_mut().enumerate() {
+                let idx = y * w + x;
+                if bed[idx] >= params.sea_level {
+                    *v = load_ref[idx];
+                    continue;
+                }
+                let flow: f32 = neighbors(x, y)
+                    .filter(|&n| bed[n] < params.sea_level)
+                    .map(|n| load_ref[n] - load_ref[idx])
+                    .sum();
+                *v = load_ref[idx] + SPREAD * flow;
+            }
+        });
+        std::mem::swap(&mut load, &mut next_load);
+
+        // Settle it where waves are weak, fastest in the shallows
+        hm.data
+            .par_iter_mut()
+            .zip(load.par_iter_mut())
+            .zip(exposed.par_iter())
+            .for_each(|((height, carried), &exposure)| {
+                if *carried <= 0.0 || *height >= params.sea_level + BEACH_CREST {
+                    return;
+                }
+                let shallow = *height >= platform - params.platform_depth;
+                let rate = params.deposit_rate * (1.0 - exposure) * if shallow { 1.0 } else { DEEP_SETTLING };
+                let settled = (*carried * rate).min(params.sea_level + BEACH_CREST - *height);
+                *height += settled;
+                *carried -= settled;
+            });
+    }
+
+    for (height, carried) in hm.data.iter_mut().zip(&load) {
+        *height += carried;
+    }
+    progress(1.0);
+}
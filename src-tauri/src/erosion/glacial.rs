@@ -0,0 +1,210 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+
+/// Glaciers grown above a snowline, flowing under the shallow-ice approximation
+/// and abrading their bed in proportion to how fast they slide. Ice thickness and
+/// carried debris share the heightmap's normalized units.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GlacialParams {
+    pub iterations: u32,
+    /// Height where snowfall and melt balance; ice builds up above it and melts
+    /// below it.
+    pub snowline: f32,
+    /// Ice gained per iteration per unit of height above the snowline, and lost at
+    /// the same rate below it.
+    pub mass_balance: f32,
+    /// Bed lowered per iteration per pixel of ice sliding speed.
+    pub erosion_rate: f32,
+    /// How readily thick ice deforms; higher values make broader, flatter glaciers.
+    #[serde(default = "default_fluidity")]
+    pub fluidity: f32,
+}
+
+fn default_fluidity() -> f32 {
+    1e-4
+}
+
+/// Ice thinner than this neither moves nor abrades.
+const MIN_ICE: f32 = 1e-6;
+/// Upper bound on the flow coefficient between two cells. A face then moves at
+/// most a quarter of the surface step, the explicit diffusion stability limit on
+/// a 4-neighbor grid.
+const MAX_DIFFUSIVITY: f32 = 0.25;
+
+/// Flow directions; also the index order of a cell's outflows.
+const NEIGHBORS: [(i64, i64); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+/// Where ice leaving a cell in one direction goes.
+#[derive(Clone, Copy)]
+enum Face {
+    Cell(usize),
+    /// Clamped and mirrored edges hold the ice on the map.
+    Wall,
+    /// Ice flowing past a fixed edge calves off at that height.
+    Outlet(f32),
+}
+
+fn face(boundary: Boundary, x: usize, y: usize, d: usize, w: usize, h: usize) -> Face {
+    let (dx, dy) = NEIGHBORS[d];
+    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+    if (0..w as i64).contains(&nx) && (0..h as i64).contains(&ny) {
+        return Face::Cell(ny as usize * w + nx as usize);
+    }
+    match boundary {
+        Boundary::Wrap => Face::Cell(ny.rem_euclid(h as i64) as usize * w + nx.rem_euclid(w as i64) as usize),
+        Boundary::Fixed { value } => Face::Outlet(value),
+        Boundary::Clamp | Boundary::Mirror => Face::Wall,
+    }
+}
+
+/// Every iteration applies the mass balance, moves ice down the gradient of its
+/// surface, then abrades the bed under moving ice. Ice deforms as a power of its
+/// thickness, so it pools and flows as a whole along valley floors and cuts them
+/// across their width, leaving U-shaped troughs and overdeepened cirques at the
+/// heads. Abraded rock travels with the ice and drops out where it melts, which
+/// piles moraines along the ablation zone and at the terminus. Any ice left at the
+/// end melts in place.
+pub fn erode(
+    hm: &mut Heightmap,
+    params: &GlacialParams,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) {
+    let w = hm.width as usize;
+    let h = hm.height as usize;
+    // Heights are normalized to the map width; the flow law needs cell units
+    let scale = w as f32;
+
+    let mut ice = vec![0.0f32; w * h];
+    let mut next_ice = vec![0.0f32; w * h];
+    let mut debris = vec![0.0f32; w * h];
+    let mut next_debris = vec![0.0f32; w * h];
+    let mut flux = vec![[0.0f32; 4]; w * h];
+    let mut speed = vec![0.0f32; w * h];
+
+    for i in 0..params.iterations {
+        if abort.load(Ordering::Relaxed) {
+            break;
+        }
+        progress(i as f32 / params.iterations as f32);
+
+        // Snowfall above the snowline, melt below; melting ice drops its share of
+        // the debris it carries
+        hm.data
+            .par_iter_mut()
+            .zip(ice.par_iter_mut())
+            .zip(debris.par_iter_mut())
+            .for_each(|((bed, thickness), carried)| {
+                let balance = params.mass_balance * (*bed + *thickness - params.snowline);
+                if balance >= 0.0 {
+                    *thickness += balance;
+                } else if *thickness > 0.0 {
+                    let melted = (-balance).min(*thickness);
+                    let dropped = *carried * melted / *thickness;
+                    *thickness -= melted;
+                    *carried -= dropped;
+                    *bed += dropped;
+                }
+            });
+
+        // Shallow-ice flow: each face carries ice in proportion to the surface
+        // step, with a diffusivity growing as H^5·S^2 (Glen's law with n = 3), and
+        // a cell can't give away more ice than it holds
+        let bed = &hm.data;
+        let ice_ref = &ice;
+        flux.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let idx = y * w + x;
+                let thickness = ice_ref[idx];
+                if thickness <= MIN_ICE {
+                    *out = [0.0; 4];
+                    continue;
+                }
+                let surface = bed[idx] + thickness;
+                let deformation = params.fluidity * (thickness * scale).powi(5);
+                for (d, f) in out.iter_mut().enumerate() {
+                    let other = match face(boundary, x, y, d, w, h) {
+                        Face::Cell(n) => bed[n] + ice_ref[n],
+                        Face::Outlet(level) => level,
+                        Face::Wall => {
+                            *f = 0.0;
+                            continue;
+                        }
+                    };
+                    let drop = (surface - other).max(0.0);
+                    let slope = drop * scale;
+                    *f = (deformation * slope * slope).min(MAX_DIFFUSIVITY) * drop;
+                }
+                let total: f32 = out.iter().sum();
+                if total > thickness {
+                    let k = thickness / total;
+                    for f in out.iter_mut() {
+                        *f *= k;
+                    }
+                }
+            }
+        });
+
+        // Move ice and its debris along the faces; sliding speed is the ice
+        // crossing a cell per unit of thickness
+        let flux_ref = &flux;
+        let debris_ref = &debris;
+        let concentration = |n: usize| {
+            if ice_ref[n] > MIN_ICE {
+                debris_ref[n] / ice_ref[n]
+            } else {
+                0.0
+            }
+        };
+        next_ice
+            .par_chunks_mut(w)
+            .zip(next_debris.par_chunks_mut(w))
+            .zip(speed.par_chunks_mut(w))
+            .enumerate()
+            .for_each(|(y, ((ice_row, debris_row), speed_row))| {
+                for x in 0..w {
+                    let idx = y * w + x;
+                    let mut inflow = 0.0;
+                    let mut debris_in = 0.0;
+                    for d in 0..4 {
+                        if let Face::Cell(n) = face(boundary, x, y, d, w, h) {
+                            let flow = flux_ref[n][d ^ 1];
+                            inflow += flow;
+                            debris_in += flow * concentration(n);
+                        }
+                    }
+                    let outflow: f32 = flux_ref[idx].iter().sum();
+                    let thickness = (ice_ref[idx] + inflow - outflow).max(0.0);
+                    ice_row[x] = thickness;
+                    debris_row[x] = (debris_ref[idx] + debris_in - outflow * concentration(idx)).max(0.0);
+
+                    let mean = (ice_ref[idx] + thickness) * 0.5;
+                    speed_row[x] = if mean > MIN_ICE { (inflow + outflow) * 0.5 / mean } else { 0.0 };
+                }
+            });
+        std::mem::swap(&mut ice, &mut next_ice);
+        std::mem::swap(&mut debris, &mut next_debris);
+
+        // Abrasion: the bed wears in proportion to sliding speed, and the rock
+        // joins the ice's debris load
+        hm.data
+            .par_iter_mut()
+            .zip(debris.par_iter_mut())
+            .zip(speed.par_iter())
+            .for_each(|((bed, carried), &speed)| {
+                let worn = params.erosion_rate * speed;
+                *bed -= worn;
+                *carried += worn;
+            });
+    }
+
+    for (bed, carried) in hm.data.iter_mut().zip(&debris) {
+        *bed += carried;
+    }
+    progress(1.0);
+}
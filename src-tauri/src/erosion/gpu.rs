@@ -0,0 +1,342 @@
+//! Compute-shader erosion backend, built with `--features gpu`. The shaders mirror
+//! the CPU algorithms; hydraulic droplets run in batches that see the terrain as it
+//! was when the batch started, so results differ slightly from the CPU backend.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, OnceLock};
+use wgpu::util::DeviceExt;
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+use super::hydraulic::{self, HydraulicParams};
+use super::thermal::ThermalParams;
+
+/// Thermal iterations encoded per submission, between progress reports.
+const THERMAL_ITERATIONS_PER_SUBMIT: u32 = 16;
+const DROPLETS_PER_BATCH: u32 = 16_384;
+
+struct Gpu {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    thermal_layout: wgpu::BindGroupLayout,
+    thermal_outflow: wgpu::ComputePipeline,
+    thermal_gather: wgpu::ComputePipeline,
+    hydraulic_layout: wgpu::BindGroupLayout,
+    hydraulic_droplets: wgpu::ComputePipeline,
+    hydraulic_apply: wgpu::ComputePipeline,
+}
+
+static GPU: OnceLock<Result<Gpu, String>> = OnceLock::new();
+
+fn gpu() -> Result<&'static Gpu, String> {
+    GPU.get_or_init(init).as_ref().map_err(Clone::clone)
+}
+
+/// Whether a usable adapter was found. The first call initializes the device.
+pub fn available() -> bool {
+    gpu().is_ok()
+}
+
+fn layout_entry(binding: u32, ty: wgpu::BufferBindingType) -> wgpu::BindGroupLayoutEntry {
+    wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Buffer { ty, has_dynamic_offset: false, min_binding_size: None },
+        count: None,
+    }
+}
+
+fn storage(read_only: bool) -> wgpu::BufferBindingType {
+    wgpu::BufferBindingType::Storage { read_only }
+}
+
+fn init() -> Result<Gpu, String> {
+    let instance = wgpu::Instance::new(&wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        ..Default::default()
+    }))
+    .map_err(|e| format!("No GPU adapter: {e}"))?;
+    let (device, queue) = pollster::block_on(adapter.request_device(&wgpu::DeviceDescriptor {
+        label: Some("erosion"),
+        // Large maps need the adapter's full buffer size limits
+        required_limits: adapter.limits(),
+        ..Default::default()
+    }))
+    .map_err(|e| format!("Failed to open GPU device: {e}"))?;
+
+    let uniform = wgpu::BufferBindingType::Uniform;
+    let thermal_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("thermal"),
+        entries: &[
+            layout_entry(0, uniform),
+            layout_entry(1, storage(true)),
+            layout_entry(2, storage(false)),
+            layout_entry(3, storage(false)),
+        ],
+    });
+    let hydraulic_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("hydraulic"),
+        entries: &[
+            layout_entry(0, uniform),
+            layout_entry(1, storage(false)),
+            layout_entry(2, storage(false)),
+            layout_entry(3, storage(true)),
+        ],
+    });
+
+    let thermal_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("thermal"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/thermal.wgsl").into()),
+    });
+    let hydraulic_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("hydraulic"),
+        source: wgpu::ShaderSource::Wgsl(include_str!("shaders/hydraulic.wgsl").into()),
+    });
+
+    let pipeline = |layout: &wgpu::BindGroupLayout, module: &wgpu::ShaderModule, entry: &str| {
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(entry),
+            bind_group_layouts: &[layout],
+            push_constant_ranges: &[],
+        });
+        device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some(entry),
+            layout: Some(&pipeline_layout),
+            module,
+            entry_point: Some(entry),
+            compilation_options: Default::default(),
+            cache: None,
+        })
+    };
+    let thermal_outflow = pipeline(&thermal_layout, &thermal_module, "outflow_pass");
+    let thermal_gather = pipeline(&thermal_layout, &thermal_module, "gather_pass");
+    let hydraulic_droplets = pipeline(&hydraulic_layout, &hydraulic_module, "droplets");
+    let hydraulic_apply = pipeline(&hydraulic_layout, &hydraulic_module, "apply");
+
+    Ok(Gpu {
+        device,
+        queue,
+        thermal_layout,
+        thermal_outflow,
+        thermal_gather,
+        hydraulic_layout,
+        hydraulic_droplets,
+        hydraulic_apply,
+    })
+}
+
+/// Little-endian bytes of 32-bit values, for uniforms and uploads.
+fn to_bytes(words: impl IntoIterator<Item = u32>) -> Vec<u8> {
+    words.into_iter().flat_map(u32::to_le_bytes).collect()
+}
+
+impl Gpu {
+    fn check_size(&self, bytes: u64) -> Result<(), String> {
+        let limits = self.device.limits();
+        let max = limits.max_buffer_size.min(limits.max_storage_buffer_binding_size as u64);
+        if bytes > max {
+            return Err(format!("Map needs a {bytes}-byte buffer; this GPU allows {max}"));
+        }
+        Ok(())
+    }
+
+    fn buffer(&self, label: &str, size: u64, usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer(&wgpu::BufferDescriptor { label: Some(label), size, usage, mapped_at_creation: false })
+    }
+
+    fn upload(&self, label: &str, contents: &[u8], usage: wgpu::BufferUsages) -> wgpu::Buffer {
+        self.device.create_buffer_init(&wgpu::util::BufferInitDescriptor { label: Some(label), contents, usage })
+    }
+
+    fn bind_group(&self, layout: &wgpu::BindGroupLayout, buffers: &[&wgpu::Buffer]) -> wgpu::BindGroup {
+        let entries: Vec<_> = buffers
+            .iter()
+            .enumerate()
+            .map(|(i, buffer)| wgpu::BindGroupEntry { binding: i as u32, resource: buffer.as_entire_binding() })
+            .collect();
+        self.device.create_bind_group(&wgpu::BindGroupDescriptor { label: None, layout, entries: &entries })
+    }
+
+    fn wait(&self) -> Result<(), String> {
+        self.device
+            .poll(wgpu::PollType::Wait)
+            .map(|_| ())
+            .map_err(|e| format!("GPU erosion failed: {e}"))
+    }
+
+    fn submit_and_wait(&self, encoder: wgpu::CommandEncoder) -> Result<(), String> {
+        self.queue.submit([encoder.finish()]);
+        self.wait()
+    }
+
+    fn read_back(&self, source: &wgpu::Buffer, hm: &mut Heightmap) -> Result<(), String> {
+        let size = source.size();
+        let staging = self.buffer("readback", size, wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST);
+        let mut encoder = self.device.create_command_encoder(&Default::default());
+        encoder.copy_buffer_to_buffer(source, 0, &staging, 0, size);
+        self.submit_and_wait(encoder)?;
+
+        let slice = staging.slice(..);
+        let (tx, rx) = mpsc::channel();
+        slice.map_async(wgpu::MapMode::Read, move |result| {
+            let _ = tx.send(result);
+        });
+        self.wait()?;
+        rx.recv()
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("Failed to read back heights: {e}"))?;
+
+        let bytes = slice.get_mapped_range();
+        for (v, chunk) in hm.data.iter_mut().zip(bytes.chunks_exact(4)) {
+            *v = f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        Ok(())
+    }
+}
+
+fn dispatch(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    bind_group: &wgpu::BindGroup,
+    groups: (u32, u32),
+) {
+    let mut pass = encoder.begin_compute_pass(&Default::default());
+    pass.set_pipeline(pipeline);
+    pass.set_bind_group(0, bind_group, &[]);
+    pass.dispatch_workgroups(groups.0, groups.1, 1);
+}
+
+/// Workgroups covering the map with the shaders' 8x8 cell groups.
+fn cell_groups(hm: &Heightmap) -> (u32, u32) {
+    (hm.width.div_ceil(8), hm.height.div_ceil(8))
+}
+
+/// GPU version of `thermal::erode`. On abort the partial result is kept, as on the CPU.
+/// The shader only knows clamped edges.
+pub fn thermal(
+    hm: &mut Heightmap,
+    params: &ThermalParams,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) -> Result<(), String> {
+    if boundary != Boundary::Clamp {
+        return Err("The GPU backend only supports clamped edges".into());
+    }
+    let gpu = gpu()?;
+    let cells = hm.data.len() as u64;
+    gpu.check_size(cells * 16)?;
+
+    let usage = wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC;
+    let heights_bytes = to_bytes(hm.data.iter().map(|v| v.to_bits()));
+    let ping = gpu.upload("heights", &heights_bytes, usage);
+    let pong = gpu.buffer("heights", cells * 4, usage);
+    let outflow = gpu.buffer("outflow", cells * 16, wgpu::BufferUsages::STORAGE);
+    let uniform = gpu.upload(
+        "thermal params",
+        &to_bytes([
+            hm.width,
+            hm.height,
+            params.talus.to_bits(),
+            params.transfer_rate.to_bits(),
+            (1.0 / hm.width as f32).to_bits(),
+            0,
+            0,
+            0,
+        ]),
+        wgpu::BufferUsages::UNIFORM,
+    );
+    // Each iteration reads one heights buffer and writes the other
+    let bind_groups = [
+        gpu.bind_group(&gpu.thermal_layout, &[&uniform, &ping, &outflow, &pong]),
+        gpu.bind_group(&gpu.thermal_layout, &[&uniform, &pong, &outflow, &ping]),
+    ];
+
+    let groups = cell_groups(hm);
+    let mut done = 0;
+    while done < params.iterations && !abort.load(Ordering::Relaxed) {
+        progress(done as f32 / params.iterations as f32);
+        let count = THERMAL_ITERATIONS_PER_SUBMIT.min(params.iterations - done);
+        let mut encoder = gpu.device.create_command_encoder(&Default::default());
+        for i in done..done + count {
+            let bind_group = &bind_groups[(i % 2) as usize];
+            dispatch(&mut encoder, &gpu.thermal_outflow, bind_group, groups);
+            dispatch(&mut encoder, &gpu.thermal_gather, bind_group, groups);
+        }
+        gpu.submit_and_wait(encoder)?;
+        done += count;
+    }
+
+    gpu.read_back(if done % 2 == 0 { &ping } else { &pong }, hm)
+}
+
+/// GPU version of `hydraulic::erode`. On abort the partial result is kept, as on the CPU.
+/// Droplets handle fixed, mirrored and clamped edges as on the CPU; only wrapping
+/// needs the CPU.
+pub fn hydraulic(
+    hm: &mut Heightmap,
+    params: &HydraulicParams,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) -> Result<(), String> {
+    if boundary.wraps() {
+        return Err("The GPU backend doesn't support wrapping edges".into());
+    }
+    let gpu = gpu()?;
+    let cells = hm.data.len() as u64;
+    gpu.check_size(cells * 4)?;
+
+    let heights_bytes = to_bytes(hm.data.iter().map(|v| v.to_bits()));
+    let heights = gpu.upload("heights", &heights_bytes, wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC);
+    let delta = gpu.buffer("delta", cells * 4, wgpu::BufferUsages::STORAGE);
+    let brush: Vec<u32> = hydraulic::compute_erosion_brush(params.erosion_radius as i32)
+        .into_iter()
+        .flat_map(|(dx, dy, weight)| [(dx as f32).to_bits(), (dy as f32).to_bits(), weight.to_bits(), 0])
+        .collect();
+    let brush = gpu.upload("brush", &to_bytes(brush), wgpu::BufferUsages::STORAGE);
+    let uniform = gpu.buffer("hydraulic params", 64, wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST);
+    let bind_group = gpu.bind_group(&gpu.hydraulic_layout, &[&uniform, &heights, &delta, &brush]);
+
+    let edge: u32 = match boundary {
+        Boundary::Fixed { .. } => 0,
+        Boundary::Mirror => 1,
+        _ => 2,
+    };
+    let seed = params.seed.unwrap_or_else(rand::random);
+    let groups = cell_groups(hm);
+    let mut done = 0;
+    while done < params.num_droplets && !abort.load(Ordering::Relaxed) {
+        progress(done as f32 / params.num_droplets as f32);
+        let count = DROPLETS_PER_BATCH.min(params.num_droplets - done);
+        let batch = to_bytes([
+            hm.width,
+            hm.height,
+            params.max_lifetime,
+            done,
+            count,
+            seed as u32,
+            (seed >> 32) as u32,
+            edge,
+            params.erosion_rate.to_bits(),
+            params.deposition_rate.to_bits(),
+            params.evaporation_rate.to_bits(),
+            params.inertia.to_bits(),
+            params.min_slope.to_bits(),
+            params.capacity_factor.to_bits(),
+            params.gravity.to_bits(),
+            0,
+        ]);
+        gpu.queue.write_buffer(&uniform, 0, &batch);
+
+        let mut encoder = gpu.device.create_command_encoder(&Default::default());
+        dispatch(&mut encoder, &gpu.hydraulic_droplets, &bind_group, (count.div_ceil(64), 1));
+        dispatch(&mut encoder, &gpu.hydraulic_apply, &bind_group, groups);
+        gpu.submit_and_wait(encoder)?;
+        done += count;
+    }
+
+    gpu.read_back(&heights, hm)?;
+    progress(1.0);
+    Ok(())
+}
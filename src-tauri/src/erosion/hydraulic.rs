@@ -1,9 +1,15 @@
-use rand::Rng;
-use serde::Deserialize;
+use rand::rngs::StdRng;
+use rayon::prelude::*;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
 use std::sync::atomic::{AtomicBool, Ordering};
+use crate::boundary::Boundary;
 use crate::heightmap::Heightmap;
+use super::strata::Strata;
+use super::{Backend, Masks, Pause};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct HydraulicParams {
     pub num_droplets: u32,
@@ -16,120 +22,574 @@ pub struct HydraulicParams {
     pub capacity_factor: f32,
     pub erosion_radius: u32,
     pub gravity: f32,
+    /// Fixed droplet seed for reproducible runs; random when unset.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub backend: Backend,
+    /// Record `ErosionMaps` for texturing. Always runs on the CPU.
+    #[serde(default)]
+    pub record_maps: bool,
+    /// Record the paths of about this many droplets, spread evenly over the run,
+    /// for debugging where erosion concentrates. Always runs on the CPU.
+    #[serde(default)]
+    pub trace_droplets: u32,
+    /// Restrict the run to the selection mask, as in `NoiseParams`.
+    #[serde(default)]
+    pub use_selection: bool,
+    /// Feather radius in pixels applied to the mask.
+    #[serde(default)]
+    pub mask_feather: u32,
+    /// Layered rock whose hardness scales erosion by depth.
+    #[serde(default)]
+    pub strata: Option<Strata>,
 }
 
+/// Auxiliary outputs of a hydraulic run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErosionMapKind {
+    Erosion,
+    Deposition,
+    Flow,
+    Wetness,
+}
+
+impl ErosionMapKind {
+    pub const ALL: [ErosionMapKind; 4] =
+        [ErosionMapKind::Erosion, ErosionMapKind::Deposition, ErosionMapKind::Flow, ErosionMapKind::Wetness];
+
+    /// Name used for export hooks and file names.
+    pub fn id(self) -> &'static str {
+        match self {
+            ErosionMapKind::Erosion => "erosion",
+            ErosionMapKind::Deposition => "deposition",
+            ErosionMapKind::Flow => "flow",
+            ErosionMapKind::Wetness => "wetness",
+        }
+    }
+}
+
+/// Per-pixel record of where a hydraulic run moved material and water, the usual
+/// inputs for texturing. Indexed by `ErosionMapKind`:
+/// - erosion: height removed, in normalized units
+/// - deposition: height laid down
+/// - flow: droplet steps through the pixel
+/// - wetness: water soaked in, i.e. what droplets lost to evaporation there plus
+///   whatever they still held when their lifetime ran out
+#[derive(Clone)]
+pub struct ErosionMaps {
+    layers: [Heightmap; 4],
+}
+
+impl ErosionMaps {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self { layers: std::array::from_fn(|_| Heightmap::new(width, height)) }
+    }
+
+    /// Maps in `ErosionMapKind::ALL` order, e.g. as read back from a project.
+    pub fn from_layers(layers: [Heightmap; 4]) -> Self {
+        Self { layers }
+    }
+
+    pub fn get(&self, kind: ErosionMapKind) -> &Heightmap {
+        &self.layers[kind as usize]
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.layers[0].width, self.layers[0].height)
+    }
+
+    /// `kind` scaled to [0, 1] by its maximum. Flow and wetness are heavy-tailed,
+    /// a few channels collecting most of the water, so they're scaled
+    /// logarithmically to keep the smaller streams visible.
+    pub fn normalized(&self, kind: ErosionMapKind) -> Heightmap {
+        let mut map = self.get(kind).clone();
+        let logarithmic = matches!(kind, ErosionMapKind::Flow | ErosionMapKind::Wetness);
+        let scale = |v: f32| if logarithmic { v.max(0.0).ln_1p() } else { v.max(0.0) };
+        let max = map.data.iter().fold(0.0f32, |m, &v| m.max(scale(v)));
+        if max > 0.0 {
+            map.data.par_iter_mut().for_each(|v| *v = scale(*v) / max);
+        }
+        map
+    }
+}
+
+/// One step of a traced droplet: where it was, the terrain height there and the
+/// sediment it carried.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TracePoint {
+    pub x: f32,
+    pub y: f32,
+    pub height: f32,
+    pub sediment: f32,
+}
+
+/// Path of one droplet from where it spawned to where it evaporated or left the
+/// map.
+pub type DropletTrace = Vec<TracePoint>;
+
+/// What a hydraulic run recorded besides the terrain.
+#[derive(Default)]
+pub struct Recording {
+    /// With `params.record_maps`.
+    pub maps: Option<ErosionMaps>,
+    /// With `params.trace_droplets`; empty otherwise.
+    pub traces: Vec<DropletTrace>,
+}
+
+/// Droplets simulated between progress reports and abort checks.
+const DROPLETS_PER_ROUND: u32 = 10_000;
+
+/// Rows of the heightmap that one batch of droplets may touch. Coordinates stay
+/// global; `y0` is the first row held in `data`.
+struct Band<'a> {
+    data: &'a mut [f32],
+    /// The same rows of each `ErosionMaps` layer; empty when not recording.
+    layers: Vec<&'a mut [f32]>,
+    /// Whole-map hardness, see `erode`.
+    hardness: Option<&'a [f32]>,
+    strata: Option<&'a Strata>,
+    width: u32,
+    height: u32,
+    y0: u32,
+    boundary: Boundary,
+}
+
+impl Band<'_> {
+    fn get(&self, x: i64, y: i64) -> f32 {
+        self.boundary.read(x, y, self.width, self.height, |x, y| {
+            self.data[((y - self.y0) * self.width + x) as usize]
+        })
+    }
+
+    /// Offset in `data` of a pixel written at `(x, y)`, if the write lands on the map.
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let x = self.boundary.resolve_write(x, self.width)?;
+        let y = self.boundary.resolve_write(y, self.height)?;
+        Some(((y - self.y0) * self.width + x) as usize)
+    }
+
+    fn add(&mut self, x: i64, y: i64, value: f32) {
+        if let Some(i) = self.index(x, y) {
+            self.data[i] += value;
+        }
+    }
+
+    /// Share of the requested erosion a pixel written at `(x, y)` gives up.
+    fn softness(&self, x: i64, y: i64) -> f32 {
+        if self.hardness.is_none() && self.strata.is_none() {
+            return 1.0;
+        }
+        let x = self.boundary.resolve_write(x, self.width);
+        let y = self.boundary.resolve_write(y, self.height);
+        let Some((x, y)) = x.zip(y) else {
+            return 1.0;
+        };
+        let mut softness = 1.0;
+        if let Some(hardness) = self.hardness {
+            softness *= 1.0 - hardness[(y * self.width + x) as usize].clamp(0.0, 1.0);
+        }
+        if let Some(strata) = self.strata {
+            let height = self.data[((y - self.y0) * self.width + x) as usize];
+            let layer = strata.layer_at(x as f32, y as f32, height, self.width);
+            softness *= 1.0 - layer.hardness.clamp(0.0, 1.0);
+        }
+        softness
+    }
+
+    fn record(&mut self, kind: ErosionMapKind, x: i64, y: i64, value: f32) {
+        if self.layers.is_empty() {
+            return;
+        }
+        if let Some(i) = self.index(x, y) {
+            self.layers[kind as usize][i] += value;
+        }
+    }
+}
+
+/// Droplets are simulated in parallel over horizontal bands. A droplet moves one
+/// pixel per step, so everything it touches lies within `reach` rows of where it
+/// spawned; bands are at least twice that tall, which makes every other band's
+/// reach disjoint. Each round runs the even bands in parallel, then the odd ones.
+/// Per-band RNGs are seeded from the run seed, so results don't depend on the
+/// thread count.
+///
+/// At the map edge droplets follow `boundary`: on a wrapped map they continue
+/// from the opposite side, so a droplet can reach any row and wrapped maps run as
+/// a single band. Past a fixed edge they flow off the map with their sediment, a
+/// mirrored edge reflects them back in, and a clamped edge holds them against
+/// it, where they drop their load as they slow.
+///
+/// Hardness and the hardness of the stratum at each pixel's depth scale erosion
+/// down locally: fully hard pixels are never cut, though sediment still settles
+/// on them. With a selection, droplets only spawn inside
+/// it, in proportion to its weight, and the result and recorded maps are blended
+/// into the original terrain by the same weights. Masks, strata and recording
+/// all run on the CPU.
+///
+/// `snapshot`, when given, sees the terrain after every `DROPLETS_PER_ROUND`
+/// droplets, already blended by the selection. GPU runs don't report snapshots.
+///
+/// With `pause`, the run suspends between the same rounds whenever a pause is
+/// requested and continues from whatever terrain `wait` leaves behind, with the
+/// droplets still to come and their random seeds unchanged. `wait` sees the
+/// terrain blended by the selection, and edits it makes are kept inside the
+/// selection and out. GPU runs can't pause.
+///
+/// Returns the recorded maps and droplet traces when `params.record_maps` or
+/// `params.trace_droplets` ask for them, also for an aborted run. Tracing doesn't
+/// change the outcome.
+#[allow(clippy::too_many_arguments)]
 pub fn erode(
     hm: &mut Heightmap,
     params: &HydraulicParams,
+    masks: Masks,
+    boundary: Boundary,
     abort: &AtomicBool,
     progress: &dyn Fn(f32),
-) {
-    let mut rng = rand::thread_rng();
-    let w = hm.width as f32;
-    let h = hm.height as f32;
+    snapshot: Option<&dyn Fn(&Heightmap)>,
+    pause: Option<&Pause>,
+) -> Recording {
+    let original = RefCell::new(masks.selection.map(|_| hm.data.clone()));
+    let blended_snapshot = |hm: &Heightmap| {
+        let Some(snapshot) = snapshot else {
+            return;
+        };
+        match (&*original.borrow(), masks.selection) {
+            (Some(original), Some(selection)) => {
+                let mut blended = hm.clone();
+                super::restrict(&mut blended, original, selection);
+                snapshot(&blended);
+            }
+            _ => snapshot(hm),
+        }
+    };
+    let snapshot = snapshot.map(|_| &blended_snapshot as &dyn Fn(&Heightmap));
+    let blended_wait = |hm: &mut Heightmap| {
+        let Some(pause) = pause else {
+            return;
+        };
+        match (&mut *original.borrow_mut(), masks.selection) {
+            (Some(original), Some(selection)) => {
+                let mut blended = hm.clone();
+                super::restrict(&mut blended, original, selection);
+                let shown = blended.data.clone();
+                (pause.wait)(&mut blended);
+                // Carry edits made meanwhile into both the run and the terrain it
+                // blends into
+                for ((value, orig), (&edited, &shown)) in
+                    hm.data.iter_mut().zip(original.iter_mut()).zip(blended.data.iter().zip(&shown))
+                {
+                    *value += edited - shown;
+                    *orig += edited - shown;
+                }
+            }
+            _ => (pause.wait)(hm),
+        }
+    };
+    let pause = pause.map(|pause| Pause { requested: pause.requested, wait: &blended_wait });
+    let mut recording = erode_everywhere(hm, params, masks, boundary, abort, progress, snapshot, pause.as_ref());
+    if let (Some(original), Some(selection)) = (original.into_inner(), masks.selection) {
+        super::restrict(hm, &original, selection);
+        for map in recording.maps.iter_mut().flat_map(|m| m.layers.iter_mut()) {
+            for (v, &w) in map.data.iter_mut().zip(selection) {
+                *v *= w.clamp(0.0, 1.0);
+            }
+        }
+    }
+    recording
+}
+
+#[allow(clippy::too_many_arguments)]
+fn erode_everywhere(
+    hm: &mut Heightmap,
+    params: &HydraulicParams,
+    masks: Masks,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+    snapshot: Option<&dyn Fn(&Heightmap)>,
+    pause: Option<&Pause>,
+) -> Recording {
+    #[cfg(feature = "gpu")]
+    {
+        let cpu_only = params.record_maps
+            || params.trace_droplets > 0
+            || params.strata.is_some()
+            || masks.hardness.is_some()
+            || masks.selection.is_some();
+        if params.backend == Backend::Gpu
+            && !cpu_only
+            && super::gpu_succeeded(super::gpu::hydraulic(hm, params, boundary, abort, progress))
+        {
+            return Recording::default();
+        }
+    }
+
+    let base_seed = params.seed.unwrap_or_else(|| rand::thread_rng().gen());
     let brush = compute_erosion_brush(params.erosion_radius as i32);
+    let (width, height) = (hm.width, hm.height);
+    let mut maps = params.record_maps.then(|| ErosionMaps::new(width, height));
+    let mut traces = Vec::new();
+    // Trace every droplet whose number in the run is a multiple of this
+    let trace_stride = (params.trace_droplets > 0).then(|| (params.num_droplets / params.trace_droplets).max(1));
+
+    let reach = params.max_lifetime + params.erosion_radius + 2;
+    let band_rows = if boundary.wraps() { height } else { (2 * reach).max(32) };
+    let band_count = height.div_ceil(band_rows);
+    // Droplets spawn in rows [0.5, height - 1.5)
+    let spawn_rows = |band: u32| {
+        let top = (band * band_rows) as f32;
+        let bottom = ((band + 1) * band_rows).min(height) as f32;
+        (top.max(0.5), bottom.min(height as f32 - 1.5))
+    };
+    let total_rows = (height as f32 - 2.0).max(1.0);
+
+    let rounds = params.num_droplets.div_ceil(DROPLETS_PER_ROUND);
+    for round in 0..rounds {
+        if abort.load(Ordering::Relaxed) {
+            return Recording { maps, traces };
+        }
+        progress(round as f32 / rounds as f32);
+        let first = round * DROPLETS_PER_ROUND;
+        let round_droplets = DROPLETS_PER_ROUND.min(params.num_droplets - first);
 
-    for i in 0..params.num_droplets {
-        if i % 1000 == 0 {
-            if abort.load(Ordering::Relaxed) {
-                return;
+        for parity in 0..2 {
+            // Carve out each band's reach as a disjoint slice of the map and of
+            // every recorded layer
+            let mut jobs = Vec::new();
+            let mut rests: Vec<&mut [f32]> = std::iter::once(&mut hm.data[..])
+                .chain(maps.iter_mut().flat_map(|m| m.layers.iter_mut().map(|l| &mut l.data[..])))
+                .collect();
+            let mut rest_y0 = 0;
+            for band in (parity..band_count).step_by(2) {
+                let y0 = (band * band_rows).saturating_sub(reach);
+                let y1 = ((band + 1) * band_rows + reach).min(height);
+                let mut slices = rests.iter_mut().map(|rest| {
+                    let (_, tail) = std::mem::take(rest).split_at_mut(((y0 - rest_y0) * width) as usize);
+                    let (slice, tail) = tail.split_at_mut(((y1 - y0) * width) as usize);
+                    *rest = tail;
+                    slice
+                });
+                let data = slices.next().unwrap();
+                let layers: Vec<&mut [f32]> = slices.collect();
+                rest_y0 = y1;
+
+                let (top, bottom) = spawn_rows(band);
+                if bottom <= top {
+                    continue;
+                }
+                // Split the round's droplets by spawn area, rounding cumulatively
+                let share = |y: f32| ((y - 0.5) / total_rows * round_droplets as f32).round() as u32;
+                let count = share(bottom) - share(top);
+                let seed = base_seed ^ ((round as u64) << 32 | band as u64);
+                // Number of the band's first droplet in the whole run
+                let number = first + share(top);
+                jobs.push((Band {
+                    data,
+                    layers,
+                    hardness: masks.hardness,
+                    strata: params.strata.as_ref(),
+                    width,
+                    height,
+                    y0,
+                    boundary,
+                }, top, bottom, count, seed, number));
             }
-            progress(i as f32 / params.num_droplets as f32);
+
+            let band_traces: Vec<Vec<DropletTrace>> = jobs
+                .into_par_iter()
+                .map(|(mut band, top, bottom, count, seed, number)| {
+                    let mut rng = StdRng::seed_from_u64(seed);
+                    let mut band_traces = Vec::new();
+                    for i in 0..count {
+                        let px = rng.gen::<f32>() * (width as f32 - 2.0) + 0.5;
+                        let py = top + rng.gen::<f32>() * (bottom - top);
+                        if let Some(selection) = masks.selection {
+                            let weight = selection[py.round() as usize * width as usize + px.round() as usize];
+                            if rng.gen::<f32>() >= weight {
+                                continue;
+                            }
+                        }
+                        let traced = trace_stride.is_some_and(|stride| (number + i) % stride == 0);
+                        let mut trace = traced.then(Vec::new);
+                        simulate_droplet(&mut band, params, &brush, &mut rng, px, py, trace.as_mut());
+                        band_traces.extend(trace);
+                    }
+                    band_traces
+                })
+                .collect();
+            traces.extend(band_traces.into_iter().flatten());
+        }
+        if let Some(snapshot) = snapshot {
+            snapshot(hm);
+        }
+        // Seeds depend only on the round, so a suspended run picks up exactly
+        // where it stopped
+        if let Some(pause) = pause.filter(|p| round + 1 < rounds && p.requested.load(Ordering::Relaxed)) {
+            (pause.wait)(hm);
         }
+    }
 
-        let mut px = rng.gen::<f32>() * (w - 2.0) + 0.5;
-        let mut py = rng.gen::<f32>() * (h - 2.0) + 0.5;
-        let mut dx = 0.0f32;
-        let mut dy = 0.0f32;
-        let mut speed = 1.0f32;
-        let mut water = 1.0f32;
-        let mut sediment = 0.0f32;
+    traces.truncate(params.trace_droplets as usize);
+    progress(1.0);
+    Recording { maps, traces }
+}
 
-        for _ in 0..params.max_lifetime {
-            let (gx, gy, h_here) = gradient_at(hm, px, py);
+fn simulate_droplet(
+    hm: &mut Band,
+    params: &HydraulicParams,
+    brush: &[(i32, i32, f32)],
+    rng: &mut StdRng,
+    mut px: f32,
+    mut py: f32,
+    mut trace: Option<&mut DropletTrace>,
+) {
+    let w = hm.width as f32;
+    let h = hm.height as f32;
+    let mut dx = 0.0f32;
+    let mut dy = 0.0f32;
+    let mut speed = 1.0f32;
+    let mut water = 1.0f32;
+    let mut sediment = 0.0f32;
+    let mut left_map = false;
+
+    for _ in 0..params.max_lifetime {
+        let (gx, gy, h_here) = gradient_at(hm, px, py);
+        hm.record(ErosionMapKind::Flow, px.round() as i64, py.round() as i64, 1.0);
+        if let Some(trace) = trace.as_mut() {
+            trace.push(TracePoint { x: px, y: py, height: h_here, sediment });
+        }
 
-            dx = dx * params.inertia - gx * (1.0 - params.inertia);
-            dy = dy * params.inertia - gy * (1.0 - params.inertia);
+        dx = dx * params.inertia - gx * (1.0 - params.inertia);
+        dy = dy * params.inertia - gy * (1.0 - params.inertia);
 
-            let len = (dx * dx + dy * dy).sqrt();
-            if len < 1e-6 {
-                let angle = rng.gen::<f32>() * std::f32::consts::TAU;
-                dx = angle.cos();
-                dy = angle.sin();
-            } else {
-                dx /= len;
-                dy /= len;
-            }
+        let len = (dx * dx + dy * dy).sqrt();
+        if len < 1e-6 {
+            let angle = rng.gen::<f32>() * std::f32::consts::TAU;
+            dx = angle.cos();
+            dy = angle.sin();
+        } else {
+            dx /= len;
+            dy /= len;
+        }
 
-            let new_px = px + dx;
-            let new_py = py + dy;
+        let mut new_px = px + dx;
+        let mut new_py = py + dy;
 
-            if new_px < 0.5 || new_px >= w - 1.5 || new_py < 0.5 || new_py >= h - 1.5 {
+        match hm.boundary {
+            Boundary::Wrap => {
+                new_px = new_px.rem_euclid(w);
+                new_py = new_py.rem_euclid(h);
+            }
+            _ if (0.5..w - 1.5).contains(&new_px) && (0.5..h - 1.5).contains(&new_py) => {}
+            Boundary::Fixed { .. } => {
+                left_map = true;
                 break;
             }
+            Boundary::Mirror => {
+                (new_px, dx) = reflect(new_px, dx, w);
+                (new_py, dy) = reflect(new_py, dy, h);
+            }
+            Boundary::Clamp => {
+                (new_px, dx) = hold(new_px, dx, w);
+                (new_py, dy) = hold(new_py, dy, h);
+            }
+        }
 
-            let h_new = interpolate_height(hm, new_px, new_py);
-            let h_diff = h_new - h_here;
+        let h_new = interpolate_height(hm, new_px, new_py);
+        let h_diff = h_new - h_here;
 
-            let capacity = (-h_diff).max(params.min_slope) * speed * water * params.capacity_factor;
+        let capacity = (-h_diff).max(params.min_slope) * speed * water * params.capacity_factor;
 
-            if sediment > capacity || h_diff > 0.0 {
-                let deposit = if h_diff > 0.0 {
-                    sediment.min(h_diff)
-                } else {
-                    (sediment - capacity) * params.deposition_rate
-                };
-                sediment -= deposit;
-                deposit_at(hm, px, py, deposit);
+        if sediment > capacity || h_diff > 0.0 {
+            let deposit = if h_diff > 0.0 {
+                sediment.min(h_diff)
             } else {
-                let erode_amount =
-                    ((capacity - sediment) * params.erosion_rate).min(-h_diff);
-                erode_at(hm, px, py, erode_amount, &brush);
-                sediment += erode_amount;
-            }
+                (sediment - capacity) * params.deposition_rate
+            };
+            sediment -= deposit;
+            deposit_at(hm, px, py, deposit);
+        } else {
+            let erode_amount =
+                ((capacity - sediment) * params.erosion_rate).min(-h_diff);
+            sediment += erode_at(hm, px, py, erode_amount, brush);
+        }
 
-            speed = (speed * speed + h_diff * params.gravity).max(0.0).sqrt();
-            water *= 1.0 - params.evaporation_rate;
-            px = new_px;
-            py = new_py;
+        speed = (speed * speed + h_diff * params.gravity).max(0.0).sqrt();
+        hm.record(ErosionMapKind::Wetness, px.round() as i64, py.round() as i64, water * params.evaporation_rate);
+        water *= 1.0 - params.evaporation_rate;
+        px = new_px;
+        py = new_py;
+    }
+
+    if !left_map {
+        hm.record(ErosionMapKind::Wetness, px.round() as i64, py.round() as i64, water);
+        if let Some(trace) = trace {
+            trace.push(TracePoint { x: px, y: py, height: interpolate_height(hm, px, py), sediment });
         }
     }
+}
 
-    progress(1.0);
+/// Droplets stay in [0.5, n - 1.5) on an axis of length `n`, where bilinear
+/// samples have both neighbors on the map.
+fn droplet_range(n: f32) -> (f32, f32) {
+    (0.5, n - 1.5 - f32::EPSILON * n)
+}
+
+/// Bounce a droplet that crossed the edge of an axis of length `n` back in, the
+/// way it would continue over the mirrored terrain, reversing its direction `d`.
+fn reflect(p: f32, d: f32, n: f32) -> (f32, f32) {
+    let (lo, hi) = droplet_range(n);
+    if p < lo {
+        ((2.0 * lo - p).min(hi), -d)
+    } else if p > hi {
+        ((2.0 * hi - p).max(lo), -d)
+    } else {
+        (p, d)
+    }
 }
 
-fn interpolate_height(hm: &Heightmap, x: f32, y: f32) -> f32 {
-    let ix = x as u32;
-    let iy = y as u32;
+/// Stop a droplet at the edge of an axis of length `n`: past it the terrain is
+/// flat at the edge height, so the droplet stays against the edge, keeps only
+/// the part of its direction along it, and settles its load there.
+fn hold(p: f32, d: f32, n: f32) -> (f32, f32) {
+    let (lo, hi) = droplet_range(n);
+    if p < lo || p > hi {
+        (p.clamp(lo, hi), 0.0)
+    } else {
+        (p, d)
+    }
+}
+
+fn interpolate_height(hm: &Band, x: f32, y: f32) -> f32 {
+    let ix = x as i64;
+    let iy = y as i64;
     let fx = x - ix as f32;
     let fy = y - iy as f32;
 
     let tl = hm.get(ix, iy);
-    let tr = hm.get((ix + 1).min(hm.width - 1), iy);
-    let bl = hm.get(ix, (iy + 1).min(hm.height - 1));
-    let br = hm.get(
-        (ix + 1).min(hm.width - 1),
-        (iy + 1).min(hm.height - 1),
-    );
+    let tr = hm.get(ix + 1, iy);
+    let bl = hm.get(ix, iy + 1);
+    let br = hm.get(ix + 1, iy + 1);
 
     let top = tl + (tr - tl) * fx;
     let bot = bl + (br - bl) * fx;
     top + (bot - top) * fy
 }
 
-fn gradient_at(hm: &Heightmap, x: f32, y: f32) -> (f32, f32, f32) {
-    let ix = x as u32;
-    let iy = y as u32;
+fn gradient_at(hm: &Band, x: f32, y: f32) -> (f32, f32, f32) {
+    let ix = x as i64;
+    let iy = y as i64;
     let fx = x - ix as f32;
     let fy = y - iy as f32;
 
     let tl = hm.get(ix, iy);
-    let tr = hm.get((ix + 1).min(hm.width - 1), iy);
-    let bl = hm.get(ix, (iy + 1).min(hm.height - 1));
-    let br = hm.get(
-        (ix + 1).min(hm.width - 1),
-        (iy + 1).min(hm.height - 1),
-    );
+    let tr = hm.get(ix + 1, iy);
+    let bl = hm.get(ix, iy + 1);
+    let br = hm.get(ix + 1, iy + 1);
 
     let gx = (tr - tl) * (1.0 - fy) + (br - bl) * fy;
     let gy = (bl - tl) * (1.0 - fx) + (br - tr) * fx;
@@ -138,46 +598,47 @@ fn gradient_at(hm: &Heightmap, x: f32, y: f32) -> (f32, f32, f32) {
     (gx, gy, height)
 }
 
-fn deposit_at(hm: &mut Heightmap, x: f32, y: f32, amount: f32) {
-    let ix = x as u32;
-    let iy = y as u32;
+fn deposit_at(hm: &mut Band, x: f32, y: f32, amount: f32) {
+    let ix = x as i64;
+    let iy = y as i64;
     let fx = x - ix as f32;
     let fy = y - iy as f32;
 
-    let w = hm.width;
-    let h = hm.height;
-
     // Bilinear distribution
     let weights = [
         ((1.0 - fx) * (1.0 - fy), ix, iy),
-        (fx * (1.0 - fy), (ix + 1).min(w - 1), iy),
-        ((1.0 - fx) * fy, ix, (iy + 1).min(h - 1)),
-        (fx * fy, (ix + 1).min(w - 1), (iy + 1).min(h - 1)),
+        (fx * (1.0 - fy), ix + 1, iy),
+        ((1.0 - fx) * fy, ix, iy + 1),
+        (fx * fy, ix + 1, iy + 1),
     ];
 
     for &(weight, cx, cy) in &weights {
-        let idx = (cy * w + cx) as usize;
-        hm.data[idx] += amount * weight;
+        hm.add(cx, cy, amount * weight);
+        hm.record(ErosionMapKind::Deposition, cx, cy, amount * weight);
     }
 }
 
-fn erode_at(hm: &mut Heightmap, x: f32, y: f32, amount: f32, brush: &[(i32, i32, f32)]) {
-    let ix = x.round() as i32;
-    let iy = y.round() as i32;
-    let w = hm.width as i32;
-    let h = hm.height as i32;
+/// Returns the height actually removed, less than `amount` under hard pixels.
+fn erode_at(hm: &mut Band, x: f32, y: f32, amount: f32, brush: &[(i32, i32, f32)]) -> f32 {
+    let ix = x.round() as i64;
+    let iy = y.round() as i64;
 
+    let mut removed = 0.0;
     for &(bx, by, weight) in brush {
-        let cx = ix + bx;
-        let cy = iy + by;
-        if cx >= 0 && cx < w && cy >= 0 && cy < h {
-            let idx = (cy * w + cx) as usize;
-            hm.data[idx] -= amount * weight;
-        }
+        let (cx, cy) = (ix + bx as i64, iy + by as i64);
+        let cut = amount * weight * hm.softness(cx, cy);
+        hm.add(cx, cy, -cut);
+        hm.record(ErosionMapKind::Erosion, cx, cy, cut);
+        removed += cut;
+    }
+    if hm.hardness.is_some() || hm.strata.is_some() {
+        removed
+    } else {
+        amount
     }
 }
 
-fn compute_erosion_brush(radius: i32) -> Vec<(i32, i32, f32)> {
+pub(super) fn compute_erosion_brush(radius: i32) -> Vec<(i32, i32, f32)> {
     let mut offsets = Vec::new();
     let mut total_weight = 0.0f32;
 
@@ -201,3 +662,92 @@ fn compute_erosion_brush(radius: i32) -> Vec<(i32, i32, f32)> {
 
     offsets
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(num_droplets: u32) -> HydraulicParams {
+        HydraulicParams {
+            num_droplets,
+            max_lifetime: 30,
+            erosion_rate: 0.3,
+            deposition_rate: 0.3,
+            evaporation_rate: 0.01,
+            inertia: 0.05,
+            min_slope: 0.01,
+            capacity_factor: 4.0,
+            erosion_radius: 3,
+            gravity: 4.0,
+            seed: Some(7),
+            backend: Backend::Cpu,
+            record_maps: false,
+            trace_droplets: 0,
+            use_selection: false,
+            mask_feather: 0,
+            strata: None,
+        }
+    }
+
+    /// Rolling hills, tall enough for several bands.
+    fn hills() -> Heightmap {
+        let (width, height) = (96, 200);
+        let data = (0..width * height)
+            .map(|i| {
+                let (x, y) = ((i % width) as f32, (i / width) as f32);
+                0.5 + 0.2 * (x * 0.13).sin() * (y * 0.07).cos() + 0.001 * y
+            })
+            .collect();
+        Heightmap { data, width, height }
+    }
+
+    fn run(hm: &mut Heightmap, params: &HydraulicParams, masks: Masks, pause: Option<&Pause>) {
+        let abort = AtomicBool::new(false);
+        erode(hm, params, masks, Boundary::Clamp, &abort, &|_| {}, None, pause);
+    }
+
+    #[test]
+    fn bands_give_the_same_result_on_any_number_of_threads() {
+        let params = params(25_000);
+        let on = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let mut hm = hills();
+            pool.install(|| run(&mut hm, &params, Masks::default(), None));
+            hm
+        };
+        let single = on(1);
+        assert_ne!(single.data, hills().data);
+        assert_eq!(on(4).data, single.data);
+    }
+
+    #[test]
+    fn pausing_between_rounds_changes_nothing() {
+        let params = params(25_000);
+        let mut straight = hills();
+        run(&mut straight, &params, Masks::default(), None);
+
+        let requested = AtomicBool::new(true);
+        let pauses = std::cell::Cell::new(0);
+        let wait = |_: &mut Heightmap| pauses.set(pauses.get() + 1);
+        let mut paused = hills();
+        run(&mut paused, &params, Masks::default(), Some(&Pause { requested: &requested, wait: &wait }));
+        assert_eq!(pauses.get(), 2);
+        assert_eq!(paused.data, straight.data);
+    }
+
+    #[test]
+    fn empty_selection_leaves_the_terrain_alone() {
+        let mut hm = hills();
+        let selection = vec![0.0; hm.data.len()];
+        run(&mut hm, &params(5_000), Masks { hardness: None, selection: Some(&selection) }, None);
+        assert_eq!(hm.data, hills().data);
+    }
+
+    #[test]
+    fn aborted_run_leaves_the_terrain_alone() {
+        let mut hm = hills();
+        let abort = AtomicBool::new(true);
+        erode(&mut hm, &params(5_000), Masks::default(), Boundary::Clamp, &abort, &|_| {}, None, None);
+        assert_eq!(hm.data, hills().data);
+    }
+}
@@ -1,2 +1,177 @@
 pub mod thermal;
 pub mod hydraulic;
+pub mod pipe;
+pub mod stream_power;
+pub mod glacial;
+pub mod coastal;
+pub mod strata;
+#[cfg(feature = "gpu")]
+mod gpu;
+
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::Mutex;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+
+/// Where erosion runs. GPU runs need the `gpu` feature; without it, or when the
+/// GPU run fails, erosion falls back to the CPU.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backend {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+/// Whether `Backend::Gpu` can run: the build has the `gpu` feature and an adapter
+/// was found.
+pub fn gpu_available() -> bool {
+    #[cfg(feature = "gpu")]
+    return gpu::available();
+    #[cfg(not(feature = "gpu"))]
+    false
+}
+
+/// GPU runs that failed and were redone on the CPU since startup.
+static GPU_FALLBACKS: AtomicU32 = AtomicU32::new(0);
+/// Why the last GPU run failed; cleared when one succeeds.
+static GPU_ERROR: Mutex<Option<String>> = Mutex::new(None);
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GpuStatus {
+    /// See [`gpu_available`].
+    pub available: bool,
+    /// Why the last GPU run fell back to the CPU, if it did.
+    pub last_error: Option<String>,
+}
+
+pub fn gpu_status() -> GpuStatus {
+    GpuStatus { available: gpu_available(), last_error: GPU_ERROR.lock().unwrap().clone() }
+}
+
+/// Count of GPU runs that have fallen back to the CPU; a run fell back if this
+/// changed while it ran.
+pub fn gpu_fallbacks() -> u32 {
+    GPU_FALLBACKS.load(Ordering::SeqCst)
+}
+
+/// Note the outcome of a GPU run, returning whether it succeeded. A failure is
+/// kept for [`gpu_status`] and the caller goes on with the CPU.
+#[cfg(feature = "gpu")]
+fn gpu_succeeded(result: Result<(), String>) -> bool {
+    let mut error = GPU_ERROR.lock().unwrap();
+    *error = result.err();
+    if error.is_some() {
+        GPU_FALLBACKS.fetch_add(1, Ordering::SeqCst);
+    }
+    error.is_none()
+}
+
+/// Per-pixel weights in [0, 1] that shape where a thermal or hydraulic run acts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Masks<'a> {
+    /// Resistance to erosion: 1 doesn't erode at all.
+    pub hardness: Option<&'a [f32]>,
+    /// Where the run applies. The result is blended into the untouched terrain by
+    /// these weights, so a feathered selection leaves no seam.
+    pub selection: Option<&'a [f32]>,
+}
+
+/// Lets a run be suspended between rounds while its owner edits the terrain.
+pub struct Pause<'a> {
+    /// Set to ask the run to suspend at the end of the current round.
+    pub requested: &'a AtomicBool,
+    /// Called with the terrain once the run has suspended; returns when it may go
+    /// on, leaving in `hm` the terrain to continue from.
+    pub wait: &'a dyn Fn(&mut Heightmap),
+}
+
+/// Blend `hm` back towards `original` by `selection`.
+fn restrict(hm: &mut Heightmap, original: &[f32], selection: &[f32]) {
+    hm.data
+        .par_iter_mut()
+        .zip(original.par_iter())
+        .zip(selection.par_iter())
+        .for_each(|((value, &orig), &w)| *value = orig + (*value - orig) * w.clamp(0.0, 1.0));
+}
+
+/// One erosion pass with its parameters, for callers that run either kind.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ErosionRun {
+    Thermal(thermal::ThermalParams),
+    Hydraulic(hydraulic::HydraulicParams),
+    Pipe(pipe::PipeParams),
+    StreamPower(stream_power::StreamPowerParams),
+    Glacial(glacial::GlacialParams),
+    Coastal(coastal::CoastalParams),
+}
+
+impl ErosionRun {
+    pub fn backend(&self) -> Backend {
+        match self {
+            ErosionRun::Thermal(params) => params.backend,
+            ErosionRun::Hydraulic(params) => params.backend,
+            ErosionRun::Pipe(_)
+            | ErosionRun::StreamPower(_)
+            | ErosionRun::Glacial(_)
+            | ErosionRun::Coastal(_) => Backend::Cpu,
+        }
+    }
+
+    /// Rock layers the run erodes through, if any.
+    pub fn strata(&self) -> Option<&strata::Strata> {
+        match self {
+            ErosionRun::Thermal(params) => params.strata.as_ref(),
+            ErosionRun::Hydraulic(params) => params.strata.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Whether the run asks to be restricted to the selection, and how far to
+    /// feather it; only thermal and hydraulic runs can be.
+    pub fn selection(&self) -> Option<u32> {
+        match self {
+            ErosionRun::Thermal(params) => params.use_selection.then_some(params.mask_feather),
+            ErosionRun::Hydraulic(params) => params.use_selection.then_some(params.mask_feather),
+            _ => None,
+        }
+    }
+
+    /// Adapt to a copy of the map resampled by `scale`, e.g. 0.25 for a quarter of
+    /// the width: droplets keep their density and pixel distances their length on
+    /// the terrain. Iteration counts stay as they are.
+    pub fn rescale(&mut self, scale: f32) {
+        if let ErosionRun::Hydraulic(params) = self {
+            let pixels = |n: u32| ((n as f32 * scale).round() as u32).max(1);
+            params.num_droplets = ((params.num_droplets as f32 * scale * scale).round() as u32).max(1);
+            params.max_lifetime = pixels(params.max_lifetime);
+            params.erosion_radius = pixels(params.erosion_radius);
+        }
+    }
+
+    /// Run on `hm`, reporting the completed fraction through `progress`. Only
+    /// thermal and hydraulic runs take `masks` into account.
+    pub fn apply(
+        &self,
+        hm: &mut Heightmap,
+        masks: Masks,
+        boundary: Boundary,
+        abort: &AtomicBool,
+        progress: &dyn Fn(f32),
+    ) {
+        match self {
+            ErosionRun::Thermal(params) => thermal::erode(hm, params, masks, boundary, abort, progress),
+            ErosionRun::Hydraulic(params) => {
+                hydraulic::erode(hm, params, masks, boundary, abort, progress, None, None);
+            }
+            ErosionRun::Pipe(params) => pipe::erode(hm, params, boundary, abort, progress),
+            ErosionRun::StreamPower(params) => stream_power::erode(hm, params, boundary, abort, progress),
+            ErosionRun::Glacial(params) => glacial::erode(hm, params, boundary, abort, progress),
+            ErosionRun::Coastal(params) => coastal::erode(hm, params, boundary, abort, progress),
+        }
+    }
+}
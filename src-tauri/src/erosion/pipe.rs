@@ -0,0 +1,225 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+
+/// Grid-based shallow-water erosion after Mei et al., "Fast Hydraulic Erosion
+/// Simulation and Visualization on GPU". Water and sediment depths share the
+/// heightmap's normalized units.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PipeParams {
+    pub iterations: u32,
+    /// Water depth added to every cell per iteration.
+    pub rainfall: f32,
+    /// Cross-section of the virtual pipes between cells; larger pipes drain faster.
+    pub pipe_area: f32,
+    /// Sediment the flow can carry per unit of slope and discharge.
+    pub sediment_capacity: f32,
+    /// Fraction of the water that evaporates per iteration.
+    pub evaporation: f32,
+    #[serde(default = "default_dissolve_rate")]
+    pub dissolve_rate: f32,
+    #[serde(default = "default_deposit_rate")]
+    pub deposit_rate: f32,
+}
+
+fn default_dissolve_rate() -> f32 {
+    0.3
+}
+
+fn default_deposit_rate() -> f32 {
+    0.3
+}
+
+/// Simulation time step.
+const DT: f32 = 0.05;
+const GRAVITY: f32 = 9.81;
+/// Water shallower than this has no velocity and carries no sediment.
+const MIN_DEPTH: f32 = 1e-6;
+
+/// Pipe directions; also the index order of a cell's outflow fluxes.
+const NEIGHBORS: [(i64, i64); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+const UP: usize = 2;
+const DOWN: usize = 3;
+
+/// Where a cell's pipe in one direction leads.
+#[derive(Clone, Copy)]
+enum Pipe {
+    Cell(usize),
+    /// Clamped and mirrored edges keep water on the map.
+    Wall,
+    /// A fixed edge drains into a reservoir at that height.
+    Outlet(f32),
+}
+
+fn pipe(boundary: Boundary, x: usize, y: usize, d: usize, w: usize, h: usize) -> Pipe {
+    let (dx, dy) = NEIGHBORS[d];
+    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+    if (0..w as i64).contains(&nx) && (0..h as i64).contains(&ny) {
+        return Pipe::Cell(ny as usize * w + nx as usize);
+    }
+    match boundary {
+        Boundary::Wrap => Pipe::Cell(ny.rem_euclid(h as i64) as usize * w + nx.rem_euclid(w as i64) as usize),
+        Boundary::Fixed { value } => Pipe::Outlet(value),
+        Boundary::Clamp | Boundary::Mirror => Pipe::Wall,
+    }
+}
+
+/// Every iteration is a sequence of per-cell passes that only read the previous
+/// pass's buffers, so each runs in parallel over rows: rain, pipe fluxes, water
+/// and sediment transport, erosion and deposition, then evaporation. Sediment
+/// travels along the same pipes as the water, so none is lost except through an
+/// outlet; whatever is still suspended at the end settles where it is.
+pub fn erode(
+    hm: &mut Heightmap,
+    params: &PipeParams,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) {
+    let w = hm.width as usize;
+    let h = hm.height as usize;
+    // Heights are normalized to the map width; slopes and pressure need cell units
+    let scale = w as f32;
+
+    let mut water = vec![0.0f32; w * h];
+    let mut next_water = vec![0.0f32; w * h];
+    let mut sediment = vec![0.0f32; w * h];
+    let mut next_sediment = vec![0.0f32; w * h];
+    let mut flux = vec![[0.0f32; 4]; w * h];
+    let mut velocity = vec![[0.0f32; 2]; w * h];
+    let mut next_terrain = vec![0.0f32; w * h];
+
+    for i in 0..params.iterations {
+        if abort.load(Ordering::Relaxed) {
+            break;
+        }
+        progress(i as f32 / params.iterations as f32);
+
+        water.par_iter_mut().for_each(|d| *d += params.rainfall);
+
+        // Accelerate each pipe by the surface height difference, then scale the
+        // cell's outflow down so it can't drain more water than it holds
+        let terrain = &hm.data;
+        let water_ref = &water;
+        flux.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let idx = y * w + x;
+                let surface = terrain[idx] + water_ref[idx];
+                for (d, f) in out.iter_mut().enumerate() {
+                    let other = match pipe(boundary, x, y, d, w, h) {
+                        Pipe::Cell(n) => terrain[n] + water_ref[n],
+                        Pipe::Outlet(level) => level,
+                        Pipe::Wall => {
+                            *f = 0.0;
+                            continue;
+                        }
+                    };
+                    *f = (*f + DT * params.pipe_area * GRAVITY * (surface - other) * scale).max(0.0);
+                }
+                let total: f32 = out.iter().sum::<f32>() * DT;
+                if total > water_ref[idx] {
+                    let k = water_ref[idx] / total;
+                    for f in out.iter_mut() {
+                        *f *= k;
+                    }
+                }
+            }
+        });
+
+        // Move water and the sediment suspended in it along the pipes
+        let flux_ref = &flux;
+        let sediment_ref = &sediment;
+        let concentration = |n: usize| {
+            if water_ref[n] > MIN_DEPTH {
+                sediment_ref[n] / water_ref[n]
+            } else {
+                0.0
+            }
+        };
+        let source = |x: usize, y: usize, d: usize| match pipe(boundary, x, y, d, w, h) {
+            Pipe::Cell(n) => Some(n),
+            Pipe::Wall | Pipe::Outlet(_) => None,
+        };
+        next_water
+            .par_chunks_mut(w)
+            .zip(next_sediment.par_chunks_mut(w))
+            .zip(velocity.par_chunks_mut(w))
+            .enumerate()
+            .for_each(|(y, ((water_row, sediment_row), velocity_row))| {
+                for x in 0..w {
+                    let idx = y * w + x;
+                    let out = flux_ref[idx];
+                    let mut inflow = [0.0f32; 4];
+                    let mut sediment_in = 0.0;
+                    for (d, flow) in inflow.iter_mut().enumerate() {
+                        if let Some(n) = source(x, y, d) {
+                            *flow = flux_ref[n][d ^ 1];
+                            sediment_in += *flow * concentration(n);
+                        }
+                    }
+                    let outflow: f32 = out.iter().sum();
+                    let depth = (water_ref[idx] + DT * (inflow.iter().sum::<f32>() - outflow)).max(0.0);
+                    water_row[x] = depth;
+                    sediment_row[x] =
+                        (sediment_ref[idx] + DT * (sediment_in - outflow * concentration(idx))).max(0.0);
+
+                    let mean_depth = (water_ref[idx] + depth) * 0.5;
+                    velocity_row[x] = if mean_depth > MIN_DEPTH {
+                        let flow_x = (inflow[LEFT] - out[LEFT] + out[RIGHT] - inflow[RIGHT]) * 0.5;
+                        let flow_y = (inflow[UP] - out[UP] + out[DOWN] - inflow[DOWN]) * 0.5;
+                        [flow_x / mean_depth, flow_y / mean_depth]
+                    } else {
+                        [0.0, 0.0]
+                    };
+                }
+            });
+        std::mem::swap(&mut water, &mut next_water);
+        std::mem::swap(&mut sediment, &mut next_sediment);
+
+        // Flow dissolves terrain while below capacity and drops sediment above it
+        let map = &*hm;
+        let velocity_ref = &velocity;
+        let water_ref = &water;
+        next_terrain
+            .par_chunks_mut(w)
+            .zip(sediment.par_chunks_mut(w))
+            .enumerate()
+            .for_each(|(y, (terrain_row, sediment_row))| {
+                for x in 0..w {
+                    let idx = y * w + x;
+                    let (ix, iy) = (x as i64, y as i64);
+                    let at = |x: i64, y: i64| map.get_bounded(x, y, boundary);
+                    let gx = (at(ix + 1, iy) - at(ix - 1, iy)) * 0.5 * scale;
+                    let gy = (at(ix, iy + 1) - at(ix, iy - 1)) * 0.5 * scale;
+                    let slope = (gx * gx + gy * gy).sqrt();
+                    let sin_tilt = slope / (1.0 + slope * slope).sqrt();
+
+                    let [u, v] = velocity_ref[idx];
+                    let discharge = (u * u + v * v).sqrt() * water_ref[idx];
+                    let capacity = params.sediment_capacity * sin_tilt * discharge;
+                    let carried = sediment_row[x];
+                    let moved = if capacity > carried {
+                        DT * params.dissolve_rate * (capacity - carried)
+                    } else {
+                        -DT * params.deposit_rate * (carried - capacity)
+                    };
+                    terrain_row[x] = map.data[idx] - moved;
+                    sediment_row[x] = carried + moved;
+                }
+            });
+        std::mem::swap(&mut hm.data, &mut next_terrain);
+
+        let keep = 1.0 - params.evaporation;
+        water.par_iter_mut().for_each(|d| *d *= keep);
+    }
+
+    for (height, s) in hm.data.iter_mut().zip(&sediment) {
+        *height += s;
+    }
+    progress(1.0);
+}
@@ -0,0 +1,175 @@
+// Hydraulic erosion, one thread per droplet. Mirrors `hydraulic::erode`, except
+// that droplets in a batch all see the terrain as it was when the batch started:
+// their erosion and deposition accumulate in `delta` as fixed point and are added
+// to the heights by `apply` afterwards.
+
+struct Params {
+    width: u32,
+    height: u32,
+    max_lifetime: u32,
+    batch_start: u32,
+    droplet_count: u32,
+    seed_lo: u32,
+    seed_hi: u32,
+    // What droplets do at the edge: 0 flow off, 1 reflect, 2 hold against it
+    edge: u32,
+    erosion_rate: f32,
+    deposition_rate: f32,
+    evaporation_rate: f32,
+    inertia: f32,
+    min_slope: f32,
+    capacity_factor: f32,
+    gravity: f32,
+    _pad1: f32,
+}
+
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read_write> heights: array<f32>;
+@group(0) @binding(2) var<storage, read_write> delta: array<atomic<i32>>;
+// (dx, dy, weight, unused) per brush cell
+@group(0) @binding(3) var<storage, read> brush: array<vec4<f32>>;
+
+// Fixed-point scale of `delta`: ±8 range, ~4e-9 resolution
+const SCALE: f32 = 268435456.0;
+
+var<private> rng_state: u32;
+
+fn hash(v: u32) -> u32 {
+    let state = v * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn next_random() -> f32 {
+    rng_state = hash(rng_state);
+    return f32(rng_state >> 8u) / 16777216.0;
+}
+
+fn height_at(x: u32, y: u32) -> f32 {
+    return heights[y * p.width + x];
+}
+
+// (gradient x, gradient y, height) at a position, bilinearly interpolated
+fn sample(pos: vec2<f32>) -> vec3<f32> {
+    let ix = u32(pos.x);
+    let iy = u32(pos.y);
+    let fx = pos.x - f32(ix);
+    let fy = pos.y - f32(iy);
+    let ix1 = min(ix + 1u, p.width - 1u);
+    let iy1 = min(iy + 1u, p.height - 1u);
+
+    let tl = height_at(ix, iy);
+    let tr = height_at(ix1, iy);
+    let bl = height_at(ix, iy1);
+    let br = height_at(ix1, iy1);
+
+    let gx = (tr - tl) * (1.0 - fy) + (br - bl) * fy;
+    let gy = (bl - tl) * (1.0 - fx) + (br - tr) * fx;
+    let h = tl + (tr - tl) * fx + (bl - tl) * fy + (tl - tr - bl + br) * fx * fy;
+    return vec3<f32>(gx, gy, h);
+}
+
+fn add(x: u32, y: u32, amount: f32) {
+    atomicAdd(&delta[y * p.width + x], i32(round(amount * SCALE)));
+}
+
+fn deposit_at(pos: vec2<f32>, amount: f32) {
+    let ix = u32(pos.x);
+    let iy = u32(pos.y);
+    let fx = pos.x - f32(ix);
+    let fy = pos.y - f32(iy);
+    let ix1 = min(ix + 1u, p.width - 1u);
+    let iy1 = min(iy + 1u, p.height - 1u);
+
+    add(ix, iy, amount * (1.0 - fx) * (1.0 - fy));
+    add(ix1, iy, amount * fx * (1.0 - fy));
+    add(ix, iy1, amount * (1.0 - fx) * fy);
+    add(ix1, iy1, amount * fx * fy);
+}
+
+fn erode_at(pos: vec2<f32>, amount: f32) {
+    let ix = i32(round(pos.x));
+    let iy = i32(round(pos.y));
+    for (var i = 0u; i < arrayLength(&brush); i++) {
+        let b = brush[i];
+        let cx = ix + i32(b.x);
+        let cy = iy + i32(b.y);
+        if (cx >= 0 && cx < i32(p.width) && cy >= 0 && cy < i32(p.height)) {
+            add(u32(cx), u32(cy), -amount * b.z);
+        }
+    }
+}
+
+@compute @workgroup_size(64)
+fn droplets(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= p.droplet_count) {
+        return;
+    }
+    rng_state = hash(hash(p.batch_start + id.x) ^ p.seed_lo) ^ p.seed_hi;
+
+    let w = f32(p.width);
+    let h = f32(p.height);
+    var pos = vec2<f32>(next_random() * (w - 2.0) + 0.5, next_random() * (h - 2.0) + 0.5);
+    var dir = vec2<f32>(0.0);
+    var speed = 1.0;
+    var water = 1.0;
+    var sediment = 0.0;
+
+    for (var step = 0u; step < p.max_lifetime; step++) {
+        let here = sample(pos);
+
+        dir = dir * p.inertia - here.xy * (1.0 - p.inertia);
+        let len = length(dir);
+        if (len < 1e-6) {
+            let angle = next_random() * 6.2831855;
+            dir = vec2<f32>(cos(angle), sin(angle));
+        } else {
+            dir = dir / len;
+        }
+
+        var new_pos = pos + dir;
+        let lo = vec2<f32>(0.5);
+        let hi = vec2<f32>(w - 1.5, h - 1.5) * (1.0 - 1.2e-7);
+        let outside = (new_pos < lo) | (new_pos > hi);
+        if (any(outside)) {
+            if (p.edge == 0u) {
+                break;
+            } else if (p.edge == 1u) {
+                new_pos = clamp(select(new_pos, select(2.0 * hi - new_pos, 2.0 * lo - new_pos, new_pos < lo), outside), lo, hi);
+                dir = select(dir, -dir, outside);
+            } else {
+                new_pos = clamp(new_pos, lo, hi);
+                dir = select(dir, vec2<f32>(0.0), outside);
+            }
+        }
+
+        let h_diff = sample(new_pos).z - here.z;
+        let capacity = max(-h_diff, p.min_slope) * speed * water * p.capacity_factor;
+
+        if (sediment > capacity || h_diff > 0.0) {
+            var amount = (sediment - capacity) * p.deposition_rate;
+            if (h_diff > 0.0) {
+                amount = min(sediment, h_diff);
+            }
+            sediment -= amount;
+            deposit_at(pos, amount);
+        } else {
+            let amount = min((capacity - sediment) * p.erosion_rate, -h_diff);
+            erode_at(pos, amount);
+            sediment += amount;
+        }
+
+        speed = sqrt(max(speed * speed + h_diff * p.gravity, 0.0));
+        water *= 1.0 - p.evaporation_rate;
+        pos = new_pos;
+    }
+}
+
+@compute @workgroup_size(8, 8)
+fn apply(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= p.width || id.y >= p.height) {
+        return;
+    }
+    let idx = id.y * p.width + id.x;
+    heights[idx] += f32(atomicExchange(&delta[idx], 0)) / SCALE;
+}
@@ -0,0 +1,79 @@
+// Thermal erosion, one iteration as two passes. Mirrors `thermal::erode`: each
+// cell computes what it sheds to its lower neighbors, then every cell gathers its
+// losses and its neighbors' inflows.
+
+struct Params {
+    width: u32,
+    height: u32,
+    talus: f32,
+    transfer_rate: f32,
+    cell_size: f32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+}
+
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var<storage, read> heights: array<f32>;
+// Transfer to the (left, right, up, down) neighbor
+@group(0) @binding(2) var<storage, read_write> outflow: array<vec4<f32>>;
+@group(0) @binding(3) var<storage, read_write> next: array<f32>;
+
+fn shed(center: f32, neighbor: f32) -> f32 {
+    let diff = center - neighbor;
+    return select(0.0, diff, diff / p.cell_size > p.talus);
+}
+
+@compute @workgroup_size(8, 8)
+fn outflow_pass(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= p.width || id.y >= p.height) {
+        return;
+    }
+    let idx = id.y * p.width + id.x;
+    let center = heights[idx];
+
+    var out = vec4<f32>(0.0);
+    if (id.x > 0u) {
+        out.x = shed(center, heights[idx - 1u]);
+    }
+    if (id.x + 1u < p.width) {
+        out.y = shed(center, heights[idx + 1u]);
+    }
+    if (id.y > 0u) {
+        out.z = shed(center, heights[idx - p.width]);
+    }
+    if (id.y + 1u < p.height) {
+        out.w = shed(center, heights[idx + p.width]);
+    }
+
+    let total = out.x + out.y + out.z + out.w;
+    if (total > 0.0) {
+        let max_diff = max(max(out.x, out.y), max(out.z, out.w));
+        let excess = (max_diff - p.talus * p.cell_size) * p.transfer_rate;
+        out = out * (excess / total);
+    }
+    outflow[idx] = out;
+}
+
+@compute @workgroup_size(8, 8)
+fn gather_pass(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (id.x >= p.width || id.y >= p.height) {
+        return;
+    }
+    let idx = id.y * p.width + id.x;
+    let own = outflow[idx];
+    var h = heights[idx] - (own.x + own.y + own.z + own.w);
+    if (id.x > 0u) {
+        h += outflow[idx - 1u].y;
+    }
+    if (id.x + 1u < p.width) {
+        h += outflow[idx + 1u].x;
+    }
+    if (id.y > 0u) {
+        h += outflow[idx - p.width].w;
+    }
+    if (id.y + 1u < p.height) {
+        h += outflow[idx + p.width].z;
+    }
+    next[idx] = h;
+}
@@ -0,0 +1,127 @@
+use serde::{Deserialize, Serialize};
+
+/// One rock layer.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Stratum {
+    /// Thickness in normalized height units.
+    pub thickness: f32,
+    /// Steepest stable slope, in the units of `ThermalParams::talus`; replaces it
+    /// within the layer.
+    pub talus: f32,
+    /// Resistance to erosion in [0, 1], as in the hardness mask.
+    #[serde(default)]
+    pub hardness: f32,
+}
+
+/// Layered bedrock that erosion cuts into: a stack of strata repeating with
+/// depth, tilted as a whole. Which layer a pixel is in depends on its current
+/// height, so cutting down exposes the next layer and resistant bands stand out as
+/// ledges and steps.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Strata {
+    /// Top to bottom; the sequence repeats above and below.
+    pub layers: Vec<Stratum>,
+    /// Tilt of the layers from horizontal, in degrees.
+    #[serde(default)]
+    pub dip: f32,
+    /// Compass direction the layers descend towards, in degrees; 0 is the top of
+    /// the map, 90 the right.
+    #[serde(default)]
+    pub dip_direction: f32,
+}
+
+impl Strata {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.layers.is_empty() {
+            return Err("Strata need at least one layer".into());
+        }
+        if self.layers.iter().any(|l| l.thickness <= 0.0) {
+            return Err("Stratum thickness must be positive".into());
+        }
+        if !(0.0..90.0).contains(&self.dip) {
+            return Err("Strata dip must be between 0 and 90 degrees".into());
+        }
+        Ok(())
+    }
+
+    /// Layer at pixel `(x, y)` of a `width`-wide map whose surface is at `height`.
+    pub fn layer_at(&self, x: f32, y: f32, height: f32, width: u32) -> &Stratum {
+        // Horizontal distance in the units heights are normalized to
+        let (sin, cos) = self.dip_direction.to_radians().sin_cos();
+        let along = (x * sin - y * cos) / width as f32;
+        let depth = height + along * self.dip.to_radians().tan();
+
+        let period: f32 = self.layers.iter().map(|l| l.thickness).sum();
+        // Layers are listed top down, so count from the top of each period
+        let mut offset = (-depth).rem_euclid(period);
+        for layer in &self.layers {
+            if offset < layer.thickness {
+                return layer;
+            }
+            offset -= layer.thickness;
+        }
+        self.layers.last().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bands(dip: f32, dip_direction: f32) -> Strata {
+        Strata {
+            layers: vec![
+                Stratum { thickness: 0.1, talus: 0.2, hardness: 0.0 },
+                Stratum { thickness: 0.3, talus: 2.0, hardness: 1.0 },
+            ],
+            dip,
+            dip_direction,
+        }
+    }
+
+    #[test]
+    fn layers_repeat_down_from_the_top() {
+        let strata = bands(0.0, 0.0);
+        // Each 0.4 period starts with the soft layer at its top
+        assert_eq!(strata.layer_at(0.0, 0.0, 0.0, 64).hardness, 0.0);
+        assert_eq!(strata.layer_at(0.0, 0.0, -0.05, 64).hardness, 0.0);
+        assert_eq!(strata.layer_at(0.0, 0.0, -0.15, 64).hardness, 1.0);
+        assert_eq!(strata.layer_at(0.0, 0.0, -0.45, 64).hardness, 0.0);
+        assert_eq!(strata.layer_at(0.0, 0.0, 0.35, 64).hardness, 0.0);
+        assert_eq!(strata.layer_at(0.0, 0.0, 0.2, 64).hardness, 1.0);
+    }
+
+    #[test]
+    fn flat_layers_ignore_position() {
+        let strata = bands(0.0, 45.0);
+        for &height in &[0.5, 0.63, 0.71] {
+            let layer = strata.layer_at(0.0, 0.0, height, 64).talus;
+            assert_eq!(strata.layer_at(63.0, 17.0, height, 64).talus, layer);
+        }
+    }
+
+    #[test]
+    fn dipping_layers_shift_along_the_dip_direction() {
+        // At 45 degrees, a tenth of the map width along the dip direction shifts
+        // the sequence by 0.1
+        let strata = bands(45.0, 90.0);
+        let width = 100;
+        assert_eq!(strata.layer_at(0.0, 0.0, -0.05, width).hardness, 0.0);
+        assert_eq!(strata.layer_at(10.0, 0.0, -0.05, width).hardness, 1.0);
+        // Across the dip direction nothing changes
+        assert_eq!(strata.layer_at(0.0, 10.0, -0.05, width).hardness, 0.0);
+    }
+
+    #[test]
+    fn invalid_strata_are_rejected() {
+        assert!(bands(10.0, 0.0).validate().is_ok());
+        assert!(bands(90.0, 0.0).validate().is_err());
+        assert!(bands(-1.0, 0.0).validate().is_err());
+        assert!(Strata { layers: Vec::new(), dip: 0.0, dip_direction: 0.0 }.validate().is_err());
+        let mut thin = bands(0.0, 0.0);
+        thin.layers[0].thickness = 0.0;
+        assert!(thin.validate().is_err());
+    }
+}
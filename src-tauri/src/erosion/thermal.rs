@@ -1,67 +1,280 @@
-use serde::Deserialize;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::boundary::Boundary;
 use crate::heightmap::Heightmap;
+use super::strata::Strata;
+use super::{Backend, Masks};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ThermalParams {
     pub iterations: u32,
     pub talus: f32,
     pub transfer_rate: f32,
+    #[serde(default)]
+    pub backend: Backend,
+    /// Restrict the run to the selection mask, as in `NoiseParams`.
+    #[serde(default)]
+    pub use_selection: bool,
+    /// Feather radius in pixels applied to the mask.
+    #[serde(default)]
+    pub mask_feather: u32,
+    /// Layered rock whose talus and hardness replace the uniform values by depth.
+    #[serde(default)]
+    pub strata: Option<Strata>,
 }
 
-pub fn erode(hm: &mut Heightmap, params: &ThermalParams) {
-    let w = hm.width as i32;
-    let h = hm.height as i32;
+/// Neighbor offsets; also the index order of a cell's outflows.
+const NEIGHBORS: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const LEFT: usize = 0;
+const RIGHT: usize = 1;
+const UP: usize = 2;
+const DOWN: usize = 3;
+
+/// Each iteration runs as two parallel passes over rows: every cell first computes
+/// what it sheds to each lower neighbor into `outflow`, then every cell gathers its
+/// own losses and its neighbors' inflows. Contributions are summed in the order the
+/// serial scatter loop applied them, so the result is bit-identical to it.
+///
+/// Edge cells shed across the border too, per `boundary`: a wrapped or mirrored
+/// neighbor receives the material, a fixed boundary absorbs it, and a clamped edge
+/// has no lower neighbor to shed to.
+///
+/// Hardness scales down what each cell sheds, so fully hard pixels hold any
+/// slope. With strata, each cell uses the talus and hardness of the layer its
+/// surface is in. Runs with either always use the CPU. A selection blends the
+/// result into the original terrain.
+pub fn erode(
+    hm: &mut Heightmap,
+    params: &ThermalParams,
+    masks: Masks,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) {
+    let original = masks.selection.map(|_| hm.data.clone());
+    erode_everywhere(hm, params, masks.hardness, boundary, abort, progress);
+    if let (Some(original), Some(selection)) = (original, masks.selection) {
+        super::restrict(hm, &original, selection);
+    }
+}
+
+fn erode_everywhere(
+    hm: &mut Heightmap,
+    params: &ThermalParams,
+    hardness: Option<&[f32]>,
+    boundary: Boundary,
+    abort: &AtomicBool,
+    progress: &dyn Fn(f32),
+) {
+    #[cfg(feature = "gpu")]
+    if params.backend == Backend::Gpu
+        && hardness.is_none()
+        && params.strata.is_none()
+        && super::gpu_succeeded(super::gpu::thermal(hm, params, boundary, abort, progress))
+    {
+        return;
+    }
+
+    let w = hm.width as usize;
+    let h = hm.height as usize;
     let cell_size = 1.0 / w as f32;
+    let min_diff = params.talus * cell_size;
 
-    let neighbors: [(i32, i32); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+    let mut outflow = vec![[0.0f32; 4]; w * h];
+    let mut next = vec![0.0f32; w * h];
+    let border = border_cells(w, h);
 
-    for _ in 0..params.iterations {
-        let snapshot = hm.data.clone();
+    for i in 0..params.iterations {
+        if abort.load(Ordering::Relaxed) {
+            return;
+        }
+        progress(i as f32 / params.iterations as f32);
+        let map = &*hm;
+        let current = &map.data;
 
-        for y in 0..h {
-            for x in 0..w {
-                let idx = (y * w + x) as usize;
-                let center = snapshot[idx];
+        outflow.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let center = current[y * w + x];
+                *out = [0.0; 4];
+                let (talus, min_diff, softness) = match &params.strata {
+                    Some(strata) => {
+                        let layer = strata.layer_at(x as f32, y as f32, center, w as u32);
+                        (layer.talus, layer.talus * cell_size, 1.0 - layer.hardness.clamp(0.0, 1.0))
+                    }
+                    None => (params.talus, min_diff, 1.0),
+                };
 
                 let mut total_diff = 0.0f32;
                 let mut max_diff = 0.0f32;
-                let mut diffs: [(f32, i32, i32); 4] = [(0.0, 0, 0); 4];
-                let mut n_lower = 0usize;
-
-                for &(dx, dy) in &neighbors {
-                    let nx = x + dx;
-                    let ny = y + dy;
-                    if nx < 0 || nx >= w || ny < 0 || ny >= h {
-                        continue;
-                    }
-                    let nidx = (ny * w + nx) as usize;
-                    let diff = center - snapshot[nidx];
-                    let slope = diff / cell_size;
-                    if slope > params.talus {
-                        diffs[n_lower] = (diff, dx, dy);
-                        total_diff += diff;
-                        if diff > max_diff {
-                            max_diff = diff;
+                for (d, &(dx, dy)) in NEIGHBORS.iter().enumerate() {
+                    let nx = x as i64 + dx as i64;
+                    let ny = y as i64 + dy as i64;
+                    let neighbor = if nx < 0 || nx >= w as i64 || ny < 0 || ny >= h as i64 {
+                        if target(boundary, nx, ny, w, h) == Some(y * w + x) {
+                            continue;
                         }
-                        n_lower += 1;
+                        map.get_bounded(nx, ny, boundary)
+                    } else {
+                        current[ny as usize * w + nx as usize]
+                    };
+                    let diff = center - neighbor;
+                    if diff / cell_size > talus {
+                        out[d] = diff;
+                        total_diff += diff;
+                        max_diff = max_diff.max(diff);
                     }
                 }
 
-                if n_lower == 0 {
+                if total_diff == 0.0 {
                     continue;
                 }
+                let mut excess = (max_diff - min_diff) * params.transfer_rate * softness;
+                if let Some(hardness) = hardness {
+                    excess *= 1.0 - hardness[y * w + x].clamp(0.0, 1.0);
+                }
+                for diff in out.iter_mut().filter(|d| **d != 0.0) {
+                    *diff = excess * (*diff / total_diff);
+                }
+            }
+        });
+
+        let outflow = &outflow;
+        next.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, v) in row.iter_mut().enumerate() {
+                let idx = y * w + x;
+                let mut height = current[idx];
+                // Serial scan order: the cell above, the left cell, this cell, the
+                // right cell, the cell below
+                if y > 0 {
+                    height += outflow[idx - w][DOWN];
+                }
+                if x > 0 {
+                    height += outflow[idx - 1][RIGHT];
+                }
+                for transfer in outflow[idx] {
+                    height -= transfer;
+                }
+                if x + 1 < w {
+                    height += outflow[idx + 1][LEFT];
+                }
+                if y + 1 < h {
+                    height += outflow[idx + w][UP];
+                }
+                *v = height;
+            }
+        });
 
-                let excess = (max_diff - params.talus * cell_size) * params.transfer_rate;
-                for i in 0..n_lower {
-                    let (diff, dx, dy) = diffs[i];
-                    let proportion = diff / total_diff;
-                    let transfer = excess * proportion;
-                    let nidx = ((y + dy) * w + (x + dx)) as usize;
-                    hm.data[idx] -= transfer;
-                    hm.data[nidx] += transfer;
+        // Material shed across the border lands on the far side of the map
+        for &(x, y) in &border {
+            for (d, &(dx, dy)) in NEIGHBORS.iter().enumerate() {
+                let transfer = outflow[y * w + x][d];
+                let (nx, ny) = (x as i64 + dx as i64, y as i64 + dy as i64);
+                if transfer == 0.0 || (0..w as i64).contains(&nx) && (0..h as i64).contains(&ny) {
+                    continue;
+                }
+                if let Some(idx) = target(boundary, nx, ny, w, h) {
+                    next[idx] += transfer;
                 }
             }
         }
+
+        std::mem::swap(&mut hm.data, &mut next);
+    }
+}
+
+/// Cell that receives material shed to `(x, y)` past the edge; `None` when a fixed
+/// boundary absorbs it.
+fn target(boundary: Boundary, x: i64, y: i64, w: usize, h: usize) -> Option<usize> {
+    let x = boundary.resolve(x, w as u32)?;
+    let y = boundary.resolve(y, h as u32)?;
+    Some(y as usize * w + x as usize)
+}
+
+/// Cells on the edge of a `w` x `h` map, each once.
+fn border_cells(w: usize, h: usize) -> Vec<(usize, usize)> {
+    let mut cells: Vec<(usize, usize)> = (0..w).map(|x| (x, 0)).collect();
+    if h > 1 {
+        cells.extend((0..w).map(|x| (x, h - 1)));
+    }
+    for y in 1..h.saturating_sub(1) {
+        cells.push((0, y));
+        if w > 1 {
+            cells.push((w - 1, y));
+        }
+    }
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(iterations: u32) -> ThermalParams {
+        ThermalParams {
+            iterations,
+            talus: 0.6,
+            transfer_rate: 0.3,
+            backend: Backend::Cpu,
+            use_selection: false,
+            mask_feather: 0,
+            strata: None,
+        }
+    }
+
+    /// A plain with a few sharp peaks, one of them on the edge.
+    fn peaks() -> Heightmap {
+        let mut hm = Heightmap::new(32, 24);
+        for &(x, y) in &[(10, 10), (20, 5), (0, 12)] {
+            hm.set(x, y, 1.0);
+        }
+        hm
+    }
+
+    fn run(hm: &mut Heightmap, params: &ThermalParams, masks: Masks, boundary: Boundary) {
+        erode(hm, params, masks, boundary, &AtomicBool::new(false), &|_| {});
+    }
+
+    #[test]
+    fn peaks_slump_without_losing_material() {
+        let mut hm = peaks();
+        run(&mut hm, &params(20), Masks::default(), Boundary::Wrap);
+        assert!(hm.get(10, 10) < 1.0);
+        assert!(hm.get(11, 10) > 0.0);
+        // Edge material moves across to the other side
+        assert!(hm.get(31, 12) > 0.0);
+        let total: f32 = hm.data.iter().sum();
+        assert!((total - 3.0).abs() < 1e-4, "{total}");
+    }
+
+    #[test]
+    fn gentle_slopes_stay_put() {
+        let mut hm = Heightmap::new(32, 24);
+        for (i, v) in hm.data.iter_mut().enumerate() {
+            *v = (i % 32) as f32 * 0.001;
+        }
+        let original = hm.data.clone();
+        run(&mut hm, &params(10), Masks::default(), Boundary::Clamp);
+        assert_eq!(hm.data, original);
+    }
+
+    #[test]
+    fn hard_rock_holds_any_slope() {
+        let mut hm = peaks();
+        let hardness = vec![1.0; hm.data.len()];
+        run(&mut hm, &params(10), Masks { hardness: Some(&hardness), selection: None }, Boundary::Clamp);
+        assert_eq!(hm.data, peaks().data);
+    }
+
+    #[test]
+    fn threads_give_the_same_result() {
+        let on = |threads: usize| {
+            let pool = rayon::ThreadPoolBuilder::new().num_threads(threads).build().unwrap();
+            let mut hm = peaks();
+            pool.install(|| run(&mut hm, &params(15), Masks::default(), Boundary::Mirror));
+            hm
+        };
+        assert_eq!(on(4).data, on(1).data);
     }
 }
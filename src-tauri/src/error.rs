@@ -0,0 +1,118 @@
+use std::fmt;
+use std::path::PathBuf;
+use serde::Serialize;
+use crate::i18n::{self, Text};
+
+/// Broad error categories the frontend and scripts can branch on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorKind {
+    /// A parameter was out of range or malformed; fix the input and retry.
+    InvalidInput,
+    /// Another long-running operation holds the resource; retry once it finishes.
+    Busy,
+    /// The referenced item (tile, patch, mip, stream) doesn't exist.
+    NotFound,
+    /// Reading or writing a file failed.
+    Io,
+    /// A file was read but its contents are invalid or unsupported.
+    Format,
+    /// The user cancelled the operation.
+    Aborted,
+    /// A whole-map operation needs a token from `confirm_destructive`; ask the user,
+    /// then retry with one.
+    ConfirmationRequired,
+    /// A user export hook failed.
+    Script,
+    /// An ML helper process failed.
+    Ml,
+    /// Anything else; usually a bug.
+    Internal,
+}
+
+/// Error returned by every command. Serialized to the frontend as
+/// `{ kind, message, hint?, logPath? }`.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopographError {
+    pub kind: ErrorKind,
+    pub message: String,
+    /// What the user can do about it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+    /// Log with the full details, e.g. a helper process's output.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_path: Option<PathBuf>,
+}
+
+impl TopographError {
+    pub fn new(kind: ErrorKind, message: impl Into<String>) -> Self {
+        Self { kind, message: message.into(), hint: None, log_path: None }
+    }
+
+    pub fn invalid(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::InvalidInput, message)
+    }
+
+    pub fn busy(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Busy, message).with_hint(i18n::tr(Text::BusyHint))
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::NotFound, message)
+    }
+
+    pub fn io(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Io, message)
+            .with_hint(i18n::tr(Text::IoHint))
+    }
+
+    pub fn format(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Format, message)
+    }
+
+    pub fn aborted(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Aborted, message)
+    }
+
+    pub fn confirmation_required(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::ConfirmationRequired, message)
+            .with_hint(i18n::tr(Text::ConfirmationHint))
+    }
+
+    pub fn script(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Script, message)
+            .with_hint(i18n::tr(Text::ScriptHint))
+    }
+
+    pub fn ml(message: impl Into<String>) -> Self {
+        Self::new(ErrorKind::Ml, message)
+            .with_hint(i18n::tr(Text::MlHint))
+    }
+
+    pub fn with_hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+
+    pub fn with_log(mut self, path: PathBuf) -> Self {
+        self.log_path = Some(path);
+        self
+    }
+}
+
+impl fmt::Display for TopographError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for TopographError {}
+
+/// Module-level helpers report plain strings; they surface as `Internal` unless the
+/// command maps them to a more specific kind.
+impl From<String> for TopographError {
+    fn from(message: String) -> Self {
+        Self::new(ErrorKind::Internal, message)
+    }
+}
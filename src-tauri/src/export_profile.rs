@@ -0,0 +1,109 @@
+//! Export profiles: a target with its settings and a destination, saved as
+//! presets of kind `export` so a routine export runs in one step.
+
+use std::path::PathBuf;
+use serde::Deserialize;
+use crate::project::{self, RawOptions};
+use crate::resample::ExportResolution;
+use crate::tile_export::TileExportParams;
+use crate::unity::UnityRawOptions;
+use crate::unreal::UnrealOptions;
+
+/// What a profile exports, with the settings of the matching export command.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "target", rename_all = "camelCase")]
+pub enum ExportTarget {
+    Heightmap {
+        /// One of `project::export_formats`.
+        format: String,
+        #[serde(default)]
+        resolution: Option<ExportResolution>,
+    },
+    Raw {
+        #[serde(default)]
+        options: RawOptions,
+    },
+    Tiles {
+        #[serde(default)]
+        params: TileExportParams,
+    },
+    Unity {
+        #[serde(default)]
+        options: UnityRawOptions,
+    },
+    Unreal {
+        #[serde(default)]
+        options: UnrealOptions,
+    },
+    Godot,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportProfile {
+    #[serde(flatten)]
+    pub target: ExportTarget,
+    /// Folder the files go to, created if missing.
+    pub destination: PathBuf,
+    /// File name without extension; tiles use it as `{name}`.
+    #[serde(default = "default_file_name")]
+    pub file_name: String,
+}
+
+fn default_file_name() -> String {
+    "terrain".to_string()
+}
+
+impl ExportProfile {
+    /// Settings checks that don't depend on the map; tile counts are checked
+    /// against it when the profile runs.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.destination.is_absolute() {
+            return Err("Export destination must be an absolute path".into());
+        }
+        let name = self.file_name.trim();
+        if name.is_empty() || name != self.file_name || name.starts_with('.') {
+            return Err(format!("Export file name {:?} can't be used", self.file_name));
+        }
+        if name.chars().any(|c| c.is_control() || r#"/\:*?"<>|"#.contains(c)) {
+            return Err(format!("Export file name {:?} can't be used as a file name", self.file_name));
+        }
+        match &self.target {
+            ExportTarget::Heightmap { format, resolution } => {
+                if project::tile_extension(format).is_none() {
+                    return Err(format!("Unknown export format: {format}"));
+                }
+                resolution.as_ref().map_or(Ok(()), ExportResolution::validate)
+            }
+            ExportTarget::Raw { options } => options.validate(),
+            ExportTarget::Tiles { params } => match project::tile_extension(&params.format) {
+                Some(_) => Ok(()),
+                None => Err(format!("Unknown export format: {}", params.format)),
+            },
+            ExportTarget::Unreal { options } => options.validate(),
+            ExportTarget::Unity { .. } | ExportTarget::Godot => Ok(()),
+        }
+    }
+
+    /// Path of the main file written, with the extension the save dialog for
+    /// the same export would suggest.
+    pub fn path(&self) -> PathBuf {
+        let extension = match &self.target {
+            ExportTarget::Heightmap { format, .. } => project::export_formats()
+                .into_iter()
+                .find(|f| f.id == format)
+                .map_or("bin", |f| f.extension),
+            // The extensions engines use for 16- and 32-bit raw heightmaps
+            ExportTarget::Raw { options } => match options.bit_depth {
+                16 => "r16",
+                32 => "r32",
+                _ => "raw",
+            },
+            ExportTarget::Tiles { params } => project::tile_extension(&params.format).unwrap_or("png"),
+            ExportTarget::Unity { .. } => "raw",
+            ExportTarget::Unreal { .. } => "png",
+            ExportTarget::Godot => "exr",
+        };
+        self.destination.join(format!("{}.{extension}", self.file_name))
+    }
+}
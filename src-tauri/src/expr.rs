@@ -0,0 +1,481 @@
+use noise::{NoiseFn, Perlin};
+use crate::heightmap::Heightmap;
+use crate::noise_gen::{Worley, WorleyMode};
+
+/// A height formula over normalized map coordinates, e.g.
+/// `0.5*fbm(x*3, y*3) + ridge(x, y)*mask(x, y)`.
+///
+/// Variables: `x`, `y` in [0, 1] across the map, and `pi`. Operators: `+ - * / % ^`
+/// and parentheses. Noise functions return values in [0, 1]:
+/// `noise(x, y)`, `fbm(x, y[, octaves])`, `ridge(x, y[, octaves])`, `cells(x, y)`.
+/// `height(x, y)` reads the current terrain and `mask(x, y)` the selection mask.
+/// Math: `abs sqrt sin cos floor min max pow clamp lerp smoothstep`.
+pub struct Expression {
+    root: Node,
+}
+
+/// Per-evaluation inputs: the noise seed and the maps the formula may read.
+pub struct Inputs<'a> {
+    pub perlin: Perlin,
+    pub worley: Worley,
+    pub terrain: &'a Heightmap,
+    pub mask: Option<&'a Heightmap>,
+}
+
+impl<'a> Inputs<'a> {
+    pub fn new(seed: u32, terrain: &'a Heightmap, mask: Option<&'a Heightmap>) -> Self {
+        Self {
+            perlin: Perlin::new(seed),
+            worley: Worley::new(seed, WorleyMode::F1),
+            terrain,
+            mask,
+        }
+    }
+}
+
+enum Node {
+    Number(f64),
+    X,
+    Y,
+    Neg(Box<Node>),
+    Binary(Op, Box<Node>, Box<Node>),
+    Call(Func, Vec<Node>),
+}
+
+#[derive(Clone, Copy)]
+enum Op {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Clone, Copy)]
+enum Func {
+    Noise,
+    Fbm,
+    Ridge,
+    Cells,
+    Height,
+    Mask,
+    Abs,
+    Sqrt,
+    Sin,
+    Cos,
+    Floor,
+    Min,
+    Max,
+    Pow,
+    Clamp,
+    Lerp,
+    Smoothstep,
+}
+
+impl Func {
+    fn lookup(name: &str) -> Option<(Func, usize, usize)> {
+        // (function, min args, max args)
+        Some(match name {
+            "noise" => (Func::Noise, 2, 2),
+            "fbm" => (Func::Fbm, 2, 3),
+            "ridge" => (Func::Ridge, 2, 3),
+            "cells" => (Func::Cells, 2, 2),
+            "height" => (Func::Height, 2, 2),
+            "mask" => (Func::Mask, 2, 2),
+            "abs" => (Func::Abs, 1, 1),
+            "sqrt" => (Func::Sqrt, 1, 1),
+            "sin" => (Func::Sin, 1, 1),
+            "cos" => (Func::Cos, 1, 1),
+            "floor" => (Func::Floor, 1, 1),
+            "min" => (Func::Min, 2, 2),
+            "max" => (Func::Max, 2, 2),
+            "pow" => (Func::Pow, 2, 2),
+            "clamp" => (Func::Clamp, 3, 3),
+            "lerp" => (Func::Lerp, 3, 3),
+            "smoothstep" => (Func::Smoothstep, 3, 3),
+            _ => return None,
+        })
+    }
+}
+
+impl Expression {
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let tokens = tokenize(source)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let root = parser.expression(0)?;
+        if let Some((token, at)) = parser.tokens.get(parser.pos) {
+            return Err(format!("Unexpected {token} at {at}"));
+        }
+        Ok(Self { root })
+    }
+
+    pub fn eval(&self, inputs: &Inputs, x: f64, y: f64) -> f64 {
+        eval(&self.root, inputs, x, y)
+    }
+}
+
+/// Evaluate `expr` at every pixel of `hm`, clamped to [0, 1]. `x` and `y` span
+/// [0, 1] across the map.
+pub fn generate(hm: &mut Heightmap, expr: &Expression, inputs: &Inputs) {
+    let sx = 1.0 / (hm.width.max(2) - 1) as f64;
+    let sy = 1.0 / (hm.height.max(2) - 1) as f64;
+    for y in 0..hm.height {
+        for x in 0..hm.width {
+            let value = expr.eval(inputs, x as f64 * sx, y as f64 * sy);
+            // NaN from e.g. sqrt(-1) lands at sea level
+            let value = if value.is_nan() { 0.0 } else { value.clamp(0.0, 1.0) };
+            hm.set(x, y, value as f32);
+        }
+    }
+}
+
+fn eval(node: &Node, inputs: &Inputs, x: f64, y: f64) -> f64 {
+    match node {
+        Node::Number(v) => *v,
+        Node::X => x,
+        Node::Y => y,
+        Node::Neg(a) => -eval(a, inputs, x, y),
+        Node::Binary(op, a, b) => {
+            let (a, b) = (eval(a, inputs, x, y), eval(b, inputs, x, y));
+            match op {
+                Op::Add => a + b,
+                Op::Sub => a - b,
+                Op::Mul => a * b,
+                Op::Div => a / b,
+                Op::Rem => a.rem_euclid(b),
+                Op::Pow => a.powf(b),
+            }
+        }
+        Node::Call(func, args) => {
+            let arg = |i: usize| eval(&args[i], inputs, x, y);
+            let octaves = || if args.len() > 2 { arg(2).clamp(1.0, 12.0) as u32 } else { 6 };
+            match func {
+                Func::Noise => inputs.perlin.get([arg(0), arg(1)]) * 0.5 + 0.5,
+                Func::Fbm => fbm(&inputs.perlin, arg(0), arg(1), octaves()) * 0.5 + 0.5,
+                Func::Ridge => ridge(&inputs.perlin, arg(0), arg(1), octaves()),
+                Func::Cells => (inputs.worley.get([arg(0), arg(1)]) * 0.5 + 0.5).clamp(0.0, 1.0),
+                Func::Height => sample(inputs.terrain, arg(0), arg(1)),
+                Func::Mask => inputs.mask.map_or(0.0, |m| sample(m, arg(0), arg(1))),
+                Func::Abs => arg(0).abs(),
+                Func::Sqrt => arg(0).sqrt(),
+                Func::Sin => arg(0).sin(),
+                Func::Cos => arg(0).cos(),
+                Func::Floor => arg(0).floor(),
+                Func::Min => arg(0).min(arg(1)),
+                Func::Max => arg(0).max(arg(1)),
+                Func::Pow => arg(0).powf(arg(1)),
+                // min/max rather than f64::clamp, which panics on NaN bounds
+                Func::Clamp => {
+                    let lo = arg(1);
+                    arg(0).max(lo).min(arg(2).max(lo))
+                }
+                Func::Lerp => {
+                    let (a, b, t) = (arg(0), arg(1), arg(2));
+                    a + (b - a) * t
+                }
+                Func::Smoothstep => {
+                    let (e0, e1, v) = (arg(0), arg(1), arg(2));
+                    let t = ((v - e0) / (e1 - e0)).clamp(0.0, 1.0);
+                    t * t * (3.0 - 2.0 * t)
+                }
+            }
+        }
+    }
+}
+
+/// Bilinear sample at normalized coordinates.
+fn sample(hm: &Heightmap, x: f64, y: f64) -> f64 {
+    hm.sample(x as f32 * (hm.width - 1) as f32, y as f32 * (hm.height - 1) as f32) as f64
+}
+
+fn fbm(source: &Perlin, x: f64, y: f64, octaves: u32) -> f64 {
+    let (mut freq, mut amp, mut sum, mut max_amp) = (1.0, 1.0, 0.0, 0.0);
+    for _ in 0..octaves {
+        sum += source.get([x * freq, y * freq]) * amp;
+        max_amp += amp;
+        freq *= 2.0;
+        amp *= 0.5;
+    }
+    sum / max_amp
+}
+
+/// Ridged multifractal in [0, 1].
+fn ridge(source: &Perlin, x: f64, y: f64, octaves: u32) -> f64 {
+    let (mut freq, mut amp, mut sum, mut max_amp) = (1.0, 1.0, 0.0, 0.0);
+    for _ in 0..octaves {
+        let r = 1.0 - source.get([x * freq, y * freq]).abs();
+        sum += r * r * amp;
+        max_amp += amp;
+        freq *= 2.0;
+        amp *= 0.5;
+    }
+    sum / max_amp
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+    Comma,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Token::Number(v) => write!(f, "'{v}'"),
+            Token::Ident(name) => write!(f, "'{name}'"),
+            Token::Op(c) => write!(f, "'{c}'"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::Comma => write!(f, "','"),
+        }
+    }
+}
+
+/// Longest formula accepted, in tokens. Evaluation recurses once per operator,
+/// so this bounds the stack a formula can take.
+const MAX_TOKENS: usize = 1000;
+
+/// Tokens with their byte offsets, for error messages.
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(at, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = at;
+            while let Some(&(i, d)) = chars.peek() {
+                let exponent_sign = (d == '-' || d == '+') && source[..i].ends_with(['e', 'E']);
+                if d.is_ascii_digit() || d == '.' || d == 'e' || d == 'E' || exponent_sign {
+                    end = i + d.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let text = &source[at..end];
+            let value = text.parse().map_err(|_| format!("Invalid number '{text}' at {at}"))?;
+            tokens.push((Token::Number(value), at));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let mut end = at;
+            while let Some(&(i, d)) = chars.peek() {
+                if d.is_ascii_alphanumeric() || d == '_' {
+                    end = i + d.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push((Token::Ident(source[at..end].to_string()), at));
+        } else {
+            let token = match c {
+                '+' | '-' | '*' | '/' | '%' | '^' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                ',' => Token::Comma,
+                _ => return Err(format!("Unexpected '{c}' at {at}")),
+            };
+            tokens.push((token, at));
+            chars.next();
+        }
+        if tokens.len() > MAX_TOKENS {
+            return Err(format!("Expression is too long; the limit is {MAX_TOKENS} terms"));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next(&mut self) -> Option<(Token, usize)> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next() {
+            Some((token, _)) if token == expected => Ok(()),
+            Some((token, at)) => Err(format!("Expected {expected}, found {token} at {at}")),
+            None => Err(format!("Expected {expected} at end of expression")),
+        }
+    }
+
+    /// Precedence climbing: `+ -` bind loosest, then `* / %`, then right-associative `^`.
+    fn expression(&mut self, min_prec: u8) -> Result<Node, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(c)) = self.peek() {
+            let (op, prec, right_assoc) = match c {
+                '+' => (Op::Add, 1, false),
+                '-' => (Op::Sub, 1, false),
+                '*' => (Op::Mul, 2, false),
+                '/' => (Op::Div, 2, false),
+                '%' => (Op::Rem, 2, false),
+                _ => (Op::Pow, 4, true),
+            };
+            if prec < min_prec {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.expression(if right_assoc { prec } else { prec + 1 })?;
+            lhs = Node::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Node, String> {
+        if self.peek() == Some(&Token::Op('-')) {
+            self.pos += 1;
+            // Binds looser than `^`, so -x^2 is -(x^2)
+            return Ok(Node::Neg(Box::new(self.expression(3)?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Node, String> {
+        match self.next() {
+            Some((Token::Number(v), _)) => Ok(Node::Number(v)),
+            Some((Token::LParen, _)) => {
+                let inner = self.expression(0)?;
+                self.expect(Token::RParen)?;
+                Ok(inner)
+            }
+            Some((Token::Ident(name), at)) => {
+                if self.peek() == Some(&Token::LParen) {
+                    self.pos += 1;
+                    return self.call(&name, at);
+                }
+                match name.as_str() {
+                    "x" => Ok(Node::X),
+                    "y" => Ok(Node::Y),
+                    "pi" => Ok(Node::Number(std::f64::consts::PI)),
+                    _ => Err(format!("Unknown variable '{name}' at {at}")),
+                }
+            }
+            Some((token, at)) => Err(format!("Unexpected {token} at {at}")),
+            None => Err("Unexpected end of expression".to_string()),
+        }
+    }
+
+    fn call(&mut self, name: &str, at: usize) -> Result<Node, String> {
+        let (func, min_args, max_args) =
+            Func::lookup(name).ok_or_else(|| format!("Unknown function '{name}' at {at}"))?;
+        let mut args = Vec::new();
+        if self.peek() != Some(&Token::RParen) {
+            loop {
+                args.push(self.expression(0)?);
+                if self.peek() == Some(&Token::Comma) {
+                    self.pos += 1;
+                } else {
+                    break;
+                }
+            }
+        }
+        self.expect(Token::RParen)?;
+        if args.len() < min_args || args.len() > max_args {
+            let expected = if min_args == max_args {
+                min_args.to_string()
+            } else {
+                format!("{min_args}-{max_args}")
+            };
+            return Err(format!("'{name}' takes {expected} arguments, got {}", args.len()));
+        }
+        Ok(Node::Call(func, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn value(source: &str, x: f64, y: f64) -> f64 {
+        let terrain = Heightmap {
+            data: vec![0.0, 1.0, 0.0, 1.0],
+            width: 2,
+            height: 2,
+        };
+        let inputs = Inputs::new(7, &terrain, None);
+        Expression::parse(source).unwrap().eval(&inputs, x, y)
+    }
+
+    #[test]
+    fn precedence_and_associativity() {
+        assert_eq!(value("1 + 2 * 3", 0.0, 0.0), 7.0);
+        assert_eq!(value("(1 + 2) * 3", 0.0, 0.0), 9.0);
+        assert_eq!(value("2 ^ 3 ^ 2", 0.0, 0.0), 512.0);
+        assert_eq!(value("-x^2", 3.0, 0.0), -9.0);
+        assert_eq!(value("10 - 4 - 3", 0.0, 0.0), 3.0);
+        assert_eq!(value("-7 % 3", 0.0, 0.0), 2.0);
+        assert_eq!(value("1.5e1 + 2e-1", 0.0, 0.0), 15.2);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(value("lerp(x, y, 0.25)", 2.0, 6.0), 3.0);
+        assert_eq!(value("clamp(x, 0, 1)", 4.0, 0.0), 1.0);
+        assert_eq!(value("smoothstep(0, 1, x)", 0.5, 0.0), 0.5);
+        assert_eq!(value("max(min(x, y), pi)", 1.0, 2.0), std::f64::consts::PI);
+        assert_eq!(value("height(x, y)", 1.0, 0.0), 1.0);
+        assert_eq!(value("mask(x, y)", 0.5, 0.5), 0.0);
+        for noise in ["noise(x, y)", "fbm(x, y, 3)", "ridge(x, y)", "cells(x, y)"] {
+            let v = value(noise, 0.3, 0.7);
+            assert!((0.0..=1.0).contains(&v), "{noise} gave {v}");
+        }
+    }
+
+    #[test]
+    fn nan_arguments_do_not_panic() {
+        assert!(value("clamp(x, sqrt(-1), 1)", 2.0, 0.0) <= 1.0);
+        assert!(value("clamp(x, 0, sqrt(-1))", 2.0, 0.0) >= 0.0);
+        assert!(value("height(sqrt(-1), 1/0)", 0.0, 0.0).is_nan());
+        assert!(value("fbm(x, y, sqrt(-1))", 0.0, 0.0).is_nan());
+    }
+
+    #[test]
+    fn generate_clamps() {
+        let mut hm = Heightmap::new(4, 3);
+        let terrain = hm.clone();
+        let inputs = Inputs::new(1, &terrain, None);
+        generate(&mut hm, &Expression::parse("x * 3 - 1").unwrap(), &inputs);
+        assert_eq!(&hm.data[..4], &[0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn malformed_expressions_are_errors() {
+        for source in [
+            "", "1 +", "(1", "1)", "x y", "1..2", "1e", "z", "foo(x)", "sin()", "min(1)",
+            "clamp(1, 2, 3, 4)", "sin(x,)", "1 $ 2", "fbm(x y)", "é", ",", "*2",
+        ] {
+            assert!(Expression::parse(source).is_err(), "{source:?} parsed");
+        }
+        // Every prefix of a valid formula either parses or is an error
+        let source = "0.5*fbm(x*3, y*3, 4) + ridge(x, y)^2*clamp(mask(x, y), 0, 1)";
+        for end in 0..source.len() {
+            let _ = Expression::parse(&source[..end]);
+        }
+    }
+
+    #[test]
+    fn overlong_expressions_are_errors() {
+        let nested = format!("{}x{}", "(".repeat(100_000), ")".repeat(100_000));
+        assert!(Expression::parse(&nested).is_err());
+        let chained = vec!["x"; 100_000].join("+");
+        assert!(Expression::parse(&chained).is_err());
+        assert!(Expression::parse(&"-".repeat(100_000)).is_err());
+        let long = vec!["x"; 400].join("+");
+        assert_eq!(value(&long, 1.0, 0.0), 400.0);
+    }
+}
@@ -0,0 +1,50 @@
+//! Files dropped onto the window, sorted by extension into the import that
+//! reads them. The frontend runs that import's usual flow, with its
+//! confirmation and settings, when it hears `file-dropped`.
+
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use tauri::{Emitter, Runtime, Window};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DroppedFile {
+    Project { path: String },
+    /// A PNG, or a float TIFF or OpenEXR.
+    #[serde(rename_all = "camelCase")]
+    Heightmap { path: String, float: bool },
+    /// Headerless samples; `.r16` and `.r32` name their bit depth.
+    #[serde(rename_all = "camelCase")]
+    Raw { path: String, bit_depth: Option<u8> },
+    /// Nothing imports this file.
+    Unsupported { path: String },
+}
+
+/// The import for `path`, by its extension.
+pub fn route(path: &Path) -> DroppedFile {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default().to_ascii_lowercase();
+    let path = path.display().to_string();
+    match extension.as_str() {
+        "topo" => DroppedFile::Project { path },
+        "png" => DroppedFile::Heightmap { path, float: false },
+        "tif" | "tiff" | "exr" => DroppedFile::Heightmap { path, float: true },
+        "r16" => DroppedFile::Raw { path, bit_depth: Some(16) },
+        "r32" => DroppedFile::Raw { path, bit_depth: Some(32) },
+        "raw" => DroppedFile::Raw { path, bit_depth: None },
+        _ => DroppedFile::Unsupported { path },
+    }
+}
+
+/// Route a drop of `paths`. Every import replaces the map, so only one file
+/// is opened: a project if there is one, else the first file that imports.
+pub fn handle<R: Runtime>(window: &Window<R>, paths: &[PathBuf]) {
+    let files: Vec<DroppedFile> = paths.iter().map(|p| route(p)).collect();
+    let chosen = files
+        .iter()
+        .find(|f| matches!(f, DroppedFile::Project { .. }))
+        .or_else(|| files.iter().find(|f| !matches!(f, DroppedFile::Unsupported { .. })))
+        .or(files.first());
+    if let Some(file) = chosen {
+        let _ = window.emit("file-dropped", file);
+    }
+}
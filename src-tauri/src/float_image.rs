@@ -0,0 +1,533 @@
+//! Full-precision float heightmap files for tools that read 32-bit images:
+//! uncompressed single-channel TIFF and OpenEXR. Both are simple enough to write
+//! directly. The EXR reader takes the float layouts other terrain tools write
+//! too, uncompressed or deflated; TIFFs are read as DEMs by `geotiff`.
+
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
+use flate2::read::ZlibDecoder;
+use serde::{Deserialize, Serialize};
+use crate::canvas;
+use crate::geotiff;
+use crate::heightmap::Heightmap;
+
+fn create(path: &Path) -> Result<BufWriter<File>, String> {
+    File::create(path)
+        .map(BufWriter::new)
+        .map_err(|e| format!("Failed to create {}: {e}", path.display()))
+}
+
+fn write_err(e: std::io::Error) -> String {
+    format!("Failed to write image: {e}")
+}
+
+/// TIFF field types.
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+
+/// Baseline grayscale TIFF with one 32-bit IEEE float sample per pixel, in a
+/// single uncompressed strip.
+pub fn write_tiff32(path: &Path, hm: &Heightmap) -> Result<(), String> {
+    let data_len = hm.data.len() as u64 * 4;
+    let data_len = u32::try_from(data_len).map_err(|_| "Heightmap too large for TIFF".to_string())?;
+
+    // Tags in ascending order, as TIFF requires
+    let mut entries: Vec<(u16, u16, u32)> = vec![
+        (256, LONG, hm.width),  // ImageWidth
+        (257, LONG, hm.height), // ImageLength
+        (258, SHORT, 32),       // BitsPerSample
+        (259, SHORT, 1),        // Compression: none
+        (262, SHORT, 1),        // PhotometricInterpretation: black is zero
+        (273, LONG, 0),         // StripOffsets, filled in below
+        (277, SHORT, 1),        // SamplesPerPixel
+        (278, LONG, hm.height), // RowsPerStrip
+        (279, LONG, data_len),  // StripByteCounts
+        (284, SHORT, 1),        // PlanarConfiguration: chunky
+        (339, SHORT, 3),        // SampleFormat: IEEE float
+    ];
+    let ifd_len = 2 + entries.len() as u32 * 12 + 4;
+    // Header, then the directory, then the pixels on a 4-byte boundary
+    let data_offset = (8 + ifd_len).next_multiple_of(4);
+    entries[5].2 = data_offset;
+
+    let mut out = create(path)?;
+    let mut header = Vec::with_capacity(data_offset as usize);
+    header.extend_from_slice(b"II");
+    header.extend_from_slice(&42u16.to_le_bytes());
+    header.extend_from_slice(&8u32.to_le_bytes());
+    header.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+    for (tag, kind, value) in entries {
+        header.extend_from_slice(&tag.to_le_bytes());
+        header.extend_from_slice(&kind.to_le_bytes());
+        header.extend_from_slice(&1u32.to_le_bytes());
+        // Values are left-justified in the 4-byte field
+        match kind {
+            SHORT => header.extend_from_slice(&[(value as u16).to_le_bytes(), [0, 0]].concat()),
+            _ => header.extend_from_slice(&value.to_le_bytes()),
+        }
+    }
+    header.extend_from_slice(&0u32.to_le_bytes()); // no further directories
+    header.resize(data_offset as usize, 0);
+    out.write_all(&header).map_err(write_err)?;
+
+    for v in &hm.data {
+        out.write_all(&v.to_le_bytes()).map_err(write_err)?;
+    }
+    out.flush().map_err(write_err)
+}
+
+/// EXR header attribute: name, type name, size and value.
+fn attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    header.extend_from_slice(name.as_bytes());
+    header.push(0);
+    header.extend_from_slice(kind.as_bytes());
+    header.push(0);
+    header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    header.extend_from_slice(value);
+}
+
+/// Single-part scanline OpenEXR with one uncompressed 32-bit float channel `Y`,
+/// the channel name image tools read as luminance.
+pub fn write_exr(path: &Path, hm: &Heightmap) -> Result<(), String> {
+    let (w, h) = (hm.width as usize, hm.height as usize);
+    let window: Vec<u8> = [0, 0, hm.width as i32 - 1, hm.height as i32 - 1]
+        .iter()
+        .flat_map(|v| v.to_le_bytes())
+        .collect();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&20000630u32.to_le_bytes());
+    header.extend_from_slice(&2u32.to_le_bytes()); // version 2, single-part scanline
+    // Name, pixel type FLOAT, not perceptually linear, reserved, no subsampling
+    let mut channels = b"Y\0".to_vec();
+    channels.extend_from_slice(&2i32.to_le_bytes());
+    channels.extend_from_slice(&[0, 0, 0, 0]);
+    channels.extend_from_slice(&1i32.to_le_bytes());
+    channels.extend_from_slice(&1i32.to_le_bytes());
+    channels.push(0);
+    attribute(&mut header, "channels", "chlist", &channels);
+    attribute(&mut header, "compression", "compression", &[0]);
+    attribute(&mut header, "dataWindow", "box2i", &window);
+    attribute(&mut header, "displayWindow", "box2i", &window);
+    attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    attribute(&mut header, "pixelAspectRatio", "float", &1.0f32.to_le_bytes());
+    attribute(&mut header, "screenWindowCenter", "v2f", &[0u8; 8]);
+    attribute(&mut header, "screenWindowWidth", "float", &1.0f32.to_le_bytes());
+    header.push(0);
+
+    // Uncompressed files hold one scanline per block: its y, its byte size and
+    // the pixels, located through a table of offsets after the header
+    let block_len = 8 + w as u64 * 4;
+    let first_block = header.len() as u64 + h as u64 * 8;
+    for y in 0..h as u64 {
+        header.extend_from_slice(&(first_block + y * block_len).to_le_bytes());
+    }
+
+    let mut out = create(path)?;
+    out.write_all(&header).map_err(write_err)?;
+    for (y, row) in hm.data.chunks_exact(w).enumerate() {
+        out.write_all(&(y as i32).to_le_bytes()).map_err(write_err)?;
+        out.write_all(&(w as i32 * 4).to_le_bytes()).map_err(write_err)?;
+        for v in row {
+            out.write_all(&v.to_le_bytes()).map_err(write_err)?;
+        }
+    }
+    out.flush().map_err(write_err)
+}
+
+/// How imported float samples map to heights.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum Normalization {
+    /// The file's lowest sample becomes 0 and its highest 1.
+    #[default]
+    Auto,
+    /// Samples from `min` to `max`, e.g. meters, map to 0 to 1; those outside
+    /// are clamped.
+    Range { min: f32, max: f32 },
+    /// Samples are used as heights as they are, including any outside [0, 1].
+    /// Exports that store normalized integers clamp those.
+    Raw,
+}
+
+impl Normalization {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Normalization::Range { min, max } if !(min.is_finite() && max.is_finite() && min < max) => {
+                Err("Import range needs a finite minimum below the maximum".into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Map `samples` to heights in place. Samples that aren't finite, as some
+    /// tools write for no data, become the lowest height.
+    pub fn apply(&self, samples: &mut [f32]) {
+        let (lo, hi) = samples
+            .iter()
+            .filter(|v| v.is_finite())
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        let (lo, hi) = if lo <= hi { (lo, hi) } else { (0.0, 1.0) };
+        for v in samples {
+            *v = match *self {
+                Normalization::Auto if v.is_finite() => (*v - lo) / (hi - lo).max(f32::EPSILON),
+                Normalization::Range { min, max } if v.is_finite() => ((*v - min) / (max - min)).clamp(0.0, 1.0),
+                Normalization::Raw if v.is_finite() => *v,
+                Normalization::Raw => lo,
+                _ => 0.0,
+            };
+        }
+    }
+}
+
+/// Read a TIFF or OpenEXR heightmap, told apart by their magic numbers.
+/// Samples come back as stored, to be normalized by the caller.
+pub fn read(path: &Path) -> Result<Heightmap, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    match bytes.get(..4) {
+        Some(b"II*\0") | Some(b"MM\0*") => geotiff::decode(&bytes).map(|dem| dem.samples),
+        Some(magic) if magic == 20000630u32.to_le_bytes() => read_exr(&bytes),
+        _ => Err("Not a TIFF or OpenEXR file".into()),
+    }
+}
+
+pub fn truncated() -> String {
+    "Image file is truncated".into()
+}
+
+pub fn check_size(width: u32, height: u32) -> Result<(), String> {
+    if width == 0 || height == 0 || width > canvas::MAX_CANVAS_SIZE || height > canvas::MAX_CANVAS_SIZE {
+        return Err(format!("Image size {width}x{height} is outside 1x1 to {0}x{0}", canvas::MAX_CANVAS_SIZE));
+    }
+    Ok(())
+}
+
+/// Decompress zlib data that should come to `expected` bytes. Corrupt sizes
+/// neither allocate nor decompress more than the data can hold.
+pub fn inflate(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    // Deflate packs at most 1032 bytes into one
+    let mut out = Vec::with_capacity(expected.min(data.len().saturating_mul(1032)));
+    ZlibDecoder::new(data)
+        .take(expected as u64 + 1)
+        .read_to_end(&mut out)
+        .map_err(|e| format!("Failed to decompress image data: {e}"))?;
+    if out.len() != expected {
+        return Err(truncated());
+    }
+    Ok(out)
+}
+
+/// Bytes in either order, as the file's header says.
+pub struct Reader<'a> {
+    pub bytes: &'a [u8],
+    pub big_endian: bool,
+}
+
+impl Reader<'_> {
+    pub fn take<const N: usize>(&self, at: usize) -> Result<[u8; N], String> {
+        let end = at.checked_add(N).ok_or_else(truncated)?;
+        let slice = self.bytes.get(at..end).ok_or_else(truncated)?;
+        let mut out = [0; N];
+        out.copy_from_slice(slice);
+        if self.big_endian {
+            out.reverse();
+        }
+        Ok(out)
+    }
+
+    pub fn u16(&self, at: usize) -> Result<u16, String> {
+        self.take(at).map(u16::from_le_bytes)
+    }
+
+    pub fn u32(&self, at: usize) -> Result<u32, String> {
+        self.take(at).map(u32::from_le_bytes)
+    }
+
+    pub fn u64(&self, at: usize) -> Result<u64, String> {
+        self.take(at).map(u64::from_le_bytes)
+    }
+
+    pub fn f32(&self, at: usize) -> Result<f32, String> {
+        self.take(at).map(f32::from_le_bytes)
+    }
+}
+
+/// EXR compression modes the reader handles, with scanlines per block.
+fn exr_lines_per_block(compression: u8) -> Result<usize, String> {
+    match compression {
+        0 | 2 => Ok(1), // none, ZIPS
+        3 => Ok(16),    // ZIP
+        other => Err(format!("Unsupported OpenEXR compression {other}; save uncompressed or with ZIP")),
+    }
+}
+
+/// Undo ZIP's byte reordering: deltas between successive bytes, and the two
+/// halves of each block's bytes interleaved.
+fn exr_unzip(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    let mut t = inflate(data, expected)?;
+    for i in 1..t.len() {
+        t[i] = t[i - 1].wrapping_add(t[i]).wrapping_sub(128);
+    }
+    let half = t.len().div_ceil(2);
+    Ok((0..t.len()).map(|i| if i % 2 == 0 { t[i / 2] } else { t[half + i / 2] }).collect())
+}
+
+/// Single-part scanline OpenEXR. Heights come from the 32-bit float channel
+/// `Y`, else `R`, else the first one.
+fn read_exr(bytes: &[u8]) -> Result<Heightmap, String> {
+    let r = Reader { bytes, big_endian: false };
+    let flags = r.u32(4)?;
+    // Tiled, deep and multi-part files
+    if flags & 0x1a00 != 0 {
+        return Err("Only single-part scanline OpenEXR files are supported".into());
+    }
+
+    let mut at = 8;
+    let mut channels: Vec<(String, i32)> = Vec::new();
+    let (mut compression, mut window) = (None, None);
+    let c_string = |at: usize| -> Result<(String, usize), String> {
+        let len = bytes.get(at..).and_then(|b| b.iter().position(|&c| c == 0)).ok_or_else(truncated)?;
+        Ok((String::from_utf8_lossy(&bytes[at..at + len]).into_owned(), at + len + 1))
+    };
+    loop {
+        let (name, next) = c_string(at)?;
+        if name.is_empty() {
+            at = next;
+            break;
+        }
+        let (_, next) = c_string(next)?;
+        let size = r.u32(next)? as usize;
+        let value = next + 4;
+        match name.as_str() {
+            "channels" => {
+                let mut c = value;
+                while bytes.get(c).is_some_and(|&b| b != 0) {
+                    let (channel, next) = c_string(c)?;
+                    channels.push((channel, r.u32(next)? as i32));
+                    c = next + 16;
+                }
+            }
+            "compression" => compression = Some(*bytes.get(value).ok_or_else(truncated)?),
+            "dataWindow" => {
+                let v: Vec<i32> = (0..4).map(|k| r.u32(value + k * 4).map(|v| v as i32)).collect::<Result<_, _>>()?;
+                window = Some([v[0], v[1], v[2], v[3]]);
+            }
+            _ => {}
+        }
+        at = value + size;
+    }
+
+    let [x0, y0, x1, y1] = window.ok_or("OpenEXR file has no data window")?;
+    let (width, height) = ((x1 - x0 + 1).max(0) as u32, (y1 - y0 + 1).max(0) as u32);
+    check_size(width, height)?;
+    let lines = exr_lines_per_block(compression.unwrap_or(0))?;
+    // Channels are stored in name order, each a whole scanline at a time
+    channels.sort();
+    let pick = ["Y", "R"]
+        .iter()
+        .find_map(|name| channels.iter().position(|(c, _)| c == name))
+        .unwrap_or(0);
+    match channels.get(pick) {
+        Some((_, 2)) => {}
+        Some((name, _)) => return Err(format!("OpenEXR channel {name} must be 32-bit float")),
+        None => return Err("OpenEXR file has no channels".into()),
+    }
+    let pixel_size = |kind: i32| if kind == 1 { 2 } else { 4 };
+    let (w, h) = (width as usize, height as usize);
+    let line_len: usize = channels.iter().map(|&(_, kind)| w * pixel_size(kind)).sum();
+    let channel_at: usize = channels[..pick].iter().map(|&(_, kind)| w * pixel_size(kind)).sum();
+
+    let mut hm = Heightmap::new(width, height);
+    for block in 0..h.div_ceil(lines) {
+        let offset = usize::try_from(r.u64(at + block * 8)?).map_err(|_| truncated())?;
+        let first = (r.u32(offset)? as i32 - y0).max(0) as usize;
+        let len = r.u32(offset + 4)? as usize;
+        let rows = lines.min(h.saturating_sub(first));
+        let raw = bytes.get(offset + 8..offset + 8 + len).ok_or_else(truncated)?;
+        let expected = rows * line_len;
+        // Blocks that wouldn't shrink are stored as they are
+        let data = if compression.unwrap_or(0) == 0 || len == expected { raw.to_vec() } else { exr_unzip(raw, expected)? };
+        let block_reader = Reader { bytes: &data, big_endian: false };
+        for y in 0..rows {
+            for x in 0..w {
+                hm.data[(first + y) * w + x] = block_reader.f32(y * line_len + channel_at + x * 4)?;
+            }
+        }
+    }
+    Ok(hm)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+
+    fn temp_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("topograph-{}-{name}", std::process::id()))
+    }
+
+    fn ramp(width: u32, height: u32) -> Heightmap {
+        let data = (0..width * height).map(|i| i as f32 * 0.25 - 100.0).collect();
+        Heightmap { data, width, height }
+    }
+
+    fn write_and_read(name: &str, hm: &Heightmap, write: fn(&Path, &Heightmap) -> Result<(), String>) -> Vec<u8> {
+        let path = temp_path(name);
+        write(&path, hm).unwrap();
+        let read_back = read(&path).unwrap();
+        assert_eq!((read_back.width, read_back.height), (hm.width, hm.height));
+        assert_eq!(read_back.data, hm.data);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        bytes
+    }
+
+    /// `read` on every prefix of `bytes` shorter than the whole file fails
+    /// rather than panicking.
+    fn assert_truncations_fail(name: &str, bytes: &[u8]) {
+        let path = temp_path(name);
+        for len in (0..bytes.len()).step_by(7) {
+            std::fs::write(&path, &bytes[..len]).unwrap();
+            assert!(read(&path).is_err(), "{len} of {} bytes read", bytes.len());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// An OpenEXR like `write_exr`'s but with ZIPS compression, one scanline a block.
+    fn zips_exr(hm: &Heightmap) -> Vec<u8> {
+        let window: Vec<u8> = [0, 0, hm.width as i32 - 1, hm.height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        let mut header = Vec::new();
+        header.extend_from_slice(&20000630u32.to_le_bytes());
+        header.extend_from_slice(&2u32.to_le_bytes());
+        let mut channels = b"Y\0".to_vec();
+        channels.extend_from_slice(&2i32.to_le_bytes());
+        channels.extend_from_slice(&[0, 0, 0, 0, 1, 0, 0, 0, 1, 0, 0, 0, 0]);
+        attribute(&mut header, "channels", "chlist", &channels);
+        attribute(&mut header, "compression", "compression", &[2]);
+        attribute(&mut header, "dataWindow", "box2i", &window);
+        header.push(0);
+
+        let blocks: Vec<Vec<u8>> = hm
+            .data
+            .chunks_exact(hm.width as usize)
+            .map(|row| {
+                let raw: Vec<u8> = row.iter().flat_map(|v| v.to_le_bytes()).collect();
+                // Split even and odd bytes into halves, then store deltas
+                let mut t: Vec<u8> = raw.iter().step_by(2).chain(raw.iter().skip(1).step_by(2)).copied().collect();
+                for i in (1..t.len()).rev() {
+                    t[i] = t[i].wrapping_sub(t[i - 1]).wrapping_add(128);
+                }
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(&t).unwrap();
+                encoder.finish().unwrap()
+            })
+            .collect();
+        let mut offset = header.len() as u64 + blocks.len() as u64 * 8;
+        let mut body = Vec::new();
+        for (y, block) in blocks.iter().enumerate() {
+            header.extend_from_slice(&offset.to_le_bytes());
+            body.extend_from_slice(&(y as i32).to_le_bytes());
+            body.extend_from_slice(&(block.len() as i32).to_le_bytes());
+            body.extend_from_slice(block);
+            offset += 8 + block.len() as u64;
+        }
+        header.extend_from_slice(&body);
+        header
+    }
+
+    #[test]
+    fn tiff_round_trip() {
+        write_and_read("round-trip.tif", &ramp(37, 21), write_tiff32);
+    }
+
+    #[test]
+    fn exr_round_trip() {
+        write_and_read("round-trip.exr", &ramp(37, 21), write_exr);
+    }
+
+    #[test]
+    fn compressed_exr_reads() {
+        let hm = ramp(40, 9);
+        let path = temp_path("zips.exr");
+        std::fs::write(&path, zips_exr(&hm)).unwrap();
+        let read_back = read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!((read_back.width, read_back.height), (40, 9));
+        assert_eq!(read_back.data, hm.data);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let hm = ramp(19, 11);
+        assert_truncations_fail("truncated.tif", &write_and_read("whole.tif", &hm, write_tiff32));
+        assert_truncations_fail("truncated.exr", &write_and_read("whole.exr", &hm, write_exr));
+        assert_truncations_fail("truncated-zips.exr", &zips_exr(&hm));
+    }
+
+    #[test]
+    fn corrupt_files_are_errors() {
+        let path = temp_path("corrupt.exr");
+        std::fs::write(&path, b"not an image").unwrap();
+        assert!(read(&path).is_err());
+
+        // A block offset pointing far past the end of the file
+        let mut bytes = zips_exr(&ramp(8, 4));
+        let header_end = header_len(&bytes);
+        bytes[header_end..header_end + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(read(&path).is_err());
+
+        // A zero-sized data window
+        let mut bytes = write_and_read("empty-window.exr", &ramp(4, 4), write_exr);
+        let window = bytes.windows(10).position(|w| w == b"dataWindow").unwrap() + b"dataWindow\0box2i\0".len() + 4;
+        bytes[window + 8..window + 12].copy_from_slice(&(-1i32).to_le_bytes());
+        std::fs::write(&path, &bytes).unwrap();
+        assert!(read(&path).is_err());
+
+        // Any damaged byte is at worst an error
+        let file = zips_exr(&ramp(8, 4));
+        for at in 0..file.len() {
+            for value in [0x00, 0x7f, 0xff] {
+                let mut corrupt = file.clone();
+                corrupt[at] = value;
+                std::fs::write(&path, &corrupt).unwrap();
+                let _ = read(&path);
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    /// Where the block offset table of a single-part EXR starts.
+    fn header_len(bytes: &[u8]) -> usize {
+        let mut at = 8;
+        loop {
+            let name_len = bytes[at..].iter().position(|&b| b == 0).unwrap();
+            if name_len == 0 {
+                return at + 1;
+            }
+            at += name_len + 1;
+            at += bytes[at..].iter().position(|&b| b == 0).unwrap() + 1;
+            at += 4 + u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap()) as usize;
+        }
+    }
+
+    #[test]
+    fn normalization() {
+        let samples = [10.0, f32::NAN, 30.0, 20.0];
+        let mut auto = samples;
+        Normalization::Auto.apply(&mut auto);
+        assert_eq!(auto, [0.0, 0.0, 1.0, 0.5]);
+        let mut range = samples;
+        Normalization::Range { min: 20.0, max: 40.0 }.apply(&mut range);
+        assert_eq!(range, [0.0, 0.0, 0.5, 0.0]);
+        let mut raw = samples;
+        Normalization::Raw.apply(&mut raw);
+        assert_eq!(raw, [10.0, 10.0, 30.0, 20.0]);
+        assert!(Normalization::Range { min: 1.0, max: 1.0 }.validate().is_err());
+    }
+}
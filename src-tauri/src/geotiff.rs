@@ -0,0 +1,648 @@
+//! Elevation models in GeoTIFF, as survey agencies and GDAL publish them: the
+//! first band of a TIFF in strips or tiles, with any integer or float samples,
+//! uncompressed, LZW or deflated, and the no-data value and georeferencing GDAL
+//! writes alongside.
+
+use std::collections::HashMap;
+use std::path::Path;
+use crate::canvas;
+use crate::float_image::{self, Reader};
+use crate::heightmap::Heightmap;
+use crate::resample::{self, ExportResolution, ResampleFilter};
+use crate::world::{Georeference, WorldScale};
+
+/// Meters along a degree of latitude, and of longitude at the equator.
+const METERS_PER_DEGREE: f64 = 111_320.0;
+
+/// A DEM as stored in the file.
+pub struct Dem {
+    /// Elevations in the file's vertical units.
+    pub samples: Heightmap,
+    /// GDAL's no-data value. NaN samples count as no data as well.
+    pub nodata: Option<f64>,
+    /// Placement of the source raster, when the file has a pixel scale and a
+    /// tie point.
+    pub georeference: Option<Georeference>,
+    /// Meters per model unit of a projected system.
+    pub linear_unit: f64,
+    /// Meters per elevation unit.
+    pub vertical_unit: f64,
+}
+
+pub fn read(path: &Path) -> Result<Dem, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    decode(&bytes)
+}
+
+/// One image file directory: its entries as tag, field type, count and the
+/// offset of the values.
+struct Ifd<'a> {
+    r: Reader<'a>,
+    entries: Vec<(u16, u16, usize, usize)>,
+}
+
+impl Ifd<'_> {
+    /// Every value of a numeric tag.
+    fn numbers(&self, tag: u16) -> Result<Option<Vec<f64>>, String> {
+        let Some(&(_, kind, count, at)) = self.entries.iter().find(|e| e.0 == tag) else {
+            return Ok(None);
+        };
+        let size = field_size(kind).ok_or_else(|| format!("Unsupported TIFF field type {kind} for tag {tag}"))?;
+        let r = &self.r;
+        let number = |at: usize| -> Result<f64, String> {
+            Ok(match kind {
+                1 | 7 => *r.bytes.get(at).ok_or_else(float_image::truncated)? as f64,
+                6 => *r.bytes.get(at).ok_or_else(float_image::truncated)? as i8 as f64,
+                3 => r.u16(at)? as f64,
+                8 => r.u16(at)? as i16 as f64,
+                4 => r.u32(at)? as f64,
+                9 => r.u32(at)? as i32 as f64,
+                5 => r.u32(at)? as f64 / r.u32(at + 4)? as f64,
+                10 => r.u32(at)? as i32 as f64 / r.u32(at + 4)? as i32 as f64,
+                11 => r.f32(at)? as f64,
+                12 => f64::from_bits(r.u64(at)?),
+                _ => return Err(format!("TIFF tag {tag} is not numeric")),
+            })
+        };
+        (0..count).map(|k| number(at + k * size)).collect::<Result<_, _>>().map(Some)
+    }
+
+    fn one(&self, tag: u16, default: Option<u32>) -> Result<u32, String> {
+        match self.numbers(tag)? {
+            Some(v) if !v.is_empty() => Ok(v[0] as u32),
+            _ => default.ok_or_else(|| format!("TIFF is missing tag {tag}")),
+        }
+    }
+
+    fn ascii(&self, tag: u16) -> Option<String> {
+        let &(_, _, count, at) = self.entries.iter().find(|e| e.0 == tag && e.1 == 2)?;
+        let text = self.r.bytes.get(at..at + count)?;
+        Some(String::from_utf8_lossy(text).trim_end_matches('\0').trim().to_string())
+    }
+}
+
+/// Bytes per value of a TIFF field type.
+fn field_size(kind: u16) -> Option<usize> {
+    match kind {
+        1 | 2 | 6 | 7 => Some(1),
+        3 | 8 => Some(2),
+        4 | 9 | 11 => Some(4),
+        5 | 10 | 12 => Some(8),
+        _ => None,
+    }
+}
+
+/// Read the first image of a TIFF. Pixels with several samples, e.g. RGB,
+/// contribute their first.
+pub fn decode(bytes: &[u8]) -> Result<Dem, String> {
+    let r = Reader { bytes, big_endian: bytes.first() == Some(&b'M') };
+    match r.u16(2)? {
+        42 => {}
+        43 => return Err("BigTIFF files are not supported".into()),
+        _ => return Err("Not a TIFF file".into()),
+    }
+    let first = r.u32(4)? as usize;
+    let entries = (0..r.u16(first)? as usize)
+        .map(|i| {
+            let entry = first + 2 + i * 12;
+            let (tag, kind, count) = (r.u16(entry)?, r.u16(entry + 2)?, r.u32(entry + 4)? as usize);
+            // Values that fit in the entry's last four bytes are stored there
+            let len = field_size(kind).unwrap_or(0) * count;
+            let at = if len <= 4 { entry + 8 } else { r.u32(entry + 8)? as usize };
+            Ok((tag, kind, count, at))
+        })
+        .collect::<Result<_, String>>()?;
+    let ifd = Ifd { r: Reader { bytes, big_endian: r.big_endian }, entries };
+
+    let (width, height) = (ifd.one(256, None)?, ifd.one(257, None)?);
+    float_image::check_size(width, height)?;
+    let samples_per_pixel = ifd.one(277, Some(1))?.max(1);
+    if samples_per_pixel > u16::MAX as u32 {
+        return Err(format!("TIFF has {samples_per_pixel} samples per pixel"));
+    }
+    let samples_per_pixel = samples_per_pixel as usize;
+    let (bits, format) = (ifd.one(258, Some(1))?, ifd.one(339, Some(1))?);
+    let sample_size = match (format, bits) {
+        (1 | 2, 8 | 16 | 32) | (3, 32 | 64) => bits as usize / 8,
+        _ => return Err(format!("Unsupported TIFF samples: {bits}-bit {}", sample_kind(format))),
+    };
+    let compression = ifd.one(259, Some(1))?;
+    if !matches!(compression, 1 | 5 | 8 | 32946) {
+        return Err(format!("Unsupported TIFF compression {compression}"));
+    }
+    let predictor = ifd.one(317, Some(1))?;
+    if !matches!((predictor, format), (1, _) | (2, 1 | 2) | (3, 3)) {
+        return Err(format!("Unsupported TIFF predictor {predictor}"));
+    }
+    // Separate planes store the first band's chunks first, one sample a pixel
+    let chunk_samples = if ifd.one(284, Some(1))? == 2 { 1 } else { samples_per_pixel };
+
+    let tiled = ifd.numbers(322)?.is_some();
+    let (chunk_w, chunk_h, offsets, counts) = if tiled {
+        (ifd.one(322, None)?, ifd.one(323, None)?, ifd.numbers(324)?, ifd.numbers(325)?)
+    } else {
+        (width, ifd.one(278, Some(height))?.min(height), ifd.numbers(273)?, ifd.numbers(279)?)
+    };
+    if chunk_w == 0 || chunk_h == 0 {
+        return Err("TIFF has empty tiles or strips".into());
+    }
+    // Tiles may overhang the image, but not by more than a canvas
+    if chunk_w.max(chunk_h) > canvas::MAX_CANVAS_SIZE {
+        return Err(format!("TIFF tiles of {chunk_w}x{chunk_h} are too large"));
+    }
+    let offsets = offsets.ok_or("TIFF is missing image data offsets")?;
+    let counts = counts.ok_or("TIFF is missing image data sizes")?;
+    let across = width.div_ceil(chunk_w) as usize;
+    let chunks = across * height.div_ceil(chunk_h) as usize;
+    if offsets.len() < chunks || counts.len() < chunks {
+        return Err(float_image::truncated());
+    }
+
+    let (w, h, cw) = (width as usize, height as usize, chunk_w as usize);
+    let row_len = cw * chunk_samples * sample_size;
+    let mut samples = Heightmap::new(width, height);
+    for chunk in 0..chunks {
+        let (x0, y0) = (chunk % across * cw, chunk / across * chunk_h as usize);
+        // Tiles are always whole; the last strip stops at the image's end
+        let rows = if tiled { chunk_h as usize } else { (chunk_h as usize).min(h - y0) };
+        let expected = rows * row_len;
+        let (offset, len) = (offsets[chunk] as usize, counts[chunk] as usize);
+        let raw = bytes.get(offset..offset.saturating_add(len)).ok_or_else(float_image::truncated)?;
+        let mut data = match compression {
+            1 => raw.get(..expected).ok_or_else(float_image::truncated)?.to_vec(),
+            5 => unlzw(raw, expected)?,
+            _ => float_image::inflate(raw, expected)?,
+        };
+        for row in data.chunks_exact_mut(row_len) {
+            match predictor {
+                2 => undo_differencing(row, sample_size, chunk_samples, r.big_endian),
+                3 => undo_float_differencing(row, sample_size, chunk_samples, r.big_endian),
+                _ => {}
+            }
+        }
+        let chunk_reader = Reader { bytes: &data, big_endian: r.big_endian };
+        for y in 0..rows.min(h - y0) {
+            for x in 0..cw.min(w - x0) {
+                let at = y * row_len + x * chunk_samples * sample_size;
+                samples.data[(y0 + y) * w + x0 + x] = sample(&chunk_reader, at, format, bits)?;
+            }
+        }
+    }
+
+    let nodata = ifd.ascii(42113).and_then(|s| s.parse::<f64>().ok());
+    let keys = geo_keys(ifd.numbers(34735)?.unwrap_or_default());
+    Ok(Dem {
+        samples,
+        nodata,
+        georeference: georeference(&ifd, &keys)?,
+        linear_unit: unit_meters(keys.get(&3076).copied()),
+        vertical_unit: unit_meters(keys.get(&4099).copied()),
+    })
+}
+
+fn sample_kind(format: u32) -> &'static str {
+    match format {
+        1 => "unsigned",
+        2 => "signed",
+        3 => "float",
+        _ => "unknown",
+    }
+}
+
+fn sample(r: &Reader, at: usize, format: u32, bits: u32) -> Result<f32, String> {
+    Ok(match (format, bits) {
+        (1, 8) => *r.bytes.get(at).ok_or_else(float_image::truncated)? as f32,
+        (2, 8) => *r.bytes.get(at).ok_or_else(float_image::truncated)? as i8 as f32,
+        (1, 16) => r.u16(at)? as f32,
+        (2, 16) => r.u16(at)? as i16 as f32,
+        (1, 32) => r.u32(at)? as f32,
+        (2, 32) => r.u32(at)? as i32 as f32,
+        (3, 32) => r.f32(at)?,
+        _ => f64::from_bits(r.u64(at)?) as f32,
+    })
+}
+
+/// Undo horizontal differencing (predictor 2): each sample was stored as the
+/// difference from the same sample of the pixel before.
+fn undo_differencing(row: &mut [u8], size: usize, stride: usize, big_endian: bool) {
+    let mask = if size == 8 { u64::MAX } else { (1u64 << (size * 8)) - 1 };
+    let get = |row: &[u8], i: usize| -> u64 {
+        let word = &row[i * size..(i + 1) * size];
+        if big_endian {
+            word.iter().fold(0, |acc, &b| acc << 8 | b as u64)
+        } else {
+            word.iter().rev().fold(0, |acc, &b| acc << 8 | b as u64)
+        }
+    };
+    for i in stride..row.len() / size {
+        let mut v = get(row, i).wrapping_add(get(row, i - stride)) & mask;
+        for k in 0..size {
+            let at = if big_endian { size - 1 - k } else { k };
+            row[i * size + at] = v as u8;
+            v >>= 8;
+        }
+    }
+}
+
+/// Undo floating-point differencing (predictor 3): the row's bytes were split
+/// into planes from most to least significant, then differenced byte by byte.
+fn undo_float_differencing(row: &mut [u8], size: usize, stride: usize, big_endian: bool) {
+    for i in stride..row.len() {
+        row[i] = row[i].wrapping_add(row[i - stride]);
+    }
+    let planes = row.to_vec();
+    let words = row.len() / size;
+    for k in 0..words {
+        for b in 0..size {
+            let at = if big_endian { b } else { size - 1 - b };
+            row[k * size + at] = planes[b * words + k];
+        }
+    }
+}
+
+/// TIFF's LZW: codes from 9 to 12 bits, most significant bit first, widening
+/// one code early. Every code's string is a run of the output already written,
+/// so the table holds only where each starts and its length.
+fn unlzw(data: &[u8], expected: usize) -> Result<Vec<u8>, String> {
+    const CLEAR: u32 = 256;
+    const END: u32 = 257;
+    let corrupt = || "TIFF LZW data is corrupt".to_string();
+    // A code never stands for more than the table's longest string
+    let mut out: Vec<u8> = Vec::with_capacity(expected.min(data.len().saturating_mul(4096)));
+    let mut table: Vec<(usize, usize)> = Vec::with_capacity(4096);
+    let mut previous: Option<(usize, usize)> = None;
+    let (mut acc, mut bits, mut width) = (0u32, 0u32, 9u32);
+    let mut input = data.iter();
+    'codes: loop {
+        while bits < width {
+            let Some(&b) = input.next() else { break 'codes };
+            acc = acc << 8 | b as u32;
+            bits += 8;
+        }
+        bits -= width;
+        let code = acc >> bits;
+        acc &= (1 << bits) - 1;
+        match code {
+            CLEAR => {
+                table.clear();
+                previous = None;
+                width = 9;
+                continue;
+            }
+            END => break,
+            _ => {}
+        }
+
+        let start = out.len();
+        let entry = if code < CLEAR {
+            out.push(code as u8);
+            (start, 1)
+        } else {
+            let index = (code - END - 1) as usize;
+            match (table.get(index), previous) {
+                (Some(&(at, len)), _) => {
+                    out.extend_from_within(at..at + len);
+                    (start, len)
+                }
+                // The code being defined by this very step: the previous
+                // string and its own first byte
+                (None, Some((at, len))) if index == table.len() => {
+                    out.extend_from_within(at..at + len);
+                    out.push(out[at]);
+                    (start, len + 1)
+                }
+                _ => return Err(corrupt()),
+            }
+        };
+        if let Some((at, len)) = previous {
+            // The previous string ends where this one starts
+            if table.len() < 4096 - 258 {
+                table.push((at, len + 1));
+            }
+        }
+        previous = Some(entry);
+        if 258 + table.len() + 1 >= 1 << width && width < 12 {
+            width += 1;
+        }
+        if out.len() >= expected {
+            break;
+        }
+    }
+    if out.len() < expected {
+        return Err(float_image::truncated());
+    }
+    out.truncate(expected);
+    Ok(out)
+}
+
+/// GeoKey directory values stored in the directory itself, by key id.
+fn geo_keys(directory: Vec<f64>) -> HashMap<u16, u16> {
+    directory
+        .chunks_exact(4)
+        .skip(1)
+        .filter(|key| key[1] == 0.0)
+        .map(|key| (key[0] as u16, key[3] as u16))
+        .collect()
+}
+
+/// Meters per unit for the EPSG linear unit codes DEMs use.
+fn unit_meters(code: Option<u16>) -> f64 {
+    match code {
+        Some(9002) => 0.3048,
+        Some(9003) => 1200.0 / 3937.0,
+        _ => 1.0,
+    }
+}
+
+fn georeference(ifd: &Ifd, keys: &HashMap<u16, u16>) -> Result<Option<Georeference>, String> {
+    let (Some(scale), Some(tie)) = (ifd.numbers(33550)?, ifd.numbers(33922)?) else {
+        return Ok(None);
+    };
+    if scale.len() < 2 || tie.len() < 6 || !(scale[0] > 0.0 && scale[1] > 0.0) {
+        return Ok(None);
+    }
+    let geographic = match keys.get(&1024) {
+        Some(&model) => model == 2,
+        None => keys.contains_key(&2048) && !keys.contains_key(&3072),
+    };
+    // 32767 marks a system defined by further keys rather than a code
+    let epsg = [3072, 2048]
+        .iter()
+        .filter_map(|id| keys.get(id))
+        .find(|&&code| code != 0 && code != 32767)
+        .map(|&code| code as u32);
+    // The tie point is a pixel's center when the raster samples points
+    let center = if keys.get(&1025) == Some(&2) { 0.5 } else { 0.0 };
+    Ok(Some(Georeference {
+        epsg,
+        geographic,
+        origin: [tie[3] - (tie[0] + center) * scale[0], tie[4] + (tie[1] + center) * scale[1]],
+        pixel_size: [scale[0], scale[1]],
+    }))
+}
+
+impl Dem {
+    /// The DEM as heights over its elevation range, resampled so its longer
+    /// side is `size`, and the world scale that puts it back in meters. No-data
+    /// cells become the lowest height. `world` supplies the cell size when the
+    /// file isn't georeferenced.
+    pub fn fit(&self, size: u32, world: &WorldScale) -> Result<(Heightmap, WorldScale), String> {
+        let (sw, sh) = (self.samples.width, self.samples.height);
+        if sw < 2 || sh < 2 {
+            return Err(format!("A {sw}x{sh} DEM is too small to import"));
+        }
+        let valid = |v: f32| v.is_finite() && self.nodata.is_none_or(|n| v != n as f32);
+        let (lo, hi) = self
+            .samples
+            .data
+            .iter()
+            .filter(|&&v| valid(v))
+            .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        if lo > hi {
+            return Err("The DEM has no elevation data".into());
+        }
+        let span = (hi - lo).max(f32::EPSILON);
+        let mut heights = self.samples.clone();
+        for v in &mut heights.data {
+            *v = if valid(*v) { (*v - lo) / span } else { 0.0 };
+        }
+        let (width, height) = ExportResolution { size, filter: ResampleFilter::Lanczos }.dims(&heights);
+        let heights = resample::resample(&heights, width, height, ResampleFilter::Lanczos);
+
+        // Corner cells stay centered on corner cells, as in `resample`
+        let georeference = self.georeference.as_ref().map(|g| {
+            let cell = [
+                g.pixel_size[0] * (sw - 1) as f64 / (width - 1) as f64,
+                g.pixel_size[1] * (sh - 1) as f64 / (height - 1) as f64,
+            ];
+            Georeference {
+                origin: [
+                    g.origin[0] + (g.pixel_size[0] - cell[0]) / 2.0,
+                    g.origin[1] - (g.pixel_size[1] - cell[1]) / 2.0,
+                ],
+                pixel_size: cell,
+                ..g.clone()
+            }
+        });
+        let meters_per_pixel = match &georeference {
+            Some(g) if g.geographic => {
+                let latitude = (g.origin[1] - g.pixel_size[1] * height as f64 / 2.0).to_radians();
+                let x = g.pixel_size[0] * METERS_PER_DEGREE * latitude.cos();
+                (x + g.pixel_size[1] * METERS_PER_DEGREE) / 2.0
+            }
+            Some(g) => (g.pixel_size[0] + g.pixel_size[1]) / 2.0 * self.linear_unit,
+            None => world.meters_per_pixel as f64,
+        };
+        let vertical = self.vertical_unit as f32;
+        let world = WorldScale {
+            meters_per_pixel: meters_per_pixel as f32,
+            min_elevation: lo * vertical,
+            max_elevation: (lo + span) * vertical,
+            georeference,
+        };
+        Ok((heights, world))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+
+    const SHORT: u16 = 3;
+    const LONG: u16 = 4;
+
+    /// A little-endian TIFF of `entries` and the given strips or tiles, whose
+    /// offset and size tags are added here.
+    fn tiff(mut entries: Vec<(u16, u16, Vec<u32>)>, chunks: &[Vec<u8>], tiled: bool) -> Vec<u8> {
+        let (offsets_tag, counts_tag) = if tiled { (324, 325) } else { (273, 279) };
+        entries.push((offsets_tag, LONG, vec![0; chunks.len()]));
+        entries.push((counts_tag, LONG, chunks.iter().map(|c| c.len() as u32).collect()));
+        entries.sort_by_key(|e| e.0);
+        let value_len = |kind: u16, count: usize| if kind == SHORT { 2 * count } else { 4 * count };
+        let ifd_end = 8 + 2 + entries.len() * 12 + 4;
+        let values_len: usize = entries.iter().map(|(_, k, v)| value_len(*k, v.len())).filter(|&n| n > 4).sum();
+        let mut at = (ifd_end + values_len) as u32;
+        for chunk in chunks.iter() {
+            let entry = entries.iter_mut().find(|e| e.0 == offsets_tag).unwrap();
+            let k = entry.2.iter().position(|&o| o == 0).unwrap();
+            entry.2[k] = at;
+            at += chunk.len() as u32;
+        }
+
+        let mut out = b"II".to_vec();
+        out.extend_from_slice(&42u16.to_le_bytes());
+        out.extend_from_slice(&8u32.to_le_bytes());
+        out.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+        let mut values = Vec::new();
+        for (tag, kind, v) in &entries {
+            let mut bytes: Vec<u8> = match *kind {
+                SHORT => v.iter().flat_map(|&n| (n as u16).to_le_bytes()).collect(),
+                _ => v.iter().flat_map(|&n| n.to_le_bytes()).collect(),
+            };
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&kind.to_le_bytes());
+            out.extend_from_slice(&(v.len() as u32).to_le_bytes());
+            if bytes.len() > 4 {
+                out.extend_from_slice(&((ifd_end + values.len()) as u32).to_le_bytes());
+                values.append(&mut bytes);
+            } else {
+                bytes.resize(4, 0);
+                out.extend_from_slice(&bytes);
+            }
+        }
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.append(&mut values);
+        for chunk in chunks {
+            out.extend_from_slice(chunk);
+        }
+        out
+    }
+
+    fn deflate(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn elevation(x: usize, y: usize) -> i16 {
+        (x as i16 - 7) * 31 + y as i16 * 5
+    }
+
+    /// A 20x10 signed 16-bit DEM in deflated 16x16 tiles with horizontal
+    /// differencing and a no-data value.
+    fn tiled_dem() -> Vec<u8> {
+        let (w, h, tile) = (20, 10, 16);
+        let chunks: Vec<Vec<u8>> = (0..2)
+            .map(|tx| {
+                let mut raw = Vec::new();
+                for y in 0..tile {
+                    let row: Vec<i16> = (0..tile)
+                        .map(|x| if x + tx * tile < w && y < h { elevation(x + tx * tile, y) } else { 0 })
+                        .collect();
+                    raw.extend(row.iter().enumerate().flat_map(|(i, &v)| {
+                        let previous = if i == 0 { 0 } else { row[i - 1] };
+                        v.wrapping_sub(previous).to_le_bytes()
+                    }));
+                }
+                deflate(&raw)
+            })
+            .collect();
+        let mut file = tiff(
+            vec![
+                (256, LONG, vec![w as u32]),
+                (257, LONG, vec![h as u32]),
+                (258, SHORT, vec![16]),
+                (259, SHORT, vec![8]),
+                (317, SHORT, vec![2]),
+                (322, LONG, vec![tile as u32]),
+                (323, LONG, vec![tile as u32]),
+                (339, SHORT, vec![2]),
+            ],
+            &chunks,
+            true,
+        );
+        // GDAL_NODATA as ASCII, appended after the pixels
+        let nodata = b"-217\0";
+        let at = file.len() as u32;
+        file.extend_from_slice(nodata);
+        let count = u16::from_le_bytes([file[8], file[9]]);
+        let mut ifd: Vec<[u8; 12]> = (0..count as usize)
+            .map(|i| file[10 + i * 12..22 + i * 12].try_into().unwrap())
+            .collect();
+        let mut entry = [0; 12];
+        entry[..2].copy_from_slice(&42113u16.to_le_bytes());
+        entry[2..4].copy_from_slice(&2u16.to_le_bytes());
+        entry[4..8].copy_from_slice(&(nodata.len() as u32).to_le_bytes());
+        entry[8..].copy_from_slice(&at.to_le_bytes());
+        ifd.push(entry);
+        // Move the directory to the end, now one entry longer
+        let ifd_at = file.len() as u32;
+        file[4..8].copy_from_slice(&ifd_at.to_le_bytes());
+        file.extend_from_slice(&(ifd.len() as u16).to_le_bytes());
+        file.extend(ifd.concat());
+        file.extend_from_slice(&0u32.to_le_bytes());
+        file
+    }
+
+    #[test]
+    fn float_round_trip() {
+        let hm = Heightmap {
+            data: (0..23 * 17).map(|i| (i as f32).sin() * 1200.0).collect(),
+            width: 23,
+            height: 17,
+        };
+        let path = std::env::temp_dir().join(format!("topograph-{}-dem.tif", std::process::id()));
+        float_image::write_tiff32(&path, &hm).unwrap();
+        let dem = read(&path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!((dem.samples.width, dem.samples.height), (23, 17));
+        assert_eq!(dem.samples.data, hm.data);
+        assert!(dem.nodata.is_none() && dem.georeference.is_none());
+    }
+
+    #[test]
+    fn deflated_tiles_with_prediction() {
+        let dem = decode(&tiled_dem()).unwrap();
+        assert_eq!((dem.samples.width, dem.samples.height), (20, 10));
+        for y in 0..10 {
+            for x in 0..20 {
+                assert_eq!(dem.samples.data[y * 20 + x], elevation(x, y) as f32, "at {x},{y}");
+            }
+        }
+        assert_eq!(dem.nodata, Some(-217.0));
+    }
+
+    #[test]
+    fn lzw_strips() {
+        // Clear, "A", "B", "AB", end, nine bits each
+        let codes = [256u32, 65, 66, 258, 257];
+        let bits: u64 = codes.iter().fold(0, |acc, &c| acc << 9 | c as u64) << 3;
+        let strip = bits.to_be_bytes()[2..].to_vec();
+        let file = tiff(
+            vec![(256, LONG, vec![4]), (257, LONG, vec![1]), (258, SHORT, vec![8]), (259, SHORT, vec![5])],
+            &[strip],
+            false,
+        );
+        assert_eq!(decode(&file).unwrap().samples.data, [65.0, 66.0, 65.0, 66.0]);
+    }
+
+    #[test]
+    fn truncated_files_are_errors() {
+        let file = tiled_dem();
+        // The directory is at the end, so every prefix loses part of it; only
+        // the next directory's offset after it goes unread
+        for len in 0..file.len() - 4 {
+            assert!(decode(&file[..len]).is_err(), "{len} of {} bytes decoded", file.len());
+        }
+    }
+
+    #[test]
+    fn corrupt_files_do_not_panic() {
+        let file = tiled_dem();
+        for at in 0..file.len() {
+            for value in [0x00, 0x7f, 0xff] {
+                let mut corrupt = file.clone();
+                corrupt[at] = value;
+                let _ = decode(&corrupt);
+            }
+        }
+        // Noise as LZW data, at every length
+        let mut seed = 0x2545_f491u32;
+        let noise: Vec<u8> = (0..2048)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect();
+        for len in 0..noise.len() {
+            let _ = unlzw(&noise[..len], 4096);
+        }
+        assert!(decode(b"II*\0").is_err());
+        assert!(decode(b"not a tiff").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+//! Heightmap export for Godot terrain plugins: a 32-bit float EXR of elevations
+//! in meters, which both HTerrain and Terrain3D import as absolute heights, and
+//! a `_godot.json` descriptor with the settings that reproduce the world scale.
+
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::float_image;
+use crate::heightmap::Heightmap;
+use crate::project;
+use crate::world::WorldScale;
+
+/// HTerrain's data sizes are 2^n + 1 within this range.
+const HTERRAIN_MIN: u32 = 65;
+const HTERRAIN_MAX: u32 = 4097;
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HTerrainSettings {
+    /// Import resolution, the EXR's size when HTerrain accepts it as is.
+    pub resolution: u32,
+    /// The node's `map_scale`; heights are already in meters, so Y is 1.
+    pub map_scale: [f32; 3],
+    pub min_height: f32,
+    pub max_height: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Terrain3DSettings {
+    pub vertex_spacing: f32,
+    pub import_scale: f32,
+    pub import_offset: f32,
+}
+
+/// Import settings for the exported heightmap, written to the sidecar.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GodotImport {
+    pub width: u32,
+    pub height: u32,
+    /// Meters between samples along X and Z. Rows run along +Z, so north, the
+    /// top of the map, faces -Z as Godot expects.
+    pub meters_per_pixel: f32,
+    /// Lowest and highest elevation in the EXR, in meters.
+    pub min_height: f32,
+    pub max_height: f32,
+    pub hterrain: HTerrainSettings,
+    pub terrain3d: Terrain3DSettings,
+    /// Steps the import needs beyond these settings.
+    pub notes: Vec<String>,
+    /// Path of the sidecar itself.
+    #[serde(skip)]
+    pub sidecar: PathBuf,
+}
+
+/// Write `hm` as an EXR of elevations in meters, plus the sidecar.
+pub fn export(path: &Path, hm: &Heightmap, world: &WorldScale) -> Result<GodotImport, String> {
+    let mut meters = hm.clone();
+    for v in &mut meters.data {
+        *v = world.elevation(*v);
+    }
+    float_image::write_exr(path, &meters)?;
+
+    let (lo, hi) = meters.data.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let mut notes = Vec::new();
+    let hterrain_size = hm.width == hm.height
+        && (HTERRAIN_MIN..=HTERRAIN_MAX).contains(&hm.width)
+        && (hm.width - 1).is_power_of_two();
+    if !hterrain_size {
+        notes.push(format!(
+            "HTerrain needs a square map of 2^n + 1 pixels between {HTERRAIN_MIN} and {HTERRAIN_MAX} (e.g. 513, 1025, 2049); \
+             resize or crop this {}x{} map before importing it there.",
+            hm.width, hm.height
+        ));
+    }
+    notes.push("Import the EXR with the heights as absolute elevations; no further height scaling is needed.".into());
+
+    let import = GodotImport {
+        width: hm.width,
+        height: hm.height,
+        meters_per_pixel: world.meters_per_pixel,
+        min_height: lo,
+        max_height: hi,
+        hterrain: HTerrainSettings {
+            resolution: hm.width.max(hm.height),
+            map_scale: [world.meters_per_pixel, 1.0, world.meters_per_pixel],
+            min_height: lo,
+            max_height: hi,
+        },
+        terrain3d: Terrain3DSettings {
+            vertex_spacing: world.meters_per_pixel,
+            import_scale: 1.0,
+            import_offset: 0.0,
+        },
+        notes,
+        sidecar: project::sidecar_path(path, "_godot", "json"),
+    };
+    let json = serde_json::to_string_pretty(&import).map_err(|e| format!("Failed to serialize Godot settings: {e}"))?;
+    std::fs::write(&import.sidecar, json).map_err(|e| format!("Failed to write Godot settings: {e}"))?;
+    Ok(import)
+}
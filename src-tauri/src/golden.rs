@@ -0,0 +1,206 @@
+//! Golden-terrain regression suite, built with `--features golden`. Each case in
+//! `golden/cases.json` generates a deterministic terrain and runs a pipeline on it;
+//! the result's fingerprint is compared with `golden/expected.json`, so refactors of
+//! generation or erosion can be checked for output equivalence.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::erosion::ErosionRun;
+use crate::heightmap::Heightmap;
+use crate::noise_gen::{self, NoiseParams};
+use crate::project;
+
+const USAGE: &str = "\
+Usage: topograph --golden [--record] [--out DIR]
+
+Run the golden-terrain suite and compare each case against its recorded fingerprint.
+
+  --record     Overwrite the recorded fingerprints with the current output
+  --out DIR    Write a 16-bit PNG of every mismatching case into DIR";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GoldenCase {
+    name: String,
+    width: u32,
+    height: u32,
+    generate: NoiseParams,
+    #[serde(default)]
+    boundary: Boundary,
+    /// Erosion passes applied in order; hydraulic passes need a fixed `seed`.
+    #[serde(default)]
+    steps: Vec<ErosionRun>,
+}
+
+/// Fingerprint of a result: a hash of the heights quantized to 16 bits, plus
+/// summary statistics to show how far off a mismatch is.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Fingerprint {
+    pub hash: String,
+    pub mean: f32,
+    pub min: f32,
+    pub max: f32,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CaseResult {
+    pub name: String,
+    pub passed: bool,
+    pub expected: Option<Fingerprint>,
+    pub actual: Fingerprint,
+}
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("golden")
+}
+
+fn load_cases() -> Result<Vec<GoldenCase>, String> {
+    let path = fixture_dir().join("cases.json");
+    let json = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let cases: Vec<GoldenCase> =
+        serde_json::from_str(&json).map_err(|e| format!("Invalid golden cases: {e}"))?;
+    for case in &cases {
+        let unseeded = case
+            .steps
+            .iter()
+            .any(|step| matches!(step, ErosionRun::Hydraulic(p) if p.seed.is_none()));
+        if unseeded {
+            return Err(format!("Case '{}' has a hydraulic step without a seed", case.name));
+        }
+    }
+    Ok(cases)
+}
+
+fn load_expected() -> Result<BTreeMap<String, Fingerprint>, String> {
+    let path = fixture_dir().join("expected.json");
+    match std::fs::read_to_string(&path) {
+        Ok(json) => serde_json::from_str(&json).map_err(|e| format!("Invalid golden fingerprints: {e}")),
+        Err(_) => Ok(BTreeMap::new()),
+    }
+}
+
+fn run_case(case: &GoldenCase) -> Heightmap {
+    let mut hm = Heightmap::new(case.width, case.height);
+    noise_gen::generate_terrain(&mut hm, &case.generate);
+    let abort = AtomicBool::new(false);
+    for step in &case.steps {
+        step.apply(&mut hm, Default::default(), case.boundary, &abort, &|_| {});
+    }
+    hm
+}
+
+pub fn fingerprint(hm: &Heightmap) -> Fingerprint {
+    // FNV-1a over the 16-bit export values, so sub-LSB float noise doesn't count
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for &v in &hm.data {
+        let q = (v.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        for byte in q.to_le_bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    let (min, max) = hm
+        .data
+        .iter()
+        .fold((f32::MAX, f32::MIN), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let mean = hm.data.iter().sum::<f32>() / hm.data.len().max(1) as f32;
+    Fingerprint { hash: format!("{hash:016x}"), mean, min, max }
+}
+
+/// Run every case and compare with the recorded fingerprints. Mismatching results
+/// are written as 16-bit PNGs into `output_dir` when given.
+pub fn check(output_dir: Option<&Path>) -> Result<Vec<CaseResult>, String> {
+    let expected = load_expected()?;
+    let mut results = Vec::new();
+    for case in load_cases()? {
+        let hm = run_case(&case);
+        let actual = fingerprint(&hm);
+        let expected = expected.get(&case.name).cloned();
+        let passed = expected.as_ref().is_some_and(|e| e.hash == actual.hash);
+        if let (false, Some(dir)) = (passed, output_dir) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+            project::export_heightmap_png16(&dir.join(format!("{}.png", case.name)), &hm)?;
+        }
+        results.push(CaseResult { name: case.name, passed, expected, actual });
+    }
+    Ok(results)
+}
+
+/// Rerun every case and overwrite the recorded fingerprints. Returns the case count.
+pub fn record() -> Result<usize, String> {
+    let cases = load_cases()?;
+    let fingerprints: BTreeMap<String, Fingerprint> = cases
+        .iter()
+        .map(|case| (case.name.clone(), fingerprint(&run_case(case))))
+        .collect();
+    let json = serde_json::to_string_pretty(&fingerprints)
+        .map_err(|e| format!("Failed to serialize fingerprints: {e}"))?;
+    std::fs::write(fixture_dir().join("expected.json"), json + "\n")
+        .map_err(|e| format!("Failed to write fingerprints: {e}"))?;
+    Ok(fingerprints.len())
+}
+
+/// Entry point for `--golden`; `args` are the arguments after the flag.
+/// Returns the process exit code.
+pub fn run(args: &[String]) -> i32 {
+    let mut record_mode = false;
+    let mut out_dir = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--record" => record_mode = true,
+            "--out" => match iter.next() {
+                Some(dir) => out_dir = Some(PathBuf::from(dir)),
+                None => {
+                    eprintln!("--out needs a directory\n\n{USAGE}");
+                    return 2;
+                }
+            },
+            _ => {
+                eprintln!("Unknown argument '{arg}'\n\n{USAGE}");
+                return 2;
+            }
+        }
+    }
+
+    if record_mode {
+        return match record() {
+            Ok(count) => {
+                println!("Recorded {count} golden cases");
+                0
+            }
+            Err(e) => {
+                eprintln!("{e}");
+                1
+            }
+        };
+    }
+
+    match check(out_dir.as_deref()) {
+        Ok(results) => {
+            let failed = results.iter().filter(|r| !r.passed).count();
+            for r in &results {
+                match (&r.expected, r.passed) {
+                    (_, true) => println!("ok    {}", r.name),
+                    (None, false) => println!("NEW   {} ({})", r.name, r.actual.hash),
+                    (Some(e), false) => println!(
+                        "FAIL  {} (hash {} != {}, mean {:.6} vs {:.6})",
+                        r.name, r.actual.hash, e.hash, r.actual.mean, e.mean
+                    ),
+                }
+            }
+            println!("{} passed, {failed} failed", results.len() - failed);
+            i32::from(failed > 0)
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            1
+        }
+    }
+}
@@ -1,5 +1,6 @@
 /// Authoritative heightmap. Row-major: index = y * width + x.
 /// Heights are in [0.0, 1.0] range.
+#[derive(Clone)]
 pub struct Heightmap {
     pub data: Vec<f32>,
     pub width: u32,
@@ -22,4 +23,20 @@ impl Heightmap {
     pub fn set(&mut self, x: u32, y: u32, val: f32) {
         self.data[(y * self.width + x) as usize] = val;
     }
+
+    /// Bilinearly sample at fractional pixel coordinates, clamping to the edges.
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        let x = x.clamp(0.0, (self.width - 1) as f32);
+        let y = y.clamp(0.0, (self.height - 1) as f32);
+        let ix = x as u32;
+        let iy = y as u32;
+        let fx = x - ix as f32;
+        let fy = y - iy as f32;
+        let ix1 = (ix + 1).min(self.width - 1);
+        let iy1 = (iy + 1).min(self.height - 1);
+
+        let top = self.get(ix, iy) + (self.get(ix1, iy) - self.get(ix, iy)) * fx;
+        let bot = self.get(ix, iy1) + (self.get(ix1, iy1) - self.get(ix, iy1)) * fx;
+        top + (bot - top) * fy
+    }
 }
@@ -0,0 +1,108 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use rhai::{Array, Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde::Serialize;
+
+/// Name of the function a hook script must define. It receives one map:
+/// `#{ kind: "heightmap", format: "png16", paths: ["/out/terrain.png", ...] }`.
+const ENTRY_POINT: &str = "on_export";
+
+/// A compiled user script invoked after every successful export.
+pub struct ExportHook {
+    pub name: String,
+    pub path: PathBuf,
+    ast: AST,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportHookInfo {
+    pub name: String,
+    pub path: String,
+}
+
+impl ExportHook {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read hook script: {e}"))?;
+        let ast = engine()
+            .compile(&source)
+            .map_err(|e| format!("Failed to compile hook script: {e}"))?;
+        if !ast.iter_functions().any(|f| f.name == ENTRY_POINT && f.params.len() == 1) {
+            return Err(format!("Hook script must define `fn {ENTRY_POINT}(event)`"));
+        }
+
+        let name = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "hook".to_string());
+        Ok(Self { name, path: path.to_path_buf(), ast })
+    }
+
+    pub fn info(&self) -> ExportHookInfo {
+        ExportHookInfo {
+            name: self.name.clone(),
+            path: self.path.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Run every hook in registration order. Stops at the first failure, since later
+/// hooks usually depend on earlier ones (compress, then copy).
+pub fn run_all(hooks: &[ExportHook], kind: &str, format: &str, paths: &[PathBuf]) -> Result<(), String> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    let engine = engine();
+    let mut event = Map::new();
+    event.insert("kind".into(), kind.into());
+    event.insert("format".into(), format.into());
+    let paths: Array = paths
+        .iter()
+        .map(|p| Dynamic::from(p.to_string_lossy().into_owned()))
+        .collect();
+    event.insert("paths".into(), paths.into());
+
+    for hook in hooks {
+        // The return value is ignored; hooks signal failure by throwing
+        let _: Dynamic = engine
+            .call_fn(&mut Scope::new(), &hook.ast, ENTRY_POINT, (event.clone(),))
+            .map_err(|e| format!("Export written, but hook '{}' failed: {e}", hook.name))?;
+    }
+    Ok(())
+}
+
+/// Script engine with the pipeline helpers hook scripts can call.
+fn engine() -> Engine {
+    let mut engine = Engine::new();
+
+    // run("cmd", ["arg", ...]) -> exit code
+    engine.register_fn("run", |program: &str, args: Array| -> Result<i64, Box<EvalAltResult>> {
+        let args: Vec<String> = args.into_iter().map(|a| a.to_string()).collect();
+        let status = Command::new(program)
+            .args(&args)
+            .status()
+            .map_err(|e| format!("Failed to run {program}: {e}"))?;
+        Ok(status.code().unwrap_or(-1) as i64)
+    });
+
+    engine.register_fn("copy_file", |src: &str, dst: &str| -> Result<(), Box<EvalAltResult>> {
+        std::fs::copy(src, dst).map_err(|e| format!("Failed to copy {src} to {dst}: {e}"))?;
+        Ok(())
+    });
+
+    engine.register_fn("create_dir", |path: &str| -> Result<(), Box<EvalAltResult>> {
+        std::fs::create_dir_all(path).map_err(|e| format!("Failed to create {path}: {e}"))?;
+        Ok(())
+    });
+
+    engine.register_fn("file_name", |path: &str| -> String {
+        Path::new(path)
+            .file_name()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    });
+
+    engine
+}
@@ -0,0 +1,244 @@
+//! Depression filling and flow routing, shared by fluvial erosion, river carving
+//! and the `fill_sinks` command.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::f32::consts::{FRAC_PI_4, SQRT_2};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+
+/// 8-connected neighbor offsets.
+pub const NEIGHBORS: [(i64, i64); 8] = [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)];
+
+/// Minimum rise per cell when filling depressions for flow routing.
+const FILL_STEP: f32 = 1e-5;
+/// Cells the flood reaches between progress reports.
+const CELLS_PER_REPORT: usize = 1 << 16;
+
+/// Terrain with its depressions filled.
+pub struct Flood {
+    pub filled: Vec<f32>,
+    /// Cells in ascending filled height, the order the flood reached them.
+    pub order: Vec<usize>,
+    /// Where water leaves the map.
+    pub outlets: Vec<bool>,
+}
+
+/// Min-heap entry for the priority flood.
+#[derive(PartialEq)]
+struct Entry(f32, usize);
+
+impl Eq for Entry {}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.0.total_cmp(&self.0).then(other.1.cmp(&self.1))
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Priority flood (Barnes et al. 2014) from the outlets inwards: each cell is
+/// raised to at least `epsilon` above the cell it was reached from, so every
+/// depression fills to its spill point. With `epsilon` 0 lakes come out flat;
+/// above it they keep a slight slope towards the spill point, so every cell has
+/// a downhill path out. Water leaves the map at its edges unless the map wraps,
+/// in which case everything drains to the lowest point. `progress` gets the
+/// share of cells reached.
+pub fn priority_flood(hm: &Heightmap, boundary: Boundary, epsilon: f32, progress: &dyn Fn(f32)) -> Flood {
+    let (w, h) = (hm.width as usize, hm.height as usize);
+    let mut filled = hm.data.clone();
+    let mut order = Vec::with_capacity(w * h);
+    let mut visited = vec![false; w * h];
+    let mut outlets = vec![false; w * h];
+    let mut queue = BinaryHeap::new();
+
+    if boundary.wraps() {
+        let lowest = (0..w * h).min_by(|&a, &b| hm.data[a].total_cmp(&hm.data[b])).unwrap_or(0);
+        outlets[lowest] = true;
+    } else {
+        for x in 0..w {
+            outlets[x] = true;
+            outlets[(h - 1) * w + x] = true;
+        }
+        for y in 0..h {
+            outlets[y * w] = true;
+            outlets[y * w + w - 1] = true;
+        }
+    }
+    for (i, _) in outlets.iter().enumerate().filter(|(_, &o)| o) {
+        visited[i] = true;
+        queue.push(Entry(filled[i], i));
+    }
+
+    while let Some(Entry(level, i)) = queue.pop() {
+        if order.len() % CELLS_PER_REPORT == 0 {
+            progress(order.len() as f32 / (w * h) as f32);
+        }
+        order.push(i);
+        let (x, y) = ((i % w) as i64, (i / w) as i64);
+        for &(dx, dy) in &NEIGHBORS {
+            let (Some(nx), Some(ny)) = (
+                boundary.resolve_write(x + dx, w as u32),
+                boundary.resolve_write(y + dy, h as u32),
+            ) else {
+                continue;
+            };
+            let n = ny as usize * w + nx as usize;
+            if visited[n] {
+                continue;
+            }
+            visited[n] = true;
+            filled[n] = filled[n].max(level + epsilon);
+            queue.push(Entry(filled[n], n));
+        }
+    }
+
+    Flood { filled, order, outlets }
+}
+
+/// Drainage tree: every cell's downstream neighbor and the distance to it, plus
+/// an order that visits each receiver before its donors.
+pub struct Drainage {
+    /// Terrain the flow was routed over, with depressions filled `FILL_STEP` apart.
+    pub filled: Vec<f32>,
+    /// Cells that drain off the map are their own receiver.
+    pub receiver: Vec<usize>,
+    pub distance: Vec<f32>,
+    pub order: Vec<usize>,
+}
+
+/// Route flow by steepest descent over the terrain with its depressions filled
+/// `FILL_STEP` apart, so water crosses them over their spill points instead of
+/// stopping in them. The flood's order is ascending in filled height, so it
+/// visits receivers before donors. `progress` follows the flood.
+pub fn drainage(hm: &Heightmap, boundary: Boundary, progress: &dyn Fn(f32)) -> Drainage {
+    let (w, h) = (hm.width as usize, hm.height as usize);
+    let Flood { filled, order, outlets } = priority_flood(hm, boundary, FILL_STEP, progress);
+
+    let neighbors = |i: usize| {
+        let (x, y) = ((i % w) as i64, (i / w) as i64);
+        NEIGHBORS.iter().filter_map(move |&(dx, dy)| {
+            let nx = boundary.resolve_write(x + dx, w as u32)?;
+            let ny = boundary.resolve_write(y + dy, h as u32)?;
+            let distance = if dx != 0 && dy != 0 { SQRT_2 } else { 1.0 };
+            Some((ny as usize * w + nx as usize, distance))
+        })
+    };
+
+    let mut receiver: Vec<usize> = (0..w * h).collect();
+    let mut distance = vec![1.0f32; w * h];
+    receiver
+        .par_iter_mut()
+        .zip(distance.par_iter_mut())
+        .enumerate()
+        .filter(|(i, _)| !outlets[*i])
+        .for_each(|(i, (receiver, distance))| {
+            let mut steepest = 0.0f32;
+            for (n, d) in neighbors(i) {
+                let slope = (filled[i] - filled[n]) / d;
+                if slope > steepest {
+                    steepest = slope;
+                    *receiver = n;
+                    *distance = d;
+                }
+            }
+        });
+
+    Drainage { filled, receiver, distance, order }
+}
+
+/// How flow leaves a cell when accumulating drainage area.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FlowMethod {
+    /// All of it to the steepest neighbor, the drainage tree's receiver.
+    #[default]
+    D8,
+    /// Split between the two neighbors either side of the steepest downhill
+    /// direction (Tarboton 1997), which spreads flow naturally over open slopes.
+    DInfinity,
+}
+
+/// The eight triangular facets around a cell, as (cardinal, diagonal) neighbor
+/// offsets.
+const FACETS: [((i64, i64), (i64, i64)); 8] = [
+    ((1, 0), (1, -1)),
+    ((0, -1), (1, -1)),
+    ((0, -1), (-1, -1)),
+    ((-1, 0), (-1, -1)),
+    ((-1, 0), (-1, 1)),
+    ((0, 1), (-1, 1)),
+    ((0, 1), (1, 1)),
+    ((1, 0), (1, 1)),
+];
+
+/// Where a cell's flow goes under D-infinity, as two (cell, share) pairs: the
+/// steepest downhill direction over the facets around the cell, with its flow
+/// divided between the facet's two corners by angle. `None` for outlets.
+fn dinf_split(tree: &Drainage, i: usize, w: usize, h: usize, boundary: Boundary) -> Option<[(usize, f32); 2]> {
+    if tree.receiver[i] == i {
+        return None;
+    }
+    let (x, y) = ((i % w) as i64, (i / w) as i64);
+    let at = |(dx, dy): (i64, i64)| {
+        let nx = boundary.resolve_write(x + dx, w as u32)?;
+        let ny = boundary.resolve_write(y + dy, h as u32)?;
+        Some(ny as usize * w + nx as usize)
+    };
+    let z0 = tree.filled[i];
+
+    let mut best = None;
+    let mut steepest = 0.0f32;
+    for &(cardinal, diagonal) in &FACETS {
+        let (Some(n1), Some(n2)) = (at(cardinal), at(diagonal)) else {
+            continue;
+        };
+        let (s1, s2) = (z0 - tree.filled[n1], tree.filled[n1] - tree.filled[n2]);
+        let (angle, slope) = match s2.atan2(s1) {
+            a if a < 0.0 => (0.0, s1),
+            a if a > FRAC_PI_4 => (FRAC_PI_4, (z0 - tree.filled[n2]) / SQRT_2),
+            a => (a, s1.hypot(s2)),
+        };
+        if slope > steepest {
+            steepest = slope;
+            let share = angle / FRAC_PI_4;
+            best = Some([(n1, 1.0 - share), (n2, share)]);
+        }
+    }
+    // The filled surface always leaves a way down; fall back to it should
+    // rounding hide every facet
+    Some(best.unwrap_or([(tree.receiver[i], 1.0), (tree.receiver[i], 0.0)]))
+}
+
+/// Upstream drainage area of every cell in pixels, counting the cell itself.
+pub fn accumulation(tree: &Drainage, hm: &Heightmap, boundary: Boundary, method: FlowMethod) -> Vec<f32> {
+    let (w, h) = (hm.width as usize, hm.height as usize);
+    let mut area = vec![1.0f32; w * h];
+    match method {
+        FlowMethod::D8 => {
+            for &cell in tree.order.iter().rev() {
+                let receiver = tree.receiver[cell];
+                if receiver != cell {
+                    area[receiver] += area[cell];
+                }
+            }
+        }
+        FlowMethod::DInfinity => {
+            let splits: Vec<_> = (0..w * h).into_par_iter().map(|i| dinf_split(tree, i, w, h, boundary)).collect();
+            // Every share goes to a strictly lower cell, which the flood reached first
+            for &cell in tree.order.iter().rev() {
+                for (n, share) in splits[cell].into_iter().flatten() {
+                    area[n] += area[cell] * share;
+                }
+            }
+        }
+    }
+    area
+}
@@ -0,0 +1,202 @@
+//! Translations for user-facing strings produced by the backend: menu labels, error
+//! hints and export format names. The frontend localizes its own UI; this only
+//! covers text it can't reach.
+
+use std::sync::RwLock;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    En,
+    De,
+    Fr,
+}
+
+impl Locale {
+    pub const ALL: [Locale; 3] = [Locale::En, Locale::De, Locale::Fr];
+
+    /// The language's own name, for a language picker.
+    pub fn native_name(self) -> &'static str {
+        match self {
+            Locale::En => "English",
+            Locale::De => "Deutsch",
+            Locale::Fr => "Français",
+        }
+    }
+
+    /// Parse a POSIX or BCP 47 tag such as `de_DE.UTF-8` or `fr-CA`.
+    pub fn from_tag(tag: &str) -> Option<Locale> {
+        let lang = tag.split(['_', '-', '.', '@']).next()?.to_ascii_lowercase();
+        match lang.as_str() {
+            "en" => Some(Locale::En),
+            "de" => Some(Locale::De),
+            "fr" => Some(Locale::Fr),
+            _ => None,
+        }
+    }
+
+    /// Locale from the environment (`LC_ALL`, `LC_MESSAGES`, `LANG`), else English.
+    pub fn detect() -> Locale {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|v| !v.is_empty())
+            .and_then(|v| Locale::from_tag(&v))
+            .unwrap_or(Locale::En)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LocaleInfo {
+    pub locale: Locale,
+    pub name: &'static str,
+}
+
+pub fn available() -> Vec<LocaleInfo> {
+    Locale::ALL
+        .iter()
+        .map(|&locale| LocaleInfo { locale, name: locale.native_name() })
+        .collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Text {
+    FileMenu,
+    EditMenu,
+    SaveProject,
+    OpenProject,
+    OpenRecentMenu,
+    ClearRecentMenu,
+    ExportPng16Menu,
+    ExportRawMenu,
+    Png16Format,
+    RawF32Format,
+    ExrFormat,
+    Tiff32Format,
+    BusyHint,
+    IoHint,
+    ScriptHint,
+    MlHint,
+    ProjectFormatHint,
+    GpuUnavailableHint,
+    ConfirmationHint,
+}
+
+static LOCALE: RwLock<Locale> = RwLock::new(Locale::En);
+
+pub fn locale() -> Locale {
+    *LOCALE.read().unwrap()
+}
+
+/// Switch the locale for strings produced from now on. The menu has to be rebuilt
+/// separately.
+pub fn set_locale(locale: Locale) {
+    *LOCALE.write().unwrap() = locale;
+}
+
+/// `text` in the current locale.
+pub fn tr(text: Text) -> &'static str {
+    match locale() {
+        Locale::En => en(text),
+        Locale::De => de(text),
+        Locale::Fr => fr(text),
+    }
+}
+
+fn en(text: Text) -> &'static str {
+    match text {
+        Text::FileMenu => "File",
+        Text::EditMenu => "Edit",
+        Text::SaveProject => "Save Project",
+        Text::OpenProject => "Open Project",
+        Text::OpenRecentMenu => "Open Recent",
+        Text::ClearRecentMenu => "Clear Menu",
+        Text::ExportPng16Menu => "Export Heightmap (PNG 16-bit)",
+        Text::ExportRawMenu => "Export Heightmap (Raw f32)",
+        Text::Png16Format => "PNG Image (16-bit)",
+        Text::RawF32Format => "Raw f32 Binary",
+        Text::ExrFormat => "OpenEXR Image (32-bit float)",
+        Text::Tiff32Format => "TIFF Image (32-bit float)",
+        Text::BusyHint => "Wait for it to finish or cancel it, then retry.",
+        Text::IoHint => "Check that the location exists and is writable, then retry.",
+        Text::ScriptHint => "The export itself finished; fix or remove the export hook.",
+        Text::MlHint => {
+            "Check that the ml/ environment is set up (ml/venv with the model dependencies)."
+        }
+        Text::ProjectFormatHint => {
+            "Make sure this is a .topo project saved by this or an older version."
+        }
+        Text::GpuUnavailableHint => {
+            "Use the CPU backend, or a build with the gpu feature on a machine with a supported GPU."
+        }
+        Text::ConfirmationHint => {
+            "Confirm the operation first; a checkpoint of the current map is kept so it can be undone."
+        }
+    }
+}
+
+fn de(text: Text) -> &'static str {
+    match text {
+        Text::FileMenu => "Datei",
+        Text::EditMenu => "Bearbeiten",
+        Text::SaveProject => "Projekt speichern",
+        Text::OpenProject => "Projekt öffnen",
+        Text::OpenRecentMenu => "Zuletzt geöffnet",
+        Text::ClearRecentMenu => "Liste leeren",
+        Text::ExportPng16Menu => "Höhenkarte exportieren (PNG 16 Bit)",
+        Text::ExportRawMenu => "Höhenkarte exportieren (Raw f32)",
+        Text::Png16Format => "PNG-Bild (16 Bit)",
+        Text::RawF32Format => "Raw-f32-Binärdatei",
+        Text::ExrFormat => "OpenEXR-Bild (32-Bit-Float)",
+        Text::Tiff32Format => "TIFF-Bild (32-Bit-Float)",
+        Text::BusyHint => "Warten Sie, bis der Vorgang abgeschlossen ist, oder brechen Sie ihn ab, und versuchen Sie es erneut.",
+        Text::IoHint => "Prüfen Sie, ob der Speicherort existiert und beschreibbar ist, und versuchen Sie es erneut.",
+        Text::ScriptHint => "Der Export selbst wurde abgeschlossen; korrigieren oder entfernen Sie den Export-Hook.",
+        Text::MlHint => {
+            "Prüfen Sie, ob die ml/-Umgebung eingerichtet ist (ml/venv mit den Modellabhängigkeiten)."
+        }
+        Text::ProjectFormatHint => {
+            "Stellen Sie sicher, dass es sich um ein .topo-Projekt dieser oder einer älteren Version handelt."
+        }
+        Text::GpuUnavailableHint => {
+            "Verwenden Sie das CPU-Backend oder einen Build mit dem Feature gpu auf einem Rechner mit unterstützter GPU."
+        }
+        Text::ConfirmationHint => {
+            "Bestätigen Sie den Vorgang zuerst; ein Prüfpunkt der aktuellen Karte wird angelegt, damit er sich rückgängig machen lässt."
+        }
+    }
+}
+
+fn fr(text: Text) -> &'static str {
+    match text {
+        Text::FileMenu => "Fichier",
+        Text::EditMenu => "Édition",
+        Text::SaveProject => "Enregistrer le projet",
+        Text::OpenProject => "Ouvrir un projet",
+        Text::OpenRecentMenu => "Ouvrir récent",
+        Text::ClearRecentMenu => "Effacer la liste",
+        Text::ExportPng16Menu => "Exporter la carte des hauteurs (PNG 16 bits)",
+        Text::ExportRawMenu => "Exporter la carte des hauteurs (Raw f32)",
+        Text::Png16Format => "Image PNG (16 bits)",
+        Text::RawF32Format => "Binaire f32 brut",
+        Text::ExrFormat => "Image OpenEXR (flottant 32 bits)",
+        Text::Tiff32Format => "Image TIFF (flottant 32 bits)",
+        Text::BusyHint => "Attendez la fin de l'opération ou annulez-la, puis réessayez.",
+        Text::IoHint => "Vérifiez que l'emplacement existe et est accessible en écriture, puis réessayez.",
+        Text::ScriptHint => "L'export lui-même est terminé ; corrigez ou supprimez le hook d'export.",
+        Text::MlHint => {
+            "Vérifiez que l'environnement ml/ est installé (ml/venv avec les dépendances des modèles)."
+        }
+        Text::ProjectFormatHint => {
+            "Vérifiez qu'il s'agit d'un projet .topo enregistré par cette version ou une version antérieure."
+        }
+        Text::GpuUnavailableHint => {
+            "Utilisez le backend CPU, ou une version compilée avec la fonctionnalité gpu sur une machine dotée d'un GPU compatible."
+        }
+        Text::ConfirmationHint => {
+            "Confirmez d'abord l'opération ; un point de restauration de la carte actuelle est conservé pour pouvoir l'annuler."
+        }
+    }
+}
@@ -48,3 +48,16 @@ pub fn pack_region(hm: &Heightmap, rx: u32, ry: u32, rw: u32, rh: u32) -> Vec<u8
 
     buf
 }
+
+/// Pack several full maps into one message: [count:u32 LE] then, per map,
+/// [byte length:u32 LE][full-map message].
+pub fn pack_full_set(maps: &[&Heightmap]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&(maps.len() as u32).to_le_bytes());
+    for hm in maps {
+        let packed = pack_full(hm);
+        buf.extend_from_slice(&(packed.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&packed);
+    }
+    buf
+}
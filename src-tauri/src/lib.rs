@@ -1,15 +1,151 @@
 mod ai;
+mod autosave;
+mod biome;
+mod boundary;
+mod bundle;
+mod canvas;
+mod cartography;
+mod clipboard;
 mod commands;
+mod contours;
+mod convert;
+mod craters;
+mod derived;
+mod detail;
+mod dunes;
+mod elevation_fetch;
 mod erosion;
+mod error;
+mod export_profile;
+mod expr;
+mod file_drop;
+mod float_image;
+mod geotiff;
+mod godot;
+#[cfg(feature = "golden")]
+mod golden;
 mod heightmap;
+mod hooks;
+mod hydrology;
+mod i18n;
 mod ipc;
+mod mask;
+mod mosaic;
+mod mountains;
 mod noise_gen;
+mod presets;
+mod progress;
 mod project;
+mod provenance;
+mod recent;
+mod render;
+mod resample;
+mod rivers;
+mod safety;
 mod sculpt;
+mod smoothing;
+mod snow;
+mod splatmap;
+mod srtm;
+mod stack;
+mod stamp;
 mod state;
+mod stl;
+mod stroke_queue;
+mod sync_export;
+mod tectonics;
+mod templates;
+mod texture;
+mod tile_export;
+mod tiles;
+mod unity;
+mod unreal;
+mod usage;
+mod volcano;
+mod world;
 
-use tauri::menu::{AboutMetadata, MenuBuilder, MenuItemBuilder, SubmenuBuilder};
-use tauri::Emitter;
+use tauri::menu::{AboutMetadata, Menu, MenuBuilder, MenuItemBuilder, SubmenuBuilder};
+use tauri::{AppHandle, DragDropEvent, Emitter, Manager, Runtime, WindowEvent};
+use i18n::Text;
+
+/// Headless `--convert` mode; returns the process exit code.
+pub fn run_convert(args: &[String]) -> i32 {
+    convert::run(args)
+}
+
+/// Headless `--golden` mode; returns the process exit code.
+#[cfg(feature = "golden")]
+pub fn run_golden(args: &[String]) -> i32 {
+    golden::run(args)
+}
+
+/// Build the app menu with labels in the current locale.
+/// Menu ids of Open Recent entries: this, then the project's path.
+const OPEN_RECENT_PREFIX: &str = "open_recent:";
+
+pub(crate) fn build_menu<R: Runtime, M: Manager<R>>(app: &M) -> tauri::Result<Menu<R>> {
+    // macOS app menu
+    let app_menu = SubmenuBuilder::new(app, "Topograph")
+        .about(Some(AboutMetadata::default()))
+        .separator()
+        .hide()
+        .hide_others()
+        .show_all()
+        .separator()
+        .quit()
+        .build()?;
+
+    // File menu
+    let save_item = MenuItemBuilder::new(i18n::tr(Text::SaveProject))
+        .id("save")
+        .accelerator("CmdOrCtrl+S")
+        .build(app)?;
+    let open_item = MenuItemBuilder::new(i18n::tr(Text::OpenProject))
+        .id("open")
+        .accelerator("CmdOrCtrl+O")
+        .build(app)?;
+
+    // Items carry the project's path in their id after OPEN_RECENT_PREFIX
+    let recent = app.path().app_config_dir().map(|dir| recent::list(&dir)).unwrap_or_default();
+    let mut recent_menu = SubmenuBuilder::new(app, i18n::tr(Text::OpenRecentMenu));
+    for path in &recent {
+        recent_menu = recent_menu.text(format!("{OPEN_RECENT_PREFIX}{path}"), path);
+    }
+    let clear_recent_item = MenuItemBuilder::new(i18n::tr(Text::ClearRecentMenu))
+        .id("clear_recent")
+        .enabled(!recent.is_empty())
+        .build(app)?;
+    let recent_menu = recent_menu.separator().item(&clear_recent_item).build()?;
+
+    let file_menu = SubmenuBuilder::new(app, i18n::tr(Text::FileMenu))
+        .item(&save_item)
+        .item(&open_item)
+        .item(&recent_menu)
+        .separator()
+        .text("export_png16", i18n::tr(Text::ExportPng16Menu))
+        .text("export_raw", i18n::tr(Text::ExportRawMenu))
+        .build()?;
+
+    let edit_menu = SubmenuBuilder::new(app, i18n::tr(Text::EditMenu))
+        .undo()
+        .redo()
+        .separator()
+        .cut()
+        .copy()
+        .paste()
+        .select_all()
+        .build()?;
+
+    MenuBuilder::new(app)
+        .items(&[&app_menu, &file_menu, &edit_menu])
+        .build()
+}
+
+/// Rebuild the menu, e.g. in a new locale or with the recent projects changed.
+pub(crate) fn refresh_menu<R: Runtime>(app: &AppHandle<R>) -> tauri::Result<()> {
+    app.set_menu(build_menu(app)?)?;
+    Ok(())
+}
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
@@ -18,74 +154,182 @@ pub fn run() {
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
         .setup(|app| {
-            // macOS app menu
-            let app_menu = SubmenuBuilder::new(app, "Topograph")
-                .about(Some(AboutMetadata::default()))
-                .separator()
-                .hide()
-                .hide_others()
-                .show_all()
-                .separator()
-                .quit()
-                .build()?;
-
-            // File menu
-            let save_item = MenuItemBuilder::new("Save Project")
-                .id("save")
-                .accelerator("CmdOrCtrl+S")
-                .build(app)?;
-            let open_item = MenuItemBuilder::new("Open Project")
-                .id("open")
-                .accelerator("CmdOrCtrl+O")
-                .build(app)?;
+            let state = app.state::<state::AppState>();
+            derived::spawn_scheduler(
+                state.heightmap.clone(),
+                state.world_scale.clone(),
+                state.boundary.clone(),
+                state.derived.clone(),
+            );
+            if let Ok(dir) = app.path().app_data_dir() {
+                autosave::start(dir, &state, app.handle().clone());
+            }
 
-            let file_menu = SubmenuBuilder::new(app, "File")
-                .item(&save_item)
-                .item(&open_item)
-                .separator()
-                .text("export_png16", "Export Heightmap (PNG 16-bit)")
-                .text("export_raw", "Export Heightmap (Raw f32)")
-                .build()?;
-
-            let edit_menu = SubmenuBuilder::new(app, "Edit")
-                .undo()
-                .redo()
-                .separator()
-                .cut()
-                .copy()
-                .paste()
-                .select_all()
-                .build()?;
-
-            let menu = MenuBuilder::new(app)
-                .items(&[&app_menu, &file_menu, &edit_menu])
-                .build()?;
-
-            // Set menu on the app (required for macOS menu bar)
-            app.set_menu(menu)?;
+            i18n::set_locale(i18n::Locale::detect());
+            app.set_menu(build_menu(app)?)?;
             app.on_menu_event(move |app_handle, event| {
                 let id = event.id().0.as_str();
-                let _ = app_handle.emit("menu-action", id);
+                if let Some(path) = id.strip_prefix(OPEN_RECENT_PREFIX) {
+                    let _ = app_handle.emit("open-recent", path);
+                } else if id == "clear_recent" {
+                    if let Ok(dir) = app_handle.path().app_config_dir() {
+                        let _ = recent::clear(&dir);
+                    }
+                    let _ = refresh_menu(app_handle);
+                } else {
+                    let _ = app_handle.emit("menu-action", id);
+                }
             });
 
             Ok(())
         })
+        .on_window_event(|window, event| {
+            if let WindowEvent::DragDrop(DragDropEvent::Drop { paths, .. }) = event {
+                file_drop::handle(window, paths);
+            }
+        })
         .invoke_handler(tauri::generate_handler![
             commands::get_heightmap,
             commands::apply_brush_stroke,
+            commands::start_stroke_stream,
+            commands::queue_brush_stroke,
+            commands::end_stroke_stream,
+            commands::apply_ramp,
+            commands::apply_platform,
+            commands::stamp_crater,
+            commands::scatter_craters,
+            commands::stamp_volcano,
+            commands::apply_mountain_range,
             commands::generate_terrain,
+            commands::generate_terrain_stack,
+            commands::generate_from_expression,
+            commands::preview_terrain,
+            commands::preview_seeds,
+            commands::expand_canvas,
+            commands::crop_canvas,
+            commands::confirm_destructive,
+            commands::list_checkpoints,
+            commands::restore_checkpoint,
+            commands::add_detail_patch,
+            commands::list_detail_patches,
+            commands::get_detail_patch,
+            commands::remove_detail_patch,
+            commands::gpu_erosion_available,
             commands::run_thermal_erosion,
             commands::run_hydraulic_erosion,
+            commands::get_droplet_traces,
+            commands::run_pipe_erosion,
+            commands::run_stream_power_erosion,
+            commands::run_glacial_erosion,
+            commands::run_coastal_erosion,
             commands::abort_erosion,
+            commands::pause_erosion,
+            commands::resume_erosion,
+            commands::set_project_settings,
+            commands::preview_erosion,
+            commands::compare_erosion,
+            commands::run_erosion_pipeline,
             commands::run_depth_estimation,
             commands::run_inpainting,
             commands::generate_controlnet_texture,
             commands::apply_heightmap_image,
+            commands::import_heightmap,
+            commands::import_float_heightmap,
+            commands::import_geotiff,
+            commands::import_raw,
+            commands::import_mosaic,
+            commands::import_srtm,
+            commands::fetch_terrain,
+            commands::import_stamp,
+            commands::normalize_heightmap,
+            commands::apply_curvature_flow,
+            commands::fill_sinks,
+            commands::carve_rivers,
             commands::set_heightmap,
             commands::save_project,
             commands::load_project,
+            commands::get_project_metadata,
+            commands::set_project_metadata,
+            commands::get_operation_history,
+            commands::list_recent_projects,
+            commands::clear_recent_projects,
+            commands::list_templates,
+            commands::save_template,
+            commands::delete_template,
+            commands::new_project_from_template,
+            commands::check_recovery,
+            commands::restore_recovery,
+            commands::discard_recovery,
+            commands::set_texture,
+            commands::import_texture,
+            commands::clear_texture,
+            commands::get_texture_info,
+            commands::list_export_formats,
             commands::export_heightmap,
+            commands::export_heightmap_sync,
+            commands::export_tiles,
+            commands::export_heightmap_raw,
+            commands::export_unity_raw,
+            commands::export_unreal_landscape,
+            commands::export_godot,
+            commands::render_preview,
+            commands::render_snapshot,
+            commands::export_map_image,
+            commands::export_biome_map,
+            commands::export_contours,
+            commands::export_splatmap,
+            commands::export_bundle,
+            commands::run_snow,
+            commands::export_snow_map,
+            commands::export_stl,
+            #[cfg(feature = "golden")]
+            commands::run_golden_suite,
+            #[cfg(feature = "golden")]
+            commands::record_golden_suite,
+            commands::get_slope_map,
+            commands::get_erosion_map,
+            commands::export_erosion_maps,
+            commands::export_gradient_map,
+            commands::get_normal_map,
+            commands::copy_map_to_clipboard,
+            commands::get_heightmap_mip,
+            commands::get_world_scale,
+            commands::set_world_scale,
+            commands::get_boundary,
+            commands::set_boundary,
+            commands::sample_height,
+            commands::sample_heights,
+            commands::get_mask,
+            commands::paint_mask,
+            commands::clear_mask,
+            commands::import_mask,
+            commands::configure_tile_grid,
+            commands::get_tile_grid,
+            commands::set_tile_lock,
+            commands::regenerate_tile,
+            commands::regenerate_unlocked_tiles,
+            commands::add_export_hook,
+            commands::list_export_hooks,
+            commands::remove_export_hook,
+            commands::get_locale,
+            commands::list_locales,
+            commands::set_locale,
+            commands::get_usage_stats,
+            commands::set_usage_tracking,
+            commands::reset_usage_stats,
+            commands::list_presets,
+            commands::save_preset,
+            commands::delete_preset,
+            commands::apply_preset,
+            commands::run_export_profile,
         ])
-        .run(tauri::generate_context!())
-        .expect("error while running Topograph");
+        .build(tauri::generate_context!())
+        .expect("error while running Topograph")
+        .run(|app_handle, event| {
+            if let tauri::RunEvent::Exit = event {
+                if let Ok(dir) = app_handle.path().app_data_dir() {
+                    autosave::finish(&dir);
+                }
+            }
+        });
 }
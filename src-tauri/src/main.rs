@@ -1,5 +1,13 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|a| a == "--convert") {
+        std::process::exit(topograph_lib::run_convert(&args[1..]));
+    }
+    #[cfg(feature = "golden")]
+    if args.first().is_some_and(|a| a == "--golden") {
+        std::process::exit(topograph_lib::run_golden(&args[1..]));
+    }
     topograph_lib::run()
 }
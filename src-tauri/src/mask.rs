@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+
+/// Named per-pixel mask channels kept alongside the heightmap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MaskChannel {
+    /// Landscape holes (caves, tunnel mouths). Values >= 0.5 are cut out on export.
+    Holes,
+    /// Active selection that restricts generation and other operations.
+    Selection,
+    /// Resistance to erosion: 1 doesn't erode at all, 0 erodes normally.
+    Hardness,
+    /// Snow cover from the last snow simulation, 1 at full depth.
+    Snow,
+    /// Painted lakes and sea. Snow doesn't settle on it, and as an erosion
+    /// hardness map it keeps erosion off the water's edge.
+    Water,
+}
+
+impl MaskChannel {
+    pub const ALL: [MaskChannel; 5] =
+        [MaskChannel::Holes, MaskChannel::Selection, MaskChannel::Hardness, MaskChannel::Snow, MaskChannel::Water];
+
+    /// Name used for file names.
+    pub fn id(self) -> &'static str {
+        match self {
+            MaskChannel::Holes => "holes",
+            MaskChannel::Selection => "selection",
+            MaskChannel::Hardness => "hardness",
+            MaskChannel::Snow => "snow",
+            MaskChannel::Water => "water",
+        }
+    }
+}
+
+/// Mask grids share the heightmap's layout; values are weights in [0.0, 1.0].
+#[derive(Default, Clone)]
+pub struct MaskSet {
+    channels: HashMap<MaskChannel, Heightmap>,
+}
+
+impl MaskSet {
+    pub fn get(&self, channel: MaskChannel) -> Option<&Heightmap> {
+        self.channels.get(&channel)
+    }
+
+    /// Get a channel for editing, creating an empty one (or replacing one with stale
+    /// dimensions) so it always matches the heightmap.
+    pub fn get_or_create(&mut self, channel: MaskChannel, width: u32, height: u32) -> &mut Heightmap {
+        let mask = self
+            .channels
+            .entry(channel)
+            .or_insert_with(|| Heightmap::new(width, height));
+        if mask.width != width || mask.height != height {
+            *mask = Heightmap::new(width, height);
+        }
+        mask
+    }
+
+    pub fn clear(&mut self, channel: MaskChannel) {
+        self.channels.remove(&channel);
+    }
+
+    pub fn insert(&mut self, channel: MaskChannel, mask: Heightmap) {
+        self.channels.insert(channel, mask);
+    }
+
+    /// Replace every channel with `f(channel)`, e.g. to follow a canvas resize.
+    pub fn transform(&mut self, f: impl Fn(&Heightmap) -> Heightmap) {
+        for mask in self.channels.values_mut() {
+            *mask = f(mask);
+        }
+    }
+}
+
+/// Decode a grayscale mask PNG into weights, resized to the heightmap.
+/// White (255) = 1.0, Black (0) = 0.0.
+pub fn decode_png(png_data: &[u8], width: u32, height: u32) -> Result<Vec<f32>, String> {
+    let img = image::load_from_memory(png_data)
+        .map_err(|e| format!("Failed to decode mask image: {e}"))?;
+    let gray = img.to_luma8();
+    let resized = if gray.width() != width || gray.height() != height {
+        image::imageops::resize(&gray, width, height, image::imageops::FilterType::Triangle)
+    } else {
+        gray
+    };
+    Ok(resized.pixels().map(|p| p.0[0] as f32 / 255.0).collect())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MaskStroke {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub strength: f32,
+    #[serde(default)]
+    pub erase: bool,
+}
+
+/// Paint into a mask with a soft round brush. Returns bounding box of affected region: (x, y, w, h).
+pub fn paint(mask: &mut Heightmap, stroke: &MaskStroke) -> (u32, u32, u32, u32) {
+    let r = stroke.radius;
+    let x0 = (stroke.x - r).floor().max(0.0) as u32;
+    let y0 = (stroke.y - r).floor().max(0.0) as u32;
+    let x1 = ((stroke.x + r).ceil().max(0.0) as u32).min(mask.width - 1);
+    let y1 = ((stroke.y + r).ceil().max(0.0) as u32).min(mask.height - 1);
+    if x0 > x1 || y0 > y1 {
+        return (0, 0, 0, 0);
+    }
+
+    let target = if stroke.erase { 0.0 } else { 1.0 };
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            let dx = px as f32 - stroke.x;
+            let dy = py as f32 - stroke.y;
+            let dist_sq = dx * dx + dy * dy;
+            if dist_sq > r * r {
+                continue;
+            }
+            let falloff = (-dist_sq / (r * r) * 3.0).exp();
+            let influence = (stroke.strength * falloff).clamp(0.0, 1.0);
+            let current = mask.get(px, py);
+            mask.set(px, py, current + (target - current) * influence);
+        }
+    }
+
+    (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+}
@@ -0,0 +1,198 @@
+//! Heightmap tiles stitched back into one map, e.g. the output of
+//! `tile_export` or of another tool's tiled pipeline. Tiles in a column share
+//! their width and tiles in a row their height; neighbors may repeat a band of
+//! pixels along their seam, which is blended across.
+
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::float_image;
+use crate::heightmap::Heightmap;
+use crate::project;
+
+/// How the files find their place in the grid.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum MosaicLayout {
+    /// The files in row-major order, `columns` to a row.
+    Grid { columns: u32 },
+    /// File names without extension like `tile_export`'s pattern: `{x}` and
+    /// `{y}` match the column and row, counted from the smallest present, and
+    /// `{name}` any text.
+    Pattern { pattern: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MosaicParams {
+    pub layout: MosaicLayout,
+    /// Pixels each tile shares with the next one right and below.
+    #[serde(default)]
+    pub overlap: u32,
+}
+
+/// Stitch `paths` per `params`. Returns the mosaic and whether its tiles hold
+/// float samples, which are left as stored for the caller to normalize.
+pub fn assemble(paths: &[PathBuf], params: &MosaicParams) -> Result<(Heightmap, bool), String> {
+    if paths.is_empty() {
+        return Err("No tiles given".into());
+    }
+    let cells = place(paths, &params.layout)?;
+    let columns = cells.iter().map(|&(x, _)| x).max().unwrap_or(0) as usize + 1;
+    let rows = cells.iter().map(|&(_, y)| y).max().unwrap_or(0) as usize + 1;
+    if columns as u64 * rows as u64 > paths.len() as u64 {
+        return Err(format!("{} tiles don't fill a {columns}x{rows} grid", paths.len()));
+    }
+    let mut grid: Vec<Option<usize>> = vec![None; columns * rows];
+    for (i, &(x, y)) in cells.iter().enumerate() {
+        let cell = &mut grid[y as usize * columns + x as usize];
+        if let Some(other) = *cell {
+            return Err(format!("{} and {} are both tile {x}, {y}", paths[other].display(), paths[i].display()));
+        }
+        *cell = Some(i);
+    }
+    if let Some(missing) = grid.iter().position(Option::is_none) {
+        return Err(format!("Tile {}, {} of the {columns}x{rows} grid is missing", missing % columns, missing / columns));
+    }
+
+    let tiles = paths.par_iter().map(|p| load(p)).collect::<Result<Vec<_>, String>>()?;
+    let float = tiles[0].1;
+    if tiles.iter().any(|(_, f)| *f != float) {
+        return Err("Tiles must all be PNG, or all float TIFF or EXR".into());
+    }
+    let tile_at = |x: usize, y: usize| &tiles[grid[y * columns + x].unwrap_or_default()].0;
+
+    // Every column takes the width of its top tile and every row the height of its leftmost
+    let widths: Vec<u32> = (0..columns).map(|x| tile_at(x, 0).width).collect();
+    let heights: Vec<u32> = (0..rows).map(|y| tile_at(0, y).height).collect();
+    for y in 0..rows {
+        for x in 0..columns {
+            let tile = tile_at(x, y);
+            if tile.width != widths[x] || tile.height != heights[y] {
+                let path = &paths[grid[y * columns + x].unwrap_or_default()];
+                return Err(format!(
+                    "{} is {}x{} but its column and row need {}x{}",
+                    path.display(),
+                    tile.width,
+                    tile.height,
+                    widths[x],
+                    heights[y]
+                ));
+            }
+        }
+    }
+    let smallest = widths.iter().chain(&heights).copied().min().unwrap_or(0);
+    let overlap = params.overlap;
+    if overlap >= smallest {
+        return Err(format!("Overlap must be less than the smallest tile side ({smallest} pixels)"));
+    }
+
+    let offsets = |sizes: &[u32]| -> Vec<u32> {
+        sizes.iter().scan(0, |at, &size| {
+            let start = *at;
+            *at += size - overlap;
+            Some(start)
+        }).collect()
+    };
+    let (xs, ys) = (offsets(&widths), offsets(&heights));
+    let width = xs[columns - 1] + widths[columns - 1];
+    let height = ys[rows - 1] + heights[rows - 1];
+    float_image::check_size(width, height).map_err(|e| format!("The mosaic is too large: {e}"))?;
+
+    // Ramps across each shared band add up to one, so seams blend linearly
+    let ramp = |i: u32, size: u32, before: bool, after: bool| {
+        let mut w = 1.0f32;
+        if overlap > 0 && before && i < overlap {
+            w = w.min((i as f32 + 0.5) / overlap as f32);
+        }
+        if overlap > 0 && after && i >= size - overlap {
+            w = w.min(((size - i) as f32 - 0.5) / overlap as f32);
+        }
+        w
+    };
+    let (w, h) = (width as usize, height as usize);
+    let mut sums = vec![0.0f32; w * h];
+    let mut weights = vec![0.0f32; w * h];
+    for (ty, &oy) in ys.iter().enumerate() {
+        for (tx, &ox) in xs.iter().enumerate() {
+            let tile = tile_at(tx, ty);
+            for py in 0..tile.height {
+                let wy = ramp(py, tile.height, ty > 0, ty + 1 < rows);
+                let row = (oy + py) as usize * w + ox as usize;
+                for px in 0..tile.width {
+                    let weight = wy * ramp(px, tile.width, tx > 0, tx + 1 < columns);
+                    sums[row + px as usize] += tile.data[(py * tile.width + px) as usize] * weight;
+                    weights[row + px as usize] += weight;
+                }
+            }
+        }
+    }
+    let data = sums.iter().zip(&weights).map(|(&s, &w)| if w > 0.0 { s / w } else { 0.0 }).collect();
+    Ok((Heightmap { data, width, height }, float))
+}
+
+/// Grid cell of each path.
+fn place(paths: &[PathBuf], layout: &MosaicLayout) -> Result<Vec<(u32, u32)>, String> {
+    match layout {
+        MosaicLayout::Grid { columns } => {
+            let columns = *columns as usize;
+            if columns == 0 || !paths.len().is_multiple_of(columns) {
+                return Err(format!("{} tiles don't fill rows of {columns}", paths.len()));
+            }
+            Ok((0..paths.len()).map(|i| ((i % columns) as u32, (i / columns) as u32)).collect())
+        }
+        MosaicLayout::Pattern { pattern } => {
+            if !(pattern.contains("{x}") && pattern.contains("{y}")) {
+                return Err("Tile name pattern needs {x} and {y}".into());
+            }
+            let cells = paths
+                .iter()
+                .map(|path| {
+                    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or_default();
+                    match_stem(pattern, stem, None, None)
+                        .ok_or_else(|| format!("{} doesn't match the pattern {pattern}", path.display()))
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let x0 = cells.iter().map(|&(x, _)| x).min().unwrap_or(0);
+            let y0 = cells.iter().map(|&(_, y)| y).min().unwrap_or(0);
+            Ok(cells.into_iter().map(|(x, y)| (x - x0, y - y0)).collect())
+        }
+    }
+}
+
+/// Column and row `stem` encodes per `pattern`, trying each way of splitting
+/// it between the placeholders.
+fn match_stem(pattern: &str, stem: &str, x: Option<u32>, y: Option<u32>) -> Option<(u32, u32)> {
+    if pattern.is_empty() {
+        return if stem.is_empty() { Some((x?, y?)) } else { None };
+    }
+    for (token, is_x) in [("{x}", true), ("{y}", false)] {
+        if let Some(rest) = pattern.strip_prefix(token) {
+            let digits = stem.bytes().take_while(u8::is_ascii_digit).count();
+            return (1..=digits).rev().find_map(|len| {
+                let v = stem[..len].parse().ok()?;
+                let (x, y) = if is_x { (Some(v), y) } else { (x, Some(v)) };
+                match_stem(rest, &stem[len..], x, y)
+            });
+        }
+    }
+    if let Some(rest) = pattern.strip_prefix("{name}") {
+        return (0..=stem.len())
+            .filter(|&i| stem.is_char_boundary(i))
+            .find_map(|i| match_stem(rest, &stem[i..], x, y));
+    }
+    let c = pattern.chars().next()?;
+    match_stem(&pattern[c.len_utf8()..], stem.strip_prefix(c)?, x, y)
+}
+
+/// A PNG at its own bit depth, or a TIFF or OpenEXR with its samples as
+/// stored. Returns whether it holds float samples.
+fn load(path: &Path) -> Result<(Heightmap, bool), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    match bytes.get(..2) {
+        Some(b"II") | Some(b"MM") | Some([0x76, 0x2f]) => Ok((float_image::read(path)?, true)),
+        _ => project::decode_heightmap_image(&bytes)
+            .map(|hm| (hm, false))
+            .map_err(|e| format!("{}: {e}", path.display())),
+    }
+}
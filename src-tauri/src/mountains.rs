@@ -0,0 +1,147 @@
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+/// A mountain range or escarpment raised along a user-drawn polyline.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MountainRangeParams {
+    /// Spine vertices in pixel coordinates.
+    pub points: Vec<[f32; 2]>,
+    /// Distance from the spine to the foot of the range in pixels.
+    pub width: f32,
+    /// Peak height above the existing terrain in meters.
+    pub height: f32,
+    /// Ridge noise strength, 0 for a smooth swell.
+    pub roughness: f32,
+    /// Raise a plateau on the left of the line (walking along it) that drops off in a
+    /// steep scarp on the right, instead of a symmetric range.
+    #[serde(default)]
+    pub escarpment: bool,
+    #[serde(default)]
+    pub seed: u32,
+}
+
+/// Spine distance, signed side and arc length of the nearest spine point.
+struct SpinePoint {
+    dist: f32,
+    side: f32,
+    along: f32,
+}
+
+/// Raise a range along the spine. Ridge noise is strongest on the spine and fades
+/// towards the foot, so the range settles into the surrounding terrain.
+/// Returns bounding box of affected region: (x, y, w, h).
+pub fn apply(hm: &mut Heightmap, range: &MountainRangeParams, world: &WorldScale) -> (u32, u32, u32, u32) {
+    if range.points.len() < 2 || range.width < 1.0 {
+        return (0, 0, 0, 0);
+    }
+
+    let reach = range.width;
+    let (min_x, min_y, max_x, max_y) = range.points.iter().fold(
+        (f32::MAX, f32::MAX, f32::MIN, f32::MIN),
+        |(a, b, c, d), p| (a.min(p[0]), b.min(p[1]), c.max(p[0]), d.max(p[1])),
+    );
+    let x0 = (min_x - reach).floor().max(0.0) as u32;
+    let y0 = (min_y - reach).floor().max(0.0) as u32;
+    let x1 = ((max_x + reach).ceil().max(0.0) as u32).min(hm.width - 1);
+    let y1 = ((max_y + reach).ceil().max(0.0) as u32).min(hm.height - 1);
+    if x0 > x1 || y0 > y1 {
+        return (0, 0, 0, 0);
+    }
+
+    let range_m = (world.max_elevation - world.min_elevation).max(1e-6);
+    let height = range.height / range_m;
+    let noise = Perlin::new(range.seed);
+    let roughness = range.roughness.clamp(0.0, 1.0);
+    let total: f32 = range
+        .points
+        .windows(2)
+        .map(|s| ((s[1][0] - s[0][0]).powi(2) + (s[1][1] - s[0][1]).powi(2)).sqrt())
+        .sum();
+
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            let spine = nearest_on_spine(&range.points, px as f32, py as f32);
+            let t = spine.dist / reach;
+            if t >= 1.0 {
+                continue;
+            }
+
+            let profile = if range.escarpment {
+                if spine.side >= 0.0 {
+                    // Plateau top, rounding off towards the back slope
+                    1.0 - smoothstep(0.5, 1.0, t)
+                } else {
+                    // Scarp face over the first quarter of the width
+                    1.0 - smoothstep(0.0, 0.25, t)
+                }
+            } else {
+                let u = 1.0 - t;
+                u * u * (3.0 - 2.0 * u)
+            };
+
+            // Peaks and saddles along the spine, tapering off past both ends
+            let along = spine.along as f64 / reach as f64;
+            let crest = 0.75 + 0.25 * noise.get([along * 0.8, 7.3]) as f32;
+            let taper = smoothstep(0.0, reach, spine.along) * smoothstep(0.0, reach, total - spine.along);
+
+            // Ridge noise in map space so corners and ends don't pinch it into rings;
+            // it fades out towards the foot
+            let ridges = ridged(&noise, px as f64 / reach as f64 * 1.5, py as f64 / reach as f64 * 1.5) as f32;
+            let detail = 1.0 + roughness * (ridges - 0.5) * 1.6 * (1.0 - t);
+
+            let lift = height * profile * crest * taper * detail.max(0.0);
+            let current = hm.get(px, py);
+            hm.set(px, py, (current + lift).clamp(0.0, 1.0));
+        }
+    }
+
+    (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+}
+
+fn nearest_on_spine(points: &[[f32; 2]], x: f32, y: f32) -> SpinePoint {
+    let mut best = SpinePoint { dist: f32::MAX, side: 0.0, along: 0.0 };
+    let mut walked = 0.0;
+    for seg in points.windows(2) {
+        let (ax, ay) = (seg[0][0], seg[0][1]);
+        let (dx, dy) = (seg[1][0] - ax, seg[1][1] - ay);
+        let len_sq = dx * dx + dy * dy;
+        let len = len_sq.sqrt();
+        if len_sq < 1e-6 {
+            continue;
+        }
+        let t = (((x - ax) * dx + (y - ay) * dy) / len_sq).clamp(0.0, 1.0);
+        let (ex, ey) = (x - (ax + dx * t), y - (ay + dy * t));
+        let dist = (ex * ex + ey * ey).sqrt();
+        if dist < best.dist {
+            // With +y pointing south, a negative cross product lies on the left
+            let cross = dx * (y - ay) - dy * (x - ax);
+            best = SpinePoint { dist, side: -cross.signum(), along: walked + t * len };
+        }
+        walked += len;
+    }
+    best
+}
+
+/// Ridged multifractal in [0, 1]: sharp crests where the noise crosses zero.
+fn ridged(noise: &Perlin, x: f64, y: f64) -> f64 {
+    let mut freq = 1.0;
+    let mut amp = 1.0;
+    let mut sum = 0.0;
+    let mut max_amp = 0.0;
+    for _ in 0..5 {
+        let r = 1.0 - noise.get([x * freq, y * freq]).abs();
+        sum += r * r * amp;
+        max_amp += amp;
+        freq *= 2.0;
+        amp *= 0.5;
+    }
+    sum / max_amp
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
@@ -1,15 +1,180 @@
 use noise::{NoiseFn, Perlin, OpenSimplex};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use crate::dunes::{DuneField, DuneParams};
 use crate::heightmap::Heightmap;
+use crate::tectonics::{TectonicField, TectonicParams};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum NoiseType {
     Perlin,
     Simplex,
+    Worley,
+    /// Aeolian dunes from `NoiseParams::dunes`, with Perlin octaves as surface detail.
+    Dunes,
+    /// Noise-free base shape from `NoiseParams::analytic`.
+    Analytic,
 }
 
-#[derive(Debug, Deserialize)]
+/// Which cellular distance Worley noise returns.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WorleyMode {
+    /// Distance to the nearest feature point: rounded cells, basins.
+    #[default]
+    F1,
+    /// Distance to the second-nearest point: faceted, plateau-like cells.
+    F2,
+    /// Distance between the two: thin ridges along cell borders (cracked earth).
+    F2MinusF1,
+}
+
+/// How octaves are accumulated.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FractalType {
+    #[default]
+    Fbm,
+    /// Musgrave's hybrid multifractal: each octave is weighted by the ones before it,
+    /// giving smooth valleys and rough peaks.
+    HybridMultifractal,
+    /// Derivative-dampened ridged noise (de Carpentier): octaves are warped by the
+    /// accumulated gradient and faded on slopes, giving eroded-looking ridges.
+    SwissTurbulence,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum FalloffShape {
+    Radial,
+    Square,
+}
+
+/// Fades heights to sea level (0.0) towards the map borders to produce islands.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FalloffParams {
+    pub shape: FalloffShape,
+    /// Island center in normalized [0, 1] map coordinates.
+    pub center_x: f64,
+    pub center_y: f64,
+    /// Normalized distance from the center at which heights reach zero.
+    pub radius: f64,
+    /// Falloff exponent: higher values keep the interior flat and drop sharply at the coast.
+    pub curve: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AnalyticShape {
+    /// Linear ramp across the whole map.
+    #[default]
+    Gradient,
+    /// Rises or falls with distance from a center: a bowl or a dome.
+    Radial,
+    /// Constant height.
+    Plane,
+}
+
+/// Simple base shapes for sloped shelves and basins under noise layers.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AnalyticParams {
+    pub shape: AnalyticShape,
+    /// Direction the gradient rises towards, in degrees clockwise from north.
+    pub angle: f64,
+    /// Radial center in normalized [0, 1] map coordinates.
+    pub center_x: f64,
+    pub center_y: f64,
+    /// Normalized distance from the center at which the radial shape reaches `to`.
+    pub radius: f64,
+    /// Normalized height at the low edge of the gradient, the radial center, or of the plane.
+    pub from: f64,
+    /// Normalized height at the high edge of the gradient or the radial rim.
+    pub to: f64,
+}
+
+impl Default for AnalyticParams {
+    fn default() -> Self {
+        Self {
+            shape: AnalyticShape::Gradient,
+            angle: 0.0,
+            center_x: 0.5,
+            center_y: 0.5,
+            radius: 0.5,
+            from: 0.0,
+            to: 1.0,
+        }
+    }
+}
+
+impl AnalyticParams {
+    fn height_at(&self, nx: f64, ny: f64) -> f64 {
+        let t = match self.shape {
+            AnalyticShape::Gradient => {
+                let angle = self.angle.to_radians();
+                let (dx, dy) = (angle.sin(), -angle.cos());
+                // Scale so the map's extreme corners along the direction span [0, 1]
+                let extent = 0.5 * (dx.abs() + dy.abs());
+                let along = (nx - 0.5) * dx + (ny - 0.5) * dy;
+                0.5 + 0.5 * along / extent.max(1e-6)
+            }
+            AnalyticShape::Radial => {
+                let (dx, dy) = (nx - self.center_x, ny - self.center_y);
+                (dx * dx + dy * dy).sqrt() / self.radius.max(1e-6)
+            }
+            AnalyticShape::Plane => 0.0,
+        };
+        self.from + (self.to - self.from) * t.clamp(0.0, 1.0)
+    }
+}
+
+/// Quantizes heights into flat benches for mesa and badlands terrain.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TerraceParams {
+    /// Number of benches across the [0, 1] height range.
+    pub steps: u32,
+    /// Fraction of each step spent on the riser: 0 gives sheer cliffs, 1 a smooth staircase.
+    pub smoothing: f64,
+}
+
+/// How generated heights combine with the existing heightmap.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlendMode {
+    #[default]
+    Replace,
+    Add,
+    Subtract,
+    Multiply,
+    Min,
+    Max,
+    Lerp,
+}
+
+impl BlendMode {
+    /// Combine one sample. `weight` fades between the base and the blended result;
+    /// `Replace` always takes the layer as-is.
+    pub fn apply(self, base: f32, layer: f32, weight: f32) -> f32 {
+        let blended = match self {
+            BlendMode::Replace => return layer,
+            BlendMode::Add => base + layer,
+            BlendMode::Subtract => base - layer,
+            BlendMode::Multiply => base * layer,
+            BlendMode::Min => base.min(layer),
+            BlendMode::Max => base.max(layer),
+            BlendMode::Lerp => layer,
+        };
+        base + (blended - base) * weight
+    }
+}
+
+fn default_blend_weight() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct NoiseParams {
     pub noise_type: NoiseType,
@@ -20,34 +185,238 @@ pub struct NoiseParams {
     pub persistence: f64,
     pub amplitude: f64,
     pub offset: f64,
+    #[serde(default)]
+    pub worley_mode: WorleyMode,
+    #[serde(default)]
+    pub fractal: FractalType,
+    #[serde(default)]
+    pub dunes: DuneParams,
+    #[serde(default)]
+    pub analytic: AnalyticParams,
+    #[serde(default)]
+    pub falloff: Option<FalloffParams>,
+    #[serde(default)]
+    pub terrace: Option<TerraceParams>,
+    /// Fault/uplift structure added under the noise.
+    #[serde(default)]
+    pub tectonic: Option<TectonicParams>,
+    #[serde(default)]
+    pub blend: BlendMode,
+    #[serde(default = "default_blend_weight")]
+    pub blend_weight: f32,
+    /// Restrict generation to the backend selection mask.
+    #[serde(default)]
+    pub use_selection: bool,
+    /// Feather radius in pixels applied to the generation mask.
+    #[serde(default)]
+    pub mask_feather: u32,
 }
 
 pub fn generate_terrain(hm: &mut Heightmap, params: &NoiseParams) {
+    if params.blend == BlendMode::Replace {
+        generate_layer(hm, params);
+        return;
+    }
+
+    let mut layer = Heightmap::new(hm.width, hm.height);
+    generate_layer(&mut layer, params);
+    for (base, &value) in hm.data.iter_mut().zip(layer.data.iter()) {
+        *base = params.blend.apply(*base, value, params.blend_weight).clamp(0.0, 1.0);
+    }
+}
+
+/// Generate, then keep the result only where `weights` is set, fading back to the
+/// original terrain elsewhere.
+pub fn generate_terrain_masked(hm: &mut Heightmap, params: &NoiseParams, weights: &[f32]) {
+    let original = hm.data.clone();
+    generate_terrain(hm, params);
+    for ((value, &orig), &w) in hm.data.iter_mut().zip(original.iter()).zip(weights.iter()) {
+        *value = orig + (*value - orig) * w.clamp(0.0, 1.0);
+    }
+}
+
+fn generate_layer(hm: &mut Heightmap, params: &NoiseParams) {
+    let full = (0, 0, hm.width, hm.height);
+    generate_region(hm, params, params.seed, full);
+}
+
+/// Raw layers for each seed at `width` x `height`, generated in parallel. Noise
+/// coordinates are resolution-independent, so these are low-res views of what a
+/// full generation with that seed would give.
+pub fn seed_previews(params: &NoiseParams, seeds: &[u32], width: u32, height: u32) -> Vec<Heightmap> {
+    std::thread::scope(|s| {
+        let handles: Vec<_> = seeds
+            .iter()
+            .map(|&seed| {
+                s.spawn(move || {
+                    let mut hm = Heightmap::new(width, height);
+                    generate_region(&mut hm, params, seed, (0, 0, width, height));
+                    hm
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    })
+}
+
+/// Generate raw heights for a sub-rectangle `(x, y, w, h)` using `seed` in place of
+/// `params.seed`. Noise coordinates stay in whole-map space so regions line up.
+pub fn generate_region(
+    hm: &mut Heightmap,
+    params: &NoiseParams,
+    seed: u32,
+    region: (u32, u32, u32, u32),
+) {
+    let frame = Frame::of(hm);
+    generate_region_in(hm, params, seed, region, frame);
+}
+
+/// Maps pixels to noise space: pixel (x, y) samples the generator at
+/// ((x + origin_x) / unit_w, (y + origin_y) / unit_h). A map is normally its own
+/// frame; an expanded canvas keeps the frame of the map it grew from.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frame {
+    pub origin_x: f64,
+    pub origin_y: f64,
+    pub unit_w: f64,
+    pub unit_h: f64,
+}
+
+impl Frame {
+    pub fn of(hm: &Heightmap) -> Self {
+        Self {
+            origin_x: 0.0,
+            origin_y: 0.0,
+            unit_w: hm.width as f64,
+            unit_h: hm.height as f64,
+        }
+    }
+}
+
+/// Raw generated heights for the whole map in an explicit frame, ignoring blending.
+pub fn generate_in_frame(hm: &mut Heightmap, params: &NoiseParams, frame: Frame) {
+    let full = (0, 0, hm.width, hm.height);
+    generate_region_in(hm, params, params.seed, full, frame);
+}
+
+fn generate_region_in(
+    hm: &mut Heightmap,
+    params: &NoiseParams,
+    seed: u32,
+    region: (u32, u32, u32, u32),
+    frame: Frame,
+) {
+    let tectonic = params.tectonic.as_ref().map(|t| TectonicField::new(t, seed));
+    let structure = Structure { tectonic: tectonic.as_ref(), dunes: None, analytic: None };
     match params.noise_type {
         NoiseType::Perlin => {
-            let source = Perlin::new(params.seed);
-            fill_heightmap(hm, &source, params);
+            let source = Perlin::new(seed);
+            fill_heightmap(hm, &source, &structure, params, region, frame);
         }
         NoiseType::Simplex => {
-            let source = OpenSimplex::new(params.seed);
-            fill_heightmap(hm, &source, params);
+            let source = OpenSimplex::new(seed);
+            fill_heightmap(hm, &source, &structure, params, region, frame);
+        }
+        NoiseType::Worley => {
+            let source = Worley::new(seed, params.worley_mode);
+            fill_heightmap(hm, &source, &structure, params, region, frame);
+        }
+        NoiseType::Dunes => {
+            let source = Perlin::new(seed);
+            let dunes = DuneField::new(&params.dunes, seed);
+            let structure = Structure { dunes: Some(&dunes), ..structure };
+            fill_heightmap(hm, &source, &structure, params, region, frame);
+        }
+        NoiseType::Analytic => {
+            let source = Perlin::new(seed);
+            let structure = Structure { analytic: Some(&params.analytic), ..structure };
+            fill_heightmap(hm, &source, &structure, params, region, frame);
         }
     }
 }
 
-fn fill_heightmap(hm: &mut Heightmap, source: &impl NoiseFn<f64, 2>, params: &NoiseParams) {
-    for y in 0..hm.height {
-        for x in 0..hm.width {
-            let nx = x as f64 / hm.width as f64;
-            let ny = y as f64 / hm.height as f64;
+/// Whole-map fields sampled alongside the noise.
+struct Structure<'a> {
+    tectonic: Option<&'a TectonicField>,
+    /// Replaces the fractal as the primary shape; the fractal is kept as faint detail.
+    dunes: Option<&'a DuneField>,
+    /// Replaces the noise entirely; amplitude and offset don't apply.
+    analytic: Option<&'a AnalyticParams>,
+}
 
-            let val = fbm(source, nx, ny, params);
-            let normalized = (val * params.amplitude + params.offset).clamp(0.0, 1.0);
+fn fill_heightmap(
+    hm: &mut Heightmap,
+    source: &impl NoiseFn<f64, 2>,
+    structure: &Structure,
+    params: &NoiseParams,
+    (rx, ry, rw, rh): (u32, u32, u32, u32),
+    frame: Frame,
+) {
+    for y in ry..(ry + rh).min(hm.height) {
+        for x in rx..(rx + rw).min(hm.width) {
+            let nx = (x as f64 + frame.origin_x) / frame.unit_w;
+            let ny = (y as f64 + frame.origin_y) / frame.unit_h;
+
+            let mut normalized = if let Some(analytic) = structure.analytic {
+                analytic.height_at(nx, ny).clamp(0.0, 1.0)
+            } else {
+                let mut val = match params.fractal {
+                    FractalType::Fbm => fbm(source, nx, ny, params),
+                    FractalType::HybridMultifractal => hybrid_multifractal(source, nx, ny, params),
+                    FractalType::SwissTurbulence => swiss_turbulence(source, nx, ny, params),
+                };
+                if let Some(dunes) = structure.dunes {
+                    val = dunes.sample(nx, ny) + val * 0.15;
+                }
+                if let (Some(field), Some(t)) = (structure.tectonic, &params.tectonic) {
+                    val += field.sample(nx, ny) * t.strength;
+                }
+                (val * params.amplitude + params.offset).clamp(0.0, 1.0)
+            };
+            if let Some(terrace) = &params.terrace {
+                normalized = terrace_height(normalized, terrace);
+            }
+            if let Some(falloff) = &params.falloff {
+                normalized *= falloff_factor(nx, ny, falloff);
+            }
             hm.set(x, y, normalized as f32);
         }
     }
 }
 
+/// Multiplier in [0, 1]: 1 at the center, 0 at `radius` and beyond.
+fn falloff_factor(nx: f64, ny: f64, falloff: &FalloffParams) -> f64 {
+    let dx = nx - falloff.center_x;
+    let dy = ny - falloff.center_y;
+    let dist = match falloff.shape {
+        FalloffShape::Radial => (dx * dx + dy * dy).sqrt(),
+        FalloffShape::Square => dx.abs().max(dy.abs()),
+    };
+    let t = (dist / falloff.radius.max(1e-6)).clamp(0.0, 1.0);
+    1.0 - t.powf(falloff.curve.max(1e-3))
+}
+
+fn terrace_height(h: f64, terrace: &TerraceParams) -> f64 {
+    if terrace.steps == 0 {
+        return h;
+    }
+    let steps = terrace.steps as f64;
+    let t = h * steps;
+    let bench = t.floor();
+    let frac = t - bench;
+
+    // The riser occupies the top `smoothing` fraction of each step
+    let riser = terrace.smoothing.clamp(0.0, 1.0);
+    let rise = if riser > 0.0 {
+        let r = ((frac - (1.0 - riser)) / riser).clamp(0.0, 1.0);
+        r * r * (3.0 - 2.0 * r)
+    } else {
+        0.0
+    };
+    ((bench + rise) / steps).min(1.0)
+}
+
 fn fbm(source: &impl NoiseFn<f64, 2>, x: f64, y: f64, params: &NoiseParams) -> f64 {
     let mut freq = params.frequency;
     let mut amp = 1.0;
@@ -67,3 +436,125 @@ fn fbm(source: &impl NoiseFn<f64, 2>, x: f64, y: f64, params: &NoiseParams) -> f
         0.0
     }
 }
+
+fn hybrid_multifractal(source: &impl NoiseFn<f64, 2>, x: f64, y: f64, params: &NoiseParams) -> f64 {
+    // Musgrave's offset keeps signals positive so they can act as weights
+    const OFFSET: f64 = 0.7;
+
+    let mut freq = params.frequency;
+    let mut amp = 1.0;
+    let mut max_amp = 0.0;
+    let mut value = 0.0;
+    let mut weight = 1.0f64;
+
+    for _ in 0..params.octaves {
+        let signal = (source.get([x * freq, y * freq]) + OFFSET) * amp;
+        value += weight.min(1.0) * signal;
+        weight *= signal;
+        max_amp += amp;
+        freq *= params.lacunarity;
+        amp *= params.persistence;
+    }
+
+    if max_amp > 0.0 {
+        // Back to roughly [-1, 1]; the first octave alone maps exactly onto the noise
+        value / max_amp - OFFSET
+    } else {
+        0.0
+    }
+}
+
+fn swiss_turbulence(source: &impl NoiseFn<f64, 2>, x: f64, y: f64, params: &NoiseParams) -> f64 {
+    // Domain warp strength from the accumulated derivative
+    const WARP: f64 = 0.15;
+    const EPS: f64 = 1e-3;
+
+    let mut freq = params.frequency;
+    let mut amp = 1.0;
+    let mut max_amp = 0.0;
+    let mut value = 0.0;
+    let mut dsum = (0.0, 0.0);
+
+    for _ in 0..params.octaves {
+        let px = (x + WARP * dsum.0) * freq;
+        let py = (y + WARP * dsum.1) * freq;
+        let n = source.get([px, py]);
+        let dx = (source.get([px + EPS, py]) - n) / EPS;
+        let dy = (source.get([px, py + EPS]) - n) / EPS;
+
+        value += amp * (1.0 - n.abs());
+        dsum.0 += amp * dx * -n;
+        dsum.1 += amp * dy * -n;
+        max_amp += amp;
+        freq *= params.lacunarity;
+        amp *= params.persistence * value.clamp(0.0, 1.0);
+    }
+
+    if max_amp > 0.0 {
+        value / max_amp * 2.0 - 1.0
+    } else {
+        0.0
+    }
+}
+
+/// Cellular noise with one jittered feature point per unit cell.
+/// Output is remapped to roughly [-1, 1] so it composes with the gradient noises.
+pub struct Worley {
+    seed: u32,
+    mode: WorleyMode,
+}
+
+impl Worley {
+    pub fn new(seed: u32, mode: WorleyMode) -> Self {
+        Self { seed, mode }
+    }
+
+    fn feature_point(&self, cx: i64, cy: i64) -> (f64, f64) {
+        let h = hash_cell(cx, cy, self.seed);
+        let jx = (h & 0xffff) as f64 / 65535.0;
+        let jy = ((h >> 16) & 0xffff) as f64 / 65535.0;
+        (cx as f64 + jx, cy as f64 + jy)
+    }
+}
+
+impl NoiseFn<f64, 2> for Worley {
+    fn get(&self, point: [f64; 2]) -> f64 {
+        let [x, y] = point;
+        let cx = x.floor() as i64;
+        let cy = y.floor() as i64;
+
+        let mut f1 = f64::MAX;
+        let mut f2 = f64::MAX;
+        for oy in -1..=1 {
+            for ox in -1..=1 {
+                let (px, py) = self.feature_point(cx + ox, cy + oy);
+                let d = ((px - x).powi(2) + (py - y).powi(2)).sqrt();
+                if d < f1 {
+                    f2 = f1;
+                    f1 = d;
+                } else if d < f2 {
+                    f2 = d;
+                }
+            }
+        }
+
+        // Scale each mode's typical range onto [-1, 1]
+        match self.mode {
+            WorleyMode::F1 => f1 * 2.0 - 1.0,
+            WorleyMode::F2 => f2 * 1.6 - 1.0,
+            WorleyMode::F2MinusF1 => (f2 - f1) * 2.0 - 1.0,
+        }
+    }
+}
+
+pub(crate) fn hash_cell(x: i64, y: i64, seed: u32) -> u64 {
+    // SplitMix64 finalizer over the packed cell coordinates
+    let mut h = (x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ (seed as u64).wrapping_mul(0x1656_67B1_9E37_79F9);
+    h ^= h >> 30;
+    h = h.wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h ^= h >> 27;
+    h = h.wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
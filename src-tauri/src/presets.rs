@@ -0,0 +1,160 @@
+//! Named parameter sets kept in the app config directory, so good recipes survive
+//! restarts. Each preset is a plain JSON file holding exactly the parameters the
+//! matching command takes, under `presets/<kind>/<name>.json`, so presets can be
+//! shared by copying the file.
+
+use std::path::PathBuf;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use crate::erosion::hydraulic::HydraulicParams;
+use crate::erosion::thermal::ThermalParams;
+use crate::export_profile::ExportProfile;
+use crate::noise_gen::NoiseParams;
+
+/// Which parameters a preset holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PresetKind {
+    Hydraulic,
+    Thermal,
+    Noise,
+    /// An `ExportProfile`, run with `run_export_profile`.
+    Export,
+}
+
+impl PresetKind {
+    /// Directory name under the preset root.
+    fn id(self) -> &'static str {
+        match self {
+            PresetKind::Hydraulic => "hydraulic",
+            PresetKind::Thermal => "thermal",
+            PresetKind::Noise => "noise",
+            PresetKind::Export => "export",
+        }
+    }
+
+    /// Check that `params` parse as this kind's parameters, so a preset can't be
+    /// saved that the commands would later reject.
+    pub fn check(self, params: &Value) -> Result<(), String> {
+        let parsed = match self {
+            PresetKind::Hydraulic => HydraulicParams::deserialize(params).map(drop).map_err(|e| e.to_string()),
+            PresetKind::Thermal => ThermalParams::deserialize(params).map(drop).map_err(|e| e.to_string()),
+            PresetKind::Noise => NoiseParams::deserialize(params).map(drop).map_err(|e| e.to_string()),
+            PresetKind::Export => ExportProfile::deserialize(params)
+                .map_err(|e| e.to_string())
+                .and_then(|profile| profile.validate()),
+        };
+        parsed.map_err(|e| format!("Invalid {} parameters: {e}", self.id()))
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preset {
+    pub name: String,
+    pub kind: PresetKind,
+    pub params: Value,
+}
+
+/// Longest preset name, in characters.
+const MAX_NAME_LEN: usize = 64;
+
+/// Preset names double as file names, so they can't contain path separators or
+/// characters some file systems reject.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    validate_file_name("Preset", name)
+}
+
+/// The checks of `validate_name` for any name used as a file name; `what`
+/// starts the error messages.
+pub fn validate_file_name(what: &str, name: &str) -> Result<(), String> {
+    if name.trim().is_empty() {
+        return Err(format!("{what} name must not be empty"));
+    }
+    if name != name.trim() {
+        return Err(format!("{what} name must not start or end with spaces"));
+    }
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(format!("{what} name must be at most {MAX_NAME_LEN} characters"));
+    }
+    if name.starts_with('.') || name.chars().any(|c| c.is_control() || r#"/\:*?"<>|"#.contains(c)) {
+        return Err(format!("{what} name {name:?} can't be used as a file name"));
+    }
+    Ok(())
+}
+
+/// Presets stored under one directory.
+pub struct PresetStore {
+    root: PathBuf,
+}
+
+impl PresetStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn dir(&self, kind: PresetKind) -> PathBuf {
+        self.root.join(kind.id())
+    }
+
+    fn path(&self, kind: PresetKind, name: &str) -> PathBuf {
+        self.dir(kind).join(format!("{name}.json"))
+    }
+
+    /// Names of the presets of `kind`, sorted case-insensitively. Files that
+    /// aren't JSON are skipped; a missing directory just means none were saved.
+    pub fn list(&self, kind: PresetKind) -> Result<Vec<String>, String> {
+        let dir = self.dir(kind);
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {e}", dir.display())),
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "json"))
+            .filter_map(|p| Some(p.file_stem()?.to_str()?.to_string()))
+            .filter(|name| validate_name(name).is_ok())
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        Ok(names)
+    }
+
+    /// The preset's parameters, or `None` if there is no such preset.
+    pub fn load(&self, kind: PresetKind, name: &str) -> Result<Option<Preset>, String> {
+        validate_name(name)?;
+        let path = self.path(kind, name);
+        let json = match std::fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {e}", path.display())),
+        };
+        let params = serde_json::from_str(&json).map_err(|e| format!("Invalid preset {}: {e}", path.display()))?;
+        kind.check(&params)?;
+        Ok(Some(Preset { name: name.to_string(), kind, params }))
+    }
+
+    /// Save `params` under `name`, replacing any preset of that name. Returns the
+    /// file written.
+    pub fn save(&self, kind: PresetKind, name: &str, params: &Value) -> Result<PathBuf, String> {
+        validate_name(name)?;
+        kind.check(params)?;
+        let dir = self.dir(kind);
+        std::fs::create_dir_all(&dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+        let path = self.path(kind, name);
+        let json = serde_json::to_string_pretty(params).map_err(|e| format!("Failed to serialize preset: {e}"))?;
+        std::fs::write(&path, json).map_err(|e| format!("Failed to write {}: {e}", path.display()))?;
+        Ok(path)
+    }
+
+    /// Remove the preset; returns whether there was one.
+    pub fn delete(&self, kind: PresetKind, name: &str) -> Result<bool, String> {
+        validate_name(name)?;
+        let path = self.path(kind, name);
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Failed to delete {}: {e}", path.display())),
+        }
+    }
+}
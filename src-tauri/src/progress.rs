@@ -0,0 +1,218 @@
+//! Progress reports for long-running jobs. Every job reports through a
+//! [`ProgressTracker`], so the frontend gets the same stage and time estimate no
+//! matter which algorithm is running.
+
+use std::sync::Mutex;
+use std::time::Instant;
+use serde::Serialize;
+use tauri::ipc::Channel;
+
+/// What a job is busy with; the frontend turns this into a localized label.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Stage {
+    Thermal,
+    Droplets,
+    /// Shallow water flowing through virtual pipes.
+    Water,
+    /// Filling depressions up to their spill points.
+    Sinks,
+    /// Carving river channels.
+    Rivers,
+    Uplift,
+    Glaciers,
+    Waves,
+    /// Writing a project file.
+    Saving,
+    /// Reading a project file.
+    Loading,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Progress {
+    pub stage: Stage,
+    /// 0 to 1.
+    pub fraction: f32,
+    /// Estimated seconds left; `None` until enough progress has been seen.
+    pub eta_secs: Option<f32>,
+    /// Which job this is when several run back to back.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub step: Option<Step>,
+    /// Set on the report announcing a change in how the job is running.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<RunState>,
+}
+
+/// A change in how a job is running, announced at the fraction it had reached.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RunState {
+    /// Suspended, with the terrain released for editing.
+    Paused,
+    Resumed,
+    /// The GPU run failed and the job goes on with the CPU.
+    CpuFallback,
+    /// Ended, completed or aborted; the terrain holds the result.
+    Finished,
+}
+
+/// Position of a job in a pipeline; `index` counts from 0.
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Step {
+    pub index: u32,
+    pub count: u32,
+}
+
+/// Time to watch a job before guessing how long it will take; the first steps
+/// often include setup such as GPU buffer uploads.
+const WARMUP_SECS: f32 = 0.5;
+/// Weight of the newest throughput sample in the running average.
+const SMOOTHING: f32 = 0.2;
+
+struct Throughput {
+    last_time: Instant,
+    last_fraction: f32,
+    /// Smoothed fraction per second.
+    rate: Option<f32>,
+}
+
+/// Sends [`Progress`] for one job, estimating the remaining time from how fast
+/// the fraction has been advancing.
+pub struct ProgressTracker {
+    stage: Stage,
+    step: Option<Step>,
+    started: Instant,
+    channel: Channel<Progress>,
+    throughput: Mutex<Throughput>,
+}
+
+impl ProgressTracker {
+    pub fn new(stage: Stage, channel: Channel<Progress>) -> Self {
+        let now = Instant::now();
+        Self {
+            stage,
+            step: None,
+            started: now,
+            channel,
+            throughput: Mutex::new(Throughput { last_time: now, last_fraction: 0.0, rate: None }),
+        }
+    }
+
+    /// Report as job `index` of `count` run back to back on the same channel.
+    pub fn with_step(mut self, index: u32, count: u32) -> Self {
+        self.step = Some(Step { index, count });
+        self
+    }
+
+    pub fn report(&self, fraction: f32) {
+        let fraction = fraction.clamp(0.0, 1.0);
+        let now = Instant::now();
+        let mut throughput = self.throughput.lock().unwrap();
+
+        let step = now.duration_since(throughput.last_time).as_secs_f32();
+        let advanced = fraction - throughput.last_fraction;
+        if step > 0.0 && advanced > 0.0 {
+            let sample = advanced / step;
+            throughput.rate = Some(match throughput.rate {
+                Some(rate) => rate + SMOOTHING * (sample - rate),
+                None => sample,
+            });
+            throughput.last_time = now;
+            throughput.last_fraction = fraction;
+        }
+
+        let warmed_up = now.duration_since(self.started).as_secs_f32() >= WARMUP_SECS;
+        let eta_secs = if fraction >= 1.0 {
+            Some(0.0)
+        } else if warmed_up {
+            throughput.rate.map(|rate| (1.0 - fraction) / rate)
+        } else {
+            None
+        };
+
+        let _ = self.channel.send(Progress { stage: self.stage, fraction, eta_secs, step: self.step, state: None });
+    }
+
+    /// Announce `state`. On resuming, the rate is measured afresh, so the time
+    /// spent paused doesn't count towards it.
+    pub fn announce(&self, state: RunState) {
+        let mut throughput = self.throughput.lock().unwrap();
+        if let RunState::Resumed = state {
+            throughput.last_time = Instant::now();
+            throughput.rate = None;
+        }
+        let fraction = throughput.last_fraction;
+        let _ = self.channel.send(Progress { stage: self.stage, fraction, eta_secs: None, step: self.step, state: Some(state) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::time::Duration;
+    use tauri::ipc::InvokeResponseBody;
+
+    /// A tracker whose reports are collected as JSON.
+    fn tracker(stage: Stage) -> (ProgressTracker, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let sink = Arc::clone(&sent);
+        let channel = Channel::new(move |body| {
+            if let InvokeResponseBody::Json(json) = body {
+                sink.lock().unwrap().push(serde_json::from_str(&json).unwrap());
+            }
+            Ok(())
+        });
+        (ProgressTracker::new(stage, channel), sent)
+    }
+
+    #[test]
+    fn reports_carry_stage_fraction_and_step() {
+        let (tracker, sent) = tracker(Stage::Sinks);
+        let tracker = tracker.with_step(1, 3);
+        tracker.report(0.25);
+        tracker.report(1.5);
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[0]["stage"], "sinks");
+        assert_eq!(sent[0]["fraction"], 0.25);
+        assert_eq!(sent[0]["step"], serde_json::json!({ "index": 1, "count": 3 }));
+        // No guess before the warmup, and nothing left once done
+        assert_eq!(sent[0]["etaSecs"], serde_json::Value::Null);
+        assert_eq!(sent[1]["fraction"], 1.0);
+        assert_eq!(sent[1]["etaSecs"], 0.0);
+        assert!(sent[0].get("state").is_none());
+    }
+
+    #[test]
+    fn announcements_keep_the_fraction_reached() {
+        let (tracker, sent) = tracker(Stage::Droplets);
+        tracker.report(0.4);
+        tracker.announce(RunState::CpuFallback);
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent[1]["state"], "cpuFallback");
+        assert!((sent[1]["fraction"].as_f64().unwrap() - 0.4).abs() < 1e-6);
+        assert_eq!(sent[1]["etaSecs"], serde_json::Value::Null);
+        assert!(sent[1].get("step").is_none());
+    }
+
+    #[test]
+    fn time_paused_doesnt_slow_the_estimate() {
+        let (tracker, sent) = tracker(Stage::Droplets);
+        // 0.1 every 400ms
+        for fraction in [0.1, 0.2] {
+            std::thread::sleep(Duration::from_millis(400));
+            tracker.report(fraction);
+        }
+        tracker.announce(RunState::Paused);
+        std::thread::sleep(Duration::from_millis(1000));
+        tracker.announce(RunState::Resumed);
+        std::thread::sleep(Duration::from_millis(50));
+        tracker.report(0.3);
+        // 0.1 in about 50ms leaves well under a second; counting the pause would
+        // have made it over three
+        let eta = sent.lock().unwrap().last().unwrap()["etaSecs"].as_f64().unwrap();
+        assert!(eta < 1.5, "{eta}");
+    }
+}
@@ -1,12 +1,39 @@
+use std::collections::BTreeMap;
+use std::fs::File;
 use std::io::{Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 use zip::write::SimpleFileOptions;
 use zip::{ZipWriter, ZipArchive, CompressionMethod};
 use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::canvas;
+use crate::detail::{self, DetailPatch, DetailPatchInfo};
+use crate::erosion::hydraulic::{ErosionMapKind, ErosionMaps};
+use crate::float_image;
 use crate::heightmap::Heightmap;
+use crate::i18n::{self, Text};
+use crate::mask::{MaskChannel, MaskSet};
+use crate::noise_gen::Frame;
+use crate::provenance::OperationLog;
+use crate::safety::{Checkpoint, DestructiveOp, TileDiff};
+use crate::usage::UsageStats;
+use crate::world::WorldScale;
 
-const FORMAT_VERSION: u32 = 1;
+/// 2 added mask channels and erosion maps. Version 1 projects load without them.
+/// 3 added the chunked heightmap of incremental saves.
+/// 4 added the water mask channel, which older versions can't parse.
+/// 5 added detail patches and left out the chunk hashes.
+/// 6 keeps checkpoints as tile diffs along with their masks, canvas frame and
+/// detail patches, and saves the document's canvas frame.
+const FORMAT_VERSION: u32 = 6;
+/// Edge in pixels of the heightmap chunks incremental saves write.
+const CHUNK_SIZE: u32 = 256;
+/// Most a JSON entry (manifest, settings, history) is read up to.
+const MAX_JSON_BYTES: u64 = 64 << 20;
+/// Most texture.png is read up to: an RGBA image at the largest canvas size,
+/// with room for PNG's framing.
+const MAX_TEXTURE_BYTES: u64 = (canvas::MAX_CANVAS_SIZE as u64).pow(2) * 4 + (64 << 20);
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +44,160 @@ struct ProjectManifest {
     height: u32,
     created_at: u64,
     has_texture: bool,
+    #[serde(default)]
+    world_scale: WorldScale,
+    #[serde(default)]
+    boundary: Boundary,
+    /// Only written while usage tracking is on.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    usage: Option<UsageStats>,
+    /// Checkpoints saved along, oldest first; each one's heightmap tiles are in
+    /// `history/{index}.bin`, the rest under `history/{index}/`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    history: Vec<HistoryRecord>,
+    /// Mask channels saved in `masks/{channel}.bin`, at the heightmap's size.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    masks: Vec<MaskChannel>,
+    /// Whether the last hydraulic run's maps are saved in `maps/{kind}.bin`, at
+    /// the heightmap's size.
+    #[serde(default)]
+    erosion_maps: bool,
+    /// Detail patches in the order they were added; each one's residual is in
+    /// `detail/{index}.bin`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    detail_patches: Vec<DetailPatchInfo>,
+    /// Noise space of an expanded canvas, so generating keeps matching the map
+    /// it grew from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    canvas_frame: Option<Frame>,
+    /// Set when the heightmap is stored in chunks instead of `heightmap.bin`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    chunks: Option<ChunkGrid>,
+    #[serde(default, skip_serializing_if = "ProjectMetadata::is_empty")]
+    metadata: ProjectMetadata,
+    /// CRC-32 of every other entry, by name. Projects saved before checksums
+    /// have none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    checksums: BTreeMap<String, u32>,
+}
+
+/// What the user wrote about the project, to find it again in a library of
+/// terrains.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProjectMetadata {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl ProjectMetadata {
+    /// Trim the fields and drop empty and repeated tags, keeping their order.
+    pub fn normalized(self) -> Self {
+        let mut tags: Vec<String> = Vec::with_capacity(self.tags.len());
+        for tag in self.tags {
+            let tag = tag.trim();
+            if !tag.is_empty() && !tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                tags.push(tag.to_string());
+            }
+        }
+        Self {
+            name: self.name.trim().to_string(),
+            author: self.author.trim().to_string(),
+            description: self.description.trim().to_string(),
+            tags,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+/// The heightmap split into `heightmap/{x}_{y}.bin` chunks, so the next save
+/// can copy the ones that didn't change instead of compressing them again.
+/// Files from before version 5 also list a content hash per chunk, which is
+/// ignored.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChunkGrid {
+    size: u32,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryRecord {
+    op: DestructiveOp,
+    created_at: u64,
+    width: u32,
+    height: u32,
+    /// Heightmap tiles stored, where they differ from the next newer
+    /// checkpoint's; all of them when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tiles: Option<Vec<u32>>,
+    /// Mask channels, each in `history/{index}/{channel}.bin`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    masks: Vec<HistoryMask>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    canvas_frame: Option<Frame>,
+    /// Each one's residual is in `history/{index}/detail/{patch}.bin`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    detail_patches: Vec<DetailPatchInfo>,
+}
+
+/// A checkpoint's mask channel, stored like its heightmap.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HistoryMask {
+    channel: MaskChannel,
+    width: u32,
+    height: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    tiles: Option<Vec<u32>>,
+}
+
+/// What opening a damaged project cost. Lost heightmap samples are set to 0.
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamageReport {
+    /// Heightmap areas that couldn't be read.
+    pub lost: Vec<LostRegion>,
+    /// Heightmap entries read in full that fail their checksum, so any of
+    /// their samples may be wrong.
+    pub suspect: Vec<DamagedEntry>,
+    /// Damaged texture, settings, checkpoints, masks, erosion maps, detail
+    /// patches or operation history, left out.
+    pub dropped: Vec<DamagedEntry>,
+}
+
+impl DamageReport {
+    fn is_empty(&self) -> bool {
+        self.lost.is_empty() && self.suspect.is_empty() && self.dropped.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DamagedEntry {
+    pub entry: String,
+    pub reason: String,
+}
+
+/// Pixels of the heightmap lost with a damaged entry.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LostRegion {
+    pub entry: String,
+    pub reason: String,
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
 }
 
 #[derive(Debug, Serialize)]
@@ -24,28 +205,239 @@ struct ProjectManifest {
 pub struct LoadProjectResponse {
     pub texture_png: Option<Vec<u8>>,
     pub settings_json: String,
+    pub world_scale: WorldScale,
+    pub boundary: Boundary,
+    pub metadata: ProjectMetadata,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub damage: Option<DamageReport>,
+}
+
+/// Everything read back from a .topo file, or the open document copied for
+/// writing one.
+pub struct LoadedProject {
+    pub heightmap: Heightmap,
+    pub texture_png: Option<Vec<u8>>,
+    pub settings_json: String,
+    pub world_scale: WorldScale,
+    pub boundary: Boundary,
+    pub usage: Option<UsageStats>,
+    /// Saved checkpoints, oldest first. A damaged one is left out along with
+    /// the older one kept against it.
+    pub history: Vec<Checkpoint>,
+    pub masks: MaskSet,
+    pub erosion_maps: Option<ErosionMaps>,
+    pub detail_patches: Vec<DetailPatch>,
+    pub canvas_frame: Option<Frame>,
+    pub metadata: ProjectMetadata,
+    pub operations: OperationLog,
+    /// Set if the file was damaged and only partly read.
+    pub damage: Option<DamageReport>,
 }
 
-pub fn save_project(
+/// Everything written to a .topo file.
+pub struct ProjectContents<'a> {
+    pub heightmap: &'a Heightmap,
+    pub texture_png: Option<&'a [u8]>,
+    pub settings_json: &'a str,
+    pub world_scale: &'a WorldScale,
+    pub boundary: Boundary,
+    pub usage: Option<&'a UsageStats>,
+    /// Checkpoints to keep with the project, oldest first; empty to leave the
+    /// history out.
+    pub history: Vec<&'a Checkpoint>,
+    /// Channels that don't match the heightmap's size are left out, as they'd
+    /// be recreated on the next edit anyway.
+    pub masks: Option<&'a MaskSet>,
+    /// Left out unless it matches the heightmap's size.
+    pub erosion_maps: Option<&'a ErosionMaps>,
+    /// Patches that no longer fit the heightmap are left out.
+    pub detail_patches: &'a [DetailPatch],
+    pub canvas_frame: Option<Frame>,
+    pub metadata: &'a ProjectMetadata,
+    /// Left out when `None` or empty.
+    pub operations: Option<&'a OperationLog>,
+}
+
+impl LoadedProject {
+    pub fn contents(&self) -> ProjectContents<'_> {
+        ProjectContents {
+            heightmap: &self.heightmap,
+            texture_png: self.texture_png.as_deref(),
+            settings_json: &self.settings_json,
+            world_scale: &self.world_scale,
+            boundary: self.boundary,
+            usage: self.usage.as_ref(),
+            history: self.history.iter().collect(),
+            masks: Some(&self.masks),
+            erosion_maps: self.erosion_maps.as_ref(),
+            detail_patches: &self.detail_patches,
+            canvas_frame: self.canvas_frame,
+            metadata: &self.metadata,
+            operations: Some(&self.operations),
+        }
+    }
+}
+
+/// Save into a temporary file next to `path` and move it over `path` once
+/// complete, so a failed save leaves the previous file intact. `incremental`
+/// stores the heightmap in chunks, copying the ones unchanged since the
+/// previous file at `path` as they are. `progress` gets the fraction written.
+pub fn save_project(path: &Path, contents: &ProjectContents, incremental: bool, progress: &dyn Fn(f32)) -> Result<(), String> {
+    let mut partial = path.as_os_str().to_owned();
+    partial.push(".partial");
+    let partial = PathBuf::from(partial);
+    let result = write_project(&partial, path, contents, incremental, progress)
+        .and_then(|()| std::fs::rename(&partial, path).map_err(|e| format!("Failed to replace {}: {e}", path.display())));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&partial);
+    }
+    result
+}
+
+/// Progress through `total` bytes, reported at most once per percent.
+struct Tally<'a> {
+    done: u64,
+    total: u64,
+    reported: f32,
+    progress: &'a dyn Fn(f32),
+}
+
+impl<'a> Tally<'a> {
+    fn new(total: u64, progress: &'a dyn Fn(f32)) -> Self {
+        progress(0.0);
+        Self { done: 0, total, reported: 0.0, progress }
+    }
+
+    fn add(&mut self, bytes: u64) {
+        self.done += bytes;
+        let fraction = (self.done as f64 / self.total.max(1) as f64).min(1.0) as f32;
+        if fraction >= self.reported + 0.01 {
+            self.reported = fraction;
+            (self.progress)(fraction);
+        }
+    }
+
+    fn finish(&mut self) {
+        if self.reported < 1.0 {
+            self.reported = 1.0;
+            (self.progress)(1.0);
+        }
+    }
+}
+
+/// Entries written so far: their checksums, and progress through them.
+struct Written<'a> {
+    checksums: BTreeMap<String, u32>,
+    tally: Tally<'a>,
+}
+
+/// What reading entries checks them against, and progress through them.
+struct Reading<'a> {
+    checksums: &'a BTreeMap<String, u32>,
+    tally: Tally<'a>,
+}
+
+/// Write the project into `path`, copying unchanged chunks from the file at
+/// `previous`.
+fn write_project(
     path: &Path,
-    heightmap: &Heightmap,
-    texture_png: Option<&[u8]>,
-    settings_json: &str,
+    previous: &Path,
+    contents: &ProjectContents,
+    incremental: bool,
+    progress: &dyn Fn(f32),
 ) -> Result<(), String> {
-    let file = std::fs::File::create(path)
+    let ProjectContents { heightmap, texture_png, settings_json, world_scale, boundary, usage, .. } = *contents;
+    let masks: Vec<(MaskChannel, &Heightmap)> = MaskChannel::ALL
+        .into_iter()
+        .filter_map(|channel| Some((channel, contents.masks?.get(channel)?)))
+        .filter(|(_, mask)| (mask.width, mask.height) == (heightmap.width, heightmap.height))
+        .collect();
+    let erosion_maps = contents.erosion_maps.filter(|maps| maps.dimensions() == (heightmap.width, heightmap.height));
+    let detail_patches: Vec<&DetailPatch> = contents.detail_patches.iter().filter(|patch| patch.fits(heightmap)).collect();
+    let chunks = incremental.then_some(ChunkGrid { size: CHUNK_SIZE });
+    let file = File::create(path)
         .map_err(|e| format!("Failed to create file: {e}"))?;
     let mut zip = ZipWriter::new(file);
     let deflate = SimpleFileOptions::default()
         .compression_method(CompressionMethod::Deflated);
     let stored = SimpleFileOptions::default()
         .compression_method(CompressionMethod::Stored);
+    // Measured in the bytes of the entries; the manifest and history.json are
+    // small enough to leave out
+    let maps = [Some(heightmap)]
+        .into_iter()
+        .chain(masks.iter().map(|&(_, mask)| Some(mask)))
+        .chain(ErosionMapKind::ALL.into_iter().map(|kind| erosion_maps.map(|maps| maps.get(kind))))
+        .chain(detail_patches.iter().map(|patch| Some(&patch.residual)))
+        .flatten();
+    let history_samples = contents.history.iter().flat_map(|c| {
+        let tiles = c.heightmap.tiles.iter().chain(c.masks.iter().flat_map(|(_, diff)| &diff.tiles));
+        tiles.map(|(_, samples)| samples.len()).chain(c.detail_patches.iter().map(|patch| patch.residual.data.len()))
+    });
+    let total = maps.map(|m| m.data.len() as u64 * 4).sum::<u64>()
+        + history_samples.map(|n| n as u64 * 4).sum::<u64>()
+        + texture_png.map_or(0, |png| png.len() as u64)
+        + settings_json.len() as u64;
+    let mut written = Written { checksums: BTreeMap::new(), tally: Tally::new(total, progress) };
 
     let timestamp = SystemTime::now()
         .duration_since(SystemTime::UNIX_EPOCH)
         .unwrap_or_default()
         .as_secs();
 
-    // 1. manifest.json
+    // 1. heightmap.bin, or heightmap/{x}_{y}.bin chunks (raw f32 LE)
+    match &chunks {
+        Some(grid) => {
+            let previous = previous_chunks(previous, heightmap.width, heightmap.height, grid);
+            write_chunks(&mut zip, &mut written, heightmap, grid, previous, deflate)?
+        }
+        None => write_heightmap(&mut zip, &mut written, "heightmap.bin", heightmap, deflate)?,
+    }
+
+    // 2. texture.png (optional, already compressed)
+    if let Some(png_data) = texture_png {
+        write_entry(&mut zip, &mut written, "texture.png", png_data, stored)?;
+    }
+
+    // 3. settings.json
+    write_entry(&mut zip, &mut written, "settings.json", settings_json.as_bytes(), deflate)?;
+
+    // 4. history/{index}.bin and history/{index}/ (optional, raw f32 LE)
+    for (i, checkpoint) in contents.history.iter().enumerate() {
+        write_tiles(&mut zip, &mut written, &format!("history/{i}.bin"), &checkpoint.heightmap, deflate)?;
+        for (channel, diff) in &checkpoint.masks {
+            write_tiles(&mut zip, &mut written, &format!("history/{i}/{}.bin", channel.id()), diff, deflate)?;
+        }
+        for (j, patch) in checkpoint.detail_patches.iter().enumerate() {
+            write_heightmap(&mut zip, &mut written, &format!("history/{i}/detail/{j}.bin"), &patch.residual, deflate)?;
+        }
+    }
+
+    // 5. masks/{channel}.bin (optional, raw f32 LE)
+    for (channel, mask) in &masks {
+        write_heightmap(&mut zip, &mut written, &format!("masks/{}.bin", channel.id()), mask, deflate)?;
+    }
+
+    // 6. maps/{kind}.bin (optional, raw f32 LE)
+    if let Some(maps) = erosion_maps {
+        for kind in ErosionMapKind::ALL {
+            write_heightmap(&mut zip, &mut written, &format!("maps/{}.bin", kind.id()), maps.get(kind), deflate)?;
+        }
+    }
+
+    // 7. detail/{index}.bin (optional, raw f32 LE)
+    for (i, patch) in detail_patches.iter().enumerate() {
+        write_heightmap(&mut zip, &mut written, &format!("detail/{i}.bin"), &patch.residual, deflate)?;
+    }
+
+    // 8. history.json (optional)
+    if let Some(operations) = contents.operations.filter(|log| !log.is_empty()) {
+        let json = serde_json::to_string_pretty(operations)
+            .map_err(|e| format!("Failed to serialize operation history: {e}"))?;
+        write_entry(&mut zip, &mut written, "history.json", json.as_bytes(), deflate)?;
+    }
+
+    // 9. manifest.json, last so it can list the other entries' checksums
     let manifest = ProjectManifest {
         format_version: FORMAT_VERSION,
         app_version: env!("CARGO_PKG_VERSION").to_string(),
@@ -53,6 +445,39 @@ pub fn save_project(
         height: heightmap.height,
         created_at: timestamp,
         has_texture: texture_png.is_some(),
+        world_scale: world_scale.clone(),
+        boundary,
+        usage: usage.filter(|u| u.enabled).cloned(),
+        history: contents
+            .history
+            .iter()
+            .map(|c| HistoryRecord {
+                op: c.op,
+                created_at: c.created_at,
+                width: c.heightmap.width,
+                height: c.heightmap.height,
+                tiles: tile_indices(&c.heightmap),
+                masks: c
+                    .masks
+                    .iter()
+                    .map(|(channel, diff)| HistoryMask {
+                        channel: *channel,
+                        width: diff.width,
+                        height: diff.height,
+                        tiles: tile_indices(diff),
+                    })
+                    .collect(),
+                canvas_frame: c.canvas_frame,
+                detail_patches: c.detail_patches.iter().map(|patch| patch.info()).collect(),
+            })
+            .collect(),
+        masks: masks.iter().map(|&(channel, _)| channel).collect(),
+        erosion_maps: erosion_maps.is_some(),
+        detail_patches: detail_patches.iter().map(|patch| patch.info()).collect(),
+        canvas_frame: contents.canvas_frame,
+        chunks,
+        metadata: contents.metadata.clone(),
+        checksums: written.checksums,
     };
     let manifest_json = serde_json::to_string_pretty(&manifest)
         .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
@@ -61,33 +486,16 @@ pub fn save_project(
     zip.write_all(manifest_json.as_bytes())
         .map_err(|e| format!("Write error: {e}"))?;
 
-    // 2. heightmap.bin (raw f32 LE)
-    zip.start_file("heightmap.bin", deflate)
-        .map_err(|e| format!("ZIP error: {e}"))?;
-    for &val in &heightmap.data {
-        zip.write_all(&val.to_le_bytes())
-            .map_err(|e| format!("Write error: {e}"))?;
-    }
-
-    // 3. texture.png (optional, already compressed)
-    if let Some(png_data) = texture_png {
-        zip.start_file("texture.png", stored)
-            .map_err(|e| format!("ZIP error: {e}"))?;
-        zip.write_all(png_data)
-            .map_err(|e| format!("Write error: {e}"))?;
-    }
-
-    // 4. settings.json
-    zip.start_file("settings.json", deflate)
-        .map_err(|e| format!("ZIP error: {e}"))?;
-    zip.write_all(settings_json.as_bytes())
-        .map_err(|e| format!("Write error: {e}"))?;
-
     zip.finish().map_err(|e| format!("ZIP finish error: {e}"))?;
+    written.tally.finish();
     Ok(())
 }
 
-pub fn load_project(path: &Path) -> Result<(Heightmap, Option<Vec<u8>>, String), String> {
+/// Load the project at `path`. A heightmap entry that is cut short or fails to
+/// read is recovered as far as it goes, and entries that don't match their
+/// checksum are kept or left out; `LoadedProject::damage` says which.
+/// `progress` gets the fraction read.
+pub fn load_project(path: &Path, progress: &dyn Fn(f32)) -> Result<LoadedProject, String> {
     let file = std::fs::File::open(path)
         .map_err(|e| format!("Failed to open file: {e}"))?;
     let mut zip = ZipArchive::new(file)
@@ -95,10 +503,10 @@ pub fn load_project(path: &Path) -> Result<(Heightmap, Option<Vec<u8>>, String),
 
     // 1. Read manifest
     let manifest: ProjectManifest = {
-        let mut entry = zip.by_name("manifest.json")
+        let entry = zip.by_name("manifest.json")
             .map_err(|_| "Missing manifest.json in .topo file".to_string())?;
         let mut buf = String::new();
-        entry.read_to_string(&mut buf)
+        entry.take(MAX_JSON_BYTES).read_to_string(&mut buf)
             .map_err(|e| format!("Read error: {e}"))?;
         serde_json::from_str(&buf)
             .map_err(|e| format!("Invalid manifest: {e}"))?
@@ -110,57 +518,530 @@ pub fn load_project(path: &Path) -> Result<(Heightmap, Option<Vec<u8>>, String),
             manifest.format_version, FORMAT_VERSION
         ));
     }
+    check_size(manifest.width, manifest.height)?;
+    let total = (0..zip.len()).filter_map(|i| zip.by_index_raw(i).ok().map(|entry| entry.size())).sum();
+    let mut reading = Reading { checksums: &manifest.checksums, tally: Tally::new(total, progress) };
+    let mut damage = DamageReport::default();
 
-    // 2. Read heightmap.bin
-    let heightmap = {
-        let mut entry = zip.by_name("heightmap.bin")
-            .map_err(|_| "Missing heightmap.bin in .topo file".to_string())?;
-        let mut bytes = Vec::new();
-        entry.read_to_end(&mut bytes)
-            .map_err(|e| format!("Read error: {e}"))?;
-
-        let expected = (manifest.width * manifest.height) as usize * 4;
-        if bytes.len() != expected {
-            return Err(format!(
-                "Heightmap size mismatch: got {} bytes, expected {expected}",
-                bytes.len()
-            ));
+    // 2. Read heightmap.bin, or its chunks
+    let heightmap = match &manifest.chunks {
+        Some(grid) => read_chunks(&mut zip, &mut reading, manifest.width, manifest.height, grid, &mut damage)?,
+        None => {
+            let mut heightmap = Heightmap::new(manifest.width, manifest.height);
+            let area = (0, 0, manifest.width, manifest.height);
+            recover_area(&mut zip, &mut reading, "heightmap.bin", &mut heightmap, area, &mut damage)?;
+            heightmap
         }
-
-        let data: Vec<f32> = bytes.chunks_exact(4)
-            .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
-            .collect();
-
-        Heightmap { data, width: manifest.width, height: manifest.height }
     };
 
     // 3. Read texture.png (optional)
     let texture_png = if manifest.has_texture {
-        match zip.by_name("texture.png") {
-            Ok(mut entry) => {
-                let mut buf = Vec::new();
-                entry.read_to_end(&mut buf)
-                    .map_err(|e| format!("Read error: {e}"))?;
-                Some(buf)
-            }
-            Err(_) => None,
-        }
+        read_optional(&mut zip, &mut reading, "texture.png", MAX_TEXTURE_BYTES, &mut damage)
     } else {
         None
     };
 
     // 4. Read settings.json
-    let settings_json = match zip.by_name("settings.json") {
-        Ok(mut entry) => {
-            let mut buf = String::new();
-            entry.read_to_string(&mut buf)
-                .map_err(|e| format!("Read error: {e}"))?;
-            buf
-        }
-        Err(_) => "{}".to_string(),
+    let settings_json = read_optional(&mut zip, &mut reading, "settings.json", MAX_JSON_BYTES, &mut damage)
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .unwrap_or_else(|| "{}".to_string());
+
+    // 5. Read history/{index}.bin and history/{index}/ (optional), newest
+    // first, as each checkpoint is kept against the next newer one. After a
+    // damaged one, only checkpoints stored in full can be read.
+    let mut history = Vec::new();
+    let mut newer = None;
+    for (i, record) in manifest.history.iter().enumerate().rev() {
+        let checkpoint = read_checkpoint(&mut zip, &mut reading, i, record, newer);
+        newer = drop_damaged(&format!("history/{i}.bin"), checkpoint, &mut damage).map(|checkpoint| {
+            history.push(checkpoint);
+            record
+        });
+    }
+    history.reverse();
+
+    // 6. Read masks/{channel}.bin (optional)
+    let (width, height) = (manifest.width, manifest.height);
+    let mut masks = MaskSet::default();
+    for &channel in &manifest.masks {
+        let name = format!("masks/{}.bin", channel.id());
+        if let Some(mask) = drop_damaged(&name, read_heightmap(&mut zip, &mut reading, &name, width, height), &mut damage) {
+            masks.insert(channel, mask);
+        }
+    }
+
+    // 7. Read maps/{kind}.bin (optional)
+    let erosion_maps = if manifest.erosion_maps {
+        let mut layers = Vec::with_capacity(ErosionMapKind::ALL.len());
+        for kind in ErosionMapKind::ALL {
+            let name = format!("maps/{}.bin", kind.id());
+            layers.extend(drop_damaged(&name, read_heightmap(&mut zip, &mut reading, &name, width, height), &mut damage));
+        }
+        // The maps only make sense together
+        let layers: Option<[Heightmap; 4]> = layers.try_into().ok();
+        layers.map(ErosionMaps::from_layers)
+    } else {
+        None
+    };
+
+    // 8. Read detail/{index}.bin (optional)
+    let detail_patches = manifest
+        .detail_patches
+        .iter()
+        .enumerate()
+        .filter_map(|(i, &info)| {
+            let name = format!("detail/{i}.bin");
+            let (w, h) = detail::residual_size(info.w, info.h, info.factor);
+            let patch = read_heightmap(&mut zip, &mut reading, &name, w, h)
+                .and_then(|residual| DetailPatch::from_parts(info, residual))
+                .and_then(|patch| {
+                    if patch.fits(&heightmap) {
+                        Ok(patch)
+                    } else {
+                        Err("Detail patch lies outside the map".to_string())
+                    }
+                });
+            drop_damaged(&name, patch, &mut damage)
+        })
+        .collect();
+
+    // 9. Read history.json (optional)
+    let operations = read_optional(&mut zip, &mut reading, "history.json", MAX_JSON_BYTES, &mut damage)
+        .map(|bytes| serde_json::from_slice(&bytes).map_err(|e| format!("Invalid operation history: {e}")))
+        .and_then(|log| drop_damaged("history.json", log, &mut damage))
+        .unwrap_or_default();
+
+    reading.tally.finish();
+    Ok(LoadedProject {
+        heightmap,
+        texture_png,
+        settings_json,
+        world_scale: manifest.world_scale,
+        boundary: manifest.boundary,
+        usage: manifest.usage,
+        history,
+        masks,
+        erosion_maps,
+        detail_patches,
+        canvas_frame: manifest.canvas_frame,
+        metadata: manifest.metadata,
+        operations,
+        damage: (!damage.is_empty()).then_some(damage),
+    })
+}
+
+/// Write entry `name`, noting its CRC-32 for the manifest.
+fn write_entry<W: Write + std::io::Seek>(
+    zip: &mut ZipWriter<W>,
+    written: &mut Written,
+    name: &str,
+    bytes: &[u8],
+    options: SimpleFileOptions,
+) -> Result<(), String> {
+    zip.start_file(name, options)
+        .map_err(|e| format!("ZIP error: {e}"))?;
+    zip.write_all(bytes)
+        .map_err(|e| format!("Write error: {e}"))?;
+    written.checksums.insert(name.to_string(), crc32fast::hash(bytes));
+    written.tally.add(bytes.len() as u64);
+    Ok(())
+}
+
+fn write_heightmap<W: Write + std::io::Seek>(
+    zip: &mut ZipWriter<W>,
+    written: &mut Written,
+    name: &str,
+    heightmap: &Heightmap,
+    options: SimpleFileOptions,
+) -> Result<(), String> {
+    zip.start_file(name, options)
+        .map_err(|e| format!("ZIP error: {e}"))?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut bytes = Vec::with_capacity(heightmap.width as usize * 4);
+    for row in heightmap.data.chunks(heightmap.width.max(1) as usize) {
+        bytes.clear();
+        for &val in row {
+            bytes.extend_from_slice(&val.to_le_bytes());
+        }
+        hasher.update(&bytes);
+        zip.write_all(&bytes)
+            .map_err(|e| format!("Write error: {e}"))?;
+        written.tally.add(bytes.len() as u64);
+    }
+    written.checksums.insert(name.to_string(), hasher.finalize());
+    Ok(())
+}
+
+/// Indices of the tiles `diff` holds, or `None` for all of them.
+fn tile_indices(diff: &TileDiff) -> Option<Vec<u32>> {
+    (!diff.is_full()).then(|| diff.tiles.iter().map(|&(i, _)| i).collect())
+}
+
+/// Write `diff`'s tiles one after another.
+fn write_tiles<W: Write + std::io::Seek>(
+    zip: &mut ZipWriter<W>,
+    written: &mut Written,
+    name: &str,
+    diff: &TileDiff,
+    options: SimpleFileOptions,
+) -> Result<(), String> {
+    let bytes: Vec<u8> = diff.tiles.iter().flat_map(|(_, samples)| samples).flat_map(|val| val.to_le_bytes()).collect();
+    write_entry(zip, written, name, &bytes, options)
+}
+
+/// Chunk `(x, y)` of `grid`, clipped to the map: its left, top, width and height.
+fn chunk_rect(width: u32, height: u32, size: u32, x: u32, y: u32) -> (u32, u32, u32, u32) {
+    let (x0, y0) = (x * size, y * size);
+    (x0, y0, size.min(width - x0), size.min(height - y0))
+}
+
+/// The previous file's archive, if it has chunks laid out like `grid` for a
+/// map of the same size.
+fn previous_chunks(path: &Path, width: u32, height: u32, grid: &ChunkGrid) -> Option<ZipArchive<File>> {
+    let mut zip = ZipArchive::new(File::open(path).ok()?).ok()?;
+    let previous: ProjectManifest = serde_json::from_reader(zip.by_name("manifest.json").ok()?).ok()?;
+    let previous_grid = previous.chunks?;
+    let same = (previous.width, previous.height) == (width, height) && previous_grid.size == grid.size;
+    same.then_some(zip)
+}
+
+fn write_chunks<W: Write + std::io::Seek>(
+    zip: &mut ZipWriter<W>,
+    written: &mut Written,
+    heightmap: &Heightmap,
+    grid: &ChunkGrid,
+    mut previous: Option<ZipArchive<File>>,
+    options: SimpleFileOptions,
+) -> Result<(), String> {
+    let (columns, rows) = (heightmap.width.div_ceil(grid.size), heightmap.height.div_ceil(grid.size));
+    for (x, y) in (0..rows).flat_map(|y| (0..columns).map(move |x| (x, y))) {
+        let name = format!("heightmap/{x}_{y}.bin");
+        let (x0, y0, w, h) = chunk_rect(heightmap.width, heightmap.height, grid.size, x, y);
+        let mut bytes = Vec::with_capacity((w * h) as usize * 4);
+        for row in y0..y0 + h {
+            let start = (row * heightmap.width + x0) as usize;
+            for &val in &heightmap.data[start..start + w as usize] {
+                bytes.extend_from_slice(&val.to_le_bytes());
+            }
+        }
+
+        // A chunk whose length and CRC-32 match the previous file's is copied
+        // still compressed; anything else is written again
+        let crc = crc32fast::hash(&bytes);
+        if let Some(archive) = previous.as_mut() {
+            let entry = archive.index_for_name(&name).and_then(|index| archive.by_index_raw(index).ok());
+            if let Some(entry) = entry.filter(|entry| entry.crc32() == crc && entry.size() == bytes.len() as u64) {
+                written.checksums.insert(name, crc);
+                written.tally.add(entry.size());
+                zip.raw_copy_file(entry).map_err(|e| format!("ZIP error: {e}"))?;
+                continue;
+            }
+        }
+        write_entry(zip, written, &name, &bytes, options)?;
+    }
+    Ok(())
+}
+
+/// Read the heightmap's chunks; a missing or damaged chunk costs its own area
+/// rather than the whole map.
+fn read_chunks<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    width: u32,
+    height: u32,
+    grid: &ChunkGrid,
+    damage: &mut DamageReport,
+) -> Result<Heightmap, String> {
+    let (columns, rows) = (width.div_ceil(grid.size.max(1)), height.div_ceil(grid.size.max(1)));
+    // More chunks than the archive has entries would be a damaged manifest
+    if grid.size == 0 || (columns * rows) as usize > zip.len() {
+        return Err(format!("Heightmap chunk grid doesn't fit a {width}x{height} map"));
+    }
+    let mut heightmap = Heightmap::new(width, height);
+    for y in 0..rows {
+        for x in 0..columns {
+            let name = format!("heightmap/{x}_{y}.bin");
+            let (x0, y0, w, h) = chunk_rect(width, height, grid.size, x, y);
+            if let Err(reason) = recover_area(zip, reading, &name, &mut heightmap, (x0, y0, w, h), damage) {
+                damage.lost.push(LostRegion { entry: name, reason, x: x0, y: y0, width: w, height: h });
+            }
+        }
+    }
+    Ok(heightmap)
+}
+
+/// Refuse a map size read from the file before anything is allocated for it, so
+/// a damaged manifest can't ask for more memory than there is.
+fn check_size(width: u32, height: u32) -> Result<(), String> {
+    let limit = canvas::MAX_CANVAS_SIZE;
+    if width == 0 || height == 0 || width > limit || height > limit {
+        return Err(format!("Map size {width}x{height} is outside 1x1 to {limit}x{limit}"));
+    }
+    Ok(())
+}
+
+/// Entry `name` as far as it could be read, up to `limit` bytes, with what's
+/// wrong with it if the read failed partway, it runs past `limit` or it doesn't
+/// match the manifest's checksum. Files from before checksums rely on the
+/// archive's own check. The limit comes from what the entry should hold, not
+/// the size the archive claims for it.
+fn read_entry<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    name: &str,
+    limit: u64,
+) -> Result<(Vec<u8>, Option<String>), String> {
+    let entry = zip.by_name(name)
+        .map_err(|_| format!("Missing {name} in .topo file"))?;
+    // One byte past the limit tells an oversized entry from one that fits exactly
+    let mut entry = entry.take(limit.saturating_add(1));
+    let mut bytes = Vec::new();
+    let mut buffer = [0; 1 << 16];
+    // Bytes read before an error are kept in `bytes`
+    let mut problem = None;
+    loop {
+        match entry.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                bytes.extend_from_slice(&buffer[..n]);
+                reading.tally.add(n as u64);
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+            Err(e) => {
+                problem = Some(format!("Read error: {e}"));
+                break;
+            }
+        }
+    }
+    if bytes.len() as u64 > limit {
+        bytes.truncate(limit as usize);
+        problem.get_or_insert_with(|| format!("Entry is larger than {limit} bytes"));
+    }
+    let problem = problem.or_else(|| {
+        let expected = *reading.checksums.get(name)?;
+        (crc32fast::hash(&bytes) != expected).then(|| "Checksum mismatch".to_string())
+    });
+    Ok((bytes, problem))
+}
+
+/// Read `name` into the `(x, y, width, height)` area of `heightmap`. What
+/// can't be read of a damaged entry is left at 0 and noted in `damage`, along
+/// with entries read in full that fail their checksum. Errs only if the entry
+/// is missing.
+fn recover_area<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    name: &str,
+    heightmap: &mut Heightmap,
+    (x0, y0, w, h): (u32, u32, u32, u32),
+    damage: &mut DamageReport,
+) -> Result<(), String> {
+    let samples = w as usize * h as usize;
+    let (bytes, problem) = read_entry(zip, reading, name, samples as u64 * 4)?;
+    let intact = samples.min(bytes.len() / 4);
+    for (i, c) in bytes[..intact * 4].chunks_exact(4).enumerate() {
+        let (x, y) = (x0 + i as u32 % w, y0 + i as u32 / w);
+        heightmap.data[(y * heightmap.width + x) as usize] = f32::from_le_bytes([c[0], c[1], c[2], c[3]]);
+    }
+
+    let size = (bytes.len() != samples * 4).then(|| format!("got {} bytes, expected {}", bytes.len(), samples * 4));
+    let reason = match (problem, size) {
+        (Some(problem), Some(size)) => format!("{problem}; {size}"),
+        (Some(problem), None) => problem,
+        (None, Some(size)) => format!("Size mismatch: {size}"),
+        (None, None) => return Ok(()),
+    };
+    if intact == samples {
+        damage.suspect.push(DamagedEntry { entry: name.to_string(), reason });
+        return Ok(());
+    }
+    // The first lost sample's row, if it doesn't start at its left edge, then
+    // every row below it
+    let (x, y) = (intact as u32 % w, intact as u32 / w);
+    if x > 0 {
+        damage.lost.push(LostRegion { entry: name.to_string(), reason: reason.clone(), x: x0 + x, y: y0 + y, width: w - x, height: 1 });
+    }
+    let y = y + u32::from(x > 0);
+    if y < h {
+        damage.lost.push(LostRegion { entry: name.to_string(), reason, x: x0, y: y0 + y, width: w, height: h - y });
+    }
+    Ok(())
+}
+
+/// Optional entry `name` of at most `limit` bytes, or `None`, noted in
+/// `damage`, if it's damaged.
+fn read_optional<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    name: &str,
+    limit: u64,
+    damage: &mut DamageReport,
+) -> Option<Vec<u8>> {
+    let (bytes, problem) = read_entry(zip, reading, name, limit).ok()?;
+    let bytes: Result<_, String> = match problem {
+        Some(reason) => Err(reason),
+        None => Ok(bytes),
+    };
+    drop_damaged(name, bytes, damage)
+}
+
+/// `read`'s value, or `None` with the entry noted in `damage` as left out.
+fn drop_damaged<T>(name: &str, read: Result<T, String>, damage: &mut DamageReport) -> Option<T> {
+    read.map_err(|reason| damage.dropped.push(DamagedEntry { entry: name.to_string(), reason })).ok()
+}
+
+/// Entry `name` as a `width` x `height` heightmap, which must be intact.
+fn read_heightmap<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    name: &str,
+    width: u32,
+    height: u32,
+) -> Result<Heightmap, String> {
+    check_size(width, height)?;
+    let expected = (width as usize)
+        .checked_mul(height as usize)
+        .and_then(|samples| samples.checked_mul(4))
+        .ok_or_else(|| format!("Heightmap size {width}x{height} in {name} is too large"))?;
+    let (bytes, problem) = read_entry(zip, reading, name, expected as u64)?;
+    if let Some(problem) = problem {
+        return Err(problem);
+    }
+    if bytes.len() != expected {
+        return Err(format!(
+            "Heightmap size mismatch in {name}: got {} bytes, expected {expected}",
+            bytes.len()
+        ));
+    }
+
+    let data: Vec<f32> = bytes.chunks_exact(4)
+        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect();
+
+    Ok(Heightmap { data, width, height })
+}
+
+/// Entry `name` as the tiles at `indices` of a `width` x `height` map, or all
+/// of them for `None`, which must be intact.
+fn read_tiles<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    name: &str,
+    (width, height): (u32, u32),
+    indices: Option<&[u32]>,
+) -> Result<TileDiff, String> {
+    check_size(width, height)?;
+    let expected = TileDiff::sample_count(width, height, indices)? * 4;
+    let (bytes, problem) = read_entry(zip, reading, name, expected as u64)?;
+    if let Some(problem) = problem {
+        return Err(problem);
+    }
+    if bytes.len() != expected {
+        return Err(format!("Size mismatch in {name}: got {} bytes, expected {expected}", bytes.len()));
+    }
+    let samples: Vec<f32> = bytes.chunks_exact(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect();
+    TileDiff::from_parts(width, height, indices, &samples)
+}
+
+/// Checkpoint `index`, whose diffs must fit `newer`, the next newer one's
+/// record, or be full.
+fn read_checkpoint<R: Read + std::io::Seek>(
+    zip: &mut ZipArchive<R>,
+    reading: &mut Reading,
+    index: usize,
+    record: &HistoryRecord,
+    newer: Option<&HistoryRecord>,
+) -> Result<Checkpoint, String> {
+    let heightmap = read_tiles(zip, reading, &format!("history/{index}.bin"), (record.width, record.height), record.tiles.as_deref())?;
+    let newer_size = newer.map(|newer| (newer.width, newer.height));
+    if !heightmap.is_full() && newer_size != Some((record.width, record.height)) {
+        return Err("Heightmap tiles don't fit the next checkpoint".to_string());
+    }
+    let mut masks = Vec::new();
+    for mask in &record.masks {
+        let name = format!("history/{index}/{}.bin", mask.channel.id());
+        let diff = read_tiles(zip, reading, &name, (mask.width, mask.height), mask.tiles.as_deref())?;
+        let newer_mask = newer.and_then(|newer| newer.masks.iter().find(|m| m.channel == mask.channel));
+        if !diff.is_full() && newer_mask.map(|m| (m.width, m.height)) != Some((mask.width, mask.height)) {
+            return Err(format!("Tiles of the {} mask don't fit the next checkpoint", mask.channel.id()));
+        }
+        masks.push((mask.channel, diff));
+    }
+    let mut detail_patches = Vec::new();
+    for (j, &info) in record.detail_patches.iter().enumerate() {
+        let (w, h) = detail::residual_size(info.w, info.h, info.factor);
+        let residual = read_heightmap(zip, reading, &format!("history/{index}/detail/{j}.bin"), w, h)?;
+        detail_patches.push(DetailPatch::from_parts(info, residual)?);
+    }
+    Ok(Checkpoint {
+        id: 0,
+        op: record.op,
+        created_at: record.created_at,
+        heightmap,
+        masks,
+        canvas_frame: record.canvas_frame,
+        detail_patches,
+    })
+}
+
+/// Decode a heightmap image at the precision it was stored with: 16-bit images
+/// keep all 65536 levels and float images their values, clamped to [0, 1], where
+/// going through 8-bit luma would leave 256 terraces. Color images are reduced to
+/// their luminance.
+pub fn decode_heightmap_image(bytes: &[u8]) -> Result<Heightmap, String> {
+    use image::DynamicImage;
+    let img = image::load_from_memory(bytes).map_err(|e| format!("Failed to decode heightmap image: {e}"))?;
+    let (width, height) = (img.width(), img.height());
+    let data: Vec<f32> = match img {
+        DynamicImage::ImageLuma16(_) | DynamicImage::ImageLumaA16(_) | DynamicImage::ImageRgb16(_) | DynamicImage::ImageRgba16(_) => {
+            img.to_luma16().pixels().map(|p| p.0[0] as f32 / 65535.0).collect()
+        }
+        DynamicImage::ImageRgb32F(_) | DynamicImage::ImageRgba32F(_) => {
+            img.to_luma32f().pixels().map(|p| p.0[0].clamp(0.0, 1.0)).collect()
+        }
+        _ => img.to_luma8().pixels().map(|p| p.0[0] as f32 / 255.0).collect(),
     };
+    Ok(Heightmap { data, width, height })
+}
+
+/// A heightmap export format as offered in save dialogs.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportFormatInfo {
+    /// Value for `export_heightmap`'s `format`.
+    pub id: &'static str,
+    /// Localized description.
+    pub name: &'static str,
+    pub extension: &'static str,
+}
 
-    Ok((heightmap, texture_png, settings_json))
+pub fn export_formats() -> Vec<ExportFormatInfo> {
+    vec![
+        ExportFormatInfo { id: "png16", name: i18n::tr(Text::Png16Format), extension: "png" },
+        ExportFormatInfo { id: "raw_f32", name: i18n::tr(Text::RawF32Format), extension: "bin" },
+        ExportFormatInfo { id: "exr", name: i18n::tr(Text::ExrFormat), extension: "exr" },
+        ExportFormatInfo { id: "tiff32", name: i18n::tr(Text::Tiff32Format), extension: "tif" },
+    ]
+}
+
+/// Write `heightmap` as export format `format`, an `ExportFormatInfo::id`.
+pub fn export_heightmap_as(path: &Path, heightmap: &Heightmap, format: &str) -> Result<(), String> {
+    match format {
+        "png16" => export_heightmap_png16(path, heightmap),
+        "raw_f32" => export_heightmap_raw(path, heightmap, &RawOptions::default()),
+        "exr" => float_image::write_exr(path, heightmap),
+        "tiff32" => float_image::write_tiff32(path, heightmap),
+        _ => Err(format!("Unknown export format: {format}")),
+    }
+}
+
+/// Extension for tiles and detail patches in `format`. Raw tiles use `r32`, as
+/// engines importing tiled landscapes expect.
+pub fn tile_extension(format: &str) -> Option<&'static str> {
+    match format {
+        "raw_f32" => Some("r32"),
+        _ => export_formats().into_iter().find(|f| f.id == format).map(|f| f.extension),
+    }
 }
 
 pub fn export_heightmap_png16(path: &Path, heightmap: &Heightmap) -> Result<(), String> {
@@ -178,12 +1059,495 @@ pub fn export_heightmap_png16(path: &Path, heightmap: &Heightmap) -> Result<(),
     Ok(())
 }
 
-pub fn export_heightmap_raw(path: &Path, heightmap: &Heightmap) -> Result<(), String> {
-    let bytes: Vec<u8> = heightmap.data.iter()
-        .flat_map(|v| v.to_le_bytes())
-        .collect();
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ByteOrder {
+    #[default]
+    Little,
+    Big,
+}
+
+/// Sample layout of a headerless raw heightmap.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RawOptions {
+    /// 8, 16 or 32.
+    pub bit_depth: u8,
+    /// IEEE floats as they are rather than unsigned integers spanning [0, 1];
+    /// 32-bit only.
+    pub float: bool,
+    pub byte_order: ByteOrder,
+    /// Rows run bottom up, for tools whose first row is the south edge.
+    pub flip_vertical: bool,
+}
+
+/// The `raw_f32` format.
+impl Default for RawOptions {
+    fn default() -> Self {
+        Self {
+            bit_depth: 32,
+            float: true,
+            byte_order: ByteOrder::Little,
+            flip_vertical: false,
+        }
+    }
+}
+
+impl RawOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if ![8, 16, 32].contains(&self.bit_depth) {
+            return Err("Raw bit depth must be 8, 16 or 32".into());
+        }
+        if self.float && self.bit_depth != 32 {
+            return Err("Float raw samples must be 32-bit".into());
+        }
+        Ok(())
+    }
+}
+
+/// Write `heightmap` as headerless samples laid out per `options`, row by row.
+pub fn export_heightmap_raw(path: &Path, heightmap: &Heightmap, options: &RawOptions) -> Result<(), String> {
+    options.validate()?;
+    let w = heightmap.width as usize;
+    let rows: Box<dyn Iterator<Item = &[f32]>> = if options.flip_vertical {
+        Box::new(heightmap.data.chunks_exact(w).rev())
+    } else {
+        Box::new(heightmap.data.chunks_exact(w))
+    };
+    let big = options.byte_order == ByteOrder::Big;
+    let mut bytes = Vec::with_capacity(heightmap.data.len() * options.bit_depth as usize / 8);
+    for &v in rows.flatten() {
+        let unit = v.clamp(0.0, 1.0) as f64;
+        match (options.bit_depth, options.float) {
+            (8, _) => bytes.push((unit * 255.0).round() as u8),
+            (16, _) => {
+                let sample = (unit * 65535.0).round() as u16;
+                bytes.extend_from_slice(&if big { sample.to_be_bytes() } else { sample.to_le_bytes() });
+            }
+            (_, true) => bytes.extend_from_slice(&if big { v.to_be_bytes() } else { v.to_le_bytes() }),
+            (_, false) => {
+                let sample = (unit * u32::MAX as f64).round() as u32;
+                bytes.extend_from_slice(&if big { sample.to_be_bytes() } else { sample.to_le_bytes() });
+            }
+        }
+    }
 
     std::fs::write(path, &bytes)
         .map_err(|e| format!("Failed to write raw file: {e}"))?;
     Ok(())
 }
+
+/// Read headerless samples laid out per `options`, the reverse of
+/// `export_heightmap_raw`. Without a size the map is taken to be square, as
+/// engine heightmaps are. Integer samples span [0, 1]; floats come back as
+/// stored.
+pub fn decode_heightmap_raw(bytes: &[u8], size: Option<(u32, u32)>, options: &RawOptions) -> Result<Heightmap, String> {
+    options.validate()?;
+    let sample_len = options.bit_depth as usize / 8;
+    let (width, height) = size.unwrap_or_else(|| {
+        let side = ((bytes.len() / sample_len) as f64).sqrt().round() as u32;
+        (side, side)
+    });
+    float_image::check_size(width, height)?;
+    let expected = width as u64 * height as u64 * sample_len as u64;
+    if bytes.len() as u64 != expected {
+        return Err(match size {
+            Some(_) => format!(
+                "A {width}x{height} raw file of {}-bit samples has {expected} bytes, but this one has {}",
+                options.bit_depth,
+                bytes.len()
+            ),
+            None => format!(
+                "A raw file of {} bytes isn't a square of {}-bit samples; give its width and height",
+                bytes.len(),
+                options.bit_depth
+            ),
+        });
+    }
+
+    let big = options.byte_order == ByteOrder::Big;
+    let w = width as usize;
+    let mut heightmap = Heightmap::new(width, height);
+    for (i, s) in bytes.chunks_exact(sample_len).enumerate() {
+        let v = match (options.bit_depth, options.float) {
+            (8, _) => s[0] as f32 / 255.0,
+            (16, _) => {
+                let b = [s[0], s[1]];
+                (if big { u16::from_be_bytes(b) } else { u16::from_le_bytes(b) }) as f32 / 65535.0
+            }
+            (_, true) => {
+                let b = [s[0], s[1], s[2], s[3]];
+                if big { f32::from_be_bytes(b) } else { f32::from_le_bytes(b) }
+            }
+            (_, false) => {
+                let b = [s[0], s[1], s[2], s[3]];
+                ((if big { u32::from_be_bytes(b) } else { u32::from_le_bytes(b) }) as f64 / u32::MAX as f64) as f32
+            }
+        };
+        let (x, y) = (i % w, i / w);
+        let y = if options.flip_vertical { height as usize - 1 - y } else { y };
+        heightmap.data[y * w + x] = v;
+    }
+    Ok(heightmap)
+}
+
+/// Path next to `path` with `suffix` appended to the file stem and a new extension,
+/// e.g. `terrain.png` -> `terrain_holes.png`.
+pub fn sidecar_path(path: &Path, suffix: &str, extension: &str) -> std::path::PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("heightmap");
+    path.with_file_name(format!("{stem}{suffix}.{extension}"))
+}
+
+/// Write the hole mask as an 8-bit PNG: white = hole, black = solid.
+pub fn export_hole_mask(path: &Path, holes: &Heightmap) -> Result<(), String> {
+    let pixels: Vec<u8> = holes.data.iter()
+        .map(|&v| if v >= 0.5 { 255 } else { 0 })
+        .collect();
+
+    let img = image::GrayImage::from_raw(holes.width, holes.height, pixels)
+        .ok_or("Failed to create image buffer".to_string())?;
+
+    img.save(path).map_err(|e| format!("Failed to save hole mask: {e}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::safety::{Checkpoints, Snapshot};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("topograph-{}-{name}", std::process::id()))
+    }
+
+    fn ramp(width: u32, height: u32) -> Heightmap {
+        let data = (0..width * height).map(|i| i as f32 / (width * height) as f32).collect();
+        Heightmap { data, width, height }
+    }
+
+    fn save(path: &Path, heightmap: &Heightmap, incremental: bool) {
+        save_with_patches(path, heightmap, incremental, &[]);
+    }
+
+    fn save_with_patches(path: &Path, heightmap: &Heightmap, incremental: bool, detail_patches: &[DetailPatch]) {
+        let world_scale = WorldScale::default();
+        let metadata = ProjectMetadata::default();
+        let contents = ProjectContents {
+            heightmap,
+            texture_png: None,
+            settings_json: "{}",
+            world_scale: &world_scale,
+            boundary: Boundary::default(),
+            usage: None,
+            history: Vec::new(),
+            masks: None,
+            erosion_maps: None,
+            detail_patches,
+            canvas_frame: None,
+            metadata: &metadata,
+            operations: None,
+        };
+        save_project(path, &contents, incremental, &|_| {}).unwrap();
+    }
+
+    /// Copy the project at `path` with each entry passed through `f`.
+    fn rewrite(path: &Path, f: impl Fn(&str, Vec<u8>) -> Vec<u8>) {
+        let mut zip = ZipArchive::new(File::open(path).unwrap()).unwrap();
+        let mut out = ZipWriter::new(std::io::Cursor::new(Vec::new()));
+        for i in 0..zip.len() {
+            let mut entry = zip.by_index(i).unwrap();
+            let name = entry.name().to_string();
+            let mut bytes = Vec::new();
+            entry.read_to_end(&mut bytes).unwrap();
+            out.start_file(name.as_str(), SimpleFileOptions::default()).unwrap();
+            out.write_all(&f(&name, bytes)).unwrap();
+        }
+        std::fs::write(path, out.finish().unwrap().into_inner()).unwrap();
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip.topo");
+        let heightmap = ramp(300, 20);
+        for incremental in [false, true] {
+            save(&path, &heightmap, incremental);
+            let loaded = load_project(&path, &|_| {}).unwrap();
+            assert_eq!((loaded.heightmap.width, loaded.heightmap.height), (300, 20));
+            assert_eq!(loaded.heightmap.data, heightmap.data);
+            assert!(loaded.damage.is_none());
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn detail_patches_round_trip() {
+        let path = temp_path("detail.topo");
+        let heightmap = ramp(32, 24);
+        let mut patch = DetailPatch::new(&heightmap, (4, 6, 10, 8), 3, None).unwrap();
+        patch.residual.set(5, 7, 0.25);
+        // Saved, then dropped on load once its residual no longer fits the patch
+        let misfit = DetailPatch::new(&heightmap, (0, 0, 4, 4), 2, None).unwrap();
+        save_with_patches(&path, &heightmap, false, &[patch.clone(), misfit]);
+        rewrite(&path, |name, bytes| if name == "detail/1.bin" { bytes[..16].to_vec() } else { bytes });
+
+        let loaded = load_project(&path, &|_| {}).unwrap();
+        assert_eq!(loaded.detail_patches.len(), 1);
+        let read = &loaded.detail_patches[0];
+        assert_eq!((read.x, read.y, read.w, read.h, read.factor), (4, 6, 10, 8, 3));
+        assert_eq!(read.residual.data, patch.residual.data);
+        assert!(loaded.damage.unwrap().dropped.iter().any(|d| d.entry == "detail/1.bin"));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn save_history(path: &Path, checkpoints: &Checkpoints) {
+        let heightmap = ramp(16, 16);
+        let world_scale = WorldScale::default();
+        let metadata = ProjectMetadata::default();
+        let contents = ProjectContents {
+            heightmap: &heightmap,
+            texture_png: None,
+            settings_json: "{}",
+            world_scale: &world_scale,
+            boundary: Boundary::default(),
+            usage: None,
+            history: checkpoints.iter().collect(),
+            masks: None,
+            erosion_maps: None,
+            detail_patches: &[],
+            canvas_frame: Some(Frame::of(&heightmap)),
+            metadata: &metadata,
+            operations: None,
+        };
+        save_project(path, &contents, false, &|_| {}).unwrap();
+    }
+
+    fn history_snapshot(heightmap: Heightmap) -> Snapshot {
+        let mut masks = MaskSet::default();
+        masks.insert(MaskChannel::Hardness, ramp(heightmap.width, heightmap.height));
+        let patch = DetailPatch::new(&heightmap, (1, 1, 4, 4), 2, None).unwrap();
+        let canvas_frame = Some(Frame { origin_x: 3.0, ..Frame::of(&heightmap) });
+        Snapshot { heightmap, masks, canvas_frame, detail_patches: vec![patch] }
+    }
+
+    #[test]
+    fn history_round_trip() {
+        let path = temp_path("history.topo");
+        let maps: Vec<Heightmap> = (0..3).map(|i| {
+            let mut heightmap = ramp(150, 100);
+            heightmap.set(i * 60, 90, 1.0);
+            heightmap
+        }).collect();
+        let mut checkpoints = Checkpoints::default();
+        for map in &maps {
+            checkpoints.push(DestructiveOp::Generate, history_snapshot(map.clone()));
+        }
+        save_history(&path, &checkpoints);
+
+        let loaded = load_project(&path, &|_| {}).unwrap();
+        assert!(loaded.damage.is_none());
+        assert!(loaded.canvas_frame.is_some());
+        let mut restored = Checkpoints::default();
+        restored.restore(loaded.history);
+        let ids: Vec<u64> = restored.info().iter().map(|c| c.id).collect();
+        for (id, map) in ids.into_iter().zip(&maps) {
+            let snapshot = restored.take(id).unwrap();
+            assert_eq!(snapshot.heightmap.data, map.data);
+            assert_eq!(snapshot.masks.get(MaskChannel::Hardness).unwrap().data, ramp(150, 100).data);
+            assert_eq!(snapshot.canvas_frame.unwrap().origin_x, 3.0);
+            assert_eq!(snapshot.detail_patches.len(), 1);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn damaged_checkpoint_drops_older_ones() {
+        let path = temp_path("history-damaged.topo");
+        let mut checkpoints = Checkpoints::default();
+        // The oldest is stored in full, as the next one is a different size
+        for width in [20, 21, 21] {
+            checkpoints.push(DestructiveOp::Normalize, history_snapshot(ramp(width, 20)));
+        }
+        save_history(&path, &checkpoints);
+        rewrite(&path, |name, bytes| if name == "history/2.bin" { bytes[..8].to_vec() } else { bytes });
+
+        let loaded = load_project(&path, &|_| {}).unwrap();
+        assert_eq!(loaded.history.len(), 1);
+        assert_eq!(loaded.history[0].heightmap.width, 20);
+        let dropped: Vec<&str> = loaded.damage.as_ref().unwrap().dropped.iter().map(|d| d.entry.as_str()).collect();
+        assert_eq!(dropped, ["history/2.bin", "history/1.bin"]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_heightmap_keeps_intact_rows() {
+        let path = temp_path("truncated.topo");
+        let heightmap = ramp(300, 20);
+        save(&path, &heightmap, false);
+        // Cut heightmap.bin 10 samples into row 4
+        let kept = (300 * 4 + 10) * 4;
+        rewrite(&path, |name, bytes| if name == "heightmap.bin" { bytes[..kept].to_vec() } else { bytes });
+
+        let loaded = load_project(&path, &|_| {}).unwrap();
+        let damage = loaded.damage.unwrap();
+        let lost: Vec<_> = damage.lost.iter().map(|r| (r.x, r.y, r.width, r.height)).collect();
+        assert_eq!(lost, [(10, 4, 290, 1), (0, 5, 300, 15)]);
+        assert_eq!(loaded.heightmap.data[..kept / 4], heightmap.data[..kept / 4]);
+        assert!(loaded.heightmap.data[kept / 4..].iter().all(|&h| h == 0.0));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn incremental_save_rewrites_changed_chunks() {
+        let path = temp_path("incremental.topo");
+        let mut heightmap = ramp(300, 20);
+        save(&path, &heightmap, true);
+        let raw = |path: &Path, name: &str| {
+            let mut zip = ZipArchive::new(File::open(path).unwrap()).unwrap();
+            let entry = zip.by_name(name).unwrap();
+            (entry.crc32(), entry.compressed_size())
+        };
+        let before = raw(&path, "heightmap/0_0.bin");
+
+        heightmap.set(290, 10, 1.0);
+        save(&path, &heightmap, true);
+        assert_eq!(raw(&path, "heightmap/0_0.bin"), before);
+        let loaded = load_project(&path, &|_| {}).unwrap();
+        assert_eq!(loaded.heightmap.data, heightmap.data);
+        assert!(loaded.damage.is_none());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn chunk_hashes_of_older_files_are_ignored() {
+        let path = temp_path("hashes.topo");
+        let heightmap = ramp(300, 20);
+        save(&path, &heightmap, true);
+        rewrite(&path, |name, bytes| {
+            if name != "manifest.json" {
+                return bytes;
+            }
+            let mut manifest: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            manifest["chunks"]["hashes"] = serde_json::json!([1, 2]);
+            serde_json::to_vec(&manifest).unwrap()
+        });
+        assert_eq!(load_project(&path, &|_| {}).unwrap().heightmap.data, heightmap.data);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn damaged_chunk_is_suspect() {
+        let path = temp_path("chunk.topo");
+        save(&path, &ramp(300, 20), true);
+        rewrite(&path, |name, mut bytes| {
+            if name == "heightmap/1_0.bin" {
+                bytes[0] ^= 1;
+            }
+            bytes
+        });
+        let damage = load_project(&path, &|_| {}).unwrap().damage.unwrap();
+        assert_eq!(damage.suspect.len(), 1);
+        assert_eq!(damage.suspect[0].entry, "heightmap/1_0.bin");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn entries_are_read_up_to_their_expected_size() {
+        let path = temp_path("padded.topo");
+        let heightmap = ramp(16, 16);
+        save(&path, &heightmap, false);
+        rewrite(&path, |name, mut bytes| {
+            if name == "heightmap.bin" {
+                bytes.resize(bytes.len() + (1 << 20), 0xff);
+            }
+            bytes
+        });
+        let loaded = load_project(&path, &|_| {}).unwrap();
+        assert_eq!(loaded.heightmap.data, heightmap.data);
+        let damage = loaded.damage.unwrap();
+        assert!(damage.lost.is_empty());
+        assert_eq!(damage.suspect.len(), 1);
+        assert!(damage.suspect[0].reason.contains("larger than 1024 bytes"), "{}", damage.suspect[0].reason);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn oversized_manifest_is_an_error() {
+        let path = temp_path("oversized.topo");
+        save(&path, &ramp(8, 8), false);
+        rewrite(&path, |name, bytes| {
+            if name != "manifest.json" {
+                return bytes;
+            }
+            let mut manifest: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+            manifest["width"] = 4_000_000_000u32.into();
+            manifest["height"] = 4_000_000_000u32.into();
+            serde_json::to_vec(&manifest).unwrap()
+        });
+        assert!(load_project(&path, &|_| {}).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn truncated_archive_is_an_error() {
+        let path = temp_path("cut.topo");
+        save(&path, &ramp(64, 64), false);
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        assert!(load_project(&path, &|_| {}).is_err());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let heightmap = ramp(7, 5);
+        let path = temp_path("round-trip.r16");
+        for (bit_depth, float) in [(8, false), (16, false), (32, false), (32, true)] {
+            for byte_order in [ByteOrder::Little, ByteOrder::Big] {
+                for flip_vertical in [false, true] {
+                    let options = RawOptions { bit_depth, float, byte_order, flip_vertical };
+                    export_heightmap_raw(&path, &heightmap, &options).unwrap();
+                    let bytes = std::fs::read(&path).unwrap();
+                    assert_eq!(bytes.len(), 7 * 5 * bit_depth as usize / 8);
+                    let read = decode_heightmap_raw(&bytes, Some((7, 5)), &options).unwrap();
+                    assert_eq!((read.width, read.height), (7, 5));
+                    // Half a step of the sample's integer range
+                    let tolerance = match bit_depth {
+                        8 => 0.5 / 255.0,
+                        16 => 0.5 / 65535.0,
+                        _ => 1e-7,
+                    };
+                    for (a, b) in read.data.iter().zip(&heightmap.data) {
+                        assert!((a - b).abs() <= tolerance, "{options:?}: {a} for {b}");
+                    }
+                }
+            }
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn square_raw_needs_no_size() {
+        let bytes: Vec<u8> = (0..16u16).flat_map(|v| (v * 4096).to_le_bytes()).collect();
+        let options = RawOptions { bit_depth: 16, float: false, ..Default::default() };
+        let read = decode_heightmap_raw(&bytes, None, &options).unwrap();
+        assert_eq!((read.width, read.height), (4, 4));
+        assert_eq!(read.data[1], 4096.0 / 65535.0);
+    }
+
+    #[test]
+    fn mismatched_raw_is_an_error() {
+        let options = RawOptions::default();
+        let bytes = vec![0; 4 * 12];
+        assert!(decode_heightmap_raw(&bytes, Some((4, 4)), &options).is_err());
+        assert!(decode_heightmap_raw(&bytes[..47], Some((4, 3)), &options).is_err());
+        // 12 samples aren't a square
+        assert!(decode_heightmap_raw(&bytes, None, &options).is_err());
+        assert!(decode_heightmap_raw(&[], None, &options).is_err());
+        assert!(decode_heightmap_raw(&[], Some((0, 4)), &options).is_err());
+        assert!(decode_heightmap_raw(&bytes, Some((u32::MAX, u32::MAX)), &options).is_err());
+        let bad_depth = RawOptions { bit_depth: 12, float: false, ..Default::default() };
+        assert!(decode_heightmap_raw(&bytes, Some((4, 3)), &bad_depth).is_err());
+        let bad_float = RawOptions { bit_depth: 16, float: true, ..Default::default() };
+        assert!(decode_heightmap_raw(&bytes, Some((4, 6)), &bad_float).is_err());
+    }
+}
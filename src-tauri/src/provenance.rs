@@ -0,0 +1,63 @@
+//! The generation, erosion and filter operations that made the open project's
+//! terrain, in order and with their full parameters, so a finished terrain's
+//! recipe can be read back and replayed. Saved in the project as
+//! `history.json`; entries are only ever added.
+
+use std::time::SystemTime;
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Operation {
+    /// The command that ran, e.g. `run_thermal_erosion`.
+    pub command: String,
+    /// Its arguments as the frontend names them, so invoking `command` with
+    /// them replays the operation. Uploaded images and masks aren't kept: a
+    /// masked operation replays unmasked, and the AI ones are logged without
+    /// arguments and can't be replayed.
+    pub args: serde_json::Value,
+    /// Size of the terrain it ran on; pixel parameters only replay the same at
+    /// this size.
+    pub width: u32,
+    pub height: u32,
+    /// Unix seconds when it finished.
+    pub recorded_at: u64,
+    /// Stopped part way, so replaying it goes further than it did.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub aborted: bool,
+}
+
+/// Operations oldest first.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct OperationLog {
+    operations: Vec<Operation>,
+}
+
+impl OperationLog {
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Add `command`, run with `args` on `terrain` as it is now. `aborted` marks
+    /// a run, like an erosion, that was stopped part way.
+    pub fn record(&mut self, command: &str, args: serde_json::Value, terrain: &Heightmap, aborted: bool) {
+        let recorded_at = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        self.operations.push(Operation {
+            command: command.to_string(),
+            args,
+            width: terrain.width,
+            height: terrain.height,
+            recorded_at,
+            aborted,
+        });
+    }
+}
@@ -0,0 +1,41 @@
+//! Projects recently opened or saved, most recent first, for the File menu's
+//! Open Recent submenu. The list is shared by every window and session, in
+//! `recent.json` in the app config directory.
+
+use std::path::Path;
+
+const RECENT_FILE: &str = "recent.json";
+/// Longest the list gets; older entries drop off the end.
+const MAX_RECENT: usize = 10;
+
+/// The listed projects that still exist, most recent first. A missing or
+/// unreadable list is an empty one.
+pub fn list(dir: &Path) -> Vec<String> {
+    read(dir).into_iter().filter(|path| Path::new(path).is_file()).collect()
+}
+
+/// Put `path` at the top of the list.
+pub fn add(dir: &Path, path: &str) -> Result<(), String> {
+    let mut paths = read(dir);
+    paths.retain(|p| p != path);
+    paths.insert(0, path.to_string());
+    paths.truncate(MAX_RECENT);
+    write(dir, &paths)
+}
+
+pub fn clear(dir: &Path) -> Result<(), String> {
+    write(dir, &[])
+}
+
+fn read(dir: &Path) -> Vec<String> {
+    std::fs::read(dir.join(RECENT_FILE))
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or_default()
+}
+
+fn write(dir: &Path, paths: &[String]) -> Result<(), String> {
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create {}: {e}", dir.display()))?;
+    let json = serde_json::to_string_pretty(paths).map_err(|e| format!("Failed to serialize recent projects: {e}"))?;
+    std::fs::write(dir.join(RECENT_FILE), json).map_err(|e| format!("Failed to save recent projects: {e}"))
+}
@@ -0,0 +1,347 @@
+use image::codecs::png::PngEncoder;
+use image::{ImageEncoder, RgbImage};
+use serde::Deserialize;
+use crate::canvas;
+use crate::detail::{self, DetailPatch};
+use crate::heightmap::Heightmap;
+
+/// Longest side of a rendered image.
+pub const MAX_RENDER_SIZE: u32 = canvas::MAX_CANVAS_SIZE;
+
+/// Most pixels in a rendered image, e.g. 8192x8192; about 200 MB as RGB.
+pub const MAX_RENDER_PIXELS: u64 = 1 << 26;
+
+/// Check a requested render size, naming the image as `what` in the error.
+pub fn check_size(what: &str, width: u32, height: u32) -> Result<(), String> {
+    if width == 0 || height == 0 {
+        return Err(format!("{what} size must be non-zero"));
+    }
+    if width > MAX_RENDER_SIZE || height > MAX_RENDER_SIZE || width as u64 * height as u64 > MAX_RENDER_PIXELS {
+        return Err(format!(
+            "{what} size {width}x{height} is too large; sides are limited to {MAX_RENDER_SIZE} and the image to {} megapixels",
+            MAX_RENDER_PIXELS >> 20
+        ));
+    }
+    Ok(())
+}
+
+/// The terrain a render sees: the base map with its detail patches composited
+/// over it. Coordinates are fractional base map pixels.
+#[derive(Clone, Copy)]
+pub struct Surface<'a> {
+    pub base: &'a Heightmap,
+    pub patches: &'a [DetailPatch],
+}
+
+impl<'a> Surface<'a> {
+    pub fn new(base: &'a Heightmap, patches: &'a [DetailPatch]) -> Self {
+        Self { base, patches }
+    }
+
+    pub fn sample(&self, x: f32, y: f32) -> f32 {
+        if self.patches.is_empty() {
+            return self.base.sample(x, y);
+        }
+        detail::sample(self.base, self.patches, x, y)
+    }
+
+    /// Most samples per base pixel anywhere on the surface.
+    fn density(&self) -> u32 {
+        self.patches.iter().map(|p| p.factor).max().unwrap_or(1)
+    }
+}
+
+/// Vertical scale applied to [0, 1] heights when the map spans one world unit.
+/// Matches `heightScale` in the frontend TerrainRenderer.
+pub const HEIGHT_SCALE: f32 = 0.3;
+
+/// Perspective camera in the frontend's world space: y up, the map spans
+/// [-0.5, 0.5] in x/z and heights span [0, HEIGHT_SCALE] in y.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Camera {
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+}
+
+impl Default for Camera {
+    /// Same framing as the viewer's initial camera.
+    fn default() -> Self {
+        Self {
+            position: [0.6, 0.5, 0.6],
+            target: [0.0, 0.0, 0.0],
+            fov: 50.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RenderStyle {
+    Grayscale,
+    Hillshade,
+    ColorRelief,
+    ShadedRelief,
+}
+
+/// Render a top-down orthographic preview of the whole surface at the given size.
+pub fn render_preview(surface: &Surface, width: u32, height: u32, style: RenderStyle) -> RgbImage {
+    let hm = surface.base;
+    let sx = hm.width as f32 / width as f32;
+    let sy = hm.height as f32 / height as f32;
+    let light = sun_direction(315.0, 45.0);
+
+    let mut img = RgbImage::new(width, height);
+    for oy in 0..height {
+        for ox in 0..width {
+            // Sample at the pixel center in heightmap space
+            let hx = (ox as f32 + 0.5) * sx - 0.5;
+            let hy = (oy as f32 + 0.5) * sy - 0.5;
+            let h = surface.sample(hx, hy);
+
+            let color = match style {
+                RenderStyle::Grayscale => [h; 3],
+                RenderStyle::Hillshade => [hillshade(surface, hx, hy, light); 3],
+                RenderStyle::ColorRelief => hypsometric_tint(h),
+                RenderStyle::ShadedRelief => {
+                    // Keep some ambient so shadowed slopes still show their tint
+                    let shade = 0.35 + 0.65 * hillshade(surface, hx, hy, light);
+                    hypsometric_tint(h).map(|c| c * shade)
+                }
+            };
+            img.put_pixel(ox, oy, image::Rgb(color.map(to_u8)));
+        }
+    }
+    img
+}
+
+/// Raycast a perspective view of the surface, shaded with the hypsometric tint.
+/// Used as a deterministic AI conditioning image, independent of the viewer's canvas.
+pub fn render_perspective(surface: &Surface, camera: &Camera, width: u32, height: u32) -> RgbImage {
+    let hm = surface.base;
+    const BACKGROUND: [f32; 3] = [0.25, 0.25, 0.25];
+
+    let forward = normalize(sub(camera.target, camera.position));
+    let right = normalize(cross(forward, [0.0, 1.0, 0.0]));
+    let up = cross(right, forward);
+    let half_h = (camera.fov.to_radians() * 0.5).tan();
+    let half_w = half_h * width as f32 / height as f32;
+
+    // Same direction as the viewer's key light, converted to shading axes
+    // (+x east, +y south, +z up)
+    let light = normalize([0.5, 0.3, 1.0]);
+    // March in steps of about one cell of the finest grid
+    let step = 1.0 / (hm.width.max(hm.height) * surface.density()) as f32;
+
+    let mut img = RgbImage::new(width, height);
+    for py in 0..height {
+        for px in 0..width {
+            let u = ((px as f32 + 0.5) / width as f32 * 2.0 - 1.0) * half_w;
+            let v = (1.0 - (py as f32 + 0.5) / height as f32 * 2.0) * half_h;
+            let dir = normalize([
+                forward[0] + right[0] * u + up[0] * v,
+                forward[1] + right[1] * u + up[1] * v,
+                forward[2] + right[2] * u + up[2] * v,
+            ]);
+
+            let color = match raycast(surface, camera.position, dir, step) {
+                // Ray entered through the side of the terrain block
+                Some((hx, hy, true)) => hypsometric_tint(surface.sample(hx, hy)).map(|c| c * 0.3),
+                Some((hx, hy, false)) => {
+                    let shade = 0.35 + 0.65 * hillshade(surface, hx, hy, light);
+                    hypsometric_tint(surface.sample(hx, hy)).map(|c| c * shade)
+                }
+                None => BACKGROUND,
+            };
+            img.put_pixel(px, py, image::Rgb(color.map(to_u8)));
+        }
+    }
+    img
+}
+
+/// March a world-space ray against the heightfield. Returns the hit in
+/// fractional heightmap pixel coordinates, and whether it hit a side wall.
+fn raycast(
+    surface: &Surface,
+    origin: [f32; 3],
+    dir: [f32; 3],
+    step: f32,
+) -> Option<(f32, f32, bool)> {
+    let (t_enter, t_exit) = intersect_bounds(origin, dir)?;
+    let hm = surface.base;
+
+    let to_pixel = |p: [f32; 3]| {
+        (
+            (p[0] + 0.5) * (hm.width - 1) as f32,
+            (p[2] + 0.5) * (hm.height - 1) as f32,
+        )
+    };
+    let above = |t: f32| {
+        let p = at(origin, dir, t);
+        let (hx, hy) = to_pixel(p);
+        p[1] - surface.sample(hx, hy) * HEIGHT_SCALE
+    };
+
+    let mut t_prev = t_enter;
+    let mut t = t_enter;
+    while t <= t_exit {
+        if above(t) <= 0.0 {
+            if t == t_enter {
+                let (hx, hy) = to_pixel(at(origin, dir, t));
+                return Some((hx, hy, true));
+            }
+            // Refine the crossing between the last two samples
+            let (mut lo, mut hi) = (t_prev, t);
+            for _ in 0..8 {
+                let mid = 0.5 * (lo + hi);
+                if above(mid) > 0.0 {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+            let (hx, hy) = to_pixel(at(origin, dir, hi));
+            return Some((hx, hy, false));
+        }
+        t_prev = t;
+        t += step;
+    }
+    None
+}
+
+/// Slab test against the terrain's bounding box. Returns (t_enter, t_exit).
+fn intersect_bounds(origin: [f32; 3], dir: [f32; 3]) -> Option<(f32, f32)> {
+    let min = [-0.5, 0.0, -0.5];
+    let max = [0.5, HEIGHT_SCALE, 0.5];
+    let mut t0 = 0.0f32;
+    let mut t1 = f32::MAX;
+    for axis in 0..3 {
+        if dir[axis].abs() < 1e-8 {
+            if origin[axis] < min[axis] || origin[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+        let a = (min[axis] - origin[axis]) / dir[axis];
+        let b = (max[axis] - origin[axis]) / dir[axis];
+        t0 = t0.max(a.min(b));
+        t1 = t1.min(a.max(b));
+    }
+    (t0 <= t1).then_some((t0, t1))
+}
+
+fn at(origin: [f32; 3], dir: [f32; 3], t: f32) -> [f32; 3] {
+    [origin[0] + dir[0] * t, origin[1] + dir[1] * t, origin[2] + dir[2] * t]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let len = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt().max(1e-12);
+    [v[0] / len, v[1] / len, v[2] / len]
+}
+
+/// Unit vector pointing towards the sun. Azimuth is clockwise from north (image up),
+/// altitude is degrees above the horizon. Axes: +x east, +y south, +z up.
+pub fn sun_direction(azimuth_deg: f32, altitude_deg: f32) -> [f32; 3] {
+    let az = azimuth_deg.to_radians();
+    let alt = altitude_deg.to_radians();
+    [az.sin() * alt.cos(), -az.cos() * alt.cos(), alt.sin()]
+}
+
+/// Surface normal at fractional pixel coordinates (+x east, +y south, +z up).
+pub fn surface_normal(surface: &Surface, x: f32, y: f32) -> [f32; 3] {
+    // Central differences over one cell of the finest grid there, in world units
+    let d = if surface.patches.is_empty() { 1.0 } else { detail::spacing(surface.patches, x, y) };
+    let cell = d / (surface.base.width.max(2) - 1) as f32;
+    let dzdx = (surface.sample(x + d, y) - surface.sample(x - d, y)) * HEIGHT_SCALE / (2.0 * cell);
+    let dzdy = (surface.sample(x, y + d) - surface.sample(x, y - d)) * HEIGHT_SCALE / (2.0 * cell);
+    let len = (dzdx * dzdx + dzdy * dzdy + 1.0).sqrt();
+    [-dzdx / len, -dzdy / len, 1.0 / len]
+}
+
+/// Lambertian hillshade in [0, 1] for the given light direction.
+pub fn hillshade(surface: &Surface, x: f32, y: f32, light: [f32; 3]) -> f32 {
+    let n = surface_normal(surface, x, y);
+    (n[0] * light[0] + n[1] * light[1] + n[2] * light[2]).max(0.0)
+}
+
+/// Height color ramp shared with the frontend's orthographic AI capture.
+pub fn hypsometric_tint(h: f32) -> [f32; 3] {
+    const WATER: [f32; 3] = [0.15, 0.25, 0.45];
+    const SAND: [f32; 3] = [0.76, 0.70, 0.50];
+    const LOWLAND: [f32; 3] = [0.30, 0.52, 0.22];
+    const HIGHLAND: [f32; 3] = [0.45, 0.36, 0.20];
+    const ROCK: [f32; 3] = [0.50, 0.48, 0.45];
+    const SNOW: [f32; 3] = [0.92, 0.93, 0.96];
+
+    let h = h.clamp(0.0, 1.0);
+    if h < 0.05 {
+        mix(WATER, SAND, h / 0.05)
+    } else if h < 0.2 {
+        mix(SAND, LOWLAND, (h - 0.05) / 0.15)
+    } else if h < 0.5 {
+        mix(LOWLAND, HIGHLAND, (h - 0.2) / 0.3)
+    } else if h < 0.75 {
+        mix(HIGHLAND, ROCK, (h - 0.5) / 0.25)
+    } else {
+        mix(ROCK, SNOW, (h - 0.75) / 0.25)
+    }
+}
+
+fn mix(a: [f32; 3], b: [f32; 3], t: f32) -> [f32; 3] {
+    [
+        a[0] + (b[0] - a[0]) * t,
+        a[1] + (b[1] - a[1]) * t,
+        a[2] + (b[2] - a[2]) * t,
+    ]
+}
+
+fn to_u8(c: f32) -> u8 {
+    (c.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+
+/// Encode an RGB image as PNG bytes.
+pub fn encode_png(img: &RgbImage) -> Result<Vec<u8>, String> {
+    let mut png_bytes = Vec::new();
+    PngEncoder::new(&mut png_bytes)
+        .write_image(img.as_raw(), img.width(), img.height(), image::ExtendedColorType::Rgb8)
+        .map_err(|e| format!("Failed to encode preview PNG: {e}"))?;
+    Ok(png_bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_sizes_are_bounded() {
+        assert!(check_size("Preview", 0, 10).is_err());
+        assert!(check_size("Preview", MAX_RENDER_SIZE + 1, 1).is_err());
+        assert!(check_size("Preview", MAX_RENDER_SIZE, MAX_RENDER_SIZE).is_err());
+        assert!(check_size("Preview", 8192, 8192).is_ok());
+        assert!(check_size("Preview", MAX_RENDER_SIZE, 16).is_ok());
+    }
+
+    #[test]
+    fn preview_has_requested_size() {
+        let hm = Heightmap { data: vec![0.0, 1.0, 0.0, 1.0], width: 2, height: 2 };
+        let img = render_preview(&Surface::new(&hm, &[]), 5, 3, RenderStyle::Grayscale);
+        assert_eq!(img.dimensions(), (5, 3));
+        // Left edge samples the low column, right edge the high one
+        assert_eq!(img.get_pixel(0, 1).0, [0; 3]);
+        assert_eq!(img.get_pixel(4, 1).0, [255; 3]);
+    }
+}
@@ -0,0 +1,122 @@
+//! Resampling of heightmaps to another resolution with a windowed filter, for
+//! exporting at a size other than the working map's without resizing it.
+
+use std::f32::consts::PI;
+use rayon::prelude::*;
+use serde::Deserialize;
+use crate::canvas;
+use crate::heightmap::Heightmap;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ResampleFilter {
+    /// Catmull-Rom, sharp with little ringing.
+    #[default]
+    Bicubic,
+    /// Lanczos with three lobes, sharper on downsampling but rings more at
+    /// cliffs.
+    Lanczos,
+}
+
+impl ResampleFilter {
+    /// Kernel radius in source pixels at unit scale.
+    fn support(self) -> f32 {
+        match self {
+            ResampleFilter::Bicubic => 2.0,
+            ResampleFilter::Lanczos => 3.0,
+        }
+    }
+
+    fn kernel(self, x: f32) -> f32 {
+        let x = x.abs();
+        match self {
+            ResampleFilter::Bicubic if x < 1.0 => (1.5 * x - 2.5) * x * x + 1.0,
+            ResampleFilter::Bicubic if x < 2.0 => ((-0.5 * x + 2.5) * x - 4.0) * x + 2.0,
+            ResampleFilter::Lanczos if x < 1e-6 => 1.0,
+            ResampleFilter::Lanczos if x < 3.0 => {
+                let px = PI * x;
+                3.0 * px.sin() * (px / 3.0).sin() / (px * px)
+            }
+            _ => 0.0,
+        }
+    }
+}
+
+/// Target resolution for an export.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportResolution {
+    /// Pixels along the longer side; the shorter follows the map's aspect ratio.
+    pub size: u32,
+    #[serde(default)]
+    pub filter: ResampleFilter,
+}
+
+impl ExportResolution {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(2..=canvas::MAX_CANVAS_SIZE).contains(&self.size) {
+            return Err(format!("Export resolution must be between 2 and {}", canvas::MAX_CANVAS_SIZE));
+        }
+        Ok(())
+    }
+
+    /// Width and height of `hm` exported at this resolution.
+    pub fn dims(&self, hm: &Heightmap) -> (u32, u32) {
+        let scale = self.size as f32 / hm.width.max(hm.height) as f32;
+        let w = ((hm.width as f32 * scale).round() as u32).max(2);
+        let h = ((hm.height as f32 * scale).round() as u32).max(2);
+        (w, h)
+    }
+}
+
+/// Source taps along one axis: for each output sample, the first source index
+/// and the normalized weights from there on. Corner samples stay on corner
+/// samples, as in `Heightmap::sample`, so the exported map covers the same
+/// ground. Downsampling widens the kernel to cover the pixels it merges.
+fn taps(src: u32, dst: u32, filter: ResampleFilter) -> Vec<(usize, Vec<f32>)> {
+    let scale = (src - 1) as f32 / (dst - 1).max(1) as f32;
+    let stretch = scale.max(1.0);
+    let radius = filter.support() * stretch;
+    (0..dst)
+        .map(|i| {
+            let center = i as f32 * scale;
+            let lo = (center - radius).ceil().max(0.0) as usize;
+            let hi = ((center + radius).floor() as usize).min(src as usize - 1);
+            let mut weights: Vec<f32> = (lo..=hi).map(|j| filter.kernel((j as f32 - center) / stretch)).collect();
+            // Kernels cut off at the border are renormalized so edges keep their level
+            let sum: f32 = weights.iter().sum();
+            for w in &mut weights {
+                *w /= sum;
+            }
+            (lo, weights)
+        })
+        .collect()
+}
+
+/// `hm` resampled to `width` x `height`, rows then columns. Filter overshoot
+/// around sharp features is clamped to the [0, 1] height range.
+pub fn resample(hm: &Heightmap, width: u32, height: u32, filter: ResampleFilter) -> Heightmap {
+    if width == hm.width && height == hm.height {
+        return hm.clone();
+    }
+    let (sw, w) = (hm.width as usize, width as usize);
+
+    let columns = taps(hm.width, width, filter);
+    let mut horizontal = vec![0.0f32; w * hm.height as usize];
+    horizontal.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        let src = &hm.data[y * sw..(y + 1) * sw];
+        for (out, (lo, weights)) in row.iter_mut().zip(&columns) {
+            *out = weights.iter().zip(&src[*lo..]).map(|(k, v)| k * v).sum();
+        }
+    });
+
+    let rows = taps(hm.height, height, filter);
+    let mut out = Heightmap::new(width, height);
+    out.data.par_chunks_mut(w).zip(&rows).for_each(|(row, (lo, weights))| {
+        for (x, v) in row.iter_mut().enumerate() {
+            let sum: f32 = weights.iter().enumerate().map(|(k, wk)| wk * horizontal[(lo + k) * w + x]).sum();
+            *v = sum.clamp(0.0, 1.0);
+        }
+    });
+    out
+}
@@ -0,0 +1,111 @@
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+use crate::hydrology::{self, FlowMethod};
+
+/// River channels cut along the drainage network: wherever enough area drains
+/// through a pixel a channel begins, and it widens and deepens downstream with
+/// discharge, taken as proportional to drainage area.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct RiverParams {
+    pub method: FlowMethod,
+    /// Upstream drainage area in pixels at which a channel begins.
+    pub threshold: f32,
+    /// Channel width in pixels where it begins.
+    pub width: f32,
+    /// Channel depth in normalized height units where it begins.
+    pub depth: f32,
+    /// Widest channel in pixels, however much flows through it.
+    pub max_width: f32,
+}
+
+impl Default for RiverParams {
+    fn default() -> Self {
+        Self {
+            method: FlowMethod::D8,
+            threshold: 1000.0,
+            width: 2.0,
+            depth: 0.002,
+            max_width: 24.0,
+        }
+    }
+}
+
+/// Hydraulic geometry exponents (Leopold & Maddock 1953): width grows with the
+/// square root of discharge, depth a little slower.
+const WIDTH_EXPONENT: f32 = 0.5;
+const DEPTH_EXPONENT: f32 = 0.4;
+/// Banks reach out to this many channel half-widths from the centerline.
+const BANK_REACH: f32 = 2.0;
+/// Share of the progress taken by routing the flow; cutting the channels is the rest.
+const ROUTING_SHARE: f32 = 0.6;
+/// Channel pixels cut between progress reports.
+const CHANNELS_PER_REPORT: usize = 4096;
+
+/// Carve the river network into `hm` and return where the channels are, 1 in a
+/// channel fading to 0 over its banks.
+///
+/// Channels follow steepest descent over the terrain with its depressions
+/// filled, and once started continue downstream to the map edge, so rivers always
+/// join up instead of ending in the middle of a slope. Each channel pixel's bed
+/// sits its depth below the filled surface and never rises downstream; a
+/// parabolic cross-section is cut around it out to the banks. Basins deeper than
+/// the channel are left alone, so rivers run into lakes rather than through them.
+/// `progress` gets the completed fraction.
+pub fn carve(hm: &mut Heightmap, params: &RiverParams, boundary: Boundary, progress: &dyn Fn(f32)) -> Heightmap {
+    let (w, h) = (hm.width as usize, hm.height as usize);
+    let tree = hydrology::drainage(hm, boundary, &|fraction| progress(fraction * ROUTING_SHARE));
+    let area = hydrology::accumulation(&tree, hm, boundary, params.method);
+
+    // Bed heights, upstream first so each can pass its level on downstream
+    let mut bed = vec![f32::INFINITY; w * h];
+    let mut discharge = vec![0.0f32; w * h];
+    for &cell in tree.order.iter().rev() {
+        if area[cell] < params.threshold && bed[cell].is_infinite() {
+            continue;
+        }
+        discharge[cell] = area[cell].max(params.threshold) / params.threshold;
+        let depth = params.depth * discharge[cell].powf(DEPTH_EXPONENT);
+        bed[cell] = bed[cell].min(tree.filled[cell] - depth);
+        let receiver = tree.receiver[cell];
+        if receiver != cell {
+            bed[receiver] = bed[receiver].min(bed[cell]);
+        }
+    }
+
+    let mut carved = hm.data.clone();
+    let mut channels = Heightmap::new(hm.width, hm.height);
+    let channel_count = bed.iter().filter(|b| b.is_finite()).count();
+    for (done, cell) in (0..w * h).filter(|&i| bed[i].is_finite()).enumerate() {
+        if done % CHANNELS_PER_REPORT == 0 {
+            progress(ROUTING_SHARE + (1.0 - ROUTING_SHARE) * done as f32 / channel_count as f32);
+        }
+        let q = discharge[cell];
+        let half_width = (params.width * q.powf(WIDTH_EXPONENT)).min(params.max_width).max(1.0) / 2.0;
+        let depth = params.depth * q.powf(DEPTH_EXPONENT);
+        let reach = (half_width * BANK_REACH).ceil() as i64;
+        let (x, y) = ((cell % w) as i64, (cell / w) as i64);
+        for dy in -reach..=reach {
+            for dx in -reach..=reach {
+                let t = (dx as f32).hypot(dy as f32) / half_width;
+                if t > BANK_REACH {
+                    continue;
+                }
+                let (Some(nx), Some(ny)) = (
+                    boundary.resolve_write(x + dx, hm.width),
+                    boundary.resolve_write(y + dy, hm.height),
+                ) else {
+                    continue;
+                };
+                let n = ny as usize * w + nx as usize;
+                carved[n] = carved[n].min(bed[cell] + depth * t * t);
+                channels.data[n] = channels.data[n].max((2.0 * (1.5 - t)).clamp(0.0, 1.0));
+            }
+        }
+    }
+
+    hm.data = carved;
+    progress(1.0);
+    channels
+}
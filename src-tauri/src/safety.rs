@@ -0,0 +1,425 @@
+//! Guard for operations that overwrite the whole map. They only run with a
+//! single-use token the frontend fetches with `confirm_destructive` once the user
+//! has agreed, and the document is checkpointed just before, so one stray click
+//! can't throw away hours of sculpting.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use crate::detail::DetailPatch;
+use crate::heightmap::Heightmap;
+use crate::mask::{MaskChannel, MaskSet};
+use crate::noise_gen::Frame;
+
+/// Whole-map operations that need confirmation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DestructiveOp {
+    /// Replacing the entire map with AI output, i.e. without a mask.
+    ReplaceFromAi,
+    /// Replacing the map with generated terrain, i.e. without a mask.
+    Generate,
+    /// Stretching the map's heights to fill 0 to 1.
+    Normalize,
+    /// Cropping or expanding the canvas.
+    Resize,
+    /// Replacing the map with a heightmap image from disk.
+    Import,
+    /// Replacing the document with a new project from a template.
+    NewProject,
+    /// Going back to a checkpoint. Doesn't need a token, since the current map is
+    /// checkpointed in its place; it only labels that checkpoint.
+    Restore,
+}
+
+impl DestructiveOp {
+    pub fn describe(self) -> &'static str {
+        match self {
+            DestructiveOp::ReplaceFromAi => "Replacing the whole map with AI output",
+            DestructiveOp::Generate => "Replacing the map with generated terrain",
+            DestructiveOp::Normalize => "Normalizing the map's heights",
+            DestructiveOp::Resize => "Resizing the canvas",
+            DestructiveOp::Import => "Replacing the map with an imported heightmap",
+            DestructiveOp::NewProject => "Starting a new project from a template",
+            DestructiveOp::Restore => "Restoring a checkpoint",
+        }
+    }
+}
+
+/// How long a token stays valid after the user confirms.
+const TOKEN_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Outstanding confirmation tokens.
+#[derive(Default)]
+pub struct Confirmations {
+    pending: HashMap<String, (DestructiveOp, Instant)>,
+}
+
+impl Confirmations {
+    pub fn issue(&mut self, op: DestructiveOp) -> String {
+        let now = Instant::now();
+        self.pending.retain(|_, (_, issued)| now.duration_since(*issued) < TOKEN_LIFETIME);
+        let token = format!("{:032x}", rand::thread_rng().gen::<u128>());
+        self.pending.insert(token.clone(), (op, now));
+        token
+    }
+
+    /// Use up `token` for `op`. False when it's missing, expired, already used or
+    /// issued for a different operation.
+    pub fn redeem(&mut self, token: Option<&str>, op: DestructiveOp) -> bool {
+        let Some((issued_for, issued)) = token.and_then(|t| self.pending.remove(t)) else {
+            return false;
+        };
+        issued_for == op && issued.elapsed() < TOKEN_LIFETIME
+    }
+}
+
+/// Checkpoints kept before the oldest is dropped. Only the newest holds a full
+/// copy of the map; each older one keeps the tiles it differs in from the next.
+pub const MAX_CHECKPOINTS: usize = 5;
+
+/// Edge in pixels of the tiles checkpoints compare maps in.
+pub const TILE_SIZE: u32 = 64;
+
+/// The document as a checkpoint puts it back.
+#[derive(Clone)]
+pub struct Snapshot {
+    pub heightmap: Heightmap,
+    pub masks: MaskSet,
+    pub canvas_frame: Option<Frame>,
+    pub detail_patches: Vec<DetailPatch>,
+}
+
+/// A map kept as the tiles where it differs from a newer map of the same size,
+/// or as all of its tiles.
+#[derive(Clone)]
+pub struct TileDiff {
+    pub width: u32,
+    pub height: u32,
+    /// Ascending row-major tile indices, each with the tile's samples row by row.
+    pub tiles: Vec<(u32, Vec<f32>)>,
+}
+
+impl TileDiff {
+    /// `map` as the tiles where it differs from `newer`, or in full without a
+    /// `newer` of the same size.
+    pub fn new(map: &Heightmap, newer: Option<&Heightmap>) -> Self {
+        let newer = newer.filter(|newer| (newer.width, newer.height) == (map.width, map.height));
+        let tiles = (0..tile_count(map.width, map.height))
+            .filter(|&i| {
+                newer.is_none_or(|newer| {
+                    tile_rows(map, i).zip(tile_rows(newer, i)).any(|(a, b)| a.iter().zip(b).any(|(a, b)| a.to_bits() != b.to_bits()))
+                })
+            })
+            .map(|i| (i, tile_rows(map, i).flatten().copied().collect()))
+            .collect();
+        Self { width: map.width, height: map.height, tiles }
+    }
+
+    /// Tiles read back, e.g. from a project: those at `indices`, or all of them
+    /// for `None`, with their samples one after another in `samples`.
+    pub fn from_parts(width: u32, height: u32, indices: Option<&[u32]>, samples: &[f32]) -> Result<Self, String> {
+        if samples.len() != Self::sample_count(width, height, indices)? {
+            return Err(format!("Tile samples don't fit a {width}x{height} map"));
+        }
+        let count = tile_count(width, height);
+        let mut rest = samples;
+        let tiles = indices
+            .map_or_else(|| (0..count).collect(), <[u32]>::to_vec)
+            .into_iter()
+            .map(|i| {
+                let (_, _, w, h) = tile_rect(width, height, i);
+                let (tile, after) = rest.split_at((w * h) as usize);
+                rest = after;
+                (i, tile.to_vec())
+            })
+            .collect();
+        Ok(Self { width, height, tiles })
+    }
+
+    /// Samples the tiles at `indices`, or all tiles for `None`, hold together.
+    /// Errs unless the indices are ascending and inside the map.
+    pub fn sample_count(width: u32, height: u32, indices: Option<&[u32]>) -> Result<usize, String> {
+        let Some(indices) = indices else {
+            return Ok(width as usize * height as usize);
+        };
+        let count = tile_count(width, height);
+        if indices.windows(2).any(|pair| pair[0] >= pair[1]) || indices.last().is_some_and(|&last| last >= count) {
+            return Err(format!("Tile indices don't fit a {width}x{height} map"));
+        }
+        Ok(indices.iter().map(|&i| {
+            let (_, _, w, h) = tile_rect(width, height, i);
+            w as usize * h as usize
+        }).sum())
+    }
+
+    /// Whether this holds every tile, needing no newer map to apply to.
+    pub fn is_full(&self) -> bool {
+        self.tiles.len() == tile_count(self.width, self.height) as usize
+    }
+
+    /// The map itself: these tiles over `newer`, or over a flat map if it isn't
+    /// the same size.
+    pub fn apply(&self, newer: Option<&Heightmap>) -> Heightmap {
+        let mut map = newer
+            .filter(|newer| (newer.width, newer.height) == (self.width, self.height))
+            .cloned()
+            .unwrap_or_else(|| Heightmap::new(self.width, self.height));
+        for (i, samples) in &self.tiles {
+            let (x0, y0, w, _) = tile_rect(self.width, self.height, *i);
+            for (row, src) in samples.chunks_exact(w as usize).enumerate() {
+                let start = ((y0 + row as u32) * self.width + x0) as usize;
+                map.data[start..start + w as usize].copy_from_slice(src);
+            }
+        }
+        map
+    }
+}
+
+fn tile_count(width: u32, height: u32) -> u32 {
+    width.div_ceil(TILE_SIZE) * height.div_ceil(TILE_SIZE)
+}
+
+/// Tile `i`, clipped to the map: its left, top, width and height.
+fn tile_rect(width: u32, height: u32, i: u32) -> (u32, u32, u32, u32) {
+    let columns = width.div_ceil(TILE_SIZE);
+    let (x0, y0) = (i % columns * TILE_SIZE, i / columns * TILE_SIZE);
+    (x0, y0, TILE_SIZE.min(width - x0), TILE_SIZE.min(height - y0))
+}
+
+fn tile_rows(map: &Heightmap, i: u32) -> impl Iterator<Item = &[f32]> {
+    let (x0, y0, w, h) = tile_rect(map.width, map.height, i);
+    (y0..y0 + h).map(move |y| {
+        let start = (y * map.width + x0) as usize;
+        &map.data[start..start + w as usize]
+    })
+}
+
+/// The document as it was before a destructive operation, kept against the
+/// next newer checkpoint.
+#[derive(Clone)]
+pub struct Checkpoint {
+    pub id: u64,
+    pub op: DestructiveOp,
+    /// Seconds since the Unix epoch.
+    pub created_at: u64,
+    pub heightmap: TileDiff,
+    /// Channels kept against the newer checkpoint's same channel.
+    pub masks: Vec<(MaskChannel, TileDiff)>,
+    pub canvas_frame: Option<Frame>,
+    pub detail_patches: Vec<DetailPatch>,
+}
+
+impl Checkpoint {
+    /// `snapshot` kept against `newer`, the document the next newer checkpoint
+    /// puts back, or in full for the newest.
+    pub fn new(id: u64, op: DestructiveOp, created_at: u64, snapshot: Snapshot, newer: Option<&Snapshot>) -> Self {
+        let masks = MaskChannel::ALL
+            .into_iter()
+            .filter_map(|channel| {
+                let mask = snapshot.masks.get(channel)?;
+                Some((channel, TileDiff::new(mask, newer.and_then(|newer| newer.masks.get(channel)))))
+            })
+            .collect();
+        Self {
+            id,
+            op,
+            created_at,
+            heightmap: TileDiff::new(&snapshot.heightmap, newer.map(|newer| &newer.heightmap)),
+            masks,
+            canvas_frame: snapshot.canvas_frame,
+            detail_patches: snapshot.detail_patches,
+        }
+    }
+
+    /// The document this puts back, given the one the next newer checkpoint
+    /// does.
+    pub fn snapshot(&self, newer: Option<&Snapshot>) -> Snapshot {
+        let mut masks = MaskSet::default();
+        for (channel, diff) in &self.masks {
+            masks.insert(*channel, diff.apply(newer.and_then(|newer| newer.masks.get(*channel))));
+        }
+        Snapshot {
+            heightmap: self.heightmap.apply(newer.map(|newer| &newer.heightmap)),
+            masks,
+            canvas_frame: self.canvas_frame,
+            detail_patches: self.detail_patches.clone(),
+        }
+    }
+}
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CheckpointInfo {
+    pub id: u64,
+    pub op: DestructiveOp,
+    pub created_at: u64,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Most recent checkpoints, oldest first.
+#[derive(Default)]
+pub struct Checkpoints {
+    next_id: u64,
+    list: VecDeque<Checkpoint>,
+}
+
+impl Checkpoints {
+    pub fn push(&mut self, op: DestructiveOp, snapshot: Snapshot) {
+        let created_at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        // The newest so far is kept against the new one from now on
+        if let Some(newest) = self.list.pop_back() {
+            let previous = newest.snapshot(None);
+            self.list.push_back(Checkpoint::new(newest.id, newest.op, newest.created_at, previous, Some(&snapshot)));
+        }
+        self.list.push_back(Checkpoint::new(self.next_id, op, created_at, snapshot, None));
+        self.next_id += 1;
+        while self.list.len() > MAX_CHECKPOINTS {
+            self.list.pop_front();
+        }
+    }
+
+    /// Remove checkpoint `id`, returning the document it puts back. The one
+    /// before it is kept against the one after it instead.
+    pub fn take(&mut self, id: u64) -> Option<Snapshot> {
+        let index = self.list.iter().position(|c| c.id == id)?;
+        let mut newer = None;
+        for checkpoint in self.list.iter().skip(index + 1).rev() {
+            newer = Some(checkpoint.snapshot(newer.as_ref()));
+        }
+        let taken = self.list[index].snapshot(newer.as_ref());
+        if index > 0 {
+            let older = self.list[index - 1].snapshot(Some(&taken));
+            let Checkpoint { id, op, created_at, .. } = self.list[index - 1];
+            self.list[index - 1] = Checkpoint::new(id, op, created_at, older, newer.as_ref());
+        }
+        self.list.remove(index);
+        Some(taken)
+    }
+
+    /// Replace the list with `checkpoints`, oldest first, e.g. from a project.
+    /// The newest must be full.
+    pub fn restore(&mut self, checkpoints: Vec<Checkpoint>) {
+        self.list.clear();
+        for mut checkpoint in checkpoints {
+            checkpoint.id = self.next_id;
+            self.next_id += 1;
+            self.list.push_back(checkpoint);
+        }
+        while self.list.len() > MAX_CHECKPOINTS {
+            self.list.pop_front();
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Checkpoint> {
+        self.list.iter()
+    }
+
+    pub fn info(&self) -> Vec<CheckpointInfo> {
+        self.list
+            .iter()
+            .map(|c| CheckpointInfo {
+                id: c.id,
+                op: c.op,
+                created_at: c.created_at,
+                width: c.heightmap.width,
+                height: c.heightmap.height,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ramp(width: u32, height: u32, offset: f32) -> Heightmap {
+        let data = (0..width * height).map(|i| i as f32 / (width * height) as f32 + offset).collect();
+        Heightmap { data, width, height }
+    }
+
+    fn snapshot(heightmap: Heightmap) -> Snapshot {
+        let mut masks = MaskSet::default();
+        masks.insert(MaskChannel::Selection, ramp(heightmap.width, heightmap.height, 0.0));
+        Snapshot { heightmap, masks, canvas_frame: None, detail_patches: Vec::new() }
+    }
+
+    #[test]
+    fn diff_keeps_changed_tiles() {
+        let newer = ramp(130, 70, 0.0);
+        let mut map = newer.clone();
+        map.set(129, 69, 1.0);
+        let diff = TileDiff::new(&map, Some(&newer));
+        let indices: Vec<u32> = diff.tiles.iter().map(|&(i, _)| i).collect();
+        assert_eq!(indices, [5]);
+        assert_eq!(diff.tiles[0].1.len(), 2 * 6);
+        assert!(!diff.is_full());
+        assert_eq!(diff.apply(Some(&newer)).data, map.data);
+
+        let resized = TileDiff::new(&map, Some(&ramp(64, 64, 0.0)));
+        assert!(resized.is_full());
+        assert_eq!(resized.apply(None).data, map.data);
+    }
+
+    #[test]
+    fn tiles_read_back_are_checked() {
+        assert_eq!(TileDiff::sample_count(130, 70, Some(&[0, 5])), Ok(64 * 64 + 2 * 6));
+        assert!(TileDiff::sample_count(130, 70, Some(&[6])).is_err());
+        assert!(TileDiff::sample_count(130, 70, Some(&[2, 1])).is_err());
+        assert!(TileDiff::from_parts(130, 70, Some(&[5]), &[0.0; 12]).is_ok());
+        assert!(TileDiff::from_parts(130, 70, Some(&[5]), &[0.0; 13]).is_err());
+        assert!(TileDiff::from_parts(130, 70, None, &[0.0; 130 * 70]).unwrap().is_full());
+    }
+
+    #[test]
+    fn only_the_newest_checkpoint_is_full() {
+        let mut checkpoints = Checkpoints::default();
+        for i in 0..3 {
+            let mut heightmap = ramp(200, 200, 0.0);
+            heightmap.set(10, 10, i as f32);
+            checkpoints.push(DestructiveOp::Generate, snapshot(heightmap));
+        }
+        let full: Vec<bool> = checkpoints.iter().map(|c| c.heightmap.is_full()).collect();
+        assert_eq!(full, [false, false, true]);
+        assert_eq!(checkpoints.iter().next().unwrap().heightmap.tiles.len(), 1);
+        assert!(checkpoints.iter().next().unwrap().masks[0].1.tiles.is_empty());
+    }
+
+    #[test]
+    fn taking_a_checkpoint_keeps_the_others() {
+        let maps: Vec<Heightmap> = (0..4).map(|i| {
+            let mut heightmap = ramp(100, 90, 0.0);
+            heightmap.set(i * 30, i * 20, 1.0);
+            heightmap
+        }).collect();
+        let mut checkpoints = Checkpoints::default();
+        for map in &maps {
+            checkpoints.push(DestructiveOp::Generate, snapshot(map.clone()));
+        }
+        // A resize in the middle of the chain
+        checkpoints.push(DestructiveOp::Resize, snapshot(ramp(50, 40, 0.5)));
+
+        let ids: Vec<u64> = checkpoints.info().iter().map(|c| c.id).collect();
+        assert_eq!(checkpoints.take(ids[1]).unwrap().heightmap.data, maps[1].data);
+        assert_eq!(checkpoints.take(ids[4]).unwrap().heightmap.data, ramp(50, 40, 0.5).data);
+        assert_eq!(checkpoints.take(ids[3]).unwrap().heightmap.data, maps[3].data);
+        let restored = checkpoints.take(ids[0]).unwrap();
+        assert_eq!(restored.heightmap.data, maps[0].data);
+        assert_eq!(restored.masks.get(MaskChannel::Selection).unwrap().data, ramp(100, 90, 0.0).data);
+        assert_eq!(checkpoints.take(ids[2]).unwrap().heightmap.data, maps[2].data);
+        assert!(checkpoints.take(ids[2]).is_none());
+    }
+
+    #[test]
+    fn oldest_checkpoints_are_dropped() {
+        let mut checkpoints = Checkpoints::default();
+        for i in 0..MAX_CHECKPOINTS + 2 {
+            checkpoints.push(DestructiveOp::Normalize, snapshot(ramp(8, 8, i as f32)));
+        }
+        let info = checkpoints.info();
+        assert_eq!(info.len(), MAX_CHECKPOINTS);
+        let oldest = checkpoints.take(info[0].id).unwrap();
+        assert_eq!(oldest.heightmap.data, ramp(8, 8, 2.0).data);
+    }
+}
@@ -1,5 +1,7 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
 use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
 
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -18,43 +20,247 @@ pub struct BrushStroke {
     pub radius: f32,
     pub strength: f32,
     pub op: BrushOp,
+    /// First dab of a drag, so usage statistics count the drag once.
+    #[serde(default)]
+    pub begins: bool,
 }
 
-/// Apply a brush stroke. Returns bounding box of affected region: (x, y, w, h).
-pub fn apply_brush(hm: &mut Heightmap, stroke: &BrushStroke) -> (u32, u32, u32, u32) {
-    let cx = stroke.x;
-    let cy = stroke.y;
-    let r = stroke.radius;
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RampParams {
+    pub x0: f32,
+    pub y0: f32,
+    pub x1: f32,
+    pub y1: f32,
+    /// Width of the flat road bed in pixels.
+    pub width: f32,
+    /// Maximum grade in percent (rise over run * 100).
+    pub max_grade: f32,
+    /// Width in pixels of the cut/fill embankment blending into the terrain.
+    pub falloff: f32,
+}
 
-    let x0 = (cx - r).floor().max(0.0) as u32;
-    let y0 = (cy - r).floor().max(0.0) as u32;
-    let x1 = ((cx + r).ceil() as u32).min(hm.width - 1);
-    let y1 = ((cy + r).ceil() as u32).min(hm.height - 1);
+/// Cut a constant-grade ramp between two points. The ramp runs between the terrain
+/// heights at both ends; if that exceeds `max_grade`, the slope is clamped around the
+/// midpoint elevation so cut and fill stay balanced.
+/// Returns bounding box of affected region: (x, y, w, h).
+pub fn apply_ramp(hm: &mut Heightmap, ramp: &RampParams, world: &WorldScale) -> (u32, u32, u32, u32) {
+    let dx = ramp.x1 - ramp.x0;
+    let dy = ramp.y1 - ramp.y0;
+    let len = (dx * dx + dy * dy).sqrt();
+    if len < 1.0 {
+        return (0, 0, 0, 0);
+    }
 
+    let h_start = hm.sample(ramp.x0, ramp.y0);
+    let h_end = hm.sample(ramp.x1, ramp.y1);
+    let range = (world.max_elevation - world.min_elevation).max(1e-6);
+    let max_rise = ramp.max_grade.max(0.0) / 100.0 * world.meters_per_pixel * len / range;
+    let rise = (h_end - h_start).clamp(-max_rise, max_rise);
+    let mid = 0.5 * (h_start + h_end);
+    let (h_start, h_end) = (mid - rise * 0.5, mid + rise * 0.5);
+
+    let half = ramp.width.max(0.0) * 0.5;
+    let reach = half + ramp.falloff.max(0.0);
+    let x0 = (ramp.x0.min(ramp.x1) - reach).floor().max(0.0) as u32;
+    let y0 = (ramp.y0.min(ramp.y1) - reach).floor().max(0.0) as u32;
+    let x1 = ((ramp.x0.max(ramp.x1) + reach).ceil().max(0.0) as u32).min(hm.width - 1);
+    let y1 = ((ramp.y0.max(ramp.y1) + reach).ceil().max(0.0) as u32).min(hm.height - 1);
     if x0 > x1 || y0 > y1 {
         return (0, 0, 0, 0);
     }
 
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            // Project onto the centerline; distance is to the segment so the ends are rounded
+            let rx = px as f32 - ramp.x0;
+            let ry = py as f32 - ramp.y0;
+            let t = ((rx * dx + ry * dy) / (len * len)).clamp(0.0, 1.0);
+            let ex = rx - dx * t;
+            let ey = ry - dy * t;
+            let dist = (ex * ex + ey * ey).sqrt();
+            if dist > reach {
+                continue;
+            }
+
+            let weight = if dist <= half {
+                1.0
+            } else {
+                let u = 1.0 - (dist - half) / (reach - half);
+                u * u * (3.0 - 2.0 * u)
+            };
+            let target = h_start + (h_end - h_start) * t;
+            let current = hm.get(px, py);
+            hm.set(px, py, (current + (target - current) * weight).clamp(0.0, 1.0));
+        }
+    }
+
+    (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PlatformParams {
+    /// Polygon vertices in pixel coordinates.
+    pub points: Vec<[f32; 2]>,
+    /// Normalized target height. Defaults to the mean height inside the polygon.
+    pub target_height: Option<f32>,
+    /// Embankment angle in degrees from horizontal for the cut/fill slopes around the pad.
+    pub embankment_angle: f32,
+}
+
+/// Flatten a polygon to a target height and grade the surroundings with embankments:
+/// outside the pad, terrain is clamped to within `distance * tan(angle)` of the target,
+/// cutting slopes into high ground and filling up low ground.
+/// Returns bounding box of affected region: (x, y, w, h).
+pub fn apply_platform(
+    hm: &mut Heightmap,
+    platform: &PlatformParams,
+    world: &WorldScale,
+) -> (u32, u32, u32, u32) {
+    let pts = &platform.points;
+    if pts.len() < 3 {
+        return (0, 0, 0, 0);
+    }
+
+    let target = platform
+        .target_height
+        .unwrap_or_else(|| polygon_mean_height(hm, pts))
+        .clamp(0.0, 1.0);
+
+    // Allowed height change per pixel of horizontal distance, in normalized units
+    let range = (world.max_elevation - world.min_elevation).max(1e-6);
+    let angle = platform.embankment_angle.clamp(1.0, 89.0).to_radians();
+    let slope = angle.tan() * world.meters_per_pixel / range;
+    let reach = (1.0 / slope).min((hm.width + hm.height) as f32);
+
+    let min_x = pts.iter().map(|p| p[0]).fold(f32::MAX, f32::min);
+    let max_x = pts.iter().map(|p| p[0]).fold(f32::MIN, f32::max);
+    let min_y = pts.iter().map(|p| p[1]).fold(f32::MAX, f32::min);
+    let max_y = pts.iter().map(|p| p[1]).fold(f32::MIN, f32::max);
+    let x0 = (min_x - reach).floor().max(0.0) as u32;
+    let y0 = (min_y - reach).floor().max(0.0) as u32;
+    let x1 = ((max_x + reach).ceil().max(0.0) as u32).min(hm.width - 1);
+    let y1 = ((max_y + reach).ceil().max(0.0) as u32).min(hm.height - 1);
+    if x0 > x1 || y0 > y1 {
+        return (0, 0, 0, 0);
+    }
+
+    let (mut cx0, mut cy0, mut cx1, mut cy1) = (u32::MAX, u32::MAX, 0, 0);
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            let p = [px as f32, py as f32];
+            let current = hm.get(px, py);
+            let new_val = if point_in_polygon(p, pts) {
+                target
+            } else {
+                let allowed = polygon_distance(p, pts) * slope;
+                current.clamp(target - allowed, target + allowed)
+            };
+            if new_val != current {
+                hm.set(px, py, new_val);
+                cx0 = cx0.min(px);
+                cy0 = cy0.min(py);
+                cx1 = cx1.max(px);
+                cy1 = cy1.max(py);
+            }
+        }
+    }
+
+    if cx0 > cx1 {
+        return (0, 0, 0, 0);
+    }
+    (cx0, cy0, cx1 - cx0 + 1, cy1 - cy0 + 1)
+}
+
+fn polygon_mean_height(hm: &Heightmap, pts: &[[f32; 2]]) -> f32 {
+    let mut sum = 0.0f32;
+    let mut count = 0u32;
+    for py in 0..hm.height {
+        for px in 0..hm.width {
+            if point_in_polygon([px as f32, py as f32], pts) {
+                sum += hm.get(px, py);
+                count += 1;
+            }
+        }
+    }
+    if count > 0 {
+        sum / count as f32
+    } else {
+        hm.sample(pts[0][0], pts[0][1])
+    }
+}
+
+/// Even-odd rule point-in-polygon test.
+fn point_in_polygon(p: [f32; 2], pts: &[[f32; 2]]) -> bool {
+    let mut inside = false;
+    let mut j = pts.len() - 1;
+    for i in 0..pts.len() {
+        let (a, b) = (pts[i], pts[j]);
+        if (a[1] > p[1]) != (b[1] > p[1])
+            && p[0] < (b[0] - a[0]) * (p[1] - a[1]) / (b[1] - a[1]) + a[0]
+        {
+            inside = !inside;
+        }
+        j = i;
+    }
+    inside
+}
+
+/// Distance from a point to the closest polygon edge.
+fn polygon_distance(p: [f32; 2], pts: &[[f32; 2]]) -> f32 {
+    let mut best = f32::MAX;
+    for i in 0..pts.len() {
+        let a = pts[i];
+        let b = pts[(i + 1) % pts.len()];
+        let (abx, aby) = (b[0] - a[0], b[1] - a[1]);
+        let len_sq = (abx * abx + aby * aby).max(1e-12);
+        let t = (((p[0] - a[0]) * abx + (p[1] - a[1]) * aby) / len_sq).clamp(0.0, 1.0);
+        let dx = p[0] - (a[0] + abx * t);
+        let dy = p[1] - (a[1] + aby * t);
+        best = best.min(dx * dx + dy * dy);
+    }
+    best.sqrt()
+}
+
+/// Apply a brush stroke. Returns bounding box of affected region: (x, y, w, h).
+/// Near the edge the footprint follows `boundary`: wrapping maps carry it over to
+/// the opposite side, every other mode clips it.
+pub fn apply_brush(hm: &mut Heightmap, stroke: &BrushStroke, boundary: Boundary) -> (u32, u32, u32, u32) {
+    let cx = stroke.x;
+    let cy = stroke.y;
+    let r = stroke.radius;
+
+    let x0 = (cx - r).floor() as i64;
+    let y0 = (cy - r).floor() as i64;
+    let x1 = (cx + r).ceil() as i64;
+    let y1 = (cy + r).ceil() as i64;
+
     // For flatten: sample target height at brush center
     let flatten_target = if matches!(stroke.op, BrushOp::Flatten) {
-        let ix = (cx.round() as u32).clamp(0, hm.width - 1);
-        let iy = (cy.round() as u32).clamp(0, hm.height - 1);
-        Some(hm.get(ix, iy))
+        Some(hm.get_bounded(cx.round() as i64, cy.round() as i64, boundary))
     } else {
         None
     };
 
     // For smooth: snapshot heights so we read original values
     let smooth_snapshot = if matches!(stroke.op, BrushOp::Smooth) {
-        Some(hm.data.clone())
+        Some(hm.clone())
     } else {
         None
     };
 
-    for py in y0..=y1 {
-        for px in x0..=x1 {
-            let dx = px as f32 - cx;
-            let dy = py as f32 - cy;
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for sy in y0..=y1 {
+        for sx in x0..=x1 {
+            let (Some(px), Some(py)) = (
+                boundary.resolve_write(sx, hm.width),
+                boundary.resolve_write(sy, hm.height),
+            ) else {
+                continue;
+            };
+            let dx = sx as f32 - cx;
+            let dy = sy as f32 - cy;
             let dist_sq = dx * dx + dy * dy;
             let r_sq = r * r;
             if dist_sq > r_sq {
@@ -75,39 +281,28 @@ pub fn apply_brush(hm: &mut Heightmap, stroke: &BrushStroke) -> (u32, u32, u32,
                 }
                 BrushOp::Smooth => {
                     let snap = smooth_snapshot.as_ref().unwrap();
-                    let avg = sample_avg(snap, hm.width, hm.height, px, py);
+                    let avg = sample_avg(snap, px, py, boundary);
                     current + (avg - current) * influence
                 }
             };
 
             hm.set(px, py, new_val.clamp(0.0, 1.0));
+            bounds = Some(match bounds {
+                Some((bx0, by0, bx1, by1)) => (bx0.min(px), by0.min(py), bx1.max(px), by1.max(py)),
+                None => (px, py, px, py),
+            });
         }
     }
 
-    let rw = x1 - x0 + 1;
-    let rh = y1 - y0 + 1;
-    (x0, y0, rw, rh)
+    match bounds {
+        Some((bx0, by0, bx1, by1)) => (bx0, by0, bx1 - bx0 + 1, by1 - by0 + 1),
+        None => (0, 0, 0, 0),
+    }
 }
 
-fn sample_avg(data: &[f32], w: u32, h: u32, x: u32, y: u32) -> f32 {
-    let idx = |x: u32, y: u32| data[(y * w + x) as usize];
-    let mut sum = idx(x, y);
-    let mut count = 1.0f32;
-    if x > 0 {
-        sum += idx(x - 1, y);
-        count += 1.0;
-    }
-    if x < w - 1 {
-        sum += idx(x + 1, y);
-        count += 1.0;
-    }
-    if y > 0 {
-        sum += idx(x, y - 1);
-        count += 1.0;
-    }
-    if y < h - 1 {
-        sum += idx(x, y + 1);
-        count += 1.0;
-    }
-    sum / count
+/// Average of a pixel and its four neighbors.
+fn sample_avg(hm: &Heightmap, x: u32, y: u32, boundary: Boundary) -> f32 {
+    let (x, y) = (x as i64, y as i64);
+    let at = |x: i64, y: i64| hm.get_bounded(x, y, boundary);
+    (at(x, y) + at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1)) / 5.0
 }
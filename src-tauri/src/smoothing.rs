@@ -0,0 +1,55 @@
+//! Feature-preserving cleanup filters for imported and generated heightmaps.
+
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CurvatureFlowParams {
+    pub iterations: u32,
+    /// Height change per pixel (normalized units) above which a slope counts as a
+    /// feature to keep. 8-bit stair-steps are 1/255 high, so a few times that
+    /// removes them while leaving cliffs and ridges alone.
+    pub feature_threshold: f32,
+}
+
+/// Explicit time step; the flow operator's coefficients sum to at most 2, so this
+/// stays below the stability limit.
+const DT: f32 = 0.2;
+
+/// Mean curvature flow of the height surface, damped on steep slopes. Heights are
+/// scaled so a slope of `feature_threshold` per pixel becomes 1: gentler slopes,
+/// which include the steps of quantized imports, smooth almost like heat
+/// diffusion, while steeper ones barely move. Large forms shrink slowly because
+/// curvature, not height, drives the flow.
+pub fn curvature_flow(hm: &mut Heightmap, params: &CurvatureFlowParams, boundary: Boundary) {
+    let w = hm.width as usize;
+    let scale = 1.0 / params.feature_threshold;
+    let mut next = vec![0.0f32; hm.data.len()];
+
+    for _ in 0..params.iterations {
+        let map = &*hm;
+        next.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, v) in row.iter_mut().enumerate() {
+                let (ix, iy) = (x as i64, y as i64);
+                let at = |dx: i64, dy: i64| map.get_bounded(ix + dx, iy + dy, boundary) * scale;
+                let center = at(0, 0);
+                let zx = (at(1, 0) - at(-1, 0)) * 0.5;
+                let zy = (at(0, 1) - at(0, -1)) * 0.5;
+                let zxx = at(1, 0) - 2.0 * center + at(-1, 0);
+                let zyy = at(0, 1) - 2.0 * center + at(0, -1);
+                let zxy = (at(1, 1) - at(1, -1) - at(-1, 1) + at(-1, -1)) * 0.25;
+
+                let slope_sq = zx * zx + zy * zy;
+                let curvature = ((1.0 + zy * zy) * zxx - 2.0 * zx * zy * zxy + (1.0 + zx * zx) * zyy)
+                    / (1.0 + slope_sq);
+                // Edge stopping, so the corners of a cliff don't round off either
+                let flow = curvature / (1.0 + slope_sq);
+                *v = (center + DT * flow) / scale;
+            }
+        });
+        std::mem::swap(&mut hm.data, &mut next);
+    }
+}
@@ -0,0 +1,145 @@
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+/// Snowfall above a snowline that slides off faces too steep to hold it. The
+/// result is a depth layer on top of the terrain; the heightmap is not changed.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SnowParams {
+    /// Normalized height where snow starts to settle.
+    pub snowline: f32,
+    /// Height band above the snowline over which snowfall ramps up to full.
+    pub transition: f32,
+    /// Depth of fresh snow at full snowfall, in normalized height units.
+    pub snowfall: f32,
+    /// Steepest snow surface in degrees that holds; snow on steeper faces slides.
+    pub max_slope: f32,
+    /// Slippage passes; more lets avalanches run further.
+    pub iterations: u32,
+}
+
+impl Default for SnowParams {
+    fn default() -> Self {
+        Self {
+            snowline: 0.6,
+            transition: 0.1,
+            snowfall: 0.005,
+            max_slope: 38.0,
+            iterations: 100,
+        }
+    }
+}
+
+/// Flow directions; also the index order of a cell's outflows.
+const NEIGHBORS: [(i64, i64); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+/// Share of its excess over the angle of repose a cell sheds per pass; half
+/// levels a pair of cells exactly.
+const SLIP_RATE: f32 = 0.5;
+
+/// Neighbor in direction `d`. Snow only crosses the edge of a wrapped map; other
+/// edges hold it.
+fn neighbor(boundary: Boundary, x: usize, y: usize, d: usize, w: usize, h: usize) -> Option<usize> {
+    let (dx, dy) = NEIGHBORS[d];
+    let (nx, ny) = (x as i64 + dx, y as i64 + dy);
+    if (0..w as i64).contains(&nx) && (0..h as i64).contains(&ny) {
+        return Some(ny as usize * w + nx as usize);
+    }
+    boundary
+        .wraps()
+        .then(|| ny.rem_euclid(h as i64) as usize * w + nx.rem_euclid(w as i64) as usize)
+}
+
+/// Snow depth per pixel, shaped like `hm`. Snow falls in proportion to height
+/// above the snowline, then repeatedly slides from any pixel whose snow surface
+/// stands above a neighbor's by more than `max_slope` allows, collecting at the
+/// feet of steep faces. Snow is only ever moved, never lost, except on `water`,
+/// a weight per pixel like the water mask's: none falls there, and what slides
+/// in melts.
+pub fn simulate(
+    hm: &Heightmap,
+    params: &SnowParams,
+    world: &WorldScale,
+    boundary: Boundary,
+    water: Option<&[f32]>,
+) -> Heightmap {
+    let w = hm.width as usize;
+    let h = hm.height as usize;
+    let relief = (world.max_elevation - world.min_elevation).max(f32::EPSILON);
+    // Steepest surface step between neighbors, in normalized units
+    let repose = params.max_slope.to_radians().tan() * world.meters_per_pixel / relief;
+
+    let mut depth = hm.clone();
+    depth.data.par_iter_mut().for_each(|d| {
+        let t = ((*d - params.snowline) / params.transition.max(f32::EPSILON)).clamp(0.0, 1.0);
+        *d = params.snowfall * t * t * (3.0 - 2.0 * t);
+    });
+    melt(&mut depth, water);
+
+    let mut outflow = vec![[0.0f32; 4]; w * h];
+    let mut next = vec![0.0f32; w * h];
+    for _ in 0..params.iterations {
+        let snow = &depth.data;
+        let surface = |i: usize| hm.data[i] + snow[i];
+        outflow.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, out) in row.iter_mut().enumerate() {
+                let idx = y * w + x;
+                *out = [0.0; 4];
+                if snow[idx] <= 0.0 {
+                    continue;
+                }
+                let mut total = 0.0;
+                let mut steepest = 0.0f32;
+                for (d, excess) in out.iter_mut().enumerate() {
+                    if let Some(n) = neighbor(boundary, x, y, d, w, h) {
+                        *excess = (surface(idx) - surface(n) - repose).max(0.0);
+                        total += *excess;
+                        steepest = steepest.max(*excess);
+                    }
+                }
+                if total <= 0.0 {
+                    continue;
+                }
+                let shed = (steepest * SLIP_RATE).min(snow[idx]);
+                for excess in out.iter_mut() {
+                    *excess *= shed / total;
+                }
+            }
+        });
+
+        let outflow = &outflow;
+        next.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+            for (x, v) in row.iter_mut().enumerate() {
+                let idx = y * w + x;
+                let mut amount = snow[idx] - outflow[idx].iter().sum::<f32>();
+                for d in 0..4 {
+                    if let Some(n) = neighbor(boundary, x, y, d, w, h) {
+                        amount += outflow[n][d ^ 1];
+                    }
+                }
+                *v = amount.max(0.0);
+            }
+        });
+        std::mem::swap(&mut depth.data, &mut next);
+    }
+    melt(&mut depth, water);
+    depth
+}
+
+/// Take away the share of each pixel's snow that lies on water.
+fn melt(depth: &mut Heightmap, water: Option<&[f32]>) {
+    if let Some(water) = water {
+        depth.data.par_iter_mut().zip(water).for_each(|(d, &w)| *d *= 1.0 - w.clamp(0.0, 1.0));
+    }
+}
+
+/// Depth scaled to [0, 1] coverage, full where it reaches `snowfall`; drifts
+/// deeper than fresh snow saturate.
+pub fn coverage(depth: &Heightmap, params: &SnowParams) -> Heightmap {
+    let mut coverage = depth.clone();
+    let full = params.snowfall.max(f32::EPSILON);
+    coverage.data.par_iter_mut().for_each(|d| *d = (*d / full).min(1.0));
+    coverage
+}
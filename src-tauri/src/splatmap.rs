@@ -0,0 +1,302 @@
+//! Splatmaps: per-pixel weights of terrain material layers, chosen by rules on
+//! height, slope, wetness and ambient occlusion, and written as RGBA weight maps
+//! of four layers each, the layout Unity and Unreal terrain layers import.
+
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+use crate::hydrology::{self, FlowMethod};
+use crate::project;
+use crate::world::WorldScale;
+
+/// Per-pixel value a rule tests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Attribute {
+    /// Normalized height.
+    Height,
+    /// Degrees.
+    Slope,
+    /// Topographic wetness index, ln(catchment / tan slope), scaled to [0, 1]
+    /// over the map: high in valley floors and flats that collect water.
+    Wetness,
+    /// Ambient occlusion, 1 under open sky down to 0 where the surrounding
+    /// terrain hides it.
+    Occlusion,
+}
+
+/// The attribute must lie in `[min, max]`; either bound may be left open.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Condition {
+    pub attribute: Attribute,
+    #[serde(default)]
+    pub min: Option<f32>,
+    #[serde(default)]
+    pub max: Option<f32>,
+    /// Width of the blend across each bound, in the attribute's units; 0 for a
+    /// hard edge.
+    #[serde(default)]
+    pub falloff: f32,
+}
+
+impl Condition {
+    fn new(attribute: Attribute, min: Option<f32>, max: Option<f32>, falloff: f32) -> Self {
+        Self { attribute, min, max, falloff }
+    }
+
+    /// How far `v` meets the condition, 0 to 1.
+    fn weight(&self, v: f32) -> f32 {
+        let above = |bound: f32| {
+            if self.falloff > 0.0 {
+                let t = ((v - bound) / self.falloff + 0.5).clamp(0.0, 1.0);
+                t * t * (3.0 - 2.0 * t)
+            } else if v >= bound {
+                1.0
+            } else {
+                0.0
+            }
+        };
+        let lower = self.min.map_or(1.0, above);
+        let upper = self.max.map_or(1.0, |max| 1.0 - above(max));
+        lower * upper
+    }
+}
+
+/// A material layer, covering the pixels where all its conditions hold.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplatLayer {
+    pub name: String,
+    #[serde(default)]
+    pub conditions: Vec<Condition>,
+}
+
+/// Layers bottom to top: each covers those before it wherever its conditions
+/// hold, and the first is the base that shows wherever no other does, so the
+/// weights at every pixel add up to 1.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SplatRules {
+    pub layers: Vec<SplatLayer>,
+}
+
+impl Default for SplatRules {
+    fn default() -> Self {
+        let layer = |name: &str, conditions: Vec<Condition>| SplatLayer { name: name.to_string(), conditions };
+        Self {
+            layers: vec![
+                layer("grass", vec![]),
+                layer("sand", vec![Condition::new(Attribute::Height, None, Some(0.08), 0.02)]),
+                layer("rock", vec![Condition::new(Attribute::Slope, Some(40.0), None, 6.0)]),
+                layer(
+                    "snow",
+                    vec![
+                        Condition::new(Attribute::Height, Some(0.85), None, 0.04),
+                        Condition::new(Attribute::Slope, None, Some(50.0), 6.0),
+                    ],
+                ),
+            ],
+        }
+    }
+}
+
+/// Most layers `SplatRules` may hold, four RGBA maps' worth.
+pub const MAX_LAYERS: usize = 16;
+
+impl SplatRules {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=MAX_LAYERS).contains(&self.layers.len()) {
+            return Err(format!("Splatmaps need between 1 and {MAX_LAYERS} layers"));
+        }
+        for (i, layer) in self.layers.iter().enumerate() {
+            if layer.name.trim().is_empty() {
+                return Err("Splat layers need a name".into());
+            }
+            if self.layers[..i].iter().any(|other| other.name == layer.name) {
+                return Err(format!("Duplicate splat layer name: {}", layer.name));
+            }
+            for c in &layer.conditions {
+                let bounds = c.min.is_none_or(f32::is_finite) && c.max.is_none_or(f32::is_finite);
+                if !(bounds && c.falloff.is_finite() && c.falloff >= 0.0) {
+                    return Err(format!("Invalid condition on splat layer {}", layer.name));
+                }
+                if let (Some(min), Some(max)) = (c.min, c.max) {
+                    if min > max {
+                        return Err(format!("Condition minimum above maximum on splat layer {}", layer.name));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn uses(&self, attribute: Attribute) -> bool {
+        self.layers.iter().flat_map(|l| &l.conditions).any(|c| c.attribute == attribute)
+    }
+}
+
+/// Topographic wetness index per pixel, scaled to [0, 1] over the map. Drainage
+/// area comes from D-infinity routing, which spreads it over open slopes.
+pub fn wetness(hm: &Heightmap, slope: &Heightmap, world: &WorldScale, boundary: Boundary) -> Vec<f32> {
+    let tree = hydrology::drainage(hm, boundary, &|_| {});
+    let area = hydrology::accumulation(&tree, hm, boundary, FlowMethod::DInfinity);
+    // Catchment per unit contour width; flats get a small slope so they stay finite
+    let mut twi: Vec<f32> = area
+        .iter()
+        .zip(&slope.data)
+        .map(|(&a, &s)| (a * world.meters_per_pixel / s.to_radians().tan().max(1e-3)).ln())
+        .collect();
+    let (lo, hi) = twi.iter().fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+    let range = (hi - lo).max(f32::EPSILON);
+    for v in &mut twi {
+        *v = (*v - lo) / range;
+    }
+    twi
+}
+
+/// Distances in pixels at which `occlusion` looks for the horizon, sparser
+/// further out.
+const HORIZON_STEPS: [f32; 11] = [1.0, 2.0, 3.0, 4.0, 6.0, 8.0, 12.0, 16.0, 24.0, 32.0, 48.0];
+
+/// Ambient occlusion per pixel from the horizon in eight directions: the sky
+/// each direction leaves visible is one minus the sine of its horizon angle,
+/// averaged. Heights are in meters, so the world scale sets how enclosed valleys
+/// are.
+pub fn occlusion(hm: &Heightmap, world: &WorldScale, boundary: Boundary) -> Vec<f32> {
+    const DIRECTIONS: [(f32, f32); 8] = [
+        (1.0, 0.0),
+        (1.0, 1.0),
+        (0.0, 1.0),
+        (-1.0, 1.0),
+        (-1.0, 0.0),
+        (-1.0, -1.0),
+        (0.0, -1.0),
+        (1.0, -1.0),
+    ];
+    let w = hm.width as usize;
+    let relief = world.max_elevation - world.min_elevation;
+    let mut ao = vec![0.0f32; hm.data.len()];
+    ao.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+        for (x, out) in row.iter_mut().enumerate() {
+            let h0 = hm.data[y * w + x];
+            let mut visible = 0.0;
+            for (dx, dy) in DIRECTIONS {
+                let step = dx.hypot(dy);
+                let mut horizon = 0.0f32;
+                for d in HORIZON_STEPS {
+                    let (sx, sy) = ((x as f32 + dx * d).round() as i64, (y as f32 + dy * d).round() as i64);
+                    let rise = (hm.get_bounded(sx, sy, boundary) - h0) * relief;
+                    horizon = horizon.max(rise / (d * step * world.meters_per_pixel));
+                }
+                // Sine of the horizon angle from its tangent
+                visible += 1.0 - horizon / horizon.hypot(1.0);
+            }
+            *out = visible / DIRECTIONS.len() as f32;
+        }
+    });
+    ao
+}
+
+/// Layer weights, one `Vec` per layer in rule order. `slope` holds degrees, as
+/// from `DerivedCache::slope`.
+pub fn weights(
+    hm: &Heightmap,
+    slope: &Heightmap,
+    world: &WorldScale,
+    boundary: Boundary,
+    rules: &SplatRules,
+) -> Vec<Vec<f32>> {
+    let wetness = rules.uses(Attribute::Wetness).then(|| wetness(hm, slope, world, boundary));
+    let occlusion = rules.uses(Attribute::Occlusion).then(|| occlusion(hm, world, boundary));
+    let value = |attribute: Attribute, i: usize| match attribute {
+        Attribute::Height => hm.data[i],
+        Attribute::Slope => slope.data[i],
+        Attribute::Wetness => wetness.as_ref().map_or(0.0, |v| v[i]),
+        Attribute::Occlusion => occlusion.as_ref().map_or(1.0, |v| v[i]),
+    };
+
+    // Top down: each layer takes its share of what the ones above leave
+    let mut remaining = vec![1.0f32; hm.data.len()];
+    let mut weights = vec![Vec::new(); rules.layers.len()];
+    for (k, layer) in rules.layers.iter().enumerate().rev() {
+        weights[k] = remaining
+            .iter_mut()
+            .enumerate()
+            .map(|(i, left)| {
+                let cover: f32 = if k == 0 {
+                    1.0
+                } else {
+                    layer.conditions.iter().map(|c| c.weight(value(c.attribute, i))).product()
+                };
+                let weight = *left * cover;
+                *left *= 1.0 - cover;
+                weight
+            })
+            .collect();
+    }
+    weights
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplatLayerInfo {
+    pub name: String,
+    /// Index of the weight map holding the layer.
+    pub texture: usize,
+    /// `r`, `g`, `b` or `a`.
+    pub channel: &'static str,
+    /// Share of the map the layer covers, 0-1.
+    pub coverage: f32,
+}
+
+/// Written files, weight maps first and the manifest last.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SplatExport {
+    pub layers: Vec<SplatLayerInfo>,
+    #[serde(skip)]
+    pub written: Vec<PathBuf>,
+}
+
+/// Write the weights as 8-bit RGBA PNGs next to `path`, four layers to each in
+/// rule order (`terrain_splat0.png`, `terrain_splat1.png`, ...), with unused
+/// channels left at zero, plus a `_splat.json` manifest of where each layer went.
+pub fn export(path: &Path, width: u32, height: u32, rules: &SplatRules, weights: &[Vec<f32>]) -> Result<SplatExport, String> {
+    const CHANNELS: [&str; 4] = ["r", "g", "b", "a"];
+    let mut written = Vec::new();
+    for (texture, group) in weights.chunks(4).enumerate() {
+        let mut pixels = vec![0u8; width as usize * height as usize * 4];
+        for (c, layer) in group.iter().enumerate() {
+            for (i, &w) in layer.iter().enumerate() {
+                pixels[i * 4 + c] = (w.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+        let img = image::RgbaImage::from_raw(width, height, pixels).ok_or("Failed to create image buffer")?;
+        let texture_path = project::sidecar_path(path, &format!("_splat{texture}"), "png");
+        img.save(&texture_path).map_err(|e| format!("Failed to save splatmap: {e}"))?;
+        written.push(texture_path);
+    }
+
+    let total = (width as usize * height as usize).max(1) as f32;
+    let layers = rules
+        .layers
+        .iter()
+        .zip(weights)
+        .enumerate()
+        .map(|(k, (layer, w))| SplatLayerInfo {
+            name: layer.name.clone(),
+            texture: k / 4,
+            channel: CHANNELS[k % 4],
+            coverage: w.iter().sum::<f32>() / total,
+        })
+        .collect();
+    let mut export = SplatExport { layers, written };
+    let manifest = project::sidecar_path(path, "_splat", "json");
+    let json = serde_json::to_string_pretty(&export).map_err(|e| format!("Failed to serialize splatmap manifest: {e}"))?;
+    std::fs::write(&manifest, json).map_err(|e| format!("Failed to write splatmap manifest: {e}"))?;
+    export.written.push(manifest);
+    Ok(export)
+}
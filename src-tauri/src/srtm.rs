@@ -0,0 +1,153 @@
+//! SRTM `.hgt` tiles: one degree square of big-endian 16-bit elevations in
+//! meters, 1201 samples a side for SRTM3 or 3601 for SRTM1, named after the
+//! south-west corner as in `N45E006.hgt`. Adjacent tiles share their edge rows
+//! and columns, so several stitch into one grid without seams.
+
+use std::path::{Path, PathBuf};
+use crate::float_image;
+use crate::geotiff::Dem;
+use crate::heightmap::Heightmap;
+use crate::world::Georeference;
+
+/// Marks samples the radar didn't return, e.g. in deep shadow or over water.
+const VOID: i16 = -32768;
+
+/// Stitch `paths`, in any order, into one DEM covering their bounding box.
+/// Voids, including tiles missing from the box, are filled from the data
+/// around them.
+pub fn read(paths: &[PathBuf]) -> Result<Dem, String> {
+    if paths.is_empty() {
+        return Err("No SRTM tiles given".into());
+    }
+    let tiles = paths
+        .iter()
+        .map(|path| {
+            let corner = tile_corner(path)?;
+            let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+            Ok((corner, bytes))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let side = match tiles[0].1.len() {
+        2_884_802 => 1201,
+        25_934_402 => 3601,
+        len => return Err(format!("{} is not an SRTM1 or SRTM3 tile ({len} bytes)", paths[0].display())),
+    };
+    if let Some(i) = tiles.iter().position(|(_, bytes)| bytes.len() != side * side * 2) {
+        return Err(format!("{} has a different resolution from the other tiles", paths[i].display()));
+    }
+    for (i, (corner, _)) in tiles.iter().enumerate() {
+        if tiles[..i].iter().any(|(c, _)| c == corner) {
+            return Err(format!("{} is given twice", paths[i].display()));
+        }
+    }
+
+    let cells = side - 1;
+    let (lat_min, lat_max) = tiles.iter().fold((i32::MAX, i32::MIN), |(lo, hi), ((lat, _), _)| (lo.min(*lat), hi.max(*lat)));
+    let (lon_min, lon_max) = tiles.iter().fold((i32::MAX, i32::MIN), |(lo, hi), ((_, lon), _)| (lo.min(*lon), hi.max(*lon)));
+    let width = (lon_max - lon_min + 1) as usize * cells + 1;
+    let height = (lat_max - lat_min + 1) as usize * cells + 1;
+    float_image::check_size(width as u32, height as u32).map_err(|e| format!("The tiles span too large an area: {e}"))?;
+
+    let mut samples = Heightmap::new(width as u32, height as u32);
+    samples.data.fill(VOID as f32);
+    for ((lat, lon), bytes) in &tiles {
+        // Rows run from the north edge south
+        let x0 = (lon - lon_min) as usize * cells;
+        let y0 = (lat_max - lat) as usize * cells;
+        for (y, row) in bytes.chunks_exact(side * 2).enumerate() {
+            let out = &mut samples.data[(y0 + y) * width + x0..][..side];
+            for (v, pair) in out.iter_mut().zip(row.chunks_exact(2)) {
+                let sample = i16::from_be_bytes([pair[0], pair[1]]);
+                // A shared edge keeps whichever tile has data there
+                if sample != VOID {
+                    *v = sample as f32;
+                }
+            }
+        }
+    }
+
+    let known: Vec<bool> = samples.data.iter().map(|&v| v != VOID as f32).collect();
+    if !known.contains(&true) {
+        return Err("The SRTM tiles hold no elevation data".into());
+    }
+    fill_voids(&mut samples.data, &known, width, height);
+
+    // Samples sit on whole fractions of a degree, so cells are centered on them
+    let cell = 1.0 / cells as f64;
+    Ok(Dem {
+        samples,
+        nodata: None,
+        georeference: Some(Georeference {
+            epsg: Some(4326),
+            geographic: true,
+            origin: [lon_min as f64 - cell / 2.0, (lat_max + 1) as f64 + cell / 2.0],
+            pixel_size: [cell, cell],
+        }),
+        linear_unit: 1.0,
+        vertical_unit: 1.0,
+    })
+}
+
+/// South-west corner of a tile in whole degrees, from the first seven
+/// characters of its name.
+fn tile_corner(path: &Path) -> Result<(i32, i32), String> {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or_default().to_ascii_uppercase();
+    let bad = || format!("{} is not named like an SRTM tile, e.g. N45E006.hgt", path.display());
+    let number = |range: std::ops::Range<usize>| name.get(range).and_then(|s| s.parse::<i32>().ok()).ok_or_else(bad);
+    let lat = match name.as_bytes().first() {
+        Some(b'N') => number(1..3)?,
+        Some(b'S') => -number(1..3)?,
+        _ => return Err(bad()),
+    };
+    let lon = match name.as_bytes().get(3) {
+        Some(b'E') => number(4..7)?,
+        Some(b'W') => -number(4..7)?,
+        _ => return Err(bad()),
+    };
+    if !(-90..90).contains(&lat) || !(-180..180).contains(&lon) {
+        return Err(bad());
+    }
+    Ok((lat, lon))
+}
+
+/// Fill the cells of `values` that aren't `known` smoothly from those that
+/// are: averages over ever coarser grids reach across voids of any size, and
+/// are interpolated back down into the voids alone.
+fn fill_voids(values: &mut [f32], known: &[bool], width: usize, height: usize) {
+    if known.iter().all(|&k| k) {
+        return;
+    }
+    let (cw, ch) = (width.div_ceil(2), height.div_ceil(2));
+    let mut sums = vec![0.0f32; cw * ch];
+    let mut counts = vec![0u32; cw * ch];
+    for y in 0..height {
+        for x in 0..width {
+            if known[y * width + x] {
+                let i = y / 2 * cw + x / 2;
+                sums[i] += values[y * width + x];
+                counts[i] += 1;
+            }
+        }
+    }
+    let mut coarse: Vec<f32> = sums.iter().zip(&counts).map(|(&s, &n)| if n > 0 { s / n as f32 } else { 0.0 }).collect();
+    let coarse_known: Vec<bool> = counts.iter().map(|&n| n > 0).collect();
+    fill_voids(&mut coarse, &coarse_known, cw, ch);
+
+    let at = |x: f32, y: f32| {
+        let (x, y) = (x.clamp(0.0, (cw - 1) as f32), y.clamp(0.0, (ch - 1) as f32));
+        let (x0, y0) = (x.floor() as usize, y.floor() as usize);
+        let (x1, y1) = ((x0 + 1).min(cw - 1), (y0 + 1).min(ch - 1));
+        let (fx, fy) = (x - x0 as f32, y - y0 as f32);
+        let top = coarse[y0 * cw + x0] * (1.0 - fx) + coarse[y0 * cw + x1] * fx;
+        let bottom = coarse[y1 * cw + x0] * (1.0 - fx) + coarse[y1 * cw + x1] * fx;
+        top * (1.0 - fy) + bottom * fy
+    };
+    for y in 0..height {
+        for x in 0..width {
+            if !known[y * width + x] {
+                values[y * width + x] = at((x as f32 + 0.5) / 2.0 - 0.5, (y as f32 + 0.5) / 2.0 - 0.5);
+            }
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+use crate::ai;
+use crate::boundary::Boundary;
+use crate::heightmap::Heightmap;
+use crate::noise_gen::{self, NoiseParams};
+use crate::world::WorldScale;
+
+/// One entry in a generative stack. Layers are applied bottom to top, each blending
+/// into the result of the layers below it with `params.blend`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoiseLayer {
+    pub params: NoiseParams,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Limit the layer to terrain below it within a height band (normalized units).
+    #[serde(default)]
+    pub height: Option<RuleRange>,
+    /// Limit the layer to terrain below it within a slope band (degrees).
+    #[serde(default)]
+    pub slope: Option<RuleRange>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Full weight inside `[min, max]`, fading to zero over `falloff` on either side.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleRange {
+    pub min: f32,
+    pub max: f32,
+    #[serde(default)]
+    pub falloff: f32,
+}
+
+impl RuleRange {
+    fn weight(&self, v: f32) -> f32 {
+        let below = self.min - v;
+        let above = v - self.max;
+        let outside = below.max(above);
+        if outside <= 0.0 {
+            1.0
+        } else if self.falloff > 0.0 {
+            let t = (1.0 - outside / self.falloff).clamp(0.0, 1.0);
+            t * t * (3.0 - 2.0 * t)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Evaluate the whole stack into `hm`. Layers with `useSelection` are additionally
+/// limited to `selection` when one is given.
+pub fn generate(
+    hm: &mut Heightmap,
+    layers: &[NoiseLayer],
+    world: &WorldScale,
+    boundary: Boundary,
+    selection: Option<&[f32]>,
+) {
+    for layer in layers.iter().filter(|l| l.enabled) {
+        let mut weights = rule_weights(hm, layer, world, boundary);
+
+        if layer.params.use_selection {
+            if let Some(selection) = selection {
+                let selection = if layer.params.mask_feather > 0 {
+                    ai::feather_mask(selection, hm.width, hm.height, layer.params.mask_feather)
+                } else {
+                    selection.to_vec()
+                };
+                let w = weights.get_or_insert_with(|| vec![1.0; hm.data.len()]);
+                for (w, s) in w.iter_mut().zip(selection) {
+                    *w *= s;
+                }
+            }
+        }
+
+        match weights {
+            Some(weights) => noise_gen::generate_terrain_masked(hm, &layer.params, &weights),
+            None => noise_gen::generate_terrain(hm, &layer.params),
+        }
+    }
+}
+
+/// Per-pixel weights from the layer's height/slope rules, measured on the terrain
+/// generated so far. `None` when the layer has no rules.
+fn rule_weights(hm: &Heightmap, layer: &NoiseLayer, world: &WorldScale, boundary: Boundary) -> Option<Vec<f32>> {
+    if layer.height.is_none() && layer.slope.is_none() {
+        return None;
+    }
+
+    let relief = world.max_elevation - world.min_elevation;
+    let run = 2.0 * world.meters_per_pixel.max(1e-6);
+    let (w, h) = (hm.width, hm.height);
+    let mut weights = Vec::with_capacity(hm.data.len());
+
+    for y in 0..h {
+        for x in 0..w {
+            let mut weight = 1.0;
+            if let Some(range) = &layer.height {
+                weight *= range.weight(hm.get(x, y));
+            }
+            if let Some(range) = &layer.slope {
+                let (ix, iy) = (x as i64, y as i64);
+                let dx = hm.get_bounded(ix + 1, iy, boundary) - hm.get_bounded(ix - 1, iy, boundary);
+                let dy = hm.get_bounded(ix, iy + 1, boundary) - hm.get_bounded(ix, iy - 1, boundary);
+                let rise = (dx * dx + dy * dy).sqrt() * relief;
+                weight *= range.weight((rise / run).atan().to_degrees());
+            }
+            weights.push(weight);
+        }
+    }
+    Some(weights)
+}
@@ -0,0 +1,163 @@
+//! Heightmap files blended into part of the map, e.g. a scanned mountain into a
+//! generated landscape, instead of replacing all of it.
+
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use crate::float_image::{self, Normalization};
+use crate::heightmap::Heightmap;
+use crate::noise_gen::BlendMode;
+use crate::project;
+
+/// Where the stamp goes.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum StampTarget {
+    /// A rectangle in map pixels the stamp is stretched over.
+    Rect { x: f32, y: f32, width: f32, height: f32 },
+    /// The bounding box of the selection, blended through the selection's
+    /// weights.
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StampParams {
+    pub target: StampTarget,
+    /// Size relative to the target, about its center.
+    #[serde(default = "one")]
+    pub scale: f32,
+    /// Degrees clockwise about the target's center.
+    #[serde(default)]
+    pub rotation: f32,
+    /// Heights the stamp's lowest and highest samples map to.
+    #[serde(default)]
+    pub height_low: f32,
+    #[serde(default = "one")]
+    pub height_high: f32,
+    /// How the remapped stamp combines with the terrain. `Replace` ignores
+    /// the feathering; `Lerp` fades into it.
+    #[serde(default = "lerp")]
+    pub blend: BlendMode,
+    #[serde(default = "one")]
+    pub opacity: f32,
+    /// Width in pixels over which the stamp's edges, or the selection's,
+    /// fade out.
+    #[serde(default)]
+    pub feather: f32,
+}
+
+fn one() -> f32 {
+    1.0
+}
+
+fn lerp() -> BlendMode {
+    BlendMode::Lerp
+}
+
+impl StampParams {
+    pub fn validate(&self) -> Result<(), String> {
+        let finite = [self.scale, self.rotation, self.height_low, self.height_high, self.opacity, self.feather]
+            .iter()
+            .all(|v| v.is_finite());
+        if !finite || self.scale <= 0.0 {
+            return Err("Stamp scale must be positive".into());
+        }
+        if !(0.0..=1.0).contains(&self.opacity) || self.feather < 0.0 {
+            return Err("Stamp opacity must be between 0 and 1 and feathering not negative".into());
+        }
+        if let StampTarget::Rect { width, height, .. } = self.target {
+            if !(width >= 1.0 && height >= 1.0) {
+                return Err("Stamp rectangle must be at least one pixel wide and high".into());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A heightmap image to stamp: PNG at its own bit depth, or a float TIFF or
+/// OpenEXR spread over its own range.
+pub fn load(path: &Path) -> Result<Heightmap, String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    match bytes.get(..2) {
+        Some(b"II") | Some(b"MM") | Some([0x76, 0x2f]) => {
+            let mut image = float_image::read(path)?;
+            Normalization::Auto.apply(&mut image.data);
+            Ok(image)
+        }
+        _ => project::decode_heightmap_image(&bytes),
+    }
+}
+
+/// Bounding box of the cells with any selection weight.
+fn selection_bounds(selection: &[f32], width: u32) -> Option<(f32, f32, f32, f32)> {
+    let w = width as usize;
+    let (mut x0, mut y0, mut x1, mut y1) = (usize::MAX, usize::MAX, 0, 0);
+    for (i, _) in selection.iter().enumerate().filter(|(_, &v)| v > 1e-3) {
+        let (x, y) = (i % w, i / w);
+        (x0, y0, x1, y1) = (x0.min(x), y0.min(y), x1.max(x), y1.max(y));
+    }
+    (x0 <= x1).then(|| (x0 as f32, y0 as f32, (x1 - x0 + 1) as f32, (y1 - y0 + 1) as f32))
+}
+
+/// Blend `image` into `hm` per `params`. `selection` holds feathered weights
+/// for `StampTarget::Selection`. Returns the bounding box of affected region:
+/// (x, y, w, h).
+pub fn stamp(
+    hm: &mut Heightmap,
+    image: &Heightmap,
+    params: &StampParams,
+    selection: Option<&[f32]>,
+) -> Result<(u32, u32, u32, u32), String> {
+    let (rx, ry, rw, rh) = match params.target {
+        StampTarget::Rect { x, y, width, height } => (x, y, width, height),
+        StampTarget::Selection => {
+            let selection = selection.ok_or("There is no selection to stamp into")?;
+            selection_bounds(selection, hm.width).ok_or("The selection is empty")?
+        }
+    };
+    let (cx, cy) = (rx + rw / 2.0, ry + rh / 2.0);
+    let (sw, sh) = (rw * params.scale, rh * params.scale);
+    let (sin, cos) = params.rotation.to_radians().sin_cos();
+
+    // Footprint of the rotated stamp on the map
+    let half_w = (sw * cos.abs() + sh * sin.abs()) / 2.0;
+    let half_h = (sw * sin.abs() + sh * cos.abs()) / 2.0;
+    let x0 = (cx - half_w).floor().max(0.0) as u32;
+    let y0 = (cy - half_h).floor().max(0.0) as u32;
+    let x1 = ((cx + half_w).ceil().max(0.0) as u32).min(hm.width - 1);
+    let y1 = ((cy + half_h).ceil().max(0.0) as u32).min(hm.height - 1);
+    if x0 > x1 || y0 > y1 {
+        return Ok((0, 0, 0, 0));
+    }
+
+    let span = params.height_high - params.height_low;
+    let (iw, ih) = ((image.width - 1) as f32, (image.height - 1) as f32);
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            // Map pixel into the stamp's frame: u and v run 0 to 1 across it
+            let (dx, dy) = (px as f32 + 0.5 - cx, py as f32 + 0.5 - cy);
+            let u = (dx * cos + dy * sin) / sw + 0.5;
+            let v = (dy * cos - dx * sin) / sh + 0.5;
+            if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+                continue;
+            }
+            let mut weight = params.opacity;
+            match (params.target, selection) {
+                (StampTarget::Selection, Some(selection)) => weight *= selection[(py * hm.width + px) as usize],
+                _ if params.feather > 0.0 => {
+                    let edge = (u.min(1.0 - u) * sw).min(v.min(1.0 - v) * sh);
+                    let t = (edge / params.feather).min(1.0);
+                    weight *= t * t * (3.0 - 2.0 * t);
+                }
+                _ => {}
+            }
+            if weight <= 0.0 {
+                continue;
+            }
+            let value = params.height_low + image.sample(u * iw, v * ih) * span;
+            let i = (py * hm.width + px) as usize;
+            hm.data[i] = params.blend.apply(hm.data[i], value, weight);
+        }
+    }
+    Ok((x0, y0, x1 - x0 + 1, y1 - y0 + 1))
+}
@@ -1,19 +1,127 @@
-use std::sync::{Arc, Mutex};
-use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::SyncSender;
+use crate::boundary::Boundary;
+use crate::derived::{DerivedCache, Edits};
+use crate::detail::DetailPatch;
+use crate::erosion::hydraulic::{DropletTrace, ErosionMaps};
 use crate::heightmap::Heightmap;
+use crate::hooks::ExportHook;
+use crate::mask::MaskSet;
+use crate::noise_gen::Frame;
+use crate::project::ProjectMetadata;
+use crate::provenance::OperationLog;
+use crate::safety::{Checkpoints, Confirmations};
+use crate::sculpt::BrushStroke;
+use crate::texture::Texture;
+use crate::tiles::TileGrid;
+use crate::usage::UsageStats;
+use crate::world::WorldScale;
 
 pub struct AppState {
     pub heightmap: Arc<Mutex<Heightmap>>,
     pub erosion_abort: Arc<AtomicBool>,
     pub erosion_running: Arc<AtomicBool>,
+    /// Asks a hydraulic run to suspend; cleared to resume it.
+    pub erosion_pause: Arc<AtomicBool>,
+    /// Set while a hydraulic run is suspended and has released the heightmap.
+    pub erosion_suspended: Arc<AtomicBool>,
+    /// Wakes whoever waits on the erosion flags above when one changes.
+    pub erosion_signal: Arc<FlagSignal>,
+    pub world_scale: Arc<Mutex<WorldScale>>,
+    /// How algorithms treat the map edge.
+    pub boundary: Arc<Mutex<Boundary>>,
+    pub masks: Arc<Mutex<MaskSet>>,
+    /// Maps from the last hydraulic run that recorded them.
+    pub erosion_maps: Arc<Mutex<Option<ErosionMaps>>>,
+    /// Droplet paths from the last hydraulic run that traced them.
+    pub droplet_traces: Arc<Mutex<Vec<DropletTrace>>>,
+    pub tile_grid: Arc<Mutex<Option<TileGrid>>>,
+    pub export_hooks: Arc<Mutex<Vec<ExportHook>>>,
+    /// Feeds the stroke worker while a stroke stream is open.
+    pub stroke_queue: Arc<Mutex<Option<SyncSender<BrushStroke>>>>,
+    /// Normals, slope and mips, refreshed in the background while idle.
+    pub derived: Arc<Mutex<DerivedCache>>,
+    /// Where heightmap edits are reported so `derived` rehashes only what changed.
+    pub edits: Arc<Edits>,
+    /// Bumped by every change to the saved document outside the heightmap, so
+    /// autosave notices it. Heightmap edits reach autosave through `derived`.
+    pub revision: Arc<AtomicU64>,
+    /// Noise frame of an expanded canvas; `None` while the map is its own frame.
+    pub canvas_frame: Arc<Mutex<Option<Frame>>>,
+    /// Higher-resolution residual grids over parts of the map.
+    pub detail_patches: Arc<Mutex<Vec<DetailPatch>>>,
+    /// Opt-in statistics for the open project.
+    pub usage: Arc<Mutex<UsageStats>>,
+    /// Tokens for whole-map operations the user has confirmed.
+    pub confirmations: Arc<Mutex<Confirmations>>,
+    /// Document states saved before destructive operations.
+    pub checkpoints: Arc<Mutex<Checkpoints>>,
+    /// The color texture shown on the terrain, if any.
+    pub texture: Arc<Mutex<Option<Texture>>>,
+    /// Name, author, description and tags saved with the project.
+    pub metadata: Arc<Mutex<ProjectMetadata>>,
+    /// Generation, erosion and filter operations run on the open project.
+    pub operations: Arc<Mutex<OperationLog>>,
+    /// Panel settings the frontend last reported, saved with autosaves.
+    pub settings_json: Arc<Mutex<String>>,
+}
+
+/// Lets threads sleep until flags set through it reach a state they wait for.
+#[derive(Default)]
+pub struct FlagSignal {
+    lock: Mutex<()>,
+    changed: Condvar,
+}
+
+impl FlagSignal {
+    /// Store `value` in `flag` and wake the waiting threads.
+    pub fn set(&self, flag: &AtomicBool, value: bool) {
+        let _guard = self.lock.lock().unwrap();
+        flag.store(value, Ordering::SeqCst);
+        self.changed.notify_all();
+    }
+
+    /// Block until `done` holds, checking again whenever a flag is set.
+    pub fn wait_until(&self, done: impl Fn() -> bool) {
+        let mut guard = self.lock.lock().unwrap();
+        while !done() {
+            guard = self.changed.wait(guard).unwrap();
+        }
+    }
 }
 
 impl AppState {
     pub fn new() -> Self {
+        let derived = DerivedCache::new();
+        let edits = derived.edits();
         Self {
             heightmap: Arc::new(Mutex::new(Heightmap::new(512, 512))),
             erosion_abort: Arc::new(AtomicBool::new(false)),
             erosion_running: Arc::new(AtomicBool::new(false)),
+            erosion_pause: Arc::new(AtomicBool::new(false)),
+            erosion_suspended: Arc::new(AtomicBool::new(false)),
+            erosion_signal: Arc::new(FlagSignal::default()),
+            world_scale: Arc::new(Mutex::new(WorldScale::default())),
+            boundary: Arc::new(Mutex::new(Boundary::default())),
+            masks: Arc::new(Mutex::new(MaskSet::default())),
+            erosion_maps: Arc::new(Mutex::new(None)),
+            droplet_traces: Arc::new(Mutex::new(Vec::new())),
+            tile_grid: Arc::new(Mutex::new(None)),
+            export_hooks: Arc::new(Mutex::new(Vec::new())),
+            stroke_queue: Arc::new(Mutex::new(None)),
+            derived: Arc::new(Mutex::new(derived)),
+            edits,
+            revision: Arc::new(AtomicU64::new(0)),
+            canvas_frame: Arc::new(Mutex::new(None)),
+            detail_patches: Arc::new(Mutex::new(Vec::new())),
+            usage: Arc::new(Mutex::new(UsageStats::default())),
+            confirmations: Arc::new(Mutex::new(Confirmations::default())),
+            checkpoints: Arc::new(Mutex::new(Checkpoints::default())),
+            texture: Arc::new(Mutex::new(None)),
+            metadata: Arc::new(Mutex::new(ProjectMetadata::default())),
+            operations: Arc::new(Mutex::new(OperationLog::default())),
+            settings_json: Arc::new(Mutex::new("{}".to_string())),
         }
     }
 }
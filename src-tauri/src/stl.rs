@@ -0,0 +1,366 @@
+//! Binary STL export of the terrain as a solid relief model for 3D printing.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use serde::Deserialize;
+use crate::heightmap::Heightmap;
+
+/// Print dimensions, all in millimetres.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct StlParams {
+    /// Length of the longer side of the model.
+    pub size_mm: f32,
+    /// Slab under the lowest point of the terrain.
+    pub base_mm: f32,
+    /// Height of the full normalized height range, so relief is exaggerated by
+    /// raising it.
+    pub vertical_mm: f32,
+    /// Grid points along the longer side; the heightmap is resampled to this,
+    /// since slicers struggle with meshes of millions of facets.
+    pub resolution: u32,
+    /// Facets to aim for, walls and bottom included. The surface is then
+    /// simplified where it is flattest first, so ridges keep their detail;
+    /// unset for the full grid.
+    pub target_triangles: Option<u32>,
+}
+
+impl Default for StlParams {
+    fn default() -> Self {
+        Self {
+            size_mm: 150.0,
+            base_mm: 3.0,
+            vertical_mm: 30.0,
+            resolution: 512,
+            target_triangles: None,
+        }
+    }
+}
+
+/// Finest grid `StlParams::resolution` allows.
+pub const MAX_RESOLUTION: u32 = 4096;
+/// Fewest facets `StlParams::target_triangles` may ask for.
+pub const MIN_TARGET_TRIANGLES: u32 = 100;
+
+impl StlParams {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, value) in [("Model size", self.size_mm), ("Base thickness", self.base_mm), ("Vertical scale", self.vertical_mm)] {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("{name} must be positive"));
+            }
+        }
+        if !(2..=MAX_RESOLUTION).contains(&self.resolution) {
+            return Err(format!("Resolution must be between 2 and {MAX_RESOLUTION}"));
+        }
+        if self.target_triangles.is_some_and(|t| t < MIN_TARGET_TRIANGLES) {
+            return Err(format!("Target triangle count must be at least {MIN_TARGET_TRIANGLES}"));
+        }
+        Ok(())
+    }
+}
+
+type Vertex = [f32; 3];
+/// Grid point `(i, j)`, column and row.
+type Point = [usize; 2];
+
+/// Top surface as grid-point triangles, counter-clockwise seen from above, and
+/// the grid points on its edge counter-clockwise from the south-west corner.
+struct Surface {
+    triangles: Vec<[Point; 3]>,
+    rim: Vec<Point>,
+}
+
+/// Grid points around the edge of an `nx` x `ny` grid, counter-clockwise seen
+/// from above starting at the south-west corner; row 0 is north.
+fn border(nx: usize, ny: usize) -> Vec<Point> {
+    let mut rim = Vec::with_capacity(2 * (nx + ny - 2));
+    rim.extend((0..nx - 1).map(|i| [i, ny - 1]));
+    rim.extend((1..ny).rev().map(|j| [nx - 1, j]));
+    rim.extend((1..nx).rev().map(|i| [i, 0]));
+    rim.extend((0..ny - 1).map(|j| [0, j]));
+    rim
+}
+
+/// Grid points `(nx, ny)` for a simplified surface. The longer side gets a power
+/// of two cells, which the bintree covers exactly, and the shorter a multiple of
+/// a 32nd of that, so triangles only need splitting down to that size along its
+/// edge.
+fn simplified_grid(hm: &Heightmap, resolution: u32) -> (usize, usize) {
+    let longer = hm.width.max(hm.height);
+    let shorter = hm.width.min(hm.height);
+    let cells = ((resolution.min(longer) - 1).max(1) as usize).next_power_of_two();
+    let block = (cells / 32).max(1);
+    let exact = (shorter - 1) as f32 * cells as f32 / (longer - 1).max(1) as f32;
+    let short_cells = ((exact / block as f32).round() as usize).max(1) * block;
+    if hm.width >= hm.height {
+        (cells + 1, short_cells + 1)
+    } else {
+        (short_cells + 1, cells + 1)
+    }
+}
+
+/// Every grid cell as two triangles.
+fn full_grid(nx: usize, ny: usize) -> Surface {
+    let mut triangles = Vec::with_capacity(2 * (nx - 1) * (ny - 1));
+    for j in 0..ny - 1 {
+        for i in 0..nx - 1 {
+            let (a, b, c, d) = ([i, j], [i + 1, j], [i, j + 1], [i + 1, j + 1]);
+            triangles.push([c, d, b]);
+            triangles.push([c, b, a]);
+        }
+    }
+    Surface { triangles, rim: border(nx, ny) }
+}
+
+/// Right-triangulated irregular network over the grid (Evans et al. 2001, as in
+/// Mapbox's Martini): a bintree of right triangles over the smallest 2^k + 1
+/// square holding the grid, each split at its hypotenuse midpoint while the
+/// surface there strays from the hypotenuse by more than a tolerance. Errors are
+/// accumulated up the tree per midpoint, so triangles sharing a hypotenuse split
+/// together and the mesh never has cracks.
+struct Rtin<'a> {
+    heights: &'a [f32],
+    nx: usize,
+    ny: usize,
+    /// Side of the square in grid cells, a power of two.
+    tile: usize,
+    /// Per grid point of the square, the largest error of any triangle split
+    /// there.
+    errors: Vec<f32>,
+}
+
+impl<'a> Rtin<'a> {
+    fn new(heights: &'a [f32], nx: usize, ny: usize) -> Self {
+        let tile = (nx.max(ny) - 1).next_power_of_two();
+        let size = tile + 1;
+        let mut rtin = Self { heights, nx, ny, tile, errors: vec![0.0; size * size] };
+
+        let triangles = tile * tile * 2 - 2;
+        let parents = triangles - tile * tile;
+        // Children before parents, so each parent sees its children's errors
+        for t in (0..triangles).rev() {
+            let [a, b, c] = rtin.corners(t);
+            let m = midpoint(a, b);
+            let error = if rtin.straddles([a, b, c]) {
+                // Split until every triangle lies wholly on or off the grid
+                f32::INFINITY
+            } else if rtin.outside([a, b, c]) {
+                continue;
+            } else {
+                (rtin.height(m) - (rtin.height(a) + rtin.height(b)) / 2.0).abs()
+            };
+            let mut error = rtin.errors[m[1] * size + m[0]].max(error);
+            if t < parents {
+                for child in [midpoint(a, c), midpoint(b, c)] {
+                    error = error.max(rtin.errors[child[1] * size + child[0]]);
+                }
+            }
+            rtin.errors[m[1] * size + m[0]] = error;
+        }
+        rtin
+    }
+
+    fn height(&self, [i, j]: Point) -> f32 {
+        self.heights[j * self.nx + i]
+    }
+
+    /// Corners of bintree triangle `t`, numbered breadth first with the two
+    /// halves of the square as roots; `c` is the right angle.
+    fn corners(&self, t: usize) -> [Point; 3] {
+        let tile = self.tile;
+        let mut id = t + 2;
+        let [mut a, mut b, mut c] = if id & 1 == 1 {
+            [[0, 0], [tile, tile], [tile, 0]]
+        } else {
+            [[tile, tile], [0, 0], [0, tile]]
+        };
+        loop {
+            id >>= 1;
+            if id <= 1 {
+                break;
+            }
+            let m = midpoint(a, b);
+            if id & 1 == 1 {
+                (a, b) = (c, a);
+            } else {
+                (a, b) = (b, c);
+            }
+            c = m;
+        }
+        [a, b, c]
+    }
+
+    fn straddles(&self, points: [Point; 3]) -> bool {
+        let (lo_i, hi_i) = (points.iter().map(|p| p[0]).min().unwrap(), points.iter().map(|p| p[0]).max().unwrap());
+        let (lo_j, hi_j) = (points.iter().map(|p| p[1]).min().unwrap(), points.iter().map(|p| p[1]).max().unwrap());
+        (lo_i < self.nx - 1 && hi_i > self.nx - 1) || (lo_j < self.ny - 1 && hi_j > self.ny - 1)
+    }
+
+    fn outside(&self, points: [Point; 3]) -> bool {
+        points.iter().any(|p| p[0] >= self.nx || p[1] >= self.ny)
+    }
+
+    /// Visit the triangles of the mesh for `tolerance` that lie on the grid.
+    fn visit(&self, tolerance: f32, emit: &mut impl FnMut([Point; 3])) {
+        let t = self.tile;
+        self.visit_triangle([0, 0], [t, t], [t, 0], tolerance, emit);
+        self.visit_triangle([t, t], [0, 0], [0, t], tolerance, emit);
+    }
+
+    fn visit_triangle(&self, a: Point, b: Point, c: Point, tolerance: f32, emit: &mut impl FnMut([Point; 3])) {
+        let m = midpoint(a, b);
+        let leaf = a[0].abs_diff(c[0]) + a[1].abs_diff(c[1]) <= 1;
+        if !leaf && self.errors[m[1] * (self.tile + 1) + m[0]] > tolerance {
+            self.visit_triangle(c, a, m, tolerance, emit);
+            self.visit_triangle(b, c, m, tolerance, emit);
+        } else if !self.outside([a, b, c]) {
+            emit([a, b, c]);
+        }
+    }
+
+    /// Facets the whole solid would have at `tolerance`: the surface, and three
+    /// for each edge around its border, two of wall and one of bottom.
+    fn facets(&self, tolerance: f32) -> usize {
+        let (last_i, last_j) = (self.nx - 1, self.ny - 1);
+        let on_border = |p: Point, q: Point| {
+            (p[0] == q[0] && (p[0] == 0 || p[0] == last_i)) || (p[1] == q[1] && (p[1] == 0 || p[1] == last_j))
+        };
+        let mut count = 0;
+        self.visit(tolerance, &mut |[a, b, c]| {
+            count += 1 + 3 * [(a, b), (b, c), (c, a)].iter().filter(|&&(p, q)| on_border(p, q)).count();
+        });
+        count
+    }
+
+    /// The mesh with as many facets as fit in `target`, found by bisecting the
+    /// tolerance, or the coarsest the grid allows.
+    fn simplify(&self, target: usize) -> Surface {
+        let finite = self.errors.iter().copied().filter(|e| e.is_finite());
+        let (mut lo, mut hi) = (0.0f32, finite.fold(0.0, f32::max));
+        if self.facets(lo) > target {
+            for _ in 0..32 {
+                let mid = (lo + hi) / 2.0;
+                if self.facets(mid) > target {
+                    lo = mid;
+                } else {
+                    hi = mid;
+                }
+            }
+        } else {
+            hi = lo;
+        }
+
+        let mut used = vec![false; self.nx * self.ny];
+        let mut triangles = Vec::new();
+        self.visit(hi, &mut |mut corners| {
+            for [i, j] in corners {
+                used[j * self.nx + i] = true;
+            }
+            // Bintree triangles come in either winding; rows run south
+            let [a, b, c] = corners;
+            let cross = (b[0] as f32 - a[0] as f32) * (a[1] as f32 - c[1] as f32)
+                - (a[1] as f32 - b[1] as f32) * (c[0] as f32 - a[0] as f32);
+            if cross < 0.0 {
+                corners.swap(1, 2);
+            }
+            triangles.push(corners);
+        });
+        // The mesh's edges along the border join the border points it uses
+        let rim = border(self.nx, self.ny).into_iter().filter(|&[i, j]| used[j * self.nx + i]).collect();
+        Surface { triangles, rim }
+    }
+}
+
+fn midpoint(a: Point, b: Point) -> Point {
+    [(a[0] + b[0]) / 2, (a[1] + b[1]) / 2]
+}
+
+/// Write `hm` as a closed solid: the terrain surface on top, a flat bottom at
+/// z = 0 and vertical walls joining them around the edge. Heights are measured
+/// from the lowest point, which sits `base_mm` above the bottom. The walls and the
+/// bottom use the surface's own edge vertices, so every edge is shared by exactly
+/// two facets and the mesh is manifold. North, the top row of the map, faces +y.
+/// With a target triangle count the surface is simplified first, see `Rtin`.
+/// Returns the facet count.
+pub fn write(path: &Path, hm: &Heightmap, params: &StlParams) -> Result<u32, String> {
+    let longer = hm.width.max(hm.height);
+    let (nx, ny) = match params.target_triangles {
+        Some(_) => simplified_grid(hm, params.resolution),
+        None => {
+            // Grid points per pixel, never more than one
+            let density = (params.resolution.min(longer) - 1) as f32 / (longer - 1).max(1) as f32;
+            let nx = (((hm.width - 1) as f32 * density).round() as usize + 1).max(2);
+            let ny = (((hm.height - 1) as f32 * density).round() as usize + 1).max(2);
+            (nx, ny)
+        }
+    };
+    // The model keeps the map's proportions even where the grid rounds them
+    let mm_per_pixel = params.size_mm / (longer - 1).max(1) as f32;
+    let spacing_x = (hm.width - 1).max(1) as f32 * mm_per_pixel / (nx - 1) as f32;
+    let spacing_y = (hm.height - 1).max(1) as f32 * mm_per_pixel / (ny - 1) as f32;
+
+    let mut surface: Vec<f32> = (0..nx * ny)
+        .map(|i| {
+            let x = (i % nx) as f32 * (hm.width - 1) as f32 / (nx - 1) as f32;
+            let y = (i / nx) as f32 * (hm.height - 1) as f32 / (ny - 1) as f32;
+            hm.sample(x, y)
+        })
+        .collect();
+    let lowest = surface.iter().copied().fold(f32::INFINITY, f32::min);
+    for z in &mut surface {
+        *z = params.base_mm + (*z - lowest) * params.vertical_mm;
+    }
+
+    let top = |[i, j]: Point| -> Vertex { [i as f32 * spacing_x, (ny - 1 - j) as f32 * spacing_y, surface[j * nx + i]] };
+    let bottom = |[i, j]: Point| -> Vertex { [i as f32 * spacing_x, (ny - 1 - j) as f32 * spacing_y, 0.0] };
+
+    let Surface { triangles, rim } = match params.target_triangles {
+        Some(target) => Rtin::new(&surface, nx, ny).simplify(target as usize),
+        None => full_grid(nx, ny),
+    };
+    let facets = triangles.len() + 3 * rim.len();
+    let facets = u32::try_from(facets).map_err(|_| "Too many facets for STL".to_string())?;
+
+    let file = File::create(path).map_err(|e| format!("Failed to create STL file: {e}"))?;
+    let mut out = BufWriter::new(file);
+    let mut header = [0u8; 80];
+    let title = b"Topograph terrain relief";
+    header[..title.len()].copy_from_slice(title);
+    let write_err = |e: std::io::Error| format!("Failed to write STL file: {e}");
+    out.write_all(&header).map_err(write_err)?;
+    out.write_all(&facets.to_le_bytes()).map_err(write_err)?;
+
+    for [a, b, c] in triangles {
+        write_facet(&mut out, [top(a), top(b), top(c)]).map_err(write_err)?;
+    }
+
+    let center = [(nx - 1) as f32 * spacing_x / 2.0, (ny - 1) as f32 * spacing_y / 2.0, 0.0];
+    for (k, &p) in rim.iter().enumerate() {
+        let q = rim[(k + 1) % rim.len()];
+        let (pt, qt, pb, qb) = (top(p), top(q), bottom(p), bottom(q));
+        write_facet(&mut out, [pb, qb, qt]).map_err(write_err)?;
+        write_facet(&mut out, [pb, qt, pt]).map_err(write_err)?;
+        // A fan from the center closes the bottom without splitting any wall edge
+        write_facet(&mut out, [center, qb, pb]).map_err(write_err)?;
+    }
+
+    out.flush().map_err(write_err)?;
+    Ok(facets)
+}
+
+/// One facet: the unit normal, the vertices counter-clockwise seen from outside,
+/// and an empty attribute word.
+fn write_facet(out: &mut impl Write, [a, b, c]: [Vertex; 3]) -> std::io::Result<()> {
+    let u = [b[0] - a[0], b[1] - a[1], b[2] - a[2]];
+    let v = [c[0] - a[0], c[1] - a[1], c[2] - a[2]];
+    let n = [u[1] * v[2] - u[2] * v[1], u[2] * v[0] - u[0] * v[2], u[0] * v[1] - u[1] * v[0]];
+    let len = (n[0] * n[0] + n[1] * n[1] + n[2] * n[2]).sqrt();
+    let normal = if len > 0.0 { n.map(|x| x / len) } else { [0.0; 3] };
+
+    let mut facet = [0u8; 50];
+    for (k, value) in normal.iter().chain(&a).chain(&b).chain(&c).enumerate() {
+        facet[k * 4..k * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+    out.write_all(&facet)
+}
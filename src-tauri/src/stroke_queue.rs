@@ -0,0 +1,74 @@
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::{Arc, Mutex};
+use tauri::ipc::{Channel, InvokeResponseBody};
+use crate::boundary::Boundary;
+use crate::derived::Edits;
+use crate::heightmap::Heightmap;
+use crate::ipc;
+use crate::sculpt::{self, BrushStroke};
+
+/// Strokes waiting beyond this block the sender, throttling the frontend to the
+/// rate the worker can apply them.
+const CAPACITY: usize = 64;
+/// Upper bound on strokes applied under one heightmap lock.
+const MAX_BATCH: usize = 32;
+
+/// Start a worker that applies queued strokes in order and reports each batch's
+/// changed region through `on_update` and to `edits`. The worker exits once the returned sender
+/// (and all its clones) is dropped and the queue has drained.
+pub fn spawn(
+    heightmap: Arc<Mutex<Heightmap>>,
+    boundary: Arc<Mutex<Boundary>>,
+    edits: Arc<Edits>,
+    on_update: Channel<InvokeResponseBody>,
+) -> SyncSender<BrushStroke> {
+    let (sender, receiver) = mpsc::sync_channel(CAPACITY);
+    std::thread::spawn(move || run(heightmap, boundary, edits, receiver, on_update));
+    sender
+}
+
+fn run(
+    heightmap: Arc<Mutex<Heightmap>>,
+    boundary: Arc<Mutex<Boundary>>,
+    edits: Arc<Edits>,
+    receiver: Receiver<BrushStroke>,
+    on_update: Channel<InvokeResponseBody>,
+) {
+    while let Ok(first) = receiver.recv() {
+        // Coalesce whatever piled up while the previous batch was being applied
+        let mut batch = vec![first];
+        while batch.len() < MAX_BATCH {
+            let Ok(stroke) = receiver.try_recv() else {
+                break;
+            };
+            batch.push(stroke);
+        }
+
+        let boundary = *boundary.lock().unwrap();
+        let mut hm = heightmap.lock().unwrap();
+        let mut bounds: Option<(u32, u32, u32, u32)> = None;
+        for stroke in &batch {
+            let (x, y, w, h) = sculpt::apply_brush(&mut hm, stroke, boundary);
+            if w == 0 || h == 0 {
+                continue;
+            }
+            let (x1, y1) = (x + w, y + h);
+            bounds = Some(match bounds {
+                Some((bx0, by0, bx1, by1)) => (bx0.min(x), by0.min(y), bx1.max(x1), by1.max(y1)),
+                None => (x, y, x1, y1),
+            });
+        }
+
+        let Some((x0, y0, x1, y1)) = bounds else {
+            continue;
+        };
+        edits.region((x0, y0, x1 - x0, y1 - y0));
+        let payload = ipc::pack_region(&hm, x0, y0, x1 - x0, y1 - y0);
+        drop(hm);
+
+        // The frontend went away; nobody is left to consume updates
+        if on_update.send(InvokeResponseBody::Raw(payload)).is_err() {
+            break;
+        }
+    }
+}
@@ -0,0 +1,123 @@
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::project;
+
+const MANIFEST_NAME: &str = "sync_manifest.json";
+
+/// Written next to the tiles; the hashes from the previous export decide which
+/// tiles need rewriting, and `changed` tells the consumer which ones to reload.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyncManifest {
+    width: u32,
+    height: u32,
+    tile_size: u32,
+    format: String,
+    tiles_x: u32,
+    tiles_y: u32,
+    /// Row-major per-tile content hashes.
+    hashes: Vec<u64>,
+    changed: Vec<[u32; 2]>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncExportReport {
+    pub tiles_total: u32,
+    pub changed: Vec<[u32; 2]>,
+    /// Files written by this export, manifest last.
+    #[serde(skip)]
+    pub written: Vec<PathBuf>,
+}
+
+/// Export the heightmap as `tile_size` tiles into `dir`, writing only tiles whose
+/// content changed since the last export to the same directory. A change of map size,
+/// tile size or format rewrites everything.
+pub fn export(dir: &Path, hm: &Heightmap, format: &str, tile_size: u32) -> Result<SyncExportReport, String> {
+    if tile_size == 0 {
+        return Err("Tile size must be non-zero".to_string());
+    }
+    let extension = project::tile_extension(format).ok_or_else(|| format!("Unknown export format: {format}"))?;
+    std::fs::create_dir_all(dir).map_err(|e| format!("Failed to create export folder: {e}"))?;
+
+    let tiles_x = hm.width.div_ceil(tile_size);
+    let tiles_y = hm.height.div_ceil(tile_size);
+    let manifest_path = dir.join(MANIFEST_NAME);
+
+    let previous = read_manifest(&manifest_path).filter(|m| {
+        m.width == hm.width && m.height == hm.height && m.tile_size == tile_size && m.format == format
+    });
+
+    let mut hashes = Vec::with_capacity((tiles_x * tiles_y) as usize);
+    let mut changed = Vec::new();
+    let mut written = Vec::new();
+
+    for ty in 0..tiles_y {
+        for tx in 0..tiles_x {
+            let tile = extract_tile(hm, tx * tile_size, ty * tile_size, tile_size);
+            let hash = hash_tile(&tile.data);
+            let index = (ty * tiles_x + tx) as usize;
+            let path = dir.join(format!("tile_{tx}_{ty}.{extension}"));
+
+            // A deleted tile file is rewritten even when the hash matches
+            let unchanged = previous.as_ref().is_some_and(|m| m.hashes[index] == hash) && path.exists();
+            if !unchanged {
+                project::export_heightmap_as(&path, &tile, format)?;
+                changed.push([tx, ty]);
+                written.push(path);
+            }
+            hashes.push(hash);
+        }
+    }
+
+    let manifest = SyncManifest {
+        width: hm.width,
+        height: hm.height,
+        tile_size,
+        format: format.to_string(),
+        tiles_x,
+        tiles_y,
+        hashes,
+        changed: changed.clone(),
+    };
+    let manifest_json = serde_json::to_string_pretty(&manifest)
+        .map_err(|e| format!("Failed to serialize manifest: {e}"))?;
+    std::fs::write(&manifest_path, manifest_json)
+        .map_err(|e| format!("Failed to write manifest: {e}"))?;
+    written.push(manifest_path);
+
+    Ok(SyncExportReport { tiles_total: tiles_x * tiles_y, changed, written })
+}
+
+fn read_manifest(path: &Path) -> Option<SyncManifest> {
+    let json = std::fs::read_to_string(path).ok()?;
+    let manifest: SyncManifest = serde_json::from_str(&json).ok()?;
+    let expected = (manifest.tiles_x * manifest.tiles_y) as usize;
+    (manifest.hashes.len() == expected).then_some(manifest)
+}
+
+/// Copy a tile out of the map; edge tiles are cropped rather than padded.
+fn extract_tile(hm: &Heightmap, x0: u32, y0: u32, size: u32) -> Heightmap {
+    let w = size.min(hm.width - x0);
+    let h = size.min(hm.height - y0);
+    let mut tile = Heightmap::new(w, h);
+    for y in 0..h {
+        let src = ((y0 + y) * hm.width + x0) as usize;
+        let dst = (y * w) as usize;
+        tile.data[dst..dst + w as usize].copy_from_slice(&hm.data[src..src + w as usize]);
+    }
+    tile
+}
+
+/// FNV-1a over the raw sample bits; stable across runs and builds, unlike `DefaultHasher`.
+fn hash_tile(data: &[f32]) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for v in data {
+        for b in v.to_bits().to_le_bytes() {
+            h ^= b as u64;
+            h = h.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    h
+}
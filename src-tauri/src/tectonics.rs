@@ -0,0 +1,115 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+
+/// Large-scale structure laid down under the noise detail: terrain is stepped up
+/// or down across random fault lines and raised in scattered uplift blocks.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TectonicParams {
+    pub faults: u32,
+    #[serde(default)]
+    pub uplift_blocks: u32,
+    /// Width of a fault scarp in normalized map units; small values give sharp cliffs.
+    pub fault_width: f64,
+    /// Weight of the structure relative to the noise, which has a range of [-1, 1].
+    pub strength: f64,
+}
+
+struct Fault {
+    /// Point on the line and unit normal; the normal side is displaced upward.
+    px: f64,
+    py: f64,
+    nx: f64,
+    ny: f64,
+    displacement: f64,
+}
+
+struct Block {
+    cx: f64,
+    cy: f64,
+    /// Rotation as (cos, sin) and the two semi-axes.
+    cos: f64,
+    sin: f64,
+    rx: f64,
+    ry: f64,
+    uplift: f64,
+}
+
+/// A seeded set of faults and blocks that can be sampled anywhere in the map.
+pub struct TectonicField {
+    faults: Vec<Fault>,
+    blocks: Vec<Block>,
+    fault_width: f64,
+    norm: f64,
+}
+
+impl TectonicField {
+    pub fn new(params: &TectonicParams, seed: u32) -> Self {
+        // Salted so the structure doesn't correlate with the noise using the same seed
+        let mut rng = StdRng::seed_from_u64(seed as u64 ^ 0x7EC7_0A1C);
+
+        // Faults are full lines across the map (great circles of the flat map), with
+        // displacement shrinking for later faults so a few dominate the structure
+        let faults: Vec<Fault> = (0..params.faults)
+            .map(|i| {
+                let angle = rng.gen_range(0.0..std::f64::consts::TAU);
+                let t = i as f64 / params.faults.max(1) as f64;
+                Fault {
+                    px: rng.gen_range(0.0..1.0),
+                    py: rng.gen_range(0.0..1.0),
+                    nx: angle.cos(),
+                    ny: angle.sin(),
+                    displacement: 1.0 - 0.7 * t,
+                }
+            })
+            .collect();
+
+        let blocks: Vec<Block> = (0..params.uplift_blocks)
+            .map(|_| {
+                let angle = rng.gen_range(0.0..std::f64::consts::PI);
+                Block {
+                    cx: rng.gen_range(0.0..1.0),
+                    cy: rng.gen_range(0.0..1.0),
+                    cos: angle.cos(),
+                    sin: angle.sin(),
+                    rx: rng.gen_range(0.08..0.3),
+                    ry: rng.gen_range(0.05..0.2),
+                    uplift: rng.gen_range(0.5..1.0),
+                }
+            })
+            .collect();
+
+        // Random-sign displacements sum like a random walk: scale by their spread
+        let energy: f64 = faults.iter().map(|f| f.displacement.powi(2)).sum::<f64>()
+            + blocks.iter().map(|b| b.uplift.powi(2)).sum::<f64>();
+        let norm = 2.0 * energy.sqrt().max(1e-6);
+
+        Self {
+            faults,
+            blocks,
+            fault_width: params.fault_width.max(1e-4),
+            norm,
+        }
+    }
+
+    /// Structure height at normalized map coordinates, roughly in [-1, 1].
+    pub fn sample(&self, x: f64, y: f64) -> f64 {
+        let mut h = 0.0;
+        for f in &self.faults {
+            let dist = (x - f.px) * f.nx + (y - f.py) * f.ny;
+            h += f.displacement * (dist / self.fault_width).tanh();
+        }
+        for b in &self.blocks {
+            let dx = x - b.cx;
+            let dy = y - b.cy;
+            let u = (dx * b.cos + dy * b.sin) / b.rx;
+            let v = (-dx * b.sin + dy * b.cos) / b.ry;
+            // Flat-topped plateau with a soft rim
+            let r = (u * u + v * v).sqrt();
+            let t = ((1.2 - r) / 0.4).clamp(0.0, 1.0);
+            h += b.uplift * t * t * (3.0 - 2.0 * t);
+        }
+        (h / self.norm).clamp(-1.0, 1.0)
+    }
+}
@@ -0,0 +1,103 @@
+//! Projects kept as starting points for new ones, as `templates/<name>.topo`
+//! in the app data directory, so a studio's terrains can all start from the
+//! same settings, world scale, masks and texture.
+
+use std::path::PathBuf;
+use crate::float_image;
+use crate::presets;
+use crate::project::{self, LoadedProject, ProjectContents};
+use crate::resample::{self, ResampleFilter};
+
+/// Templates stored under one directory.
+pub struct TemplateStore {
+    root: PathBuf,
+}
+
+impl TemplateStore {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        self.root.join(format!("{name}.topo"))
+    }
+
+    /// Names of the templates, sorted case-insensitively. A missing directory
+    /// just means none were saved.
+    pub fn list(&self) -> Result<Vec<String>, String> {
+        let entries = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(format!("Failed to read {}: {e}", self.root.display())),
+        };
+        let mut names: Vec<String> = entries
+            .filter_map(|e| e.ok().map(|e| e.path()))
+            .filter(|p| p.extension().is_some_and(|e| e == "topo"))
+            .filter_map(|p| Some(p.file_stem()?.to_str()?.to_string()))
+            .filter(|name| validate_name(name).is_ok())
+            .collect();
+        names.sort_by_key(|name| name.to_lowercase());
+        Ok(names)
+    }
+
+    /// The template's project, or `None` if there is no such template.
+    pub fn load(&self, name: &str) -> Result<Option<LoadedProject>, String> {
+        validate_name(name)?;
+        let path = self.path(name);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        project::load_project(&path, &|_| {}).map(Some)
+    }
+
+    /// Save `contents` under `name`, replacing any template of that name.
+    pub fn save(&self, name: &str, contents: &ProjectContents) -> Result<PathBuf, String> {
+        validate_name(name)?;
+        std::fs::create_dir_all(&self.root).map_err(|e| format!("Failed to create {}: {e}", self.root.display()))?;
+        let path = self.path(name);
+        project::save_project(&path, contents, false, &|_| {})?;
+        Ok(path)
+    }
+
+    /// Remove the template; returns whether there was one.
+    pub fn delete(&self, name: &str) -> Result<bool, String> {
+        validate_name(name)?;
+        let path = self.path(name);
+        match std::fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(format!("Failed to delete {}: {e}", path.display())),
+        }
+    }
+}
+
+/// Template names double as file names, like preset names.
+pub fn validate_name(name: &str) -> Result<(), String> {
+    presets::validate_file_name("Template", name)
+}
+
+/// A new project from `template` at `width` x `height`. The terrain and masks
+/// are resampled to the new size over the same ground, so the cell size
+/// changes with it; erosion maps of a run at another size are dropped. The
+/// template's checkpoints, usage counters and name don't carry over.
+pub fn instantiate(mut template: LoadedProject, width: u32, height: u32) -> Result<LoadedProject, String> {
+    float_image::check_size(width, height)?;
+    let (old_width, old_height) = (template.heightmap.width, template.heightmap.height);
+    if (width, height) != (old_width, old_height) {
+        template.heightmap = resample::resample(&template.heightmap, width, height, ResampleFilter::Bicubic);
+        template.masks.transform(|mask| resample::resample(mask, width, height, ResampleFilter::Bicubic));
+        template.erosion_maps = None;
+
+        let (sx, sy) = (old_width as f64 / width as f64, old_height as f64 / height as f64);
+        let world = &mut template.world_scale;
+        world.meters_per_pixel *= sx as f32;
+        if let Some(georeference) = &mut world.georeference {
+            georeference.pixel_size[0] *= sx;
+            georeference.pixel_size[1] *= sy;
+        }
+    }
+    template.history.clear();
+    template.usage = None;
+    template.metadata.name.clear();
+    Ok(template)
+}
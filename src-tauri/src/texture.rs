@@ -0,0 +1,48 @@
+//! The color texture draped over the terrain. The backend keeps it so saving,
+//! exports and generators read it directly instead of the frontend sending
+//! the PNG with every call.
+
+use std::path::Path;
+use image::RgbaImage;
+use serde::Serialize;
+use crate::canvas;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+pub struct Texture {
+    /// The PNG as given, written unchanged into projects and exports.
+    pub png: Vec<u8>,
+    pub image: RgbaImage,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextureInfo {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Texture {
+    pub fn from_png(png: Vec<u8>) -> Result<Self, String> {
+        if !png.starts_with(PNG_SIGNATURE) {
+            return Err("Textures must be PNG images".into());
+        }
+        let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .map_err(|e| format!("Failed to decode texture: {e}"))?
+            .to_rgba8();
+        let limit = canvas::MAX_CANVAS_SIZE;
+        if image.width() > limit || image.height() > limit {
+            return Err(format!("Textures can be at most {limit} pixels a side"));
+        }
+        Ok(Self { png, image })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, String> {
+        let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        Self::from_png(bytes)
+    }
+
+    pub fn info(&self) -> TextureInfo {
+        TextureInfo { width: self.image.width(), height: self.image.height() }
+    }
+}
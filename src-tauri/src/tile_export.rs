@@ -0,0 +1,185 @@
+//! Export of the heightmap, and optionally its texture, sliced into a grid of
+//! tiles for open-world engines that stream terrain in chunks.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::project;
+use crate::world::WorldScale;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TileExportParams {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    /// Pixels each tile repeats from the next one past its right and bottom
+    /// edges. With 1, neighboring tiles share their seam samples, which is what
+    /// engines expect to stitch them without gaps.
+    pub overlap: u32,
+    /// Tile file name without extension: `{name}` is the chosen file's name,
+    /// `{x}` and `{y}` the tile's column and row from the top left, `{index}` its
+    /// row-major index.
+    pub pattern: String,
+    /// One of `project::export_formats`.
+    pub format: String,
+}
+
+impl Default for TileExportParams {
+    fn default() -> Self {
+        Self {
+            tiles_x: 4,
+            tiles_y: 4,
+            overlap: 1,
+            pattern: "{name}_x{x}_y{y}".to_string(),
+            format: "png16".to_string(),
+        }
+    }
+}
+
+/// Most tiles along either axis.
+pub const MAX_TILES: u32 = 64;
+
+impl TileExportParams {
+    pub fn validate(&self, hm: &Heightmap) -> Result<(), String> {
+        if !(1..=MAX_TILES).contains(&self.tiles_x) || !(1..=MAX_TILES).contains(&self.tiles_y) {
+            return Err(format!("Tile counts must be between 1 and {MAX_TILES}"));
+        }
+        let smallest = (hm.width / self.tiles_x).min(hm.height / self.tiles_y);
+        if smallest < 2 {
+            return Err(format!("A {}x{} map is too small for {}x{} tiles", hm.width, hm.height, self.tiles_x, self.tiles_y));
+        }
+        if self.overlap >= smallest {
+            return Err(format!("Overlap must be less than the tile size ({smallest} pixels)"));
+        }
+        if project::tile_extension(&self.format).is_none() {
+            return Err(format!("Unknown export format: {}", self.format));
+        }
+        let unique = self.pattern.contains("{index}") || (self.pattern.contains("{x}") && self.pattern.contains("{y}"));
+        if !unique {
+            return Err("Tile name pattern needs {x} and {y}, or {index}".into());
+        }
+        if self.pattern.contains(['/', '\\']) {
+            return Err("Tile name pattern can't contain path separators".into());
+        }
+        Ok(())
+    }
+
+    fn file_stem(&self, name: &str, tx: u32, ty: u32) -> String {
+        self.pattern
+            .replace("{name}", name)
+            .replace("{x}", &tx.to_string())
+            .replace("{y}", &ty.to_string())
+            .replace("{index}", &(ty * self.tiles_x + tx).to_string())
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TileInfo {
+    x: u32,
+    y: u32,
+    file: String,
+    texture: Option<String>,
+    /// Heightmap pixels the tile covers, `[x, y, width, height]`, overlap
+    /// included.
+    rect: [u32; 4],
+    /// Position of the tile's top-left sample in meters from the map's.
+    origin: [f32; 2],
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct TileManifest {
+    width: u32,
+    height: u32,
+    tiles_x: u32,
+    tiles_y: u32,
+    overlap: u32,
+    format: String,
+    meters_per_pixel: f32,
+    tiles: Vec<TileInfo>,
+}
+
+/// Start and end along an axis of `n` pixels of tile `t` out of `count`, the
+/// overlap included. Tiles split the axis as evenly as whole pixels allow.
+fn span(t: u32, count: u32, n: u32, overlap: u32) -> (u32, u32) {
+    let start = t * n / count;
+    let end = ((t + 1) * n / count + overlap).min(n);
+    (start, end)
+}
+
+/// Write every tile next to `path`, named by the pattern, and a `_tiles.json`
+/// manifest of where each one sits. `texture` is sliced along with the heightmap
+/// at its own resolution and written as PNG tiles with a `_texture` suffix.
+/// Returns the written paths, manifest last.
+pub fn export(
+    path: &Path,
+    hm: &Heightmap,
+    texture: Option<&image::RgbaImage>,
+    world: &WorldScale,
+    params: &TileExportParams,
+) -> Result<Vec<PathBuf>, String> {
+    let extension = project::tile_extension(&params.format).ok_or_else(|| format!("Unknown export format: {}", params.format))?;
+    let name = path.file_stem().and_then(|s| s.to_str()).unwrap_or("terrain");
+    let mut written = Vec::new();
+    let mut tiles = Vec::new();
+
+    for ty in 0..params.tiles_y {
+        let (y0, y1) = span(ty, params.tiles_y, hm.height, params.overlap);
+        for tx in 0..params.tiles_x {
+            let (x0, x1) = span(tx, params.tiles_x, hm.width, params.overlap);
+            let (w, h) = (x1 - x0, y1 - y0);
+            let mut tile = Heightmap::new(w, h);
+            for (y, row) in tile.data.chunks_exact_mut(w as usize).enumerate() {
+                let src = ((y0 + y as u32) * hm.width + x0) as usize;
+                row.copy_from_slice(&hm.data[src..src + w as usize]);
+            }
+            let stem = params.file_stem(name, tx, ty);
+            let tile_path = path.with_file_name(format!("{stem}.{extension}"));
+            project::export_heightmap_as(&tile_path, &tile, &params.format)?;
+
+            let texture_path = match texture {
+                Some(img) => {
+                    // The same share of the texture, whatever its resolution
+                    let scale_x = img.width() as f32 / hm.width as f32;
+                    let scale_y = img.height() as f32 / hm.height as f32;
+                    let (tx0, tx1) = ((x0 as f32 * scale_x).round() as u32, (x1 as f32 * scale_x).round() as u32);
+                    let (ty0, ty1) = ((y0 as f32 * scale_y).round() as u32, (y1 as f32 * scale_y).round() as u32);
+                    let crop = image::imageops::crop_imm(img, tx0, ty0, (tx1 - tx0).max(1), (ty1 - ty0).max(1)).to_image();
+                    let texture_path = project::sidecar_path(&tile_path, "_texture", "png");
+                    crop.save(&texture_path).map_err(|e| format!("Failed to save texture tile: {e}"))?;
+                    Some(texture_path)
+                }
+                None => None,
+            };
+
+            let file_name = |p: &Path| p.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+            tiles.push(TileInfo {
+                x: tx,
+                y: ty,
+                file: file_name(&tile_path),
+                texture: texture_path.as_deref().map(file_name),
+                rect: [x0, y0, w, h],
+                origin: [x0 as f32 * world.meters_per_pixel, y0 as f32 * world.meters_per_pixel],
+            });
+            written.push(tile_path);
+            written.extend(texture_path);
+        }
+    }
+
+    let manifest = TileManifest {
+        width: hm.width,
+        height: hm.height,
+        tiles_x: params.tiles_x,
+        tiles_y: params.tiles_y,
+        overlap: params.overlap,
+        format: params.format.clone(),
+        meters_per_pixel: world.meters_per_pixel,
+        tiles,
+    };
+    let manifest_path = project::sidecar_path(path, "_tiles", "json");
+    let json = serde_json::to_string_pretty(&manifest).map_err(|e| format!("Failed to serialize tile manifest: {e}"))?;
+    std::fs::write(&manifest_path, json).map_err(|e| format!("Failed to write tile manifest: {e}"))?;
+    written.push(manifest_path);
+    Ok(written)
+}
@@ -0,0 +1,234 @@
+use serde::Serialize;
+use crate::heightmap::Heightmap;
+use crate::noise_gen::{self, NoiseParams};
+
+/// Per-tile generation seeds. Locked tiles keep their seed when the rest of the
+/// grid is reseeded, so a single bad tile can be regenerated on its own.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TileGrid {
+    pub tiles_x: u32,
+    pub tiles_y: u32,
+    pub seeds: Vec<u32>,
+    pub locked: Vec<bool>,
+}
+
+impl TileGrid {
+    /// Create a grid whose seeds are derived deterministically from `base_seed`.
+    pub fn new(tiles_x: u32, tiles_y: u32, base_seed: u32) -> Self {
+        let count = (tiles_x * tiles_y) as usize;
+        let seeds = (0..count)
+            .map(|i| {
+                let tx = (i as u32 % tiles_x) as i64;
+                let ty = (i as u32 / tiles_x) as i64;
+                noise_gen::hash_cell(tx, ty, base_seed) as u32
+            })
+            .collect();
+        Self {
+            tiles_x,
+            tiles_y,
+            seeds,
+            locked: vec![false; count],
+        }
+    }
+
+    pub fn index(&self, tx: u32, ty: u32) -> Option<usize> {
+        (tx < self.tiles_x && ty < self.tiles_y).then_some((ty * self.tiles_x + tx) as usize)
+    }
+
+    /// Pixel rectangle `(x, y, w, h)` covered by a tile. Edge tiles absorb the remainder.
+    pub fn tile_rect(&self, tx: u32, ty: u32, width: u32, height: u32) -> (u32, u32, u32, u32) {
+        let x0 = tx * width / self.tiles_x;
+        let x1 = (tx + 1) * width / self.tiles_x;
+        let y0 = ty * height / self.tiles_y;
+        let y1 = (ty + 1) * height / self.tiles_y;
+        (x0, y0, x1 - x0, y1 - y0)
+    }
+}
+
+/// Regenerate one tile with `seed`. The new content fades into the existing
+/// terrain over `blend_width` pixels at borders shared with neighboring tiles, so
+/// neighbors are never modified. Returns the tile rectangle `(x, y, w, h)`.
+pub fn regenerate_tile(
+    hm: &mut Heightmap,
+    grid: &TileGrid,
+    tx: u32,
+    ty: u32,
+    seed: u32,
+    params: &NoiseParams,
+    blend_width: u32,
+) -> (u32, u32, u32, u32) {
+    let (rx, ry, rw, rh) = grid.tile_rect(tx, ty, hm.width, hm.height);
+    let mut layer = Heightmap::new(hm.width, hm.height);
+    noise_gen::generate_region(&mut layer, params, seed, (rx, ry, rw, rh));
+
+    // Map borders have no neighbor to blend into
+    let has_left = tx > 0;
+    let has_right = tx + 1 < grid.tiles_x;
+    let has_top = ty > 0;
+    let has_bottom = ty + 1 < grid.tiles_y;
+
+    for y in ry..ry + rh {
+        for x in rx..rx + rw {
+            let mut edge = f32::MAX;
+            if has_left {
+                edge = edge.min((x - rx) as f32);
+            }
+            if has_right {
+                edge = edge.min((rx + rw - 1 - x) as f32);
+            }
+            if has_top {
+                edge = edge.min((y - ry) as f32);
+            }
+            if has_bottom {
+                edge = edge.min((ry + rh - 1 - y) as f32);
+            }
+            let t = if blend_width == 0 {
+                1.0
+            } else {
+                (edge / blend_width as f32).clamp(0.0, 1.0)
+            };
+            let weight = t * t * (3.0 - 2.0 * t);
+
+            let current = hm.get(x, y);
+            let generated = params
+                .blend
+                .apply(current, layer.get(x, y), params.blend_weight)
+                .clamp(0.0, 1.0);
+            hm.set(x, y, current + (generated - current) * weight);
+        }
+    }
+
+    (rx, ry, rw, rh)
+}
+
+/// Generate the whole map with each tile using its own seed. Tiles cross-fade over
+/// `blend_width` pixels centered on their shared borders; the per-tile weights sum to
+/// one everywhere, so the result is seamless.
+pub fn generate_tiled(hm: &mut Heightmap, grid: &TileGrid, params: &NoiseParams, blend_width: u32) {
+    let (w, h) = (hm.width, hm.height);
+    let half = blend_width / 2;
+    let mut accum = vec![0.0f32; hm.data.len()];
+    let mut layer = Heightmap::new(w, h);
+
+    for ty in 0..grid.tiles_y {
+        for tx in 0..grid.tiles_x {
+            let (rx, ry, rw, rh) = grid.tile_rect(tx, ty, w, h);
+            // Extend into neighbors by half the blend zone
+            let ex0 = rx.saturating_sub(half);
+            let ey0 = ry.saturating_sub(half);
+            let ex1 = (rx + rw + half).min(w);
+            let ey1 = (ry + rh + half).min(h);
+            let seed = grid.seeds[(ty * grid.tiles_x + tx) as usize];
+            noise_gen::generate_region(&mut layer, params, seed, (ex0, ey0, ex1 - ex0, ey1 - ey0));
+
+            for y in ey0..ey1 {
+                let wy = border_weight(y, ry, ry + rh, ty > 0, ty + 1 < grid.tiles_y, blend_width);
+                for x in ex0..ex1 {
+                    let wx = border_weight(x, rx, rx + rw, tx > 0, tx + 1 < grid.tiles_x, blend_width);
+                    let idx = (y * w + x) as usize;
+                    accum[idx] += layer.data[idx] * wx * wy;
+                }
+            }
+        }
+    }
+
+    for (base, &value) in hm.data.iter_mut().zip(accum.iter()) {
+        *base = params.blend.apply(*base, value, params.blend_weight).clamp(0.0, 1.0);
+    }
+}
+
+/// 1D weight of a tile spanning [start, end) at coordinate `p`. Ramps are smoothsteps
+/// centered on interior borders, so weights of adjacent tiles are complementary.
+fn border_weight(p: u32, start: u32, end: u32, blend_start: bool, blend_end: bool, width: u32) -> f32 {
+    let smooth = |t: f32| {
+        let t = t.clamp(0.0, 1.0);
+        t * t * (3.0 - 2.0 * t)
+    };
+    let p = p as f32 + 0.5;
+    let bw = width.max(1) as f32;
+    let mut weight = 1.0;
+    if blend_start {
+        weight *= smooth((p - start as f32) / bw + 0.5);
+    }
+    if blend_end {
+        weight *= smooth((end as f32 - p) / bw + 0.5);
+    }
+    weight
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> NoiseParams {
+        serde_json::from_value(serde_json::json!({
+            "noiseType": "perlin",
+            "seed": 1,
+            "octaves": 4,
+            "frequency": 4.0,
+            "lacunarity": 2.0,
+            "persistence": 0.5,
+            "amplitude": 1.0,
+            "offset": 0.0,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn seeds_are_derived_from_the_base_seed() {
+        let grid = TileGrid::new(3, 2, 42);
+        assert_eq!(grid.seeds, TileGrid::new(3, 2, 42).seeds);
+        assert_ne!(grid.seeds, TileGrid::new(3, 2, 43).seeds);
+        assert_ne!(grid.seeds[0], grid.seeds[1]);
+        assert_eq!(grid.locked, vec![false; 6]);
+        assert_eq!(grid.index(2, 1), Some(5));
+        assert_eq!(grid.index(3, 0), None);
+        assert_eq!(grid.index(0, 2), None);
+    }
+
+    #[test]
+    fn tiles_cover_the_map_exactly() {
+        let grid = TileGrid::new(3, 4, 0);
+        let (width, height) = (100, 61);
+        let mut covered = vec![0; (width * height) as usize];
+        for ty in 0..4 {
+            for tx in 0..3 {
+                let (x, y, w, h) = grid.tile_rect(tx, ty, width, height);
+                for py in y..y + h {
+                    for px in x..x + w {
+                        covered[(py * width + px) as usize] += 1;
+                    }
+                }
+            }
+        }
+        assert!(covered.iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn regenerating_a_tile_leaves_its_neighbors_alone() {
+        let grid = TileGrid::new(2, 2, 9);
+        let mut hm = Heightmap::new(64, 64);
+        hm.data.fill(0.25);
+        let (x, y, w, h) = regenerate_tile(&mut hm, &grid, 1, 0, 1234, &params(), 8);
+        assert_eq!((x, y, w, h), (32, 0, 32, 32));
+        for py in 0..64 {
+            for px in 0..64 {
+                let inside = (x..x + w).contains(&px) && (y..y + h).contains(&py);
+                // Pixels on a shared border fade fully back to the old terrain
+                if !inside || px == x || py == y + h - 1 {
+                    assert_eq!(hm.get(px, py), 0.25, "({px}, {py})");
+                }
+            }
+        }
+        assert!(hm.data.iter().any(|&v| v != 0.25));
+    }
+
+    #[test]
+    fn neighboring_weights_sum_to_one() {
+        for p in 0..20 {
+            let sum = border_weight(p, 0, 10, false, true, 6) + border_weight(p, 10, 20, true, false, 6);
+            assert!((sum - 1.0).abs() < 1e-6, "{p}: {sum}");
+        }
+    }
+}
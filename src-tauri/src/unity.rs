@@ -0,0 +1,76 @@
+//! Heightmap export in the 16-bit RAW layout Unity's terrain importer reads, with
+//! a sidecar listing the settings to enter in its Import Raw dialog.
+
+use std::fmt::Write as _;
+use std::path::{Path, PathBuf};
+use serde::Deserialize;
+use crate::heightmap::Heightmap;
+use crate::project::{self, ByteOrder, RawOptions};
+use crate::world::WorldScale;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UnityRawOptions {
+    /// Unity labels little endian "Windows" and big endian "Mac".
+    pub byte_order: ByteOrder,
+    /// Write the rows bottom up. Unity puts the first row at the terrain's
+    /// south edge, so this keeps north at +z without flipping on import.
+    pub flip_vertical: bool,
+}
+
+/// Unity terrains are square with 2^n + 1 samples a side.
+fn unity_resolution(hm: &Heightmap) -> bool {
+    hm.width == hm.height && hm.width >= 33 && (hm.width - 1).is_power_of_two()
+}
+
+/// Write `hm` as headerless unsigned 16-bit samples, row by row, plus a
+/// `_unity.txt` sidecar next to it. Returns the sidecar's path.
+pub fn export(path: &Path, hm: &Heightmap, world: &WorldScale, options: &UnityRawOptions) -> Result<PathBuf, String> {
+    let raw = RawOptions {
+        bit_depth: 16,
+        float: false,
+        byte_order: options.byte_order,
+        flip_vertical: options.flip_vertical,
+    };
+    project::export_heightmap_raw(path, hm, &raw)?;
+
+    let sidecar = project::sidecar_path(path, "_unity", "txt");
+    std::fs::write(&sidecar, import_notes(path, hm, world, options))
+        .map_err(|e| format!("Failed to write Unity import notes: {e}"))?;
+    Ok(sidecar)
+}
+
+/// The Import Raw dialog's settings for the file, and the terrain size that keeps
+/// the world scale.
+fn import_notes(path: &Path, hm: &Heightmap, world: &WorldScale, options: &UnityRawOptions) -> String {
+    let name = path.file_name().and_then(|n| n.to_str()).unwrap_or("heightmap.raw");
+    let byte_order = match options.byte_order {
+        ByteOrder::Little => "Windows",
+        ByteOrder::Big => "Mac",
+    };
+    let flip = if options.flip_vertical { "Off (flipped on export)" } else { "On" };
+    let size_x = (hm.width - 1) as f32 * world.meters_per_pixel;
+    let size_z = (hm.height - 1) as f32 * world.meters_per_pixel;
+    let size_y = world.max_elevation - world.min_elevation;
+
+    let mut notes = String::new();
+    let _ = writeln!(notes, "Unity terrain import settings for {name}");
+    let _ = writeln!(notes);
+    let _ = writeln!(notes, "Depth: Bit 16");
+    let _ = writeln!(notes, "Width: {}", hm.width);
+    let _ = writeln!(notes, "Height: {}", hm.height);
+    let _ = writeln!(notes, "Byte Order: {byte_order}");
+    let _ = writeln!(notes, "Flip Vertically: {flip}");
+    let _ = writeln!(notes, "Terrain Size: X {size_x} Y {size_y} Z {size_z}");
+    let _ = writeln!(notes);
+    let _ = writeln!(notes, "Place the terrain at Y {} so heights match the world scale.", world.min_elevation);
+    if !unity_resolution(hm) {
+        let _ = writeln!(
+            notes,
+            "Unity terrains need a square heightmap of 2^n + 1 pixels (e.g. 513, 1025, 2049); \
+             resize or crop this {}x{} map before importing.",
+            hm.width, hm.height
+        );
+    }
+    notes
+}
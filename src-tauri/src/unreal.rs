@@ -0,0 +1,119 @@
+//! Heightmap export for Unreal Engine landscapes: a 16-bit PNG resampled to a
+//! size Unreal's landscape grid fits exactly, and a `_unreal.json` sidecar with
+//! the actor scale and location that reproduce the world scale.
+
+use std::path::{Path, PathBuf};
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::project;
+use crate::world::WorldScale;
+
+/// Epic's recommended landscape sizes: (vertices per side, quads per section,
+/// sections per component along each axis).
+const RECOMMENDED: [(u32, u32, u32); 7] = [
+    (127, 63, 1),
+    (253, 63, 2),
+    (505, 63, 2),
+    (1009, 63, 2),
+    (2017, 63, 2),
+    (4033, 63, 2),
+    (8129, 127, 2),
+];
+
+/// Unreal spans the full 16-bit range over 512 units of the actor's Z scale.
+const HEIGHT_RANGE_UNITS: f32 = 512.0;
+const CM_PER_METER: f32 = 100.0;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UnrealOptions {
+    /// Vertices along the longer side, one of the recommended sizes; the nearest
+    /// to the map's own size when unset.
+    pub resolution: Option<u32>,
+}
+
+impl UnrealOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.resolution {
+            Some(r) if !RECOMMENDED.iter().any(|&(size, ..)| size == r) => {
+                let sizes: Vec<String> = RECOMMENDED.iter().map(|(size, ..)| size.to_string()).collect();
+                Err(format!("Unreal landscape size must be one of {}", sizes.join(", ")))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Import settings for the exported landscape, written to the sidecar.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnrealImport {
+    pub width: u32,
+    pub height: u32,
+    pub source_width: u32,
+    pub source_height: u32,
+    /// Landscape actor scale; X and Y are centimetres between vertices.
+    pub scale: [f32; 3],
+    /// Actor Z location in centimetres, putting heights at their world elevation.
+    pub location_z: f32,
+    /// Quads per section.
+    pub section_size: u32,
+    pub sections_per_component: u32,
+    /// Components along X and Y.
+    pub components: [u32; 2],
+    /// Path of the sidecar itself.
+    #[serde(skip)]
+    pub sidecar: PathBuf,
+}
+
+/// Resample `hm` to a landscape size and write it as a 16-bit PNG, plus the
+/// sidecar. The longer side becomes a recommended size; the shorter one the
+/// nearest whole number of components at the same spacing, so the aspect ratio
+/// is kept as closely as the grid allows.
+pub fn export(path: &Path, hm: &Heightmap, world: &WorldScale, options: &UnrealOptions) -> Result<UnrealImport, String> {
+    let longer = hm.width.max(hm.height);
+    let (size, section_size, sections) = match options.resolution {
+        Some(r) => *RECOMMENDED.iter().find(|&&(size, ..)| size == r).ok_or("Unknown Unreal landscape size")?,
+        None => *RECOMMENDED.iter().min_by_key(|&&(size, ..)| size.abs_diff(longer)).unwrap(),
+    };
+    let component_quads = section_size * sections;
+    let factor = (size - 1) as f32 / (longer - 1).max(1) as f32;
+    let fit = |n: u32| {
+        let components = (((n - 1) as f32 * factor) / component_quads as f32).round().max(1.0) as u32;
+        (components, components * component_quads + 1)
+    };
+    let (components_x, width) = fit(hm.width);
+    let (components_y, height) = fit(hm.height);
+
+    let mut landscape = Heightmap::new(width, height);
+    let sx = (hm.width - 1) as f32 / (width - 1) as f32;
+    let sy = (hm.height - 1) as f32 / (height - 1) as f32;
+    for y in 0..height {
+        for x in 0..width {
+            landscape.set(x, y, hm.sample(x as f32 * sx, y as f32 * sy));
+        }
+    }
+    project::export_heightmap_png16(path, &landscape)?;
+
+    let relief = world.max_elevation - world.min_elevation;
+    let import = UnrealImport {
+        width,
+        height,
+        source_width: hm.width,
+        source_height: hm.height,
+        scale: [
+            world.meters_per_pixel * sx * CM_PER_METER,
+            world.meters_per_pixel * sy * CM_PER_METER,
+            relief * CM_PER_METER / HEIGHT_RANGE_UNITS,
+        ],
+        // Mid-range samples sit at the actor's own height
+        location_z: world.elevation(0.5) * CM_PER_METER,
+        section_size,
+        sections_per_component: sections,
+        components: [components_x, components_y],
+        sidecar: project::sidecar_path(path, "_unreal", "json"),
+    };
+    let json = serde_json::to_string_pretty(&import).map_err(|e| format!("Failed to serialize Unreal settings: {e}"))?;
+    std::fs::write(&import.sidecar, json).map_err(|e| format!("Failed to write Unreal settings: {e}"))?;
+    Ok(import)
+}
@@ -0,0 +1,59 @@
+//! Opt-in usage statistics for the open project: strokes, erosion time and exports.
+//! Nothing is sent anywhere; the counters only live in memory and in the project
+//! file, so artists can see how much work went into a terrain.
+
+use std::collections::BTreeMap;
+use std::time::{Duration, SystemTime};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct UsageStats {
+    /// Off until the user turns it on; nothing is counted while off.
+    pub enabled: bool,
+    /// Unix seconds when tracking was first turned on for this project.
+    pub tracking_since: Option<u64>,
+    /// Brush strokes, counting a whole drag as one.
+    pub strokes: u64,
+    pub erosion_seconds: f64,
+    /// Exports by kind, e.g. `png16`, `sync`, `mapImage`.
+    pub exports: BTreeMap<String, u64>,
+}
+
+impl UsageStats {
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+        if enabled && self.tracking_since.is_none() {
+            let now = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs();
+            self.tracking_since = Some(now);
+        }
+    }
+
+    /// Clear the counters, keeping tracking on or off as it was.
+    pub fn reset(&mut self) {
+        let enabled = self.enabled;
+        *self = Self::default();
+        self.set_enabled(enabled);
+    }
+
+    pub fn record_stroke(&mut self) {
+        if self.enabled {
+            self.strokes += 1;
+        }
+    }
+
+    pub fn record_erosion(&mut self, elapsed: Duration) {
+        if self.enabled {
+            self.erosion_seconds += elapsed.as_secs_f64();
+        }
+    }
+
+    pub fn record_export(&mut self, kind: &str) {
+        if self.enabled {
+            *self.exports.entry(kind.to_string()).or_default() += 1;
+        }
+    }
+}
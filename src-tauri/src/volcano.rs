@@ -0,0 +1,104 @@
+use noise::{NoiseFn, Perlin};
+use serde::{Deserialize, Serialize};
+use crate::heightmap::Heightmap;
+use crate::world::WorldScale;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VolcanoParams {
+    pub x: f32,
+    pub y: f32,
+    /// Radius of the cone's footprint in pixels.
+    pub base_radius: f32,
+    /// Summit height above the surrounding terrain in meters.
+    pub height: f32,
+    /// Flank profile exponent: 1 gives a straight cinder cone, 2-3 the concave
+    /// flanks of a stratovolcano.
+    pub profile: f32,
+    /// Crater rim radius as a fraction of the base radius.
+    pub crater_radius: f32,
+    /// Caldera floor depth below the rim in meters.
+    pub caldera_depth: f32,
+    /// Strength of radial gullies on the flanks, 0 for a smooth cone.
+    #[serde(default)]
+    pub flank_noise: f32,
+    #[serde(default)]
+    pub seed: u32,
+}
+
+/// Build a volcano on the terrain. The cone rises from the mean height around its
+/// footprint and only adds material outside the caldera, so existing ridges poke
+/// through the lower flanks; the outer rim fades into the surroundings.
+/// Returns bounding box of affected region: (x, y, w, h).
+pub fn stamp(hm: &mut Heightmap, volcano: &VolcanoParams, world: &WorldScale) -> (u32, u32, u32, u32) {
+    let radius = volcano.base_radius;
+    if radius < 1.0 {
+        return (0, 0, 0, 0);
+    }
+
+    let x0 = (volcano.x - radius).floor().max(0.0) as u32;
+    let y0 = (volcano.y - radius).floor().max(0.0) as u32;
+    let x1 = ((volcano.x + radius).ceil().max(0.0) as u32).min(hm.width - 1);
+    let y1 = ((volcano.y + radius).ceil().max(0.0) as u32).min(hm.height - 1);
+    if x0 > x1 || y0 > y1 {
+        return (0, 0, 0, 0);
+    }
+
+    let range = (world.max_elevation - world.min_elevation).max(1e-6);
+    let height = volcano.height / range;
+    let caldera_depth = volcano.caldera_depth / range;
+    let rim = volcano.crater_radius.clamp(0.0, 0.9);
+    let profile = volcano.profile.max(0.1);
+    let base = footprint_mean(hm, volcano.x, volcano.y, radius);
+    let gullies = Perlin::new(volcano.seed);
+
+    for py in y0..=y1 {
+        for px in x0..=x1 {
+            let dx = px as f32 - volcano.x;
+            let dy = py as f32 - volcano.y;
+            let r = (dx * dx + dy * dy).sqrt() / radius;
+            if r >= 1.0 {
+                continue;
+            }
+
+            let current = hm.get(px, py);
+            let in_caldera = r < rim;
+            let target = if in_caldera {
+                // Flat-floored caldera with steep inner walls
+                let s = r / rim.max(1e-6);
+                let wall = ((s - 0.6) / 0.4).clamp(0.0, 1.0);
+                base + height - caldera_depth * (1.0 - wall * wall * (3.0 - 2.0 * wall))
+            } else {
+                let t = (r - rim) / (1.0 - rim);
+                let mut h = height * (1.0 - t).powf(profile);
+                if volcano.flank_noise > 0.0 {
+                    // Gullies: stretched along the radius, strongest on the lower flanks
+                    let angle = dy.atan2(dx) as f64;
+                    let n = gullies.get([angle.cos() * 12.0, angle.sin() * 12.0 + r as f64 * 2.0]) as f32;
+                    h *= 1.0 + volcano.flank_noise * n * t;
+                }
+                base + h
+            };
+
+            // Fade the outermost 20% of the footprint into the surroundings
+            let edge = ((1.0 - r) / 0.2).clamp(0.0, 1.0);
+            let weight = edge * edge * (3.0 - 2.0 * edge);
+            let target = if in_caldera { target } else { target.max(current) };
+            hm.set(px, py, (current + (target - current) * weight).clamp(0.0, 1.0));
+        }
+    }
+
+    (x0, y0, x1 - x0 + 1, y1 - y0 + 1)
+}
+
+/// Mean terrain height along the footprint's outline, the level the cone rises from.
+fn footprint_mean(hm: &Heightmap, cx: f32, cy: f32, radius: f32) -> f32 {
+    let samples = 64;
+    let sum: f32 = (0..samples)
+        .map(|i| {
+            let a = i as f32 / samples as f32 * std::f32::consts::TAU;
+            hm.sample(cx + a.cos() * radius, cy + a.sin() * radius)
+        })
+        .sum();
+    sum / samples as f32
+}
@@ -0,0 +1,48 @@
+use serde::{Deserialize, Serialize};
+
+/// Real-world dimensions of the document, used wherever output needs units
+/// (scale bars, engine export scales, sampled elevations).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldScale {
+    /// Horizontal size of one heightmap cell in meters.
+    pub meters_per_pixel: f32,
+    /// Elevation in meters that a normalized height of 0.0 maps to.
+    pub min_elevation: f32,
+    /// Elevation in meters that a normalized height of 1.0 maps to.
+    pub max_elevation: f32,
+    /// Where the map lies on Earth, when it came from a georeferenced DEM.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub georeference: Option<Georeference>,
+}
+
+/// Placement of the heightmap in a coordinate reference system.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Georeference {
+    /// EPSG code of the coordinate reference system, when the source names one.
+    pub epsg: Option<u32>,
+    /// Coordinates are longitude and latitude in degrees rather than projected.
+    pub geographic: bool,
+    /// Model coordinates of the top-left corner of the top-left cell.
+    pub origin: [f64; 2],
+    /// Width and height of one heightmap cell in model units.
+    pub pixel_size: [f64; 2],
+}
+
+impl Default for WorldScale {
+    fn default() -> Self {
+        Self {
+            meters_per_pixel: 10.0,
+            min_elevation: 0.0,
+            max_elevation: 1000.0,
+            georeference: None,
+        }
+    }
+}
+
+impl WorldScale {
+    pub fn elevation(&self, h: f32) -> f32 {
+        self.min_elevation + h * (self.max_elevation - self.min_elevation)
+    }
+}
@@ -1,5 +1,11 @@
 import * as THREE from "three";
-import type { HeightmapData, HeightmapRegion } from "../types";
+import type { DetailPatchInfo, HeightmapData, HeightmapRegion } from "../types";
+
+interface DetailPatchMesh {
+  info: DetailPatchInfo;
+  residual: HeightmapData;
+  mesh: THREE.Mesh;
+}
 
 export class TerrainRenderer {
   private mesh: THREE.Mesh | null = null;
@@ -13,6 +19,8 @@ export class TerrainRenderer {
   /** Persistent canvas that accumulates AI texture edits */
   private textureCanvas: OffscreenCanvas | null = null;
   private textureCtx: OffscreenCanvasRenderingContext2D | null = null;
+  /** Higher-resolution meshes drawn in place of the base quads they cover */
+  private patches: DetailPatchMesh[] = [];
 
   getMesh(): THREE.Mesh | null {
     return this.mesh;
@@ -49,32 +57,13 @@ export class TerrainRenderer {
       }
     }
 
-    // Index buffer: 2 triangles per quad
-    const quads = (data.width - 1) * (data.height - 1);
-    const indices = new Uint32Array(quads * 6);
-    let idx = 0;
-    for (let gy = 0; gy < data.height - 1; gy++) {
-      for (let gx = 0; gx < data.width - 1; gx++) {
-        const tl = gy * data.width + gx;
-        const tr = tl + 1;
-        const bl = (gy + 1) * data.width + gx;
-        const br = bl + 1;
-        indices[idx++] = tl;
-        indices[idx++] = bl;
-        indices[idx++] = tr;
-        indices[idx++] = tr;
-        indices[idx++] = bl;
-        indices[idx++] = br;
-      }
-    }
-
     this.geometry = new THREE.BufferGeometry();
     this.positionAttr = new THREE.Float32BufferAttribute(positions, 3);
     this.normalAttr = new THREE.Float32BufferAttribute(normals, 3);
     this.geometry.setAttribute("position", this.positionAttr);
     this.geometry.setAttribute("normal", this.normalAttr);
     this.geometry.setAttribute("uv", new THREE.Float32BufferAttribute(uvs, 2));
-    this.geometry.setIndex(new THREE.Uint32BufferAttribute(indices, 1));
+    this.geometry.setIndex(this.buildIndex());
 
     this.computeNormals(0, 0, data.width, data.height);
 
@@ -118,6 +107,99 @@ export class TerrainRenderer {
     const nh = Math.min(this.hmHeight, region.y + region.h + 1) - ny;
     this.computeNormals(nx, ny, nw, nh);
     this.normalAttr.needsUpdate = true;
+
+    for (const patch of this.patches) {
+      const { x, y, w, h } = patch.info;
+      if (region.x < x + w && region.x + region.w > x && region.y < y + h && region.y + region.h > y) {
+        this.updatePatchPositions(patch);
+      }
+    }
+  }
+
+  /**
+   * Show detail patches: each residual grid is added to the upsampled base and
+   * drawn as its own mesh, with the base quads underneath left out.
+   */
+  setDetailPatches(
+    patches: { info: DetailPatchInfo; residual: HeightmapData }[],
+    scene: THREE.Scene
+  ): void {
+    this.disposePatches(scene);
+    if (!this.mesh || !this.geometry) return;
+
+    for (const { info, residual } of patches) {
+      if (info.x + info.w > this.hmWidth || info.y + info.h > this.hmHeight) continue;
+      const count = residual.width * residual.height;
+      const geometry = new THREE.BufferGeometry();
+      geometry.setAttribute("position", new THREE.Float32BufferAttribute(new Float32Array(count * 3), 3));
+      geometry.setAttribute("normal", new THREE.Float32BufferAttribute(new Float32Array(count * 3), 3));
+      geometry.setAttribute("uv", new THREE.Float32BufferAttribute(new Float32Array(count * 2), 2));
+      geometry.setIndex(gridIndex(residual.width, residual.height, () => true));
+
+      // Shares the base material so textures apply to both
+      const mesh = new THREE.Mesh(geometry, this.mesh.material);
+      const patch = { info, residual, mesh };
+      this.updatePatchPositions(patch);
+      scene.add(mesh);
+      this.patches.push(patch);
+    }
+
+    this.geometry.setIndex(this.buildIndex());
+  }
+
+  /** Base index buffer: 2 triangles per quad, skipping quads under detail patches. */
+  private buildIndex(): THREE.Uint32BufferAttribute {
+    return gridIndex(this.hmWidth, this.hmHeight, (gx, gy) =>
+      !this.patches.some(
+        ({ info }) => gx >= info.x && gx < info.x + info.w - 1 && gy >= info.y && gy < info.y + info.h - 1
+      )
+    );
+  }
+
+  private updatePatchPositions(patch: DetailPatchMesh): void {
+    if (!this.positionAttr) return;
+    const base = this.positionAttr.array as Float32Array;
+    const { info, residual } = patch;
+    const geometry = patch.mesh.geometry;
+    const positions = geometry.getAttribute("position").array as Float32Array;
+    const uvs = geometry.getAttribute("uv").array as Float32Array;
+    const baseHeight = (gx: number, gy: number) => base[(gy * this.hmWidth + gx) * 3 + 1];
+
+    for (let py = 0; py < residual.height; py++) {
+      for (let px = 0; px < residual.width; px++) {
+        const i = py * residual.width + px;
+        // Bilinear base height at the patch vertex, matching the backend composite
+        const bx = info.x + px / info.factor;
+        const by = info.y + py / info.factor;
+        const ix = Math.min(Math.floor(bx), this.hmWidth - 1);
+        const iy = Math.min(Math.floor(by), this.hmHeight - 1);
+        const ix1 = Math.min(ix + 1, this.hmWidth - 1);
+        const iy1 = Math.min(iy + 1, this.hmHeight - 1);
+        const fx = bx - ix;
+        const fy = by - iy;
+        const top = baseHeight(ix, iy) + (baseHeight(ix1, iy) - baseHeight(ix, iy)) * fx;
+        const bot = baseHeight(ix, iy1) + (baseHeight(ix1, iy1) - baseHeight(ix, iy1)) * fx;
+        const h = top + (bot - top) * fy + residual.data[i] * this.heightScale;
+
+        positions[i * 3] = bx / (this.hmWidth - 1) - 0.5;
+        positions[i * 3 + 1] = Math.min(Math.max(h, 0), this.heightScale);
+        positions[i * 3 + 2] = by / (this.hmHeight - 1) - 0.5;
+        uvs[i * 2] = bx / (this.hmWidth - 1);
+        uvs[i * 2 + 1] = by / (this.hmHeight - 1);
+      }
+    }
+
+    geometry.getAttribute("position").needsUpdate = true;
+    geometry.getAttribute("uv").needsUpdate = true;
+    geometry.computeVertexNormals();
+  }
+
+  private disposePatches(scene: THREE.Scene): void {
+    for (const { mesh } of this.patches) {
+      scene.remove(mesh);
+      mesh.geometry.dispose();
+    }
+    this.patches = [];
   }
 
   /**
@@ -384,6 +466,7 @@ export class TerrainRenderer {
   }
 
   dispose(scene: THREE.Scene): void {
+    this.disposePatches(scene);
     if (this.mesh) {
       scene.remove(this.mesh);
       this.geometry?.dispose();
@@ -398,3 +481,29 @@ export class TerrainRenderer {
     }
   }
 }
+
+/** Index buffer for a width x height vertex grid, 2 triangles per included quad. */
+function gridIndex(
+  width: number,
+  height: number,
+  include: (gx: number, gy: number) => boolean
+): THREE.Uint32BufferAttribute {
+  const indices = new Uint32Array((width - 1) * (height - 1) * 6);
+  let idx = 0;
+  for (let gy = 0; gy < height - 1; gy++) {
+    for (let gx = 0; gx < width - 1; gx++) {
+      if (!include(gx, gy)) continue;
+      const tl = gy * width + gx;
+      const tr = tl + 1;
+      const bl = (gy + 1) * width + gx;
+      const br = bl + 1;
+      indices[idx++] = tl;
+      indices[idx++] = bl;
+      indices[idx++] = tr;
+      indices[idx++] = tr;
+      indices[idx++] = bl;
+      indices[idx++] = br;
+    }
+  }
+  return new THREE.Uint32BufferAttribute(idx === indices.length ? indices : indices.slice(0, idx), 1);
+}
@@ -1,12 +1,79 @@
 import { invoke, Channel } from "@tauri-apps/api/core";
+import { ask } from "@tauri-apps/plugin-dialog";
 import type {
   HeightmapData,
+  ErosionMapKind,
+  ClipboardMap,
+  ExportResolution,
+  Normalization,
+  FetchParams,
+  StampParams,
+  MosaicParams,
+  TextureInfo,
+  RecoveryInfo,
+  ProjectMetadata,
+  Operation,
+  MapKind,
+  DropletTrace,
+  SnowParams,
+  StlParams,
+  UnityRawOptions,
+  RawOptions,
+  UnrealImport,
+  UnrealOptions,
+  GodotImport,
+  GradientMap,
+  MapUnit,
+  RiverParams,
   HeightmapRegion,
   BrushStroke,
+  RampParams,
+  PlatformParams,
+  CraterParams,
+  CraterFieldParams,
+  VolcanoParams,
+  MountainRangeParams,
   NoiseParams,
+  NoiseLayer,
   ThermalParams,
   HydraulicParams,
+  PipeParams,
+  Progress,
+  GpuStatus,
+  StreamPowerParams,
+  GlacialParams,
+  CoastalParams,
+  CurvatureFlowParams,
+  ErosionRun,
+  ErosionComparison,
   LoadProjectResponse,
+  RenderStyle,
+  Camera,
+  WorldScale,
+  Boundary,
+  MapFurniture,
+  BiomeRules,
+  ContourParams,
+  SplatRules,
+  SplatExport,
+  MaskChannel,
+  MaskStroke,
+  TileGrid,
+  Expansion,
+  DetailPatchInfo,
+  ExportHookInfo,
+  SyncExportReport,
+  TileExportParams,
+  TopographError,
+  Locale,
+  LocaleInfo,
+  ExportFormatInfo,
+  UsageStats,
+  Preset,
+  PresetKind,
+  PresetParams,
+  DestructiveOp,
+  CheckpointInfo,
 } from "./types";
 
 const IPC_VERSION = 1;
@@ -35,6 +102,33 @@ function parseResponse(buffer: ArrayBuffer): HeightmapData | HeightmapRegion {
   }
 }
 
+/** Unpack several full maps sent as one message by `ipc::pack_full_set`. */
+function parseResponseSet(buffer: ArrayBuffer): HeightmapData[] {
+  const view = new DataView(buffer);
+  const maps: HeightmapData[] = [];
+  let offset = 4;
+  for (let i = 0; i < view.getUint32(0, true); i++) {
+    const len = view.getUint32(offset, true);
+    offset += 4;
+    maps.push(parseResponse(buffer.slice(offset, offset + len)) as HeightmapData);
+    offset += len;
+  }
+  return maps;
+}
+
+export function isTopographError(e: unknown): e is TopographError {
+  return typeof e === "object" && e !== null && "kind" in e && "message" in e;
+}
+
+/** One-line description of a rejected command: message, hint and log location. */
+export function describeError(e: unknown): string {
+  if (!isTopographError(e)) return e instanceof Error ? e.message : String(e);
+  let text = e.message;
+  if (e.hint) text += ` ${e.hint}`;
+  if (e.logPath) text += ` (details in ${e.logPath})`;
+  return text;
+}
+
 export function isRegion(
   r: HeightmapData | HeightmapRegion
 ): r is HeightmapRegion {
@@ -53,110 +147,1026 @@ export async function applyBrushStroke(
   return parseResponse(buffer);
 }
 
+/**
+ * Open a stroke stream. Changed regions from queued strokes are delivered to
+ * `onRegion` as the backend applies them.
+ */
+export async function startStrokeStream(
+  onRegion: (region: HeightmapRegion) => void
+): Promise<void> {
+  const channel = new Channel<ArrayBuffer>();
+  channel.onmessage = (buffer) => {
+    onRegion(parseResponse(buffer) as HeightmapRegion);
+  };
+  await invoke("start_stroke_stream", { onUpdate: channel });
+}
+
+/** Resolves once the stroke is queued; waits while the backend queue is full. */
+export async function queueBrushStroke(stroke: BrushStroke): Promise<void> {
+  await invoke("queue_brush_stroke", { stroke });
+}
+
+export async function endStrokeStream(): Promise<void> {
+  await invoke("end_stroke_stream");
+}
+
+export async function applyRamp(
+  ramp: RampParams
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("apply_ramp", { ramp });
+  return parseResponse(buffer);
+}
+
+export async function applyPlatform(
+  platform: PlatformParams
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("apply_platform", { platform });
+  return parseResponse(buffer);
+}
+
+export async function stampCrater(
+  crater: CraterParams
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("stamp_crater", { crater });
+  return parseResponse(buffer);
+}
+
+export async function scatterCraters(
+  field: CraterFieldParams
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("scatter_craters", { field });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function stampVolcano(
+  volcano: VolcanoParams
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("stamp_volcano", { volcano });
+  return parseResponse(buffer);
+}
+
+export async function applyMountainRange(
+  range: MountainRangeParams
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("apply_mountain_range", { range });
+  return parseResponse(buffer);
+}
+
 export async function generateTerrain(
-  params: NoiseParams
+  params: NoiseParams,
+  maskData?: Uint8Array,
+  confirmation?: string
 ): Promise<HeightmapData> {
-  const buffer: ArrayBuffer = await invoke("generate_terrain", { params });
+  const buffer: ArrayBuffer = await invoke("generate_terrain", {
+    params,
+    maskData: maskData ? Array.from(maskData) : null,
+    confirmation: confirmation ?? null,
+  });
   return parseResponse(buffer) as HeightmapData;
 }
 
-export async function runThermalErosion(
-  params: ThermalParams
+/** Replace the terrain with a per-pixel formula such as `0.5*fbm(x*3, y*3)`. */
+export async function generateFromExpression(
+  expression: string,
+  seed: number,
+  confirmation?: string
 ): Promise<HeightmapData> {
-  const buffer: ArrayBuffer = await invoke("run_thermal_erosion", { params });
+  const buffer: ArrayBuffer = await invoke("generate_from_expression", {
+    expression,
+    seed,
+    confirmation: confirmation ?? null,
+  });
   return parseResponse(buffer) as HeightmapData;
 }
 
+/** Low-res generation into a scratch buffer; the document is left untouched. */
+export async function previewTerrain(
+  params: NoiseParams,
+  previewSize: number
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("preview_terrain", { params, previewSize });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Low-res raw layers of `params` for each seed, longest edge `size` pixels. */
+export async function previewSeeds(
+  params: NoiseParams,
+  seeds: number[],
+  size: number
+): Promise<HeightmapData[]> {
+  const buffer: ArrayBuffer = await invoke("preview_seeds", { params, seeds, size });
+  return parseResponseSet(buffer);
+}
+
+export async function generateTerrainStack(
+  layers: NoiseLayer[],
+  confirmation?: string
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("generate_terrain_stack", { layers, confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Grow the canvas, continuing generation with `params` into the new area. */
+export async function expandCanvas(
+  expansion: Expansion,
+  params: NoiseParams,
+  blendWidth?: number,
+  confirmation?: string
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("expand_canvas", {
+    expansion,
+    params,
+    blendWidth: blendWidth ?? null,
+    confirmation: confirmation ?? null,
+  });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function cropCanvas(
+  x: number,
+  y: number,
+  w: number,
+  h: number,
+  confirmation?: string
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("crop_canvas", { x, y, w, h, confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Token that lets one run of `op` through; only fetch it once the user agreed. */
+export async function confirmDestructive(op: DestructiveOp): Promise<string> {
+  return await invoke("confirm_destructive", { op });
+}
+
+/**
+ * Run a whole-map operation the backend may guard. If it asks for confirmation,
+ * show `question` and retry with a token when the user agrees. Resolves to null
+ * when they decline.
+ */
+export async function withConfirmation<T>(
+  op: DestructiveOp,
+  question: string,
+  run: (confirmation?: string) => Promise<T>
+): Promise<T | null> {
+  try {
+    return await run();
+  } catch (e) {
+    if (!isTopographError(e) || e.kind !== "confirmationRequired") throw e;
+  }
+  if (!(await ask(question, { title: "Topograph", kind: "warning" }))) return null;
+  return await run(await confirmDestructive(op));
+}
+
+export async function listCheckpoints(): Promise<CheckpointInfo[]> {
+  return await invoke("list_checkpoints");
+}
+
+/** Go back to a checkpoint; the current map is checkpointed in its place. */
+export async function restoreCheckpoint(id: number): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("restore_checkpoint", { id });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Whether erosion can run with `backend: "gpu"`, and why the last GPU run fell back to the CPU if it did. */
+export async function gpuErosionAvailable(): Promise<GpuStatus> {
+  return await invoke("gpu_erosion_available");
+}
+
+/**
+ * `hardness` names the mask channel that protects pixels from erosion, 1 being
+ * fully resistant; runs with one always use the CPU. `maskData`, a grayscale PNG,
+ * restricts the run like `params.useSelection` and takes precedence over it.
+ */
+export async function runThermalErosion(
+  params: ThermalParams,
+  onProgress: (progress: Progress) => void,
+  hardness?: MaskChannel | null,
+  maskData?: Uint8Array,
+): Promise<void> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (progress) => {
+    onProgress(progress);
+  };
+  await invoke("run_thermal_erosion", {
+    params,
+    hardness: hardness ?? null,
+    maskData: maskData ? Array.from(maskData) : null,
+    channel,
+  });
+}
+
+/**
+ * `hardness` and `maskData` as for `runThermalErosion`. `onSnapshot` receives
+ * low-resolution views of the terrain a few times a second while a CPU run is in
+ * progress. Unlike the other runs this resolves once the run has finished;
+ * `onProgress` also hears when it pauses and resumes.
+ */
 export async function runHydraulicErosion(
   params: HydraulicParams,
-  onProgress: (progress: number) => void
+  onProgress: (progress: Progress) => void,
+  hardness?: MaskChannel | null,
+  maskData?: Uint8Array,
+  onSnapshot?: (hm: HeightmapData) => void,
 ): Promise<void> {
-  const channel = new Channel<number>();
+  const snapshots = new Channel<ArrayBuffer>();
+  snapshots.onmessage = (buffer) => {
+    onSnapshot?.(parseResponse(buffer) as HeightmapData);
+  };
+  await new Promise<void>((resolve, reject) => {
+    const channel = new Channel<Progress>();
+    channel.onmessage = (progress) => {
+      onProgress(progress);
+      if (progress.state === "finished") resolve();
+    };
+    invoke("run_hydraulic_erosion", {
+      params,
+      hardness: hardness ?? null,
+      maskData: maskData ? Array.from(maskData) : null,
+      channel,
+      snapshots,
+    }).catch(reject);
+  });
+}
+
+/** Stretch the heights to fill 0 to 1. */
+export async function normalizeHeightmap(confirmation?: string): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("normalize_heightmap", { confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Smooth away stair-step artifacts while keeping steep features. */
+export async function applyCurvatureFlow(params: CurvatureFlowParams): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("apply_curvature_flow", { params });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/**
+ * Fill every depression to its spill point. With `epsilon` above 0 the filled
+ * basins keep a slight slope so water can drain from anywhere; `lakeDepth` also
+ * returns how far each pixel was raised.
+ */
+export async function fillSinks(
+  epsilon: number,
+  lakeDepth: boolean,
+  onProgress?: (progress: Progress) => void,
+): Promise<{ heightmap: HeightmapData; lakeDepth?: HeightmapData }> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (p) => onProgress?.(p);
+  const buffer: ArrayBuffer = await invoke("fill_sinks", { epsilon, lakeDepth, channel });
+  const [heightmap, depth] = parseResponseSet(buffer);
+  return { heightmap, lakeDepth: depth };
+}
+
+export async function runPipeErosion(
+  params: PipeParams,
+  onProgress: (progress: Progress) => void
+): Promise<void> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (progress) => {
+    onProgress(progress);
+  };
+  await invoke("run_pipe_erosion", { params, channel });
+}
+
+export async function runStreamPowerErosion(
+  params: StreamPowerParams,
+  onProgress: (progress: Progress) => void
+): Promise<void> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (progress) => {
+    onProgress(progress);
+  };
+  await invoke("run_stream_power_erosion", { params, channel });
+}
+
+export async function runGlacialErosion(
+  params: GlacialParams,
+  onProgress: (progress: Progress) => void
+): Promise<void> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (progress) => {
+    onProgress(progress);
+  };
+  await invoke("run_glacial_erosion", { params, channel });
+}
+
+export async function runCoastalErosion(
+  params: CoastalParams,
+  onProgress: (progress: Progress) => void
+): Promise<void> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (progress) => {
+    onProgress(progress);
+  };
+  await invoke("run_coastal_erosion", { params, channel });
+}
+
+/** Run two erosion setups on copies of the current terrain without changing it. */
+/**
+ * Run erosion on a copy of the terrain downsampled to `previewSize` on its long
+ * edge; the document is untouched.
+ */
+export async function previewErosion(run: ErosionRun, previewSize: number): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("preview_erosion", { params: run, previewSize });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** `hardness` applies to thermal and hydraulic runs, as for `runThermalErosion`. */
+export async function compareErosion(
+  a: ErosionRun,
+  b: ErosionRun,
+  hardness?: MaskChannel | null,
+): Promise<ErosionComparison> {
+  const buffer: ArrayBuffer = await invoke("compare_erosion", { a, b, hardness: hardness ?? null });
+  const [ra, rb, diff] = parseResponseSet(buffer);
+  return { a: ra, b: rb, diff };
+}
+
+/**
+ * Run `stages` one after another as a single job; progress for each arrives
+ * tagged with its `step`. `hardness` and `maskData` as for `runThermalErosion`.
+ */
+export async function runErosionPipeline(
+  stages: ErosionRun[],
+  onProgress: (progress: Progress) => void,
+  hardness?: MaskChannel | null,
+  maskData?: Uint8Array,
+): Promise<void> {
+  const channel = new Channel<Progress>();
   channel.onmessage = (progress) => {
     onProgress(progress);
   };
-  await invoke("run_hydraulic_erosion", { params, channel });
+  await invoke("run_erosion_pipeline", {
+    stages,
+    hardness: hardness ?? null,
+    maskData: maskData ? Array.from(maskData) : null,
+    channel,
+  });
 }
 
 export async function abortErosion(): Promise<void> {
   await invoke("abort_erosion");
 }
 
+/**
+ * Suspend a running CPU hydraulic erosion so the terrain can be edited; resolves
+ * once it has, with false when the run ended first.
+ */
+export async function pauseErosion(): Promise<boolean> {
+  return await invoke("pause_erosion");
+}
+
+/** Continue a paused run from the terrain as it now is. */
+export async function resumeErosion(): Promise<void> {
+  await invoke("resume_erosion");
+}
+
 export async function runDepthEstimation(
   imageData: Uint8Array,
-  maskData?: Uint8Array
+  maskData?: Uint8Array,
+  confirmation?: string
 ): Promise<HeightmapData> {
   const buffer: ArrayBuffer = await invoke("run_depth_estimation", {
     imageData: Array.from(imageData),
     maskData: maskData ? Array.from(maskData) : null,
+    confirmation: confirmation ?? null,
   });
   return parseResponse(buffer) as HeightmapData;
 }
 
+/** Pass `imageData: null` to have the backend render the conditioning image. */
 export async function runInpainting(
-  imageData: Uint8Array,
+  imageData: Uint8Array | null,
   maskData: Uint8Array,
   prompt: string,
-  mode: string = "texture"
+  mode: string = "texture",
+  camera?: Camera,
 ): Promise<Uint8Array> {
   const result: number[] = await invoke("run_inpainting", {
-    imageData: Array.from(imageData),
+    imageData: imageData ? Array.from(imageData) : null,
     maskData: Array.from(maskData),
     prompt,
     mode,
+    camera: camera ?? null,
   });
   return new Uint8Array(result);
 }
 
 export async function generateControlnetTexture(
-  imageData: Uint8Array,
+  imageData: Uint8Array | null,
   maskData: Uint8Array,
   prompt: string,
+  camera?: Camera,
 ): Promise<Uint8Array> {
   const result: number[] = await invoke("generate_controlnet_texture", {
-    imageData: Array.from(imageData),
+    imageData: imageData ? Array.from(imageData) : null,
     maskData: Array.from(maskData),
     prompt,
+    camera: camera ?? null,
   });
   return new Uint8Array(result);
 }
 
 export async function applyHeightmapImage(
   imageData: Uint8Array,
-  maskData?: Uint8Array
+  maskData?: Uint8Array,
+  confirmation?: string
 ): Promise<HeightmapData> {
   const buffer: ArrayBuffer = await invoke("apply_heightmap_image", {
     imageData: Array.from(imageData),
     maskData: maskData ? Array.from(maskData) : null,
+    confirmation: confirmation ?? null,
+  });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Replace the map with a heightmap image from disk, at its own size and bit
+ * depth; 16-bit PNGs keep full precision. */
+export async function importHeightmap(path: string, confirmation?: string): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_heightmap", { path, confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Replace the map with a 32-bit float TIFF or EXR, mapped to heights by
+ * `normalization` (the file's min/max by default). */
+export async function importFloatHeightmap(
+  path: string,
+  normalization?: Normalization,
+  confirmation?: string
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_float_heightmap", {
+    path,
+    normalization: normalization ?? null,
+    confirmation: confirmation ?? null,
+  });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Replace the map with a headerless raw file laid out per `options`; without
+ * a size the file is taken to be square. Float samples are mapped to heights
+ * by `normalization`. */
+export async function importRaw(
+  path: string,
+  size: [number, number] | null,
+  options: RawOptions,
+  normalization?: Normalization,
+  confirmation?: string
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_raw", {
+    path,
+    width: size?.[0] ?? null,
+    height: size?.[1] ?? null,
+    options,
+    normalization: normalization ?? null,
+    confirmation: confirmation ?? null,
+  });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Replace the map with heightmap tiles stitched into one, their overlaps
+ * blended. Float tiles are normalized together. */
+export async function importMosaic(
+  paths: string[],
+  params: MosaicParams,
+  normalization?: Normalization,
+  confirmation?: string
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_mosaic", {
+    paths,
+    params,
+    normalization: normalization ?? null,
+    confirmation: confirmation ?? null,
   });
   return parseResponse(buffer) as HeightmapData;
 }
 
-export async function setHeightmap(data: Float32Array): Promise<void> {
-  await invoke("set_heightmap", { data: Array.from(data) });
+/** Replace the map with a GeoTIFF DEM at the working resolution; the world
+ * scale takes its elevation range, cell size and georeference. */
+export async function importGeotiff(path: string, confirmation?: string): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_geotiff", { path, confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Replace the map with SRTM .hgt tiles stitched into one, voids filled;
+ * otherwise as `importGeotiff`. */
+export async function importSrtm(paths: string[], confirmation?: string): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_srtm", { paths, confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Blend a heightmap file into a rectangle or the selection without replacing
+ * the rest of the map. */
+export async function importStamp(path: string, params: StampParams): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("import_stamp", { path, params });
+  return parseResponse(buffer);
+}
+
+/** Download terrain for a latitude/longitude box (AWS Terrain Tiles, which
+ * ask for attribution) and replace the map with it; otherwise as `importGeotiff`. */
+export async function fetchTerrain(params: FetchParams, confirmation?: string): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("fetch_terrain", { params, confirmation: confirmation ?? null });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function setHeightmap(data: Float32Array, confirmation?: string): Promise<void> {
+  await invoke("set_heightmap", { data: Array.from(data), confirmation: confirmation ?? null });
 }
 
+/** Save the heightmap, the backend's texture and `settingsJson` to `path`, with
+ * the checkpoints when `includeHistory` is set. */
+/** `incremental` stores the heightmap in chunks and, saving over the same
+ *  project, rewrites only the ones edited since. `onProgress` receives the
+ *  share written and the time left. */
 export async function saveProject(
-  texturePng: Uint8Array | null,
   settingsJson: string,
   path: string,
+  includeHistory = false,
+  incremental = false,
+  onProgress?: (progress: Progress) => void,
 ): Promise<void> {
-  await invoke("save_project", {
-    path,
-    texturePng: texturePng ? Array.from(texturePng) : null,
-    settingsJson,
-  });
+  const progress = new Channel<Progress>();
+  progress.onmessage = (p) => onProgress?.(p);
+  await invoke("save_project", { path, settingsJson, includeHistory, incremental, progress });
 }
 
+/** `onProgress` receives the share read and the time left. */
 export async function loadProject(
   path: string,
+  onProgress?: (progress: Progress) => void,
+): Promise<LoadProjectResponse> {
+  const progress = new Channel<Progress>();
+  progress.onmessage = (p) => onProgress?.(p);
+  return await invoke("load_project", { path, progress });
+}
+
+/** Projects recently opened or saved that still exist, most recent first, as
+ *  in the File menu's Open Recent. */
+export async function listRecentProjects(): Promise<string[]> {
+  return await invoke("list_recent_projects");
+}
+
+export async function clearRecentProjects(): Promise<void> {
+  await invoke("clear_recent_projects");
+}
+
+/** Names of the saved templates. */
+export async function listTemplates(): Promise<string[]> {
+  return await invoke("list_templates");
+}
+
+/** Save the open project as a template, replacing any of the same name;
+ *  resolves to the templates. */
+export async function saveTemplate(name: string, settingsJson: string): Promise<string[]> {
+  return await invoke("save_template", { name, settingsJson });
+}
+
+export async function deleteTemplate(name: string): Promise<string[]> {
+  return await invoke("delete_template", { name });
+}
+
+/** Replace the document with a new project from a template, its terrain
+ *  resampled to `width` x `height`. */
+export async function newProjectFromTemplate(
+  name: string,
+  width: number,
+  height: number,
+  confirmation?: string
 ): Promise<LoadProjectResponse> {
-  return await invoke("load_project", { path });
+  return await invoke("new_project_from_template", { name, width, height, confirmation: confirmation ?? null });
+}
+
+export async function getProjectMetadata(): Promise<ProjectMetadata> {
+  return await invoke("get_project_metadata");
+}
+
+/** Replace the open project's metadata; resolves to it as stored, trimmed and
+ *  without repeated tags. */
+export async function setProjectMetadata(metadata: ProjectMetadata): Promise<ProjectMetadata> {
+  return await invoke("set_project_metadata", { metadata });
 }
 
+/** The generation, erosion and filter operations run on the open project,
+ *  oldest first: the terrain's recipe. */
+export async function getOperationHistory(): Promise<Operation[]> {
+  return await invoke("get_operation_history");
+}
+
+/** Run a recorded operation again on the current terrain. Erosion runs
+ *  resolve once started, like `runThermalErosion`, and report on `onProgress`. */
+export async function replayOperation(operation: Operation, onProgress?: (progress: Progress) => void): Promise<void> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (progress) => onProgress?.(progress);
+  // Only the hydraulic run streams snapshots; replays don't show them
+  const snapshots = new Channel<ArrayBuffer>();
+  await invoke(operation.command, { ...operation.args, channel, snapshots });
+}
+
+/** Tell the backend the panels' settings, for autosaves to keep. */
+export async function setProjectSettings(settingsJson: string): Promise<void> {
+  await invoke("set_project_settings", { settingsJson });
+}
+
+/** The autosave of a session that crashed, if there is one. */
+export async function checkRecovery(): Promise<RecoveryInfo | null> {
+  return await invoke("check_recovery");
+}
+
+/** Load the crashed session's autosave like a project, then remove it. */
+export async function restoreRecovery(): Promise<LoadProjectResponse> {
+  return await invoke("restore_recovery");
+}
+
+export async function discardRecovery(): Promise<void> {
+  await invoke("discard_recovery");
+}
+
+/** Make `png` the texture saves and exports use, e.g. after compositing into it. */
+export async function setTexture(png: Uint8Array): Promise<TextureInfo> {
+  return await invoke("set_texture", { png: Array.from(png) });
+}
+
+/** Replace the texture with the PNG at `path`. Returns its bytes for the viewer. */
+export async function importTexture(path: string): Promise<Uint8Array> {
+  const buffer: ArrayBuffer = await invoke("import_texture", { path });
+  return new Uint8Array(buffer);
+}
+
+export async function clearTexture(): Promise<void> {
+  await invoke("clear_texture");
+}
+
+/** Size of the backend's texture, or null without one. */
+export async function getTextureInfo(): Promise<TextureInfo | null> {
+  return await invoke("get_texture_info");
+}
+
+export async function listExportFormats(): Promise<ExportFormatInfo[]> {
+  return await invoke("list_export_formats");
+}
+
+/** Export the heightmap, resampled to `resolution` when given. */
 export async function exportHeightmap(
   path: string,
   format: string,
+  resolution: ExportResolution | null = null,
 ): Promise<void> {
-  await invoke("export_heightmap", { path, format });
+  await invoke("export_heightmap", { path, format, resolution });
+}
+
+export async function exportHeightmapSync(
+  dir: string,
+  format: string,
+  tileSize: number,
+): Promise<SyncExportReport> {
+  return await invoke("export_heightmap_sync", { dir, format, tileSize });
+}
+
+/** Heightmap tiles, and texture tiles with `includeTexture`, next to `path`
+ * with a `_tiles.json` manifest. Returns the written paths. */
+export async function exportTiles(
+  path: string,
+  params: TileExportParams,
+  includeTexture: boolean,
+): Promise<string[]> {
+  return await invoke("export_tiles", { path, params, includeTexture });
+}
+
+/** Headerless raw heightmap in the given bit depth, sample type, byte order and row order. */
+export async function exportHeightmapRaw(path: string, options: RawOptions): Promise<void> {
+  await invoke("export_heightmap_raw", { path, options });
+}
+
+/** 16-bit RAW for Unity's terrain importer, with a `_unity.txt` sidecar of import settings. */
+export async function exportUnityRaw(path: string, options: UnityRawOptions): Promise<void> {
+  await invoke("export_unity_raw", { path, options });
+}
+
+/** 16-bit PNG resampled to an Unreal landscape size, with a `_unreal.json` sidecar. */
+export async function exportUnrealLandscape(path: string, options: UnrealOptions): Promise<UnrealImport> {
+  return await invoke("export_unreal_landscape", { path, options });
+}
+
+/** EXR of elevations in meters for Godot terrain plugins, with a `_godot.json` sidecar. */
+export async function exportGodot(path: string): Promise<GodotImport> {
+  return await invoke("export_godot", { path });
+}
+
+export async function renderPreview(
+  width: number,
+  height: number,
+  style: RenderStyle,
+): Promise<Uint8Array> {
+  const buffer: ArrayBuffer = await invoke("render_preview", { width, height, style });
+  return new Uint8Array(buffer);
+}
+
+export async function renderSnapshot(
+  width: number,
+  height: number,
+  camera?: Camera,
+): Promise<Uint8Array> {
+  const buffer: ArrayBuffer = await invoke("render_snapshot", {
+    camera: camera ?? null,
+    width,
+    height,
+  });
+  return new Uint8Array(buffer);
+}
+
+export async function exportMapImage(
+  path: string,
+  width: number,
+  height: number,
+  style: RenderStyle,
+  furniture?: MapFurniture,
+): Promise<void> {
+  await invoke("export_map_image", {
+    path,
+    width,
+    height,
+    style,
+    furniture: furniture ?? null,
+  });
+}
+
+/** Indexed PNG of the terrain classes plus a `_legend.json` sidecar. */
+export async function exportBiomeMap(path: string, rules?: BiomeRules): Promise<void> {
+  await invoke("export_biome_map", { path, rules: rules ?? null });
+}
+
+/** Write the chosen maps into the folder `dir` in one pass; the texture map
+ * needs a texture. Returns the written paths. */
+export async function exportBundle(dir: string, maps: MapKind[]): Promise<string[]> {
+  return await invoke("export_bundle", { path: dir, maps });
+}
+
+/** Vector contour lines as SVG (pixel units) or GeoJSON (meters, north up). */
+export async function exportContours(path: string, params: ContourParams): Promise<void> {
+  await invoke("export_contours", { path, params });
+}
+
+/** RGBA material weight maps, four layers each, plus a `_splat.json` manifest. */
+export async function exportSplatmap(path: string, rules?: SplatRules): Promise<SplatExport> {
+  return await invoke("export_splatmap", { path, rules: rules ?? null });
+}
+
+/** Carve rivers along the drainage network; also returns the channels in [0, 1]. */
+export async function carveRivers(
+  params: RiverParams,
+  onProgress?: (progress: Progress) => void,
+): Promise<{ heightmap: HeightmapData; rivers: HeightmapData }> {
+  const channel = new Channel<Progress>();
+  channel.onmessage = (p) => onProgress?.(p);
+  const buffer: ArrayBuffer = await invoke("carve_rivers", { params, channel });
+  const [heightmap, rivers] = parseResponseSet(buffer);
+  return { heightmap, rivers };
+}
+
+/** Simulate snow cover into the "snow" mask channel; returns the coverage in [0, 1]. */
+export async function runSnow(params: SnowParams): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("run_snow", { params });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function exportSnowMap(path: string): Promise<void> {
+  await invoke("export_snow_map", { path });
+}
+
+/** Watertight binary STL of the terrain on a base slab, for 3D printing. */
+export async function exportStl(path: string, params: StlParams): Promise<void> {
+  await invoke("export_stl", { path, params });
+}
+
+/** Slope in degrees per pixel, shaped like a heightmap. */
+export async function getSlopeMap(): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("get_slope_map");
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Droplet paths from the last hydraulic run with `traceDroplets`. */
+export async function getDropletTraces(): Promise<DropletTrace[]> {
+  return await invoke("get_droplet_traces");
+}
+
+/** One map from the last hydraulic run with `recordMaps`, scaled to [0, 1]. */
+export async function getErosionMap(kind: ErosionMapKind): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("get_erosion_map", { kind });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+/** Writes every erosion map as a 16-bit PNG next to `path`; returns the files written. */
+export async function exportErosionMaps(path: string): Promise<string[]> {
+  return await invoke("export_erosion_maps", { path });
+}
+
+/** Slope or aspect map; degrees need a float format (exr, tiff32 or raw_f32). */
+export async function exportGradientMap(path: string, map: GradientMap, unit: MapUnit, format: string): Promise<void> {
+  await invoke("export_gradient_map", { path, map, unit, format });
+}
+
+export async function getNormalMap(): Promise<Uint8Array> {
+  const bytes: ArrayBuffer = await invoke("get_normal_map");
+  return new Uint8Array(bytes);
+}
+
+/** Put a PNG of `map` on the system clipboard, longer side capped at `maxSize`
+ * (2048 by default). On Linux this needs wl-copy or xclip. */
+export async function copyMapToClipboard(map: ClipboardMap, maxSize?: number): Promise<void> {
+  await invoke("copy_map_to_clipboard", { map, maxSize });
+}
+
+export async function getHeightmapMip(level: number): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("get_heightmap_mip", { level });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function getWorldScale(): Promise<WorldScale> {
+  return await invoke("get_world_scale");
+}
+
+export async function setWorldScale(worldScale: WorldScale): Promise<void> {
+  await invoke("set_world_scale", { worldScale });
+}
+
+export async function getBoundary(): Promise<Boundary> {
+  return await invoke("get_boundary");
+}
+
+export async function setBoundary(boundary: Boundary): Promise<void> {
+  await invoke("set_boundary", { boundary });
+}
+
+/** Elevation in meters at a pixel position, bilinearly interpolated. */
+export async function sampleHeight(x: number, y: number): Promise<number> {
+  return await invoke("sample_height", { x, y });
+}
+
+/** Elevations in meters at several pixel positions, in order. */
+export async function sampleHeights(points: [number, number][]): Promise<number[]> {
+  return await invoke("sample_heights", { points });
+}
+
+export async function getMask(channel: MaskChannel): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("get_mask", { channel });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function paintMask(
+  channel: MaskChannel,
+  stroke: MaskStroke,
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("paint_mask", { channel, stroke });
+  return parseResponse(buffer);
+}
+
+export async function clearMask(channel: MaskChannel): Promise<void> {
+  await invoke("clear_mask", { channel });
+}
+
+/** Fill a mask channel from a grayscale PNG on disk; white = 1. */
+export async function importMask(channel: MaskChannel, path: string): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("import_mask", { channel, path });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function configureTileGrid(
+  tilesX: number,
+  tilesY: number,
+  baseSeed: number,
+): Promise<TileGrid> {
+  return await invoke("configure_tile_grid", { tilesX, tilesY, baseSeed });
+}
+
+export async function getTileGrid(): Promise<TileGrid | null> {
+  return await invoke("get_tile_grid");
+}
+
+export async function setTileLock(
+  tx: number,
+  ty: number,
+  locked: boolean,
+): Promise<void> {
+  await invoke("set_tile_lock", { tx, ty, locked });
+}
+
+export async function regenerateTile(
+  tx: number,
+  ty: number,
+  params: NoiseParams,
+  seed?: number,
+  blendWidth?: number,
+): Promise<HeightmapData | HeightmapRegion> {
+  const buffer: ArrayBuffer = await invoke("regenerate_tile", {
+    tx,
+    ty,
+    params,
+    seed: seed ?? null,
+    blendWidth: blendWidth ?? null,
+  });
+  return parseResponse(buffer);
+}
+
+export async function regenerateUnlockedTiles(
+  params: NoiseParams,
+  blendWidth?: number,
+): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("regenerate_unlocked_tiles", {
+    params,
+    blendWidth: blendWidth ?? null,
+  });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function addExportHook(path: string): Promise<ExportHookInfo[]> {
+  return await invoke("add_export_hook", { path });
+}
+
+export async function listExportHooks(): Promise<ExportHookInfo[]> {
+  return await invoke("list_export_hooks");
+}
+
+export async function removeExportHook(path: string): Promise<ExportHookInfo[]> {
+  return await invoke("remove_export_hook", { path });
+}
+
+/** Attach a higher-resolution patch over a base rectangle, optionally with fine noise. */
+export async function addDetailPatch(
+  x: number,
+  y: number,
+  w: number,
+  h: number,
+  factor: number,
+  detail?: NoiseParams
+): Promise<DetailPatchInfo[]> {
+  return await invoke("add_detail_patch", { x, y, w, h, factor, detail: detail ?? null });
+}
+
+export async function listDetailPatches(): Promise<DetailPatchInfo[]> {
+  return await invoke("list_detail_patches");
+}
+
+/** A patch's residual grid, added to the upsampled base for display. */
+export async function getDetailPatch(index: number): Promise<HeightmapData> {
+  const buffer: ArrayBuffer = await invoke("get_detail_patch", { index });
+  return parseResponse(buffer) as HeightmapData;
+}
+
+export async function removeDetailPatch(index: number): Promise<DetailPatchInfo[]> {
+  return await invoke("remove_detail_patch", { index });
+}
+
+/** Every patch with its residual, ready for the viewer. */
+export async function fetchDetailPatches(): Promise<{ info: DetailPatchInfo; residual: HeightmapData }[]> {
+  const infos = await listDetailPatches();
+  return Promise.all(infos.map(async (info, i) => ({ info, residual: await getDetailPatch(i) })));
+}
+
+export async function getLocale(): Promise<Locale> {
+  return await invoke("get_locale");
+}
+
+export async function listLocales(): Promise<LocaleInfo[]> {
+  return await invoke("list_locales");
+}
+
+/** Switch the language of backend strings and rebuild the menu. */
+export async function setLocale(locale: Locale): Promise<void> {
+  await invoke("set_locale", { locale });
+}
+
+export async function getUsageStats(): Promise<UsageStats> {
+  return await invoke("get_usage_stats");
+}
+
+/** Turn usage tracking for the open project on or off. */
+export async function setUsageTracking(enabled: boolean): Promise<UsageStats> {
+  return await invoke("set_usage_tracking", { enabled });
+}
+
+export async function resetUsageStats(): Promise<UsageStats> {
+  return await invoke("reset_usage_stats");
+}
+
+/** Names of the saved presets of `kind`. */
+export async function listPresets(kind: PresetKind): Promise<string[]> {
+  return await invoke("list_presets", { kind });
+}
+
+/** Save `params` under `name`, replacing any preset of that name. Returns the updated list. */
+export async function savePreset<K extends PresetKind>(kind: K, name: string, params: PresetParams[K]): Promise<string[]> {
+  return await invoke("save_preset", { kind, name, params });
+}
+
+export async function deletePreset(kind: PresetKind, name: string): Promise<string[]> {
+  return await invoke("delete_preset", { kind, name });
+}
+
+/** A saved preset's parameters, to load into the controls. */
+export async function applyPreset<K extends PresetKind>(kind: K, name: string): Promise<Preset<K>> {
+  return await invoke("apply_preset", { kind, name });
+}
+
+/** Run the export profile saved as preset `name`. Returns the main files written. */
+export async function runExportProfile(name: string): Promise<string[]> {
+  return await invoke("run_export_profile", { name });
 }
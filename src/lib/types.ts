@@ -20,10 +20,132 @@ export interface BrushStroke {
   radius: number;
   strength: number;
   op: BrushOp;
+  /** First dab of a drag; usage statistics count one stroke per drag. */
+  begins?: boolean;
+}
+
+export type MaskChannel = "holes" | "selection" | "hardness" | "snow" | "water";
+
+export interface MaskStroke {
+  x: number;
+  y: number;
+  radius: number;
+  strength: number;
+  erase?: boolean;
+}
+
+export interface RampParams {
+  x0: number;
+  y0: number;
+  x1: number;
+  y1: number;
+  width: number;
+  maxGrade: number;
+  falloff: number;
+}
+
+export interface PlatformParams {
+  points: [number, number][];
+  targetHeight?: number | null;
+  embankmentAngle: number;
+}
+
+export interface CraterParams {
+  x: number;
+  y: number;
+  diameter: number;
+  rimHeight: number;
+  floorDepth: number;
+  ejecta?: number;
+  seed?: number;
+}
+
+export interface CraterFieldParams {
+  count: number;
+  minDiameter: number;
+  maxDiameter: number;
+  sizeExponent: number;
+  depthRatio: number;
+  rimRatio: number;
+  ejecta?: number;
+  seed: number;
+}
+
+export interface VolcanoParams {
+  x: number;
+  y: number;
+  baseRadius: number;
+  height: number;
+  profile: number;
+  craterRadius: number;
+  calderaDepth: number;
+  flankNoise?: number;
+  seed?: number;
+}
+
+export interface MountainRangeParams {
+  points: [number, number][];
+  width: number;
+  height: number;
+  roughness: number;
+  escarpment?: boolean;
+  seed?: number;
+}
+
+export type NoiseType = "perlin" | "simplex" | "worley" | "dunes" | "analytic";
+export type DunePattern = "transverse" | "barchan" | "star";
+
+export interface DuneParams {
+  pattern?: DunePattern;
+  windDirection?: number;
+  wavelength?: number;
+  irregularity?: number;
+}
+export type AnalyticShape = "gradient" | "radial" | "plane";
+
+export interface AnalyticParams {
+  shape?: AnalyticShape;
+  angle?: number;
+  centerX?: number;
+  centerY?: number;
+  radius?: number;
+  from?: number;
+  to?: number;
+}
+export type WorleyMode = "f1" | "f2" | "f2MinusF1";
+export type FractalType = "fbm" | "hybridMultifractal" | "swissTurbulence";
+
+export interface TerraceParams {
+  steps: number;
+  smoothing: number;
+}
+
+export interface TectonicParams {
+  faults: number;
+  upliftBlocks?: number;
+  faultWidth: number;
+  strength: number;
+}
+
+export type BlendMode =
+  | "replace"
+  | "add"
+  | "subtract"
+  | "multiply"
+  | "min"
+  | "max"
+  | "lerp";
+
+export interface FalloffParams {
+  shape: "radial" | "square";
+  centerX: number;
+  centerY: number;
+  radius: number;
+  curve: number;
 }
 
 export interface NoiseParams {
-  noiseType: "perlin" | "simplex";
+  noiseType: NoiseType;
   seed: number;
   octaves: number;
   frequency: number;
@@ -31,12 +153,84 @@ export interface NoiseParams {
   persistence: number;
   amplitude: number;
   offset: number;
+  worleyMode?: WorleyMode;
+  fractal?: FractalType;
+  dunes?: DuneParams;
+  analytic?: AnalyticParams;
+  falloff?: FalloffParams | null;
+  terrace?: TerraceParams | null;
+  tectonic?: TectonicParams | null;
+  blend?: BlendMode;
+  blendWeight?: number;
+  useSelection?: boolean;
+  maskFeather?: number;
+}
+
+export interface RuleRange {
+  min: number;
+  max: number;
+  falloff?: number;
+}
+
+export interface NoiseLayer {
+  params: NoiseParams;
+  enabled?: boolean;
+  height?: RuleRange | null;
+  slope?: RuleRange | null;
+}
+
+export interface DetailPatchInfo {
+  x: number;
+  y: number;
+  w: number;
+  h: number;
+  factor: number;
+}
+
+export interface Expansion {
+  left?: number;
+  top?: number;
+  right?: number;
+  bottom?: number;
+}
+
+export interface TileGrid {
+  tilesX: number;
+  tilesY: number;
+  seeds: number[];
+  locked: boolean[];
+}
+
+/** Where erosion runs; "gpu" needs a build with the gpu feature. */
+export type ErosionBackend = "cpu" | "gpu";
+
+/** One rock layer; thickness in normalized height units. */
+export interface Stratum {
+  thickness: number;
+  /** Stable slope within the layer, in the units of `ThermalParams.talus`. */
+  talus: number;
+  /** Resistance to erosion in [0, 1]. */
+  hardness?: number;
+}
+
+/** Repeating rock layers, top to bottom, tilted by `dip` degrees. */
+export interface Strata {
+  layers: Stratum[];
+  dip?: number;
+  /** Compass direction the layers descend towards; 0 is the top of the map. */
+  dipDirection?: number;
 }
 
 export interface ThermalParams {
   iterations: number;
   talus: number;
   transferRate: number;
+  backend?: ErosionBackend;
+  /** Restrict the run to the selection mask, blending in over `maskFeather` pixels. */
+  useSelection?: boolean;
+  maskFeather?: number;
+  /** Layered rock; replaces `talus` by depth and adds hardness. CPU only. */
+  strata?: Strata | null;
 }
 
 export interface HydraulicParams {
@@ -50,8 +244,312 @@ export interface HydraulicParams {
   capacityFactor: number;
   erosionRadius: number;
   gravity: number;
+  /** Fixed droplet seed for reproducible runs; random when omitted. */
+  seed?: number | null;
+  backend?: ErosionBackend;
+  /** Record erosion maps for texturing; always runs on the CPU. */
+  recordMaps?: boolean;
+  /** Record the paths of about this many droplets; always runs on the CPU. */
+  traceDroplets?: number;
+  /** As in `ThermalParams`; droplets then only spawn inside the mask. */
+  useSelection?: boolean;
+  maskFeather?: number;
+  /** As in `ThermalParams`; only hardness applies. */
+  strata?: Strata | null;
+}
+
+/** How flow leaves a cell: all to the steepest neighbor, or split between two. */
+export type FlowMethod = "d8" | "dInfinity";
+
+/** River carving settings; channels widen and deepen downstream with discharge. */
+export interface RiverParams {
+  method?: FlowMethod;
+  /** Upstream drainage area in pixels at which a channel begins. */
+  threshold?: number;
+  /** Channel width in pixels where it begins. */
+  width?: number;
+  /** Channel depth where it begins, normalized. */
+  depth?: number;
+  maxWidth?: number;
+}
+
+/** Snow simulation settings; heights are normalized. */
+export interface SnowParams {
+  snowline?: number;
+  /** Height band above the snowline over which snowfall ramps up. */
+  transition?: number;
+  /** Fresh snow depth at full snowfall. */
+  snowfall?: number;
+  /** Steepest snow surface in degrees that holds before sliding. */
+  maxSlope?: number;
+  iterations?: number;
+}
+
+/** Unity calls little endian "Windows" and big endian "Mac". */
+/** Slope is 0-90 degrees; aspect is the downhill compass direction, 0-360
+ * degrees clockwise from north, or -1 on level ground. */
+export type GradientMap = "slope" | "aspect";
+
+/** Normalized scales degrees into [0, 1]: slope over 90, aspect over 360. */
+export type MapUnit = "degrees" | "normalized";
+
+export type ByteOrder = "little" | "big";
+
+/** How float TIFF and EXR samples become heights: the file's own min/max, an
+ * explicit range mapped to 0-1, or the values as they are, outside 0-1 included. */
+export type Normalization =
+  | { mode: "auto" }
+  | { mode: "range"; min: number; max: number }
+  | { mode: "raw" };
+
+/** Where an imported stamp goes: a rectangle in map pixels, or the
+ * selection's bounding box blended through its weights. */
+export type StampTarget =
+  | { mode: "rect"; x: number; y: number; width: number; height: number }
+  | { mode: "selection" };
+
+export interface StampParams {
+  target: StampTarget;
+  /** Size relative to the target, about its center. */
+  scale?: number;
+  /** Degrees clockwise. */
+  rotation?: number;
+  /** Heights the stamp's lowest and highest samples map to. */
+  heightLow?: number;
+  heightHigh?: number;
+  /** Defaults to "lerp", which fades with the feathering. */
+  blend?: BlendMode;
+  opacity?: number;
+  /** Pixels over which the edges fade out. */
+  feather?: number;
+}
+
+/** How mosaic tiles find their place: in row-major order `columns` to a row, or
+ * by file names where `{x}` and `{y}` are the column and row and `{name}`
+ * matches any text. */
+export type MosaicLayout =
+  | { mode: "grid"; columns: number }
+  | { mode: "pattern"; pattern: string };
+
+export interface MosaicParams {
+  layout: MosaicLayout;
+  /** Pixels each tile shares with the next one right and below. */
+  overlap?: number;
+}
+
+/** Latitude/longitude box in degrees to download terrain for. */
+export interface FetchParams {
+  west: number;
+  south: number;
+  east: number;
+  north: number;
+  /** Pixels along the longer side; the other follows the box on the ground. */
+  resolution: number;
+}
+
+/** Bicubic is Catmull-Rom; Lanczos is sharper but rings more at cliffs. */
+export type ResampleFilter = "bicubic" | "lanczos";
+
+/** Resolution to export at, leaving the working map as it is. */
+export interface ExportResolution {
+  /** Pixels along the longer side; the shorter keeps the aspect ratio. */
+  size: number;
+  filter?: ResampleFilter;
+}
+
+/** Sample layout of a headerless raw heightmap. */
+export interface RawOptions {
+  bitDepth?: 8 | 16 | 32;
+  /** IEEE float samples instead of unsigned integers; 32-bit only. */
+  float?: boolean;
+  byteOrder?: ByteOrder;
+  /** Write rows bottom up. */
+  flipVertical?: boolean;
+}
+
+export interface UnityRawOptions {
+  byteOrder?: ByteOrder;
+  /** Write rows bottom up, so Unity needs no flip on import. */
+  flipVertical?: boolean;
+}
+
+/** Epic's recommended landscape sizes, in vertices per side. */
+export type UnrealResolution = 127 | 253 | 505 | 1009 | 2017 | 4033 | 8129;
+
+export interface UnrealOptions {
+  /** Vertices along the longer side; the nearest to the map's size when omitted. */
+  resolution?: UnrealResolution | null;
+}
+
+/** Settings for Unreal's landscape import, also written to the `_unreal.json` sidecar. */
+export interface UnrealImport {
+  width: number;
+  height: number;
+  sourceWidth: number;
+  sourceHeight: number;
+  /** Actor scale; X and Y are centimetres between vertices. */
+  scale: [number, number, number];
+  /** Actor Z location in centimetres. */
+  locationZ: number;
+  sectionSize: number;
+  sectionsPerComponent: number;
+  components: [number, number];
+}
+
+/** Settings for Godot's HTerrain and Terrain3D imports, also written to the
+ * `_godot.json` sidecar. */
+export interface GodotImport {
+  width: number;
+  height: number;
+  metersPerPixel: number;
+  /** Elevation range of the EXR, in meters. */
+  minHeight: number;
+  maxHeight: number;
+  hterrain: {
+    resolution: number;
+    mapScale: [number, number, number];
+    minHeight: number;
+    maxHeight: number;
+  };
+  terrain3d: {
+    vertexSpacing: number;
+    importScale: number;
+    importOffset: number;
+  };
+  notes: string[];
+}
+
+/** Dimensions of a printed relief model, in millimetres. */
+export interface StlParams {
+  /** Length of the longer side. */
+  sizeMm?: number;
+  /** Slab under the lowest point. */
+  baseMm?: number;
+  /** Height of the full normalized height range. */
+  verticalMm?: number;
+  /** Grid points along the longer side. */
+  resolution?: number;
+  /** Facets to simplify down to, keeping detail where the surface bends most;
+   * null for the full grid. At least 100. */
+  targetTriangles?: number | null;
+}
+
+/** One step of a traced droplet, in pixels and normalized heights. */
+export interface TracePoint {
+  x: number;
+  y: number;
+  height: number;
+  sediment: number;
+}
+
+/** Path of one droplet from where it spawned to where it evaporated or left the map. */
+export type DropletTrace = TracePoint[];
+
+/** Per-pixel maps a recording hydraulic run leaves behind. */
+/** Map in an export bundle; files are named `<folder>_<id>.png`, with `ao`
+ * for occlusion. */
+export type MapKind = "height" | "normal" | "occlusion" | "slope" | "flow" | "splat" | "texture";
+
+export type ErosionMapKind = "erosion" | "deposition" | "flow" | "wetness";
+
+/** What a long-running job is busy with. */
+export type ProgressStage = "thermal" | "droplets" | "water" | "sinks" | "rivers" | "uplift" | "glaciers" | "waves" | "saving" | "loading";
+
+/** Progress report from a long-running job. */
+export interface Progress {
+  stage: ProgressStage;
+  /** 0 to 1. */
+  fraction: number;
+  /** Estimated seconds left; null until the backend has seen enough progress. */
+  etaSecs: number | null;
+  /** Position of the job when several run back to back; `index` counts from 0. */
+  step?: { index: number; count: number };
+  /** Set on the report announcing that the job paused, resumed or finished. */
+  state?: RunState;
+}
+
+export type RunState = "paused" | "resumed" | "cpuFallback" | "finished";
+
+export interface GpuStatus {
+  available: boolean;
+  /** Why the last GPU run fell back to the CPU, if it did. */
+  lastError: string | null;
+}
+
+/** Shallow-water erosion; depths are in normalized height units. */
+export interface PipeParams {
+  iterations: number;
+  rainfall: number;
+  pipeArea: number;
+  sedimentCapacity: number;
+  evaporation: number;
+  dissolveRate?: number;
+  depositRate?: number;
+}
+
+/** Stream-power incision; `uplift` is added per iteration in normalized height units. */
+export interface StreamPowerParams {
+  iterations: number;
+  erodibility: number;
+  uplift: number;
+  areaExponent?: number;
+  slopeExponent?: number;
+}
+
+/** Glacial erosion; `snowline` is a normalized height. */
+export interface GlacialParams {
+  iterations: number;
+  snowline: number;
+  massBalance: number;
+  erosionRate: number;
+  fluidity?: number;
+}
+
+/** Coastal erosion; heights are normalized, `windDirection` is a compass bearing in degrees. */
+export interface CoastalParams {
+  iterations: number;
+  seaLevel: number;
+  waveEnergy: number;
+  windDirection?: number;
+  directionality?: number;
+  platformDepth?: number;
+  depositRate?: number;
+  maxFetch?: number;
+}
+
+/** Stair-step cleanup; `featureThreshold` is a normalized height change per pixel. */
+export interface CurvatureFlowParams {
+  iterations: number;
+  featureThreshold: number;
+}
+
+/** One erosion pass, tagged with its kind. */
+export type ErosionRun =
+  | ({ kind: "thermal" } & ThermalParams)
+  | ({ kind: "hydraulic" } & HydraulicParams)
+  | ({ kind: "pipe" } & PipeParams)
+  | ({ kind: "streamPower" } & StreamPowerParams)
+  | ({ kind: "glacial" } & GlacialParams)
+  | ({ kind: "coastal" } & CoastalParams);
+
+export interface ErosionComparison {
+  a: HeightmapData;
+  b: HeightmapData;
+  /** Per-pixel b - a in normalized height units. */
+  diff: HeightmapData;
 }
 
+export interface Camera {
+  position: [number, number, number];
+  target: [number, number, number];
+  fov: number;
+}
+
+export type RenderStyle = "grayscale" | "hillshade" | "colorRelief" | "shadedRelief";
+
+/** Image `copyMapToClipboard` renders; heightmaps are copied as 8-bit grayscale. */
+export type ClipboardMap = "heightmap" | "hillshade" | "normal";
+
 export type AISculptMode = "texture" | "heightmap" | "texture_gen";
 export type AIStatus = "idle" | "running" | "error";
 
@@ -63,7 +561,7 @@ export interface ProjectSettings {
     strength: number;
   };
   generation: {
-    noiseType: "perlin" | "simplex";
+    noiseType: NoiseType;
     seed: number;
     octaves: number;
     frequency: number;
@@ -79,11 +577,327 @@ export interface ProjectSettings {
     erosionRate: number;
     depositionRate: number;
     inertia: number;
+    /** Record-maps checkbox; missing in projects saved before it existed. */
+    recordMaps?: boolean;
+    traceDroplets?: boolean;
+    /** Rock layer controls, likewise optional. */
+    strataEnabled?: boolean;
+    strataSpacing?: number;
+    strataHardBand?: number;
+    strataDip?: number;
+    /** Pipe-model sliders; missing in projects saved before they existed. */
+    pipeIterations?: number;
+    rainfall?: number;
+    pipeArea?: number;
+    sedimentCapacity?: number;
+    evaporation?: number;
+    /** Stream-power sliders, likewise optional. */
+    streamPowerIterations?: number;
+    erodibility?: number;
+    uplift?: number;
+    /** Glacier sliders, likewise optional. */
+    glacialIterations?: number;
+    snowline?: number;
+    massBalance?: number;
+    glacialErosion?: number;
+    /** Coast sliders, likewise optional. */
+    coastalIterations?: number;
+    seaLevel?: number;
+    waveEnergy?: number;
+    windDirection?: number;
+    directionality?: number;
+    /** Mask channel protecting terrain from erosion, restored with the masks. */
+    hardness?: MaskChannel | null;
   };
 }
 
+export interface WorldScale {
+  metersPerPixel: number;
+  minElevation: number;
+  maxElevation: number;
+  /** Set by DEM imports; pass it back to `setWorldScale` to keep it. */
+  georeference?: Georeference | null;
+}
+
+/** Placement of the map in a coordinate reference system. */
+export interface Georeference {
+  epsg: number | null;
+  /** Coordinates are longitude and latitude in degrees. */
+  geographic: boolean;
+  /** Top-left corner of the top-left cell, in model units. */
+  origin: [number, number];
+  /** Cell width and height in model units. */
+  pixelSize: [number, number];
+}
+
+/** What algorithms read past the map edge; `value` is a normalized height. */
+export type Boundary =
+  | { mode: "clamp" }
+  | { mode: "mirror" }
+  | { mode: "wrap" }
+  | { mode: "fixed"; value: number };
+
+/** Classification thresholds; heights are normalized, `cliffSlope` in degrees. */
+export interface BiomeRules {
+  waterLevel?: number;
+  beachLevel?: number;
+  highlandLevel?: number;
+  rockLevel?: number;
+  snowLevel?: number;
+  cliffSlope?: number;
+}
+
+export type ContourFormat = "svg" | "geojson";
+
+export interface ContourParams {
+  /** Spacing in meters. */
+  interval?: number;
+  /** Every this many contours is an index contour; 0 for none. */
+  indexEvery?: number;
+  format?: ContourFormat;
+}
+
+/** Value a splat rule tests: normalized height, slope in degrees, wetness
+ * (topographic wetness index scaled to [0, 1] over the map) or ambient
+ * occlusion (1 under open sky). */
+export type SplatAttribute = "height" | "slope" | "wetness" | "occlusion";
+
+export interface SplatCondition {
+  attribute: SplatAttribute;
+  min?: number | null;
+  max?: number | null;
+  /** Blend width across each bound, in the attribute's units. */
+  falloff?: number;
+}
+
+export interface SplatLayer {
+  name: string;
+  conditions?: SplatCondition[];
+}
+
+/** Layers bottom to top; each covers the ones before it where its conditions
+ * hold, and the first is the base. At most 16. */
+export interface SplatRules {
+  layers?: SplatLayer[];
+}
+
+export interface SplatLayerInfo {
+  name: string;
+  /** Index of the `_splatN.png` weight map holding the layer. */
+  texture: number;
+  channel: "r" | "g" | "b" | "a";
+  coverage: number;
+}
+
+export interface SplatExport {
+  layers: SplatLayerInfo[];
+}
+
+export interface MapFurniture {
+  scaleBar?: boolean;
+  northArrow?: boolean;
+  contourInterval?: number | null;
+}
+
+export interface SyncExportReport {
+  tilesTotal: number;
+  changed: [number, number][];
+}
+
+export interface TileExportParams {
+  tilesX?: number;
+  tilesY?: number;
+  /** Pixels each tile repeats from the next past its right and bottom edges;
+   * 1 shares seam samples between neighbors. */
+  overlap?: number;
+  /** File name without extension; `{name}`, `{x}`, `{y}` and `{index}` are
+   * filled in per tile. */
+  pattern?: string;
+  /** Export format id, as from `listExportFormats`. */
+  format?: string;
+}
+
+export interface ExportHookInfo {
+  name: string;
+  path: string;
+}
+
 export interface LoadProjectResponse {
   texturePng: number[] | null;
   settingsJson: string;
+  worldScale: WorldScale;
+  boundary: Boundary;
+  metadata: ProjectMetadata;
+  /** Set if the file was damaged and only partly read. */
+  damage?: DamageReport;
+}
+
+/** What opening a damaged project cost. Lost heightmap samples are set to 0. */
+export interface DamageReport {
+  /** Heightmap areas that couldn't be read. */
+  lost: LostRegion[];
+  /** Heightmap entries read in full that fail their checksum. */
+  suspect: DamagedEntry[];
+  /** Damaged texture, settings, checkpoints, masks, erosion maps or operation
+   * history, left out. */
+  dropped: DamagedEntry[];
+}
+
+export interface DamagedEntry {
+  entry: string;
+  reason: string;
 }
 
+/** Pixels of the heightmap lost with a damaged entry. */
+export interface LostRegion extends DamagedEntry {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+/** What the user wrote about the project, saved in its manifest. */
+export interface ProjectMetadata {
+  name: string;
+  author: string;
+  description: string;
+  tags: string[];
+}
+
+/** A generation, erosion or filter operation recorded in the project. */
+export interface Operation {
+  /** The command that ran, e.g. `run_thermal_erosion`. */
+  command: string;
+  /** Its arguments; invoking `command` with them replays it. Uploaded masks
+   * aren't kept. */
+  args: Record<string, unknown>;
+  /** Size of the terrain it ran on. */
+  width: number;
+  height: number;
+  /** Seconds since the Unix epoch. */
+  recordedAt: number;
+  /** Stopped part way. */
+  aborted?: boolean;
+}
+
+/** Outcome of an autosave, from the `autosave` event. */
+export interface AutosaveStatus {
+  /** Seconds since the Unix epoch. */
+  at: number;
+  /** Why it failed; null once a save succeeds. */
+  error: string | null;
+}
+
+/** Autosave left by a session that didn't exit cleanly. */
+export interface RecoveryInfo {
+  path: string;
+  /** Seconds since the Unix epoch. */
+  savedAt: number;
+}
+
+/** A file dropped on the window, sorted by the import that reads it. `.r16`
+ * and `.r32` raw files name their bit depth. */
+export type DroppedFile =
+  | { kind: "project"; path: string }
+  | { kind: "heightmap"; path: string; float: boolean }
+  | { kind: "raw"; path: string; bitDepth: 16 | 32 | null }
+  | { kind: "unsupported"; path: string };
+
+/** Size of the texture the backend holds for saves and exports. */
+export interface TextureInfo {
+  width: number;
+  height: number;
+}
+
+/** Whole-map operations the backend only runs with a confirmation token. */
+export type DestructiveOp = "replaceFromAi" | "generate" | "normalize" | "resize" | "import" | "newProject" | "restore";
+
+/** Document state saved before a destructive operation. */
+export interface CheckpointInfo {
+  id: number;
+  op: DestructiveOp;
+  /** Seconds since the Unix epoch. */
+  createdAt: number;
+  width: number;
+  height: number;
+}
+
+export type ErrorKind =
+  | "invalidInput"
+  | "busy"
+  | "notFound"
+  | "io"
+  | "format"
+  | "aborted"
+  | "confirmationRequired"
+  | "script"
+  | "ml"
+  | "internal";
+
+/** Error rejected by every command. */
+export interface TopographError {
+  kind: ErrorKind;
+  message: string;
+  hint?: string;
+  logPath?: string;
+}
+
+export type Locale = "en" | "de" | "fr";
+
+export interface LocaleInfo {
+  locale: Locale;
+  name: string;
+}
+
+export interface ExportFormatInfo {
+  id: string;
+  name: string;
+  extension: string;
+}
+
+/** Opt-in, local-only statistics for the open project. */
+export interface UsageStats {
+  enabled: boolean;
+  /** Unix seconds when tracking was first turned on. */
+  trackingSince: number | null;
+  strokes: number;
+  erosionSeconds: number;
+  /** Export counts by kind, e.g. "png16", "sync", "mapImage". */
+  exports: Record<string, number>;
+}
+
+/** What an export profile exports, with that export's settings. */
+export type ExportTarget =
+  | { target: "heightmap"; format: string; resolution?: ExportResolution | null }
+  | { target: "raw"; options: RawOptions }
+  | { target: "tiles"; params: TileExportParams }
+  | { target: "unity"; options: UnityRawOptions }
+  | { target: "unreal"; options: UnrealOptions }
+  | { target: "godot" };
+
+/** Export a profile is saved from: a heightmap format or one of the other exports. */
+export type ExportProfileSource = "png16" | "exr" | "tiff32" | "raw" | "tiles" | "unity" | "unreal" | "godot";
+
+/** Saved export settings, run in one step with `runExportProfile`. */
+export type ExportProfile = ExportTarget & {
+  /** Absolute folder path, created if missing. */
+  destination: string;
+  /** File name without extension; "terrain" by default. */
+  fileName?: string;
+};
+
+/** Which parameters a preset holds. */
+export type PresetKind = "hydraulic" | "thermal" | "noise" | "export";
+
+export interface PresetParams {
+  hydraulic: HydraulicParams;
+  thermal: ThermalParams;
+  noise: NoiseParams;
+  export: ExportProfile;
+}
+
+export interface Preset<K extends PresetKind = PresetKind> {
+  name: string;
+  kind: K;
+  params: PresetParams[K];
+}